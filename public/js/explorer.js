@@ -87,7 +87,11 @@ async function fetchDependenciesAsync(dir) {
     const data = await response.json();
     
     if (data.success) {
-      dir.dependencyGraph = data.dependencyGraph || {};
+      // The UI only needs the paths each file depends on, not the edges' metadata.
+      dir.dependencyGraph = {};
+      for (const [file, edges] of Object.entries(data.dependencyGraph || {})) {
+        dir.dependencyGraph[file] = edges.map(edge => edge.target);
+      }
       console.log('Dependencies loaded successfully:', Object.keys(dir.dependencyGraph).length, 'files');
       
       // Update the dependency graph visualization with the new data
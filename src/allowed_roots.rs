@@ -0,0 +1,271 @@
+use crate::file_system::{canonicalize_path, validate_path};
+use crate::utils::{from_api_path, to_api_path};
+use log::{info, warn};
+use path_clean::PathClean;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Directories that may be browsed and read.
+///
+/// Roots configured with `ALLOWED_ROOTS` or `--root` restrict every endpoint to paths under
+/// them. Without any, every directory may be browsed, and the file endpoints may serve files
+/// under the roots opened through `/api/directory`. The filesystem root, the home directory
+/// and its ancestors are never opened unless configured or `ALLOW_BROAD_ROOTS` is set.
+pub struct AllowedRoots {
+    /// Whether roots were configured, even if none of them exist.
+    restricted: bool,
+    configured: Vec<PathBuf>,
+    /// The configured roots as written (made absolute and cleaned), so requested paths can be
+    /// checked before they are resolved on disk.
+    configured_as_written: Vec<PathBuf>,
+    opened: RwLock<Vec<PathBuf>>,
+    /// Whether the filesystem root and home directory may be opened without being configured.
+    allow_broad: bool,
+    home: Option<PathBuf>,
+}
+
+impl AllowedRoots {
+    /// Starts from the configured roots, skipping (with a warning) any that don't exist.
+    pub fn new(configured: &[PathBuf], allow_broad: bool) -> Self {
+        let home = env::var_os("HOME")
+            .or_else(|| env::var_os("USERPROFILE"))
+            .and_then(|home| canonicalize_path(Path::new(&home)).ok());
+        Self::with_home(configured, allow_broad, home)
+    }
+
+    fn with_home(configured: &[PathBuf], allow_broad: bool, home: Option<PathBuf>) -> Self {
+        let roots = configured
+            .iter()
+            .filter_map(|root| match canonicalize_path(root) {
+                Ok(root) => Some(root),
+                Err(e) => {
+                    warn!("Ignoring allowed root '{}': {}", root.display(), e);
+                    None
+                }
+            })
+            .collect();
+        let configured_as_written = configured
+            .iter()
+            .map(|root| env::current_dir().map(|cwd| cwd.join(root)).unwrap_or_else(|_| root.clone()).clean())
+            .collect();
+        AllowedRoots {
+            restricted: !configured.is_empty(),
+            configured: roots,
+            configured_as_written,
+            opened: RwLock::new(Vec::new()),
+            allow_broad,
+            home,
+        }
+    }
+
+    /// Whether access is restricted to configured roots.
+    pub fn is_restricted(&self) -> bool {
+        self.restricted
+    }
+
+    /// The configured roots that exist, canonicalized.
+    pub fn configured(&self) -> &[PathBuf] {
+        &self.configured
+    }
+
+    /// Canonicalizes a requested path like `validate_path`, then rejects it if roots are
+    /// configured and it isn't equal to or under one of them. The path as written is checked
+    /// before it is looked up, so that the error doesn't reveal whether paths outside the
+    /// roots exist; the canonicalized path is checked again to catch symlinks out of a root.
+    pub fn validate(&self, requested_path: &str) -> Result<PathBuf, String> {
+        let outside = || format!("Path is outside the allowed roots: {}", requested_path);
+        if self.restricted {
+            let written = from_api_path(requested_path).clean();
+            let under = |roots: &[PathBuf]| roots.iter().any(|root| written.starts_with(root));
+            if !under(&self.configured) && !under(&self.configured_as_written) {
+                return Err(outside());
+            }
+        }
+        let path = validate_path(requested_path)?;
+        if self.restricted && !self.configured.iter().any(|root| path.starts_with(root)) {
+            return Err(outside());
+        }
+        Ok(path)
+    }
+
+    /// `validate`, for a directory about to be walked and opened as a root: the filesystem
+    /// root, the home directory and its ancestors are refused unless they are configured
+    /// roots or `ALLOW_BROAD_ROOTS` is set.
+    pub fn validate_root(&self, requested_path: &str) -> Result<PathBuf, String> {
+        let path = self.validate(requested_path)?;
+        if self.is_refused_broad_root(&path) {
+            return Err(format!(
+                "Refusing to open '{}': it contains the whole filesystem or home directory. Open a project directory, or set ALLOW_BROAD_ROOTS=true.",
+                to_api_path(&path)
+            ));
+        }
+        Ok(path)
+    }
+
+    fn is_refused_broad_root(&self, path: &Path) -> bool {
+        let broad = path.parent().is_none() || self.home.as_deref().is_some_and(|home| home.starts_with(path));
+        broad && !self.allow_broad && !self.configured.iter().any(|root| root == path)
+    }
+
+    /// Allows files under `root`, which must already be canonicalized. Broad roots refused
+    /// by `validate_root` are never allowed.
+    pub fn allow(&self, root: &Path) {
+        if self.contains(root) {
+            return;
+        }
+        if self.is_refused_broad_root(root) {
+            warn!("Not allowing file access under '{}': too broad.", root.display());
+            return;
+        }
+        info!("Allowing file access under '{}'.", root.display());
+        self.opened.write().unwrap().push(root.to_path_buf());
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.configured.iter().any(|root| path.starts_with(root))
+            || self.opened.read().unwrap().iter().any(|root| path.starts_with(root))
+    }
+
+    /// Canonicalizes `path`, resolving symlinks, and returns it if it lies under an allowed
+    /// root. Paths that can't be canonicalized (e.g. missing files) are checked as written,
+    /// so that the error doesn't reveal whether files outside the roots exist.
+    pub fn check(&self, path: &Path) -> Result<PathBuf, String> {
+        let resolved = canonicalize_path(path).unwrap_or_else(|_| path.clean());
+        if self.contains(&resolved) {
+            Ok(resolved)
+        } else {
+            Err(format!("Access denied: '{}' is outside the allowed roots", to_api_path(path)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+    use std::fs;
+
+    fn api(path: &Path) -> String {
+        to_api_path(path)
+    }
+
+    #[test]
+    fn filesystem_root_is_refused_unless_opted_in() {
+        let roots = AllowedRoots::with_home(&[], false, None);
+        assert!(roots.validate_root("/").is_err());
+        roots.allow(Path::new("/"));
+        assert!(roots.check(Path::new("/etc/hostname")).is_err());
+
+        let opted_in = AllowedRoots::with_home(&[], true, None);
+        assert_eq!(opted_in.validate_root("/").unwrap(), PathBuf::from("/"));
+    }
+
+    #[test]
+    fn home_and_its_ancestors_are_refused_but_projects_inside_are_not() {
+        let home = TempDir::new();
+        let project = home.path().join("project");
+        fs::create_dir(&project).unwrap();
+        let roots = AllowedRoots::with_home(&[], false, Some(home.path().to_path_buf()));
+
+        assert!(roots.validate_root(&api(home.path())).is_err());
+        assert!(roots.validate_root(&api(home.path().parent().unwrap())).is_err());
+        assert_eq!(roots.validate_root(&api(&project)).unwrap(), project);
+    }
+
+    #[test]
+    fn configured_broad_root_counts_as_opt_in() {
+        let home = TempDir::new();
+        let roots = AllowedRoots::with_home(&[home.path().to_path_buf()], false, Some(home.path().to_path_buf()));
+        assert!(roots.validate_root(&api(home.path())).is_ok());
+    }
+
+    #[test]
+    fn files_are_only_served_under_opened_roots() {
+        let dir = TempDir::new();
+        let file = dir.write("project/a.txt", "a");
+        let outside = dir.write("other/b.txt", "b");
+        let roots = AllowedRoots::with_home(&[], false, None);
+        assert!(roots.check(&file).is_err());
+
+        roots.allow(&dir.path().join("project"));
+        assert_eq!(roots.check(&file).unwrap(), file);
+        assert!(roots.check(&outside).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_an_opened_root_are_refused() {
+        let dir = TempDir::new();
+        let secret = dir.write("secret.txt", "s");
+        let project = dir.path().join("project");
+        fs::create_dir(&project).unwrap();
+        std::os::unix::fs::symlink(&secret, project.join("link.txt")).unwrap();
+        let roots = AllowedRoots::with_home(&[], false, None);
+        roots.allow(&project);
+        assert!(roots.check(&project.join("link.txt")).is_err());
+    }
+
+    #[test]
+    fn configured_roots_restrict_validation() {
+        let dir = TempDir::new();
+        let project = dir.path().join("project");
+        fs::create_dir(&project).unwrap();
+        let roots = AllowedRoots::with_home(std::slice::from_ref(&project), false, None);
+        assert!(roots.is_restricted());
+        assert!(roots.validate(&api(&project.join("."))).is_ok());
+        assert!(roots.validate(&api(dir.path())).is_err());
+        assert!(roots.validate(&format!("{}/../", api(&project))).is_err());
+    }
+
+    #[test]
+    fn traversal_out_of_a_configured_root_is_refused() {
+        let dir = TempDir::new();
+        let allowed = dir.path().join("allowed");
+        fs::create_dir_all(allowed.join("src")).unwrap();
+        fs::create_dir(dir.path().join("etc")).unwrap();
+        let roots = AllowedRoots::with_home(std::slice::from_ref(&allowed), false, None);
+
+        assert_eq!(roots.validate(&format!("{}/src/..", api(&allowed))), Ok(allowed.clone()));
+        // Enough `..` to climb from the root to the filesystem root.
+        let to_filesystem_root = "../".repeat(allowed.components().count());
+        for escape in ["../etc", "src/../../etc", "src/../../allowed/../etc", &to_filesystem_root] {
+            let requested = format!("{}/{}", api(&allowed), escape);
+            let err = roots.validate(&requested).unwrap_err();
+            assert!(err.contains("outside the allowed roots"), "{}: {}", requested, err);
+        }
+        // A sibling sharing the root's name as a prefix isn't under it.
+        fs::create_dir(dir.path().join("allowed-other")).unwrap();
+        assert!(roots.validate(&api(&dir.path().join("allowed-other"))).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_a_configured_root_are_refused() {
+        let dir = TempDir::new();
+        let allowed = dir.path().join("allowed");
+        fs::create_dir(&allowed).unwrap();
+        fs::create_dir(dir.path().join("etc")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("etc"), allowed.join("etc")).unwrap();
+        let roots = AllowedRoots::with_home(std::slice::from_ref(&allowed), false, None);
+        assert!(roots.validate(&api(&allowed.join("etc"))).is_err());
+    }
+
+
+    #[test]
+    fn validation_does_not_reveal_whether_paths_outside_the_roots_exist() {
+        let dir = TempDir::new();
+        let allowed = dir.path().join("allowed");
+        fs::create_dir(&allowed).unwrap();
+        dir.write("secret.txt", "s");
+        let roots = AllowedRoots::with_home(std::slice::from_ref(&allowed), false, None);
+
+        let existing = roots.validate(&api(&dir.path().join("secret.txt"))).unwrap_err();
+        let missing = roots.validate(&api(&dir.path().join("missing.txt"))).unwrap_err();
+        assert_eq!(existing.replace("secret", "missing"), missing);
+        assert!(missing.contains("outside the allowed roots"), "{}", missing);
+        // Missing paths inside a root are still reported as missing.
+        let inside = roots.validate(&api(&allowed.join("missing.txt"))).unwrap_err();
+        assert!(inside.contains("does not exist"), "{}", inside);
+    }
+}
\ No newline at end of file
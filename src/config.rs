@@ -0,0 +1,285 @@
+use crate::dependency_analyzer::DisabledLanguages;
+use log::warn;
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+/// Default cap on the size of files served by the file content endpoints.
+const DEFAULT_MAX_FILE_READ_BYTES: u64 = 10 * 1024 * 1024;
+/// Default cap on the number of roots watched at once by `/api/watch`.
+const DEFAULT_MAX_WATCHERS: usize = 16;
+/// Default time allowed for in-flight requests to finish after a shutdown signal.
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+/// Total blocking threads shared out between workers by default, as actix-web does.
+const DEFAULT_TOTAL_BLOCKING_THREADS: usize = 512;
+/// Default number of roots kept by `/api/recent`.
+const DEFAULT_RECENT_ROOTS_LIMIT: usize = 20;
+/// Default number of bytes of a file sent by the file content endpoints.
+const DEFAULT_MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+/// Default number of bytes of content sent by one `/api/files` request.
+const DEFAULT_MAX_BATCH_BYTES: u64 = 25 * 1024 * 1024;
+/// Default cap on the size of JSON request bodies.
+const DEFAULT_MAX_BODY_BYTES: usize = 8 * 1024 * 1024;
+/// Default cap on the number of paths in one `/api/files` request.
+const DEFAULT_MAX_BATCH_PATHS: usize = 2000;
+/// Default cap on the number of files one glob in a `/api/files` request expands to.
+const DEFAULT_MAX_GLOB_MATCHES: usize = 1000;
+/// Default cap on the number of matches one `/api/analyze/custom-query` request returns.
+const DEFAULT_MAX_CUSTOM_QUERY_RESULTS: usize = 1000;
+
+/// Whether the server may modify the filesystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ServerMode {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl ServerMode {
+    /// The name reported by `/api/connect`, as accepted by `MODE`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ServerMode::ReadOnly => "readonly",
+            ServerMode::ReadWrite => "readwrite",
+        }
+    }
+}
+
+impl FromStr for ServerMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.to_ascii_lowercase().as_str() {
+            "readonly" | "read-only" => Ok(ServerMode::ReadOnly),
+            "readwrite" | "read-write" => Ok(ServerMode::ReadWrite),
+            other => Err(format!("Unknown mode '{}'", other)),
+        }
+    }
+}
+
+/// Which encodings responses may be compressed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    /// Never compress, e.g. behind a reverse proxy that compresses itself.
+    Off,
+    /// Gzip only, for clients that accept it.
+    Gzip,
+    /// Whichever of brotli, zstd, gzip and deflate the client prefers.
+    Auto,
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(compression: &str) -> Result<Self, Self::Err> {
+        match compression.to_ascii_lowercase().as_str() {
+            "off" | "none" | "false" => Ok(Compression::Off),
+            "gzip" => Ok(Compression::Gzip),
+            "auto" | "on" | "true" => Ok(Compression::Auto),
+            other => Err(format!("Unknown compression '{}'", other)),
+        }
+    }
+}
+
+/// Server configuration, read from environment variables at startup.
+pub struct ServerConfig {
+    /// Files larger than this are rejected by the Markdown context and skipped by analyses
+    /// such as duplicates and lines of code, and no request's `max_bytes` may read more of a
+    /// file (`MAX_FILE_READ_BYTES`).
+    pub max_file_read_bytes: u64,
+    /// Files larger than this are truncated by `/api/file` and `/api/files` unless the request
+    /// passes a larger `max_bytes` (`MAX_FILE_BYTES`).
+    pub max_file_bytes: u64,
+    /// Total bytes of content one `/api/files` request may return (`MAX_BATCH_BYTES`).
+    pub max_batch_bytes: u64,
+    /// Bytes of recently read file contents kept in memory by `/api/file` and `/api/files`
+    /// (`FILE_CACHE_BYTES`). 0, the default, disables the cache.
+    pub file_cache_bytes: u64,
+    /// Whether endpoints that modify the filesystem are enabled (`MODE=readonly|readwrite`,
+    /// or `--read-only`). Read-only by default.
+    pub mode: ServerMode,
+    /// Maximum number of roots watched concurrently, to avoid exhausting inotify/fds (`MAX_WATCHERS`).
+    pub max_watchers: usize,
+    /// Seconds to wait for in-flight requests on shutdown before exiting anyway (`SHUTDOWN_TIMEOUT_SECS`).
+    pub shutdown_timeout_secs: u64,
+    /// Number of actix-web worker threads (`WORKER_THREADS`, default: logical CPUs).
+    pub worker_threads: usize,
+    /// Maximum blocking threads per worker, used by `web::block` for tree walks and other
+    /// filesystem work (`BLOCKING_THREADS`).
+    pub blocking_threads: usize,
+    /// Directory for persistent server data such as the recent roots (`DATA_DIR`,
+    /// default `~/.repoprompt`).
+    pub data_dir: PathBuf,
+    /// Directory for caches that can be rebuilt, such as dependency graphs (`CACHE_DIR`,
+    /// default `$XDG_CACHE_HOME/repoprompt` or `~/.cache/repoprompt`). Nothing is cached
+    /// inside the browsed roots themselves.
+    pub cache_dir: PathBuf,
+    /// Number of roots remembered by `/api/recent` (`RECENT_ROOTS_LIMIT`).
+    pub recent_roots_limit: usize,
+    /// Directories that may be browsed and read (`ALLOWED_ROOTS`, separated like `PATH`, plus
+    /// any `--root` flags). When empty, any directory may be browsed.
+    pub allowed_roots: Vec<PathBuf>,
+    /// Whether the filesystem root and the home directory (or its ancestors) may be opened
+    /// through `/api/directory` without being listed in `ALLOWED_ROOTS` (`ALLOW_BROAD_ROOTS`,
+    /// default `false`).
+    pub allow_broad_roots: bool,
+    /// Languages skipped by dependency analysis on every request (`DISABLE_LANGUAGES`,
+    /// e.g. `js,ts,cpp`), in addition to the request's own `disable` parameter.
+    pub disabled_languages: DisabledLanguages,
+    /// Requests per minute each client may make to endpoints that walk or analyze a tree
+    /// (`RATE_LIMIT_EXPENSIVE_PER_MIN`, default 0: unlimited).
+    pub rate_limit_expensive_per_min: u32,
+    /// Requests per minute each client may make to other API endpoints
+    /// (`RATE_LIMIT_CHEAP_PER_MIN`, default 0: unlimited).
+    pub rate_limit_cheap_per_min: u32,
+    /// JSON request bodies larger than this are rejected with 413 (`MAX_BODY_BYTES`).
+    pub max_body_bytes: usize,
+    /// Maximum number of paths read by one `/api/files` request (`MAX_BATCH_PATHS`).
+    pub max_batch_paths: usize,
+    /// Maximum number of files one glob in a `/api/files` request expands to (`MAX_GLOB_MATCHES`).
+    pub max_glob_matches: usize,
+    /// Maximum number of matches one `/api/analyze/custom-query` request returns
+    /// (`MAX_CUSTOM_QUERY_RESULTS`).
+    pub max_custom_query_results: usize,
+    /// How responses are compressed (`COMPRESSION=off|gzip|auto`, default `auto`).
+    pub compression: Compression,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let worker_threads = thread::available_parallelism().map_or(1, |n| n.get());
+        ServerConfig {
+            max_file_read_bytes: DEFAULT_MAX_FILE_READ_BYTES,
+            max_file_bytes: DEFAULT_MAX_FILE_BYTES,
+            max_batch_bytes: DEFAULT_MAX_BATCH_BYTES,
+            file_cache_bytes: 0,
+            mode: ServerMode::ReadOnly,
+            max_watchers: DEFAULT_MAX_WATCHERS,
+            shutdown_timeout_secs: DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+            worker_threads,
+            blocking_threads: default_blocking_threads(worker_threads),
+            data_dir: default_data_dir(),
+            cache_dir: default_cache_dir(),
+            recent_roots_limit: DEFAULT_RECENT_ROOTS_LIMIT,
+            allowed_roots: Vec::new(),
+            allow_broad_roots: false,
+            disabled_languages: DisabledLanguages::default(),
+            rate_limit_expensive_per_min: 0,
+            rate_limit_cheap_per_min: 0,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            max_batch_paths: DEFAULT_MAX_BATCH_PATHS,
+            max_glob_matches: DEFAULT_MAX_GLOB_MATCHES,
+            max_custom_query_results: DEFAULT_MAX_CUSTOM_QUERY_RESULTS,
+            compression: Compression::Auto,
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_env() -> Self {
+        let defaults = ServerConfig::default();
+        let worker_threads = env_or("WORKER_THREADS", defaults.worker_threads).max(1);
+        ServerConfig {
+            max_file_read_bytes: env_or("MAX_FILE_READ_BYTES", defaults.max_file_read_bytes),
+            max_file_bytes: env_or("MAX_FILE_BYTES", defaults.max_file_bytes),
+            max_batch_bytes: env_or("MAX_BATCH_BYTES", defaults.max_batch_bytes),
+            file_cache_bytes: env_or("FILE_CACHE_BYTES", defaults.file_cache_bytes),
+            mode: server_mode_from_env(defaults.mode),
+            max_watchers: env_or("MAX_WATCHERS", defaults.max_watchers),
+            shutdown_timeout_secs: env_or("SHUTDOWN_TIMEOUT_SECS", defaults.shutdown_timeout_secs),
+            worker_threads,
+            blocking_threads: env_or("BLOCKING_THREADS", default_blocking_threads(worker_threads)).max(1),
+            data_dir: env_or("DATA_DIR", defaults.data_dir),
+            cache_dir: env_or("CACHE_DIR", defaults.cache_dir),
+            recent_roots_limit: env_or("RECENT_ROOTS_LIMIT", defaults.recent_roots_limit),
+            allowed_roots: env::var_os("ALLOWED_ROOTS")
+                .map(|roots| env::split_paths(&roots).filter(|p| !p.as_os_str().is_empty()).collect())
+                .unwrap_or(defaults.allowed_roots),
+            allow_broad_roots: env_or("ALLOW_BROAD_ROOTS", defaults.allow_broad_roots),
+            disabled_languages: env_or("DISABLE_LANGUAGES", defaults.disabled_languages),
+            rate_limit_expensive_per_min: env_or("RATE_LIMIT_EXPENSIVE_PER_MIN", defaults.rate_limit_expensive_per_min),
+            rate_limit_cheap_per_min: env_or("RATE_LIMIT_CHEAP_PER_MIN", defaults.rate_limit_cheap_per_min),
+            max_body_bytes: env_or("MAX_BODY_BYTES", defaults.max_body_bytes),
+            max_batch_paths: env_or("MAX_BATCH_PATHS", defaults.max_batch_paths),
+            max_glob_matches: env_or("MAX_GLOB_MATCHES", defaults.max_glob_matches),
+            max_custom_query_results: env_or("MAX_CUSTOM_QUERY_RESULTS", defaults.max_custom_query_results),
+            compression: env_or("COMPRESSION", defaults.compression),
+        }
+    }
+
+    /// Applies command-line flags on top of the environment: `--root <dir>` or `--root=<dir>`
+    /// (repeatable) adds an allowed root, and `--read-only` forces read-only mode.
+    pub fn apply_args(&mut self, args: impl IntoIterator<Item = String>) {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg == "--root" {
+                match args.next() {
+                    Some(root) => self.allowed_roots.push(PathBuf::from(root)),
+                    None => warn!("Ignoring --root without a directory."),
+                }
+            } else if let Some(root) = arg.strip_prefix("--root=") {
+                self.allowed_roots.push(PathBuf::from(root));
+            } else if arg == "--read-only" {
+                self.mode = ServerMode::ReadOnly;
+            } else {
+                warn!("Ignoring unknown argument '{}'.", arg);
+            }
+        }
+    }
+}
+
+/// Reads `MODE`, falling back to the legacy `ALLOW_WRITES=true` when it is unset.
+fn server_mode_from_env(default: ServerMode) -> ServerMode {
+    if env::var_os("MODE").is_none() && env_or("ALLOW_WRITES", false) {
+        return ServerMode::ReadWrite;
+    }
+    env_or("MODE", default)
+}
+
+fn default_blocking_threads(worker_threads: usize) -> usize {
+    (DEFAULT_TOTAL_BLOCKING_THREADS / worker_threads).max(1)
+}
+
+/// `.repoprompt` in the user's home directory, or in the working directory if there is none.
+fn default_data_dir() -> PathBuf {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default()
+        .join(".repoprompt")
+}
+
+/// `repoprompt` in the user's cache directory: `XDG_CACHE_HOME`, `LOCALAPPDATA` on Windows,
+/// or `~/.cache`, falling back to the system temporary directory.
+fn default_cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .or_else(|| env::var_os("LOCALAPPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("repoprompt")
+}
+
+/// Parses an environment variable, falling back to `default` if it is unset or invalid.
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            warn!("Ignoring invalid value '{}' for {}.", value, name);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_flags_add_allowed_roots() {
+        let mut config = ServerConfig { allowed_roots: vec![PathBuf::from("/env")], ..Default::default() };
+        let args = ["--root", "/a", "--root=/b", "--unknown", "--root"].map(String::from);
+        config.apply_args(args);
+        assert_eq!(config.allowed_roots, ["/env", "/a", "/b"].map(PathBuf::from));
+    }
+}
\ No newline at end of file
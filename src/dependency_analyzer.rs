@@ -1,160 +1,2033 @@
-use crate::models::TreeNode;
+use crate::file_system::write_file_atomically;
+use crate::models::{DependencyEdge, EdgeKind, TreeNode};
+use crate::syntax::{CPP, GO, JAVASCRIPT, PYTHON, R, RUST, SWIFT};
+use crate::utils::{on_disk_path, resolve_unicode_path, to_api_path, to_nfc};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
 use log::{debug, info, warn};
 use path_clean::PathClean;
-use std::collections::{HashMap, HashSet};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::env;
 use std::error::Error;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use streaming_iterator::StreamingIterator;
-use tree_sitter::{Language, Parser, Query, QueryCursor};
+use tree_sitter::{Node, Parser, Query, QueryCursor};
 
-// Type alias for the dependency graph for clarity
-pub type DependencyGraph = HashMap<String, Vec<String>>;
+/// Each analyzed file's dependencies, by file.
+pub type DependencyGraph = HashMap<String, Vec<DependencyEdge>>;
+
+/// Paths of some of each file's dependencies, by file.
+pub type EdgeTargets = HashMap<String, Vec<String>>;
+
+/// Names each Python file receives through `from module import *`, taken from the imported
+/// modules' `__all__`. These are soft references: they don't add edges to the graph.
+pub type ExportsMap = HashMap<String, Vec<String>>;
+
+/// Edges of the graph that only hold for some tools or builds, by file. They are in the
+/// graph like any other edge; these maps mark them so clients can filter them out.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct EdgeFlags {
+    /// Dependencies Python files only import under `if TYPE_CHECKING:`, i.e. for type
+    /// checking but not at runtime.
+    #[serde(default)]
+    pub type_checking_only: EdgeTargets,
+    /// Includes C/C++ files only make inside `#if`/`#ifdef` blocks (include guards aside).
+    #[serde(default)]
+    pub conditional: EdgeTargets,
+}
+
+impl EdgeFlags {
+    fn maps_mut(&mut self) -> [&mut EdgeTargets; 2] {
+        [&mut self.type_checking_only, &mut self.conditional]
+    }
+
+    /// Forgets the flags of `file`'s edges.
+    fn remove(&mut self, file: &str) {
+        for map in self.maps_mut() {
+            map.remove(file);
+        }
+    }
+
+    /// Drops flagged edges pointing at deleted files.
+    fn remove_targets(&mut self, deleted_files: &HashSet<String>) {
+        for map in self.maps_mut() {
+            for deps in map.values_mut() {
+                deps.retain(|dep| !deleted_files.contains(dep));
+            }
+            map.retain(|_, deps| !deps.is_empty());
+        }
+    }
+
+    fn extend(&mut self, other: EdgeFlags) {
+        self.type_checking_only.extend(other.type_checking_only);
+        self.conditional.extend(other.conditional);
+    }
+}
+
+/// A per-language analyzer, adding the dependencies of the files it handles to the graph.
+type Analyzer = fn(&Path, &[String], &mut DependencyGraph);
+
+/// Name of the dependency cache earlier versions wrote into each analyzed root. Leftover
+/// copies are still hidden from trees.
+pub const DEPENDENCY_CACHE_FILE: &str = ".repoprompt-cache.json";
+
+/// Files whose content changes how imports resolve (module paths, workspace members, path
+/// aliases), besides the bundler configs in `BUNDLER_CONFIG_FILES`.
+const RESOLUTION_CONFIG_FILES: [&str; 5] = ["go.mod", "Package.swift", "Cargo.toml", "tsconfig.json", "jsconfig.json"];
+
+/// Languages skipped by dependency analysis, as a bitfield (the `disable` query parameter
+/// and `DISABLE_LANGUAGES`). Files of disabled languages are neither parsed nor tracked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct DisabledLanguages(u16);
+
+impl DisabledLanguages {
+    pub const JS_TS: u16 = 1 << 0;
+    pub const PYTHON: u16 = 1 << 1;
+    pub const RUST: u16 = 1 << 2;
+    pub const CPP: u16 = 1 << 3;
+    pub const GO: u16 = 1 << 4;
+    pub const SWIFT: u16 = 1 << 5;
+    pub const R: u16 = 1 << 6;
+    // These languages have no source analyzer yet; disabling Java skips its build files.
+    pub const JAVA: u16 = 1 << 7;
+    pub const RUBY: u16 = 1 << 8;
+    pub const PHP: u16 = 1 << 9;
+
+    /// Canonical names of the language groups, as reported in responses.
+    const NAMES: [(u16, &'static str); 10] = [
+        (Self::JS_TS, "js"),
+        (Self::PYTHON, "py"),
+        (Self::RUST, "rs"),
+        (Self::CPP, "cpp"),
+        (Self::GO, "go"),
+        (Self::SWIFT, "swift"),
+        (Self::R, "r"),
+        (Self::JAVA, "java"),
+        (Self::RUBY, "ruby"),
+        (Self::PHP, "php"),
+    ];
+
+    pub fn union(self, other: DisabledLanguages) -> DisabledLanguages {
+        DisabledLanguages(self.0 | other.0)
+    }
+
+    pub fn is_disabled(self, language: u16) -> bool {
+        self.0 & language != 0
+    }
+
+    /// Names of the disabled language groups, e.g. `["js", "cpp"]`.
+    pub fn names(self) -> Vec<&'static str> {
+        Self::NAMES.iter().filter(|(language, _)| self.is_disabled(*language)).map(|(_, name)| *name).collect()
+    }
+
+    /// Whether `file` should be analyzed. Files of languages without an analyzer are kept,
+    /// since they may be imported (e.g. JSON modules).
+    pub fn allows_file(self, file: &str) -> bool {
+        let ext = Path::new(file).extension().and_then(|e| e.to_str()).unwrap_or("");
+        let language = match ext {
+            "js" | "jsx" | "ts" | "tsx" => Self::JS_TS,
+            "py" => Self::PYTHON,
+            "rs" => Self::RUST,
+            "cpp" | "c" | "h" | "hpp" | "hxx" | "cc" | "hh" => Self::CPP,
+            "go" => Self::GO,
+            "swift" => Self::SWIFT,
+            "R" | "r" | "Rmd" | "rmd" => Self::R,
+            _ => return true,
+        };
+        !self.is_disabled(language)
+    }
+}
+
+impl FromStr for DisabledLanguages {
+    type Err = String;
+
+    /// Parses a comma-separated list such as `js,ts,cpp`. `js`, `ts`, `jsx` and `tsx` all
+    /// disable JS/TS analysis, and `c` is the same as `cpp`.
+    fn from_str(list: &str) -> Result<Self, Self::Err> {
+        let mut disabled = 0;
+        for token in list.split(',').map(|t| t.trim().to_ascii_lowercase()).filter(|t| !t.is_empty()) {
+            disabled |= match token.as_str() {
+                "js" | "ts" | "jsx" | "tsx" => Self::JS_TS,
+                "py" => Self::PYTHON,
+                "rs" => Self::RUST,
+                "cpp" | "c" => Self::CPP,
+                "go" => Self::GO,
+                "swift" => Self::SWIFT,
+                "r" => Self::R,
+                "java" => Self::JAVA,
+                "ruby" => Self::RUBY,
+                "php" => Self::PHP,
+                other => return Err(format!("Unknown language '{}' in disable list", other)),
+            };
+        }
+        Ok(DisabledLanguages(disabled))
+    }
+}
 
 /// Analyzes the file tree to build a dependency graph for supported languages.
+///
+/// With a `cache` path (see `dependency_cache_path`), results are persisted there, and on
+/// later runs only files whose mtime differs from the cached value are re-parsed, unless
+/// `files_to_rescan` asks for a full analysis.
 pub fn analyze_dependencies(
     root_path: &Path,
-    tree: &HashMap<String, TreeNode>,
-) -> Result<DependencyGraph, Box<dyn Error>> {
+    tree: &IndexMap<String, TreeNode>,
+    disabled: DisabledLanguages,
+    cache: Option<&Path>,
+) -> Result<(DependencyGraph, ExportsMap, EdgeFlags), Box<dyn Error>> {
     info!("Starting dependency analysis for '{}'...", root_path.display());
     let start_time = Instant::now();
-    let mut dependency_graph = HashMap::new();
-    let mut files_to_scan = Vec::new();
 
-    fn collect_files(node: &HashMap<String, TreeNode>, files: &mut Vec<String>) {
-        for (_, child) in node {
-            if child.node_type == "file" {
+    let files: Vec<(String, u64)> = collect_files(tree, disabled)
+        .into_iter()
+        .filter_map(|file| file_mtime(&file).map(|mtime| (file, mtime)))
+        .collect();
+    let mtimes: HashMap<String, u64> = files.iter().cloned().collect();
+    let cached = cache
+        .and_then(load_dependency_cache)
+        .and_then(|(graph, exports, edge_flags, cached_mtimes)| {
+            let (changed, deleted) = files_to_rescan(&cached_mtimes, &mtimes)?;
+            Some(((graph, exports, edge_flags), changed, deleted))
+        });
+    let ((mut dependency_graph, mut exports, mut edge_flags), changed_files, deleted_files) =
+        cached.unwrap_or_else(|| {
+            let all_files = files.into_iter().map(|(file, _)| file).collect();
+            (Default::default(), all_files, HashSet::new())
+        });
+
+    apply_changes(
+        root_path,
+        (&mut dependency_graph, &mut exports, &mut edge_flags),
+        &changed_files,
+        &deleted_files,
+        disabled,
+    );
+
+    if let Some(cache) = cache {
+        if let Err(e) = save_dependency_cache(cache, &dependency_graph, &exports, &edge_flags, &mtimes) {
+            warn!("Failed to save dependency cache for '{}': {}", root_path.display(), e);
+        }
+    }
+
+    let duration = start_time.elapsed();
+    info!(
+        "Dependency analysis for '{}' finished in {:.2?}. Re-scanned {} of {} files; found dependencies for {} files.",
+        root_path.display(),
+        duration,
+        changed_files.len(),
+        mtimes.len(),
+        dependency_graph.len()
+    );
+    Ok((dependency_graph, exports, edge_flags))
+}
+
+#[derive(Serialize, Deserialize)]
+struct DependencyCache {
+    graph: DependencyGraph,
+    #[serde(default)]
+    exports: ExportsMap,
+    #[serde(default)]
+    edge_flags: EdgeFlags,
+    /// File modification times in milliseconds since the Unix epoch.
+    mtimes: HashMap<String, u64>,
+}
+
+/// Where the dependency cache of `root`, analyzed without the `disabled` languages, is kept
+/// under `cache_dir`: a file named by a hash of both, so that roots and language selections
+/// never share a cache and nothing is written into the root itself.
+pub fn dependency_cache_path(cache_dir: &Path, root: &Path, disabled: DisabledLanguages) -> PathBuf {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(to_api_path(root).as_bytes());
+    hasher.update(&disabled.0.to_le_bytes());
+    let hash = hasher.finalize().to_hex();
+    cache_dir.join("dependencies").join(format!("{}.json", &hash[..32]))
+}
+
+/// Writes the dependency graph, the Python exports, the edge flags and the file mtimes they
+/// were computed from to `cache_path`, replacing it atomically.
+pub fn save_dependency_cache(
+    cache_path: &Path,
+    graph: &DependencyGraph,
+    exports: &ExportsMap,
+    edge_flags: &EdgeFlags,
+    mtime_map: &HashMap<String, u64>,
+) -> Result<(), Box<dyn Error>> {
+    let cache = DependencyCache {
+        graph: graph.clone(),
+        exports: exports.clone(),
+        edge_flags: edge_flags.clone(),
+        mtimes: mtime_map.clone(),
+    };
+    if let Some(dir) = cache_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    write_file_atomically(cache_path, &serde_json::to_vec(&cache)?)?;
+    Ok(())
+}
+
+/// Reads a cache file written by `save_dependency_cache`, if present and well-formed.
+#[allow(clippy::type_complexity)]
+pub fn load_dependency_cache(
+    cache_path: &Path,
+) -> Option<(DependencyGraph, ExportsMap, EdgeFlags, HashMap<String, u64>)> {
+    let content = fs::read(cache_path).ok()?;
+    match serde_json::from_slice::<DependencyCache>(&content) {
+        Ok(cache) => {
+            debug!("Loaded dependency cache from '{}'", cache_path.display());
+            Some((cache.graph, cache.exports, cache.edge_flags, cache.mtimes))
+        }
+        Err(e) => {
+            warn!("Ignoring malformed dependency cache '{}': {}", cache_path.display(), e);
+            None
+        }
+    }
+}
+
+/// Compares the files of a tree and their stamps (mtime, or mtime and size) with those an
+/// earlier analysis saw, returning the files to re-parse and the files that are gone.
+///
+/// Returns `None` when every file must be re-parsed instead: when files were added, since
+/// imports of them that previously failed to resolve live in files that haven't changed,
+/// and when a file affecting import resolution (`RESOLUTION_CONFIG_FILES`,
+/// `BUNDLER_CONFIG_FILES`) was added, changed or removed.
+fn files_to_rescan<S: PartialEq>(
+    previous: &HashMap<String, S>,
+    current: &HashMap<String, S>,
+) -> Option<(Vec<String>, HashSet<String>)> {
+    let affects_resolution = |file: &str| {
+        let name = Path::new(file).file_name().and_then(|n| n.to_str()).unwrap_or("");
+        RESOLUTION_CONFIG_FILES.contains(&name) || BUNDLER_CONFIG_FILES.contains(&name)
+    };
+    let mut changed = Vec::new();
+    for (file, stamp) in current {
+        match previous.get(file) {
+            None => {
+                debug!("'{}' was added; re-analyzing every file.", file);
+                return None;
+            }
+            Some(previous) if previous != stamp => changed.push(file.clone()),
+            Some(_) => {}
+        }
+    }
+    let deleted: HashSet<String> = previous.keys().filter(|file| !current.contains_key(*file)).cloned().collect();
+    if let Some(config) = changed.iter().chain(&deleted).find(|file| affects_resolution(file)) {
+        debug!("'{}' affects import resolution and changed; re-analyzing every file.", config);
+        return None;
+    }
+    Some((changed, deleted))
+}
+
+fn file_mtime(file: &str) -> Option<u64> {
+    let modified = fs::metadata(on_disk_path(file)).and_then(|m| m.modified()).ok()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_millis() as u64)
+}
+
+/// Re-parses `changed_files` and drops `deleted_files` from the graph, including
+/// edges that point at them. Edges of all other files are left untouched.
+fn apply_changes(
+    root_path: &Path,
+    (graph, exports, edge_flags): (&mut DependencyGraph, &mut ExportsMap, &mut EdgeFlags),
+    changed_files: &[String],
+    deleted_files: &HashSet<String>,
+    disabled: DisabledLanguages,
+) {
+    for file in changed_files.iter().chain(deleted_files.iter()) {
+        graph.remove(file);
+        exports.remove(file);
+        edge_flags.remove(file);
+    }
+    if !deleted_files.is_empty() {
+        for deps in graph.values_mut() {
+            deps.retain(|dep| !deleted_files.contains(&dep.target));
+        }
+        graph.retain(|_, deps| !deps.is_empty());
+        edge_flags.remove_targets(deleted_files);
+    }
+    if !changed_files.is_empty() {
+        let (changed_graph, changed_exports, changed_edge_flags) = analyze_files(root_path, changed_files, disabled);
+        graph.extend(changed_graph);
+        exports.extend(changed_exports);
+        edge_flags.extend(changed_edge_flags);
+    }
+}
+
+/// Flattens the file tree into a list of file paths, skipping files flagged as binary
+/// and files of disabled languages.
+fn collect_files(tree: &IndexMap<String, TreeNode>, disabled: DisabledLanguages) -> Vec<String> {
+    fn walk(node: &IndexMap<String, TreeNode>, disabled: DisabledLanguages, files: &mut Vec<String>) {
+        for child in node.values() {
+            if child.node_type == "file" && child.is_binary != Some(true) && disabled.allows_file(&child.path) {
                 files.push(child.path.clone());
             }
             if let Some(children) = &child.children {
-                collect_files(children, files);
+                walk(children, disabled, files);
+            }
+        }
+    }
+    let mut files = Vec::new();
+    walk(tree, disabled, &mut files);
+    files
+}
+
+/// Runs every language analyzer over the given files, and collects the names Python files
+/// import with `*` and the edges only needed for type checking or in some builds. Imports
+/// are resolved against the filesystem, so the files' dependencies need not be in
+/// `files_to_scan` themselves.
+pub fn analyze_files(
+    root_path: &Path,
+    files_to_scan: &[String],
+    disabled: DisabledLanguages,
+) -> (DependencyGraph, ExportsMap, EdgeFlags) {
+    let mut dependency_graph = HashMap::new();
+
+    // Analyze each supported language that isn't disabled
+    let analyzers: [(u16, Analyzer); 7] = [
+        (DisabledLanguages::JS_TS, analyze_javascript_typescript),
+        (DisabledLanguages::PYTHON, analyze_python),
+        (DisabledLanguages::RUST, analyze_rust),
+        (DisabledLanguages::CPP, analyze_cpp),
+        (DisabledLanguages::GO, analyze_go),
+        (DisabledLanguages::SWIFT, analyze_swift),
+        (DisabledLanguages::R, analyze_r),
+    ];
+    for (language, analyze) in analyzers {
+        if !disabled.is_disabled(language) {
+            analyze(root_path, files_to_scan, &mut dependency_graph);
+        }
+    }
+
+    let (exports, type_checking_only) = if disabled.is_disabled(DisabledLanguages::PYTHON) {
+        (HashMap::new(), HashMap::new())
+    } else {
+        (analyze_python_exports(root_path, files_to_scan), analyze_python_type_checking(root_path, files_to_scan))
+    };
+    let conditional = if disabled.is_disabled(DisabledLanguages::CPP) {
+        HashMap::new()
+    } else {
+        analyze_cpp_conditional(root_path, files_to_scan)
+    };
+
+    // Paths are reported as NFC API paths, matching the tree.
+    let api_path = |file: &str| to_nfc(&to_api_path(file));
+    let api_paths = |targets: EdgeTargets| -> EdgeTargets {
+        targets
+            .into_iter()
+            .map(|(file, deps)| (api_path(&file), deps.iter().map(|dep| api_path(dep)).collect()))
+            .collect()
+    };
+    let dependency_graph = dependency_graph
+        .into_iter()
+        .map(|(file, deps): (String, Vec<DependencyEdge>)| {
+            let deps = deps.into_iter().map(|dep| DependencyEdge { target: api_path(&dep.target), ..dep }).collect();
+            (api_path(&file), deps)
+        })
+        .collect();
+    let exports = exports.into_iter().map(|(file, names)| (api_path(&file), names)).collect();
+    let edge_flags = EdgeFlags {
+        type_checking_only: api_paths(type_checking_only),
+        conditional: api_paths(conditional),
+    };
+    (dependency_graph, exports, edge_flags)
+}
+
+/// Re-analyzes a root incrementally, only re-parsing files whose mtime or size
+/// changed since the previous run.
+///
+/// Edges of unchanged files are reused as-is, except that edges pointing at
+/// files that have since been deleted are dropped. When files are added or import
+/// resolution config changes, everything is re-analyzed (see `files_to_rescan`). An
+/// analyzer must always be run with the same `DisabledLanguages` and cache path.
+#[derive(Default)]
+pub struct IncrementalAnalyzer {
+    graph: DependencyGraph,
+    exports: ExportsMap,
+    edge_flags: EdgeFlags,
+    file_stamps: HashMap<String, (SystemTime, u64)>,
+}
+
+impl IncrementalAnalyzer {
+    /// Brings the cached graph, exports and edge flags up to date with `tree` and returns a
+    /// copy of them. The first run starts from the persisted `cache`, if any.
+    pub fn analyze(
+        &mut self,
+        root_path: &Path,
+        tree: &IndexMap<String, TreeNode>,
+        disabled: DisabledLanguages,
+        cache: Option<&Path>,
+    ) -> Result<(DependencyGraph, ExportsMap, EdgeFlags), Box<dyn Error>> {
+        let current_stamps: HashMap<String, (SystemTime, u64)> = collect_files(tree, disabled)
+            .into_iter()
+            .filter_map(|file| file_stamp(&file).map(|stamp| (file, stamp)))
+            .collect();
+        let rescan = if self.file_stamps.is_empty() {
+            None
+        } else {
+            files_to_rescan(&self.file_stamps, &current_stamps)
+        };
+        let Some((changed_files, deleted_files)) = rescan else {
+            self.file_stamps = current_stamps;
+            (self.graph, self.exports, self.edge_flags) = analyze_dependencies(root_path, tree, disabled, cache)?;
+            return Ok((self.graph.clone(), self.exports.clone(), self.edge_flags.clone()));
+        };
+
+        info!("Starting incremental dependency analysis for '{}'...", root_path.display());
+        let start_time = Instant::now();
+
+        apply_changes(
+            root_path,
+            (&mut self.graph, &mut self.exports, &mut self.edge_flags),
+            &changed_files,
+            &deleted_files,
+            disabled,
+        );
+        self.file_stamps = current_stamps;
+
+        let duration = start_time.elapsed();
+        info!(
+            "Incremental dependency analysis for '{}' finished in {:.2?}. Re-scanned {} files, removed {}.",
+            root_path.display(),
+            duration,
+            changed_files.len(),
+            deleted_files.len()
+        );
+        Ok((self.graph.clone(), self.exports.clone(), self.edge_flags.clone()))
+    }
+}
+
+/// Returns the `(mtime, size)` pair used to detect changed files.
+pub fn file_stamp(file: &str) -> Option<(SystemTime, u64)> {
+    match fs::metadata(on_disk_path(file)).and_then(|m| Ok((m.modified()?, m.len()))) {
+        Ok(stamp) => Some(stamp),
+        Err(e) => {
+            debug!("Failed to stat '{}' for incremental updates: {}", file, e);
+            None
+        }
+    }
+}
+
+/// External packages a project declares, by ecosystem (`npm`, `python`, `cargo`, `java`).
+pub type ExternalDependencies = BTreeMap<&'static str, ExternalPackages>;
+
+/// The packages one ecosystem declares.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum ExternalPackages {
+    /// Package names, sorted and deduplicated.
+    Names(Vec<String>),
+    /// Packages with their versions and scopes, in declaration order.
+    Detailed(Vec<ExternalDep>),
+}
+
+/// A package declared in a build file, e.g. a Maven dependency.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct ExternalDep {
+    /// `groupId:artifactId` for Maven and Gradle.
+    pub name: String,
+    /// The declared version, with `${...}` properties resolved where possible.
+    pub version: Option<String>,
+    /// `compile`, `test`, or Maven's `provided`, `runtime`, `system` and `import`.
+    pub scope: String,
+}
+
+/// Reads the package names declared by one ecosystem's manifests in a root.
+type ManifestReader = fn(&Path) -> BTreeSet<String>;
+
+/// Reads the package manifests in the root: `package.json` (`dependencies`,
+/// `devDependencies`, `peerDependencies`), `requirements.txt` and `pyproject.toml` (PEP 621
+/// and Poetry), `Cargo.toml` (including dev, build and workspace dependencies), and
+/// `pom.xml`, `build.gradle` or `build.gradle.kts`. Ecosystems of disabled languages, or
+/// without a manifest, are omitted.
+pub fn external_dependencies(root: &Path, disabled: DisabledLanguages) -> ExternalDependencies {
+    let mut external = ExternalDependencies::new();
+    let manifests: [(u16, &'static str, ManifestReader); 3] = [
+        (DisabledLanguages::JS_TS, "npm", npm_dependencies),
+        (DisabledLanguages::PYTHON, "python", python_dependencies),
+        (DisabledLanguages::RUST, "cargo", cargo_dependencies),
+    ];
+    for (language, ecosystem, read) in manifests {
+        if disabled.is_disabled(language) {
+            continue;
+        }
+        let packages = read(root);
+        if !packages.is_empty() {
+            external.insert(ecosystem, ExternalPackages::Names(packages.into_iter().collect()));
+        }
+    }
+    if !disabled.is_disabled(DisabledLanguages::JAVA) {
+        let packages = extract_java_build_deps(root);
+        if !packages.is_empty() {
+            external.insert("java", ExternalPackages::Detailed(packages));
+        }
+    }
+    external
+}
+
+fn npm_dependencies(root: &Path) -> BTreeSet<String> {
+    let Some(manifest) = fs::read(root.join("package.json"))
+        .ok()
+        .and_then(|content| serde_json::from_slice::<serde_json::Value>(&content).ok())
+    else {
+        return BTreeSet::new();
+    };
+    ["dependencies", "devDependencies", "peerDependencies"]
+        .iter()
+        .filter_map(|section| manifest.get(section)?.as_object())
+        .flat_map(|packages| packages.keys().cloned())
+        .collect()
+}
+
+fn python_dependencies(root: &Path) -> BTreeSet<String> {
+    // The package name of a requirement such as `requests[socks]>=2.0; python_version<"3.8"`.
+    fn requirement_name(requirement: &str) -> Option<String> {
+        let requirement = requirement.split('#').next()?.trim();
+        if requirement.is_empty() || requirement.starts_with('-') {
+            return None;
+        }
+        let end = requirement
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'))
+            .unwrap_or(requirement.len());
+        (end > 0).then(|| requirement[..end].to_string())
+    }
+
+    let mut packages: BTreeSet<String> = fs::read_to_string(root.join("requirements.txt"))
+        .map(|content| content.lines().filter_map(requirement_name).collect())
+        .unwrap_or_default();
+
+    let Some(pyproject) = fs::read_to_string(root.join("pyproject.toml")).ok().and_then(|c| c.parse::<toml::Table>().ok())
+    else {
+        return packages;
+    };
+    let requirements = |value: Option<&toml::Value>| -> Vec<String> {
+        value
+            .and_then(|v| v.as_array())
+            .map(|list| list.iter().filter_map(|v| requirement_name(v.as_str()?)).collect())
+            .unwrap_or_default()
+    };
+    if let Some(project) = pyproject.get("project") {
+        packages.extend(requirements(project.get("dependencies")));
+        if let Some(optional) = project.get("optional-dependencies").and_then(|v| v.as_table()) {
+            for group in optional.values() {
+                packages.extend(requirements(Some(group)));
+            }
+        }
+    }
+    if let Some(poetry) = pyproject.get("tool").and_then(|tool| tool.get("poetry")) {
+        let mut tables = vec![poetry.get("dependencies"), poetry.get("dev-dependencies")];
+        if let Some(groups) = poetry.get("group").and_then(|v| v.as_table()) {
+            tables.extend(groups.values().map(|group| group.get("dependencies")));
+        }
+        for table in tables.into_iter().flatten().filter_map(|v| v.as_table()) {
+            packages.extend(table.keys().filter(|name| *name != "python").cloned());
+        }
+    }
+    packages
+}
+
+fn cargo_dependencies(root: &Path) -> BTreeSet<String> {
+    let Some(manifest) = fs::read_to_string(root.join("Cargo.toml")).ok().and_then(|c| c.parse::<toml::Table>().ok())
+    else {
+        return BTreeSet::new();
+    };
+    let workspace = manifest.get("workspace");
+    ["dependencies", "dev-dependencies", "build-dependencies"]
+        .iter()
+        .filter_map(|section| manifest.get(*section))
+        .chain(workspace.and_then(|w| w.get("dependencies")))
+        .filter_map(|table| table.as_table())
+        .flat_map(|table| table.keys().cloned())
+        .collect()
+}
+
+/// Reads the dependencies declared by a Java project's `pom.xml` and its `build.gradle` or
+/// `build.gradle.kts`.
+pub fn extract_java_build_deps(root: &Path) -> Vec<ExternalDep> {
+    let mut deps = fs::read_to_string(root.join("pom.xml")).map(|pom| maven_dependencies(&pom)).unwrap_or_default();
+    for build_file in ["build.gradle", "build.gradle.kts"] {
+        if let Ok(script) = fs::read_to_string(root.join(build_file)) {
+            deps.extend(gradle_dependencies(&script));
+        }
+    }
+    deps
+}
+
+/// Reads the `<dependencies>` of a POM. Versions missing from a dependency are taken from
+/// `<dependencyManagement>`, and `${...}` references to `<properties>` or the project's own
+/// coordinates are expanded. Parent POMs and profiles aren't read.
+fn maven_dependencies(pom: &str) -> Vec<ExternalDep> {
+    #[derive(Default)]
+    struct Dependency {
+        group: String,
+        artifact: String,
+        version: Option<String>,
+        scope: Option<String>,
+    }
+
+    let mut reader = quick_xml::Reader::from_str(pom);
+    reader.config_mut().trim_text(true);
+    let mut elements: Vec<String> = Vec::new();
+    let mut properties: HashMap<String, String> = HashMap::new();
+    let mut direct = Vec::new();
+    let mut managed = HashMap::new();
+    let mut current = Dependency::default();
+    loop {
+        match reader.read_event() {
+            Ok(quick_xml::events::Event::Start(start)) => {
+                elements.push(String::from_utf8_lossy(start.local_name().as_ref()).into_owned());
+            }
+            Ok(quick_xml::events::Event::Text(text)) => {
+                let Ok(text) = text.unescape() else {
+                    continue;
+                };
+                let path: Vec<&str> = elements.iter().map(String::as_str).collect();
+                match path.as_slice() {
+                    ["project", "properties", name] => {
+                        properties.insert(name.to_string(), text.into_owned());
+                    }
+                    ["project", field @ ("groupId" | "artifactId" | "version")] => {
+                        properties.insert(format!("project.{}", field), text.into_owned());
+                    }
+                    ["project", "parent", field @ ("groupId" | "version")] => {
+                        properties.entry(format!("project.{}", field)).or_insert_with(|| text.into_owned());
+                    }
+                    [.., "dependencies", "dependency", field] => match *field {
+                        "groupId" => current.group = text.into_owned(),
+                        "artifactId" => current.artifact = text.into_owned(),
+                        "version" => current.version = Some(text.into_owned()),
+                        "scope" => current.scope = Some(text.into_owned()),
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            Ok(quick_xml::events::Event::End(_)) => {
+                let path: Vec<&str> = elements.iter().map(String::as_str).collect();
+                match path.as_slice() {
+                    ["project", "dependencies", "dependency"] => direct.push(std::mem::take(&mut current)),
+                    ["project", "dependencyManagement", "dependencies", "dependency"] => {
+                        let dependency = std::mem::take(&mut current);
+                        if let Some(version) = dependency.version {
+                            managed.insert((dependency.group, dependency.artifact), version);
+                        }
+                    }
+                    [.., "dependency"] => current = Dependency::default(),
+                    _ => {}
+                }
+                elements.pop();
+            }
+            Ok(quick_xml::events::Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to parse pom.xml: {}", e);
+                break;
+            }
+        }
+    }
+
+    let expand = |value: &str| -> String {
+        let mut value = value.to_string();
+        // Bounded, in case properties refer to each other in a loop.
+        for _ in 0..8 {
+            let Some(start) = value.find("${") else {
+                break;
+            };
+            let Some(end) = value[start..].find('}').map(|end| start + end) else {
+                break;
+            };
+            let Some(resolved) = properties.get(&value[start + 2..end]) else {
+                break;
+            };
+            value.replace_range(start..=end, resolved);
+        }
+        value
+    };
+    direct
+        .into_iter()
+        .filter(|dependency| !dependency.group.is_empty() && !dependency.artifact.is_empty())
+        .map(|dependency| {
+            let version = dependency
+                .version
+                .or_else(|| managed.get(&(dependency.group.clone(), dependency.artifact.clone())).cloned());
+            ExternalDep {
+                name: format!("{}:{}", expand(&dependency.group), expand(&dependency.artifact)),
+                version: version.map(|version| expand(&version)),
+                scope: dependency.scope.unwrap_or_else(|| "compile".to_string()),
+            }
+        })
+        .collect()
+}
+
+lazy_static! {
+    /// A Gradle dependency declared with a string notation, in Groovy or Kotlin DSL, e.g.
+    /// `implementation "group:artifact:1.0"` or `testImplementation("group:artifact")`.
+    static ref GRADLE_DEPENDENCY: Regex =
+        Regex::new(r#"(?m)^\s*(\w+)\s*\(?\s*["']([^"':\s]+):([^"':\s]+)(?::([^"'@\s]+))?(?:@\w+)?["']"#).unwrap();
+}
+
+/// Reads the dependencies a Gradle build script declares with string notation. Configurations
+/// starting with `test` or `androidTest` have the `test` scope, others `compile`. Map
+/// notation (`group: ..., name: ...`) and version catalogs aren't read.
+fn gradle_dependencies(script: &str) -> Vec<ExternalDep> {
+    const CONFIGURATIONS: [&str; 8] =
+        ["implementation", "api", "compileOnly", "runtimeOnly", "compile", "runtime", "annotationProcessor", "kapt"];
+
+    GRADLE_DEPENDENCY
+        .captures_iter(script)
+        .filter_map(|captures| {
+            let configuration = &captures[1];
+            let test_base = configuration.strip_prefix("test").or_else(|| configuration.strip_prefix("androidTest"));
+            let base = test_base.unwrap_or(configuration);
+            let known = CONFIGURATIONS.iter().any(|known| base.eq_ignore_ascii_case(known));
+            known.then(|| ExternalDep {
+                name: format!("{}:{}", &captures[2], &captures[3]),
+                version: captures.get(4).map(|version| version.as_str().to_string()),
+                scope: if test_base.is_some() { "test" } else { "compile" }.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Expands dependencies for Python's `__init__.py` files.
+/// If a file depends on an `__init__.py`, it implicitly depends on everything
+/// that `__init__.py` file imports, transitively. Those implied edges take the kind and
+/// line of the file's import of the `__init__.py`. Each file's edges are deduplicated by
+/// target.
+pub fn expand_init_dependencies(dependency_graph: &DependencyGraph) -> DependencyGraph {
+    let mut expanded_graph = HashMap::new();
+
+    for (file, direct_deps) in dependency_graph {
+        let mut final_deps: Vec<DependencyEdge> = direct_deps.clone();
+
+        for dep in direct_deps {
+            if Path::new(&dep.target).file_name().and_then(|s| s.to_str()) == Some("__init__.py") {
+                let mut visited = HashSet::new();
+                collect_transitive_init_deps(dep, &dep.target, dependency_graph, &mut final_deps, &mut visited);
+            }
+        }
+
+        expanded_graph.insert(file.clone(), sorted_unique(final_deps));
+    }
+
+    expanded_graph
+}
+
+fn collect_transitive_init_deps(
+    import: &DependencyEdge,
+    init_file: &str,
+    original_graph: &DependencyGraph,
+    final_deps: &mut Vec<DependencyEdge>,
+    visited: &mut HashSet<String>,
+) {
+    if !visited.insert(init_file.to_string()) {
+        return; // Cycle detected or already visited
+    }
+
+    if let Some(init_direct_deps) = original_graph.get(init_file) {
+        for dep in init_direct_deps {
+            final_deps.push(DependencyEdge { target: dep.target.clone(), ..import.clone() });
+            if Path::new(&dep.target).file_name().and_then(|s| s.to_str()) == Some("__init__.py") {
+                collect_transitive_init_deps(import, &dep.target, original_graph, final_deps, visited);
+            }
+        }
+    }
+}
+
+/// An edge to `target` from the import at `node`.
+fn edge(target: String, kind: EdgeKind, node: Node) -> DependencyEdge {
+    DependencyEdge { target, kind, conditional: false, line: Some(node.start_position().row + 1) }
+}
+
+/// Sorts edges naturally by target and keeps one per target: the first unconditional one if
+/// any, otherwise the first.
+fn sorted_unique(mut edges: Vec<DependencyEdge>) -> Vec<DependencyEdge> {
+    edges.sort_by(|a, b| natord::compare(&a.target, &b.target).then(a.conditional.cmp(&b.conditional)));
+    edges.dedup_by(|a, b| a.target == b.target);
+    edges
+}
+
+/// Finds the dependency cycles in a graph: its strongly connected components with more than
+/// one file, found with Tarjan's algorithm. Each component lists its files in natural order,
+/// and components are sorted by their first file. Files that only depend on themselves are
+/// not reported (see `is_dag`).
+pub fn tarjan_sccs(graph: &DependencyGraph) -> Vec<Vec<String>> {
+    struct Visit {
+        index: usize,
+        lowlink: usize,
+        on_stack: bool,
+    }
+
+    let dependencies = |file: &str| graph.get(file).map_or(&[][..], |deps| deps.as_slice());
+    let mut files: Vec<&str> = graph.keys().map(String::as_str).collect();
+    files.sort_by(|a, b| natord::compare(a, b));
+
+    let mut visits: HashMap<&str, Visit> = HashMap::new();
+    let mut stack: Vec<&str> = Vec::new();
+    let mut sccs = Vec::new();
+    for start in files {
+        if visits.contains_key(start) {
+            continue;
+        }
+        // An explicit DFS stack of (file, index of its next dependency), so that long
+        // import chains can't overflow the thread's stack.
+        let mut path: Vec<(&str, usize)> = vec![(start, 0)];
+        visits.insert(start, Visit { index: visits.len(), lowlink: visits.len(), on_stack: true });
+        stack.push(start);
+
+        while let Some(&(file, next)) = path.last() {
+            if let Some(dep) = dependencies(file).get(next) {
+                path.last_mut().unwrap().1 += 1;
+                let dep = dep.target.as_str();
+                match visits.get(dep) {
+                    None => {
+                        visits.insert(dep, Visit { index: visits.len(), lowlink: visits.len(), on_stack: true });
+                        stack.push(dep);
+                        path.push((dep, 0));
+                    }
+                    Some(visit) if visit.on_stack => {
+                        let dep_index = visit.index;
+                        let visit = visits.get_mut(file).unwrap();
+                        visit.lowlink = visit.lowlink.min(dep_index);
+                    }
+                    Some(_) => {}
+                }
+                continue;
             }
+
+            path.pop();
+            let (index, lowlink) = (visits[file].index, visits[file].lowlink);
+            if let Some(&(parent, _)) = path.last() {
+                let parent = visits.get_mut(parent).unwrap();
+                parent.lowlink = parent.lowlink.min(lowlink);
+            }
+            if lowlink == index {
+                let mut scc = Vec::new();
+                while let Some(member) = stack.pop() {
+                    visits.get_mut(member).unwrap().on_stack = false;
+                    scc.push(member.to_string());
+                    if member == file {
+                        break;
+                    }
+                }
+                if scc.len() > 1 {
+                    scc.sort_by(|a, b| natord::compare(a, b));
+                    sccs.push(scc);
+                }
+            }
+        }
+    }
+    sccs.sort_by(|a, b| natord::compare(&a[0], &b[0]));
+    sccs
+}
+
+/// Whether the graph has no dependency cycles, including files that depend on themselves.
+pub fn is_dag(graph: &DependencyGraph) -> bool {
+    !graph.iter().any(|(file, deps)| depends_on(deps, file)) && tarjan_sccs(graph).is_empty()
+}
+
+fn depends_on(deps: &[DependencyEdge], file: &str) -> bool {
+    deps.iter().any(|dep| dep.target == file)
+}
+
+/// Orders every file in the graph after its dependencies (leaves first), using Kahn's
+/// algorithm. Files that become ready at the same time are ordered naturally, so the result
+/// is stable. Fails with the graph's cycles, including files that depend on themselves.
+pub fn topological_sort(graph: &DependencyGraph) -> Result<Vec<String>, Vec<Vec<String>>> {
+    let mut remaining: HashMap<&str, usize> = HashMap::new();
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (file, deps) in graph {
+        let deps: HashSet<&str> = deps.iter().map(|dep| dep.target.as_str()).collect();
+        remaining.insert(file, deps.len());
+        for dep in deps {
+            remaining.entry(dep).or_insert(0);
+            dependents.entry(dep).or_default().push(file);
+        }
+    }
+
+    let mut order = Vec::with_capacity(remaining.len());
+    let mut ready: Vec<&str> = remaining.iter().filter(|(_, count)| **count == 0).map(|(file, _)| *file).collect();
+    while !ready.is_empty() {
+        ready.sort_by(|a, b| natord::compare(a, b));
+        let mut next = Vec::new();
+        for file in ready {
+            order.push(file.to_string());
+            for dependent in dependents.get(file).into_iter().flatten() {
+                let count = remaining.get_mut(dependent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    next.push(*dependent);
+                }
+            }
+        }
+        ready = next;
+    }
+
+    if order.len() == remaining.len() {
+        return Ok(order);
+    }
+    let mut self_loops: Vec<Vec<String>> =
+        graph.iter().filter(|(file, deps)| depends_on(deps, file)).map(|(file, _)| vec![file.clone()]).collect();
+    let mut cycles = tarjan_sccs(graph);
+    cycles.append(&mut self_loops);
+    cycles.sort_by(|a, b| natord::compare(&a[0], &b[0]));
+    Err(cycles)
+}
+
+/// Bundler configs in the root whose `resolve.alias` is applied to JS/TS imports.
+const BUNDLER_CONFIG_FILES: [&str; 5] =
+    ["webpack.config.js", "webpack.config.mjs", "vite.config.ts", "vite.config.js", "vite.config.mjs"];
+
+/// Reads the `alias` settings of the root's Webpack and Vite configs, as (alias, target
+/// directory) pairs with the longest aliases first. Both the object form
+/// (`{'@components': path.resolve(__dirname, 'src/components')}`) and Vite's array form
+/// (`[{find: '@', replacement: '/src'}]`) are understood. A target's string literals are
+/// joined onto the root, which covers `path.resolve(__dirname, ...)`, root-relative
+/// strings and `new URL('./src', import.meta.url)`. Configs are parsed with the JavaScript
+/// grammar, so type annotations in `vite.config.ts` are skipped over as errors.
+fn load_bundler_aliases(parser: &mut Parser, root_path: &Path) -> Vec<(String, PathBuf)> {
+    fn strings(node: tree_sitter::Node, source: &str, out: &mut Vec<String>) {
+        if node.kind() == "string_fragment" {
+            out.push(source[node.byte_range()].to_string());
+            return;
+        }
+        let mut walker = node.walk();
+        for child in node.named_children(&mut walker) {
+            strings(child, source, out);
+        }
+    }
+
+    fn key_text(pair: tree_sitter::Node, source: &str) -> Option<String> {
+        let key = pair.child_by_field_name("key")?;
+        Some(source[key.byte_range()].trim_matches(|c| c == '"' || c == '\'' || c == '`').to_string())
+    }
+
+    fn target(value: tree_sitter::Node, source: &str, root_path: &Path) -> Option<PathBuf> {
+        let mut parts = Vec::new();
+        strings(value, source, &mut parts);
+        if parts.is_empty() {
+            return None;
+        }
+        let mut path = root_path.to_path_buf();
+        for part in parts {
+            path.push(part.trim_start_matches('/'));
+        }
+        Some(path.clean())
+    }
+
+    fn collect(node: tree_sitter::Node, source: &str, root_path: &Path, aliases: &mut Vec<(String, PathBuf)>) {
+        if node.kind() == "pair" && key_text(node, source).as_deref() == Some("alias") {
+            let Some(value) = node.child_by_field_name("value") else {
+                return;
+            };
+            let mut walker = value.walk();
+            for entry in value.named_children(&mut walker) {
+                match entry.kind() {
+                    // `{'@': path.resolve(__dirname, 'src')}`
+                    "pair" => {
+                        let alias = key_text(entry, source);
+                        let target = entry.child_by_field_name("value").and_then(|v| target(v, source, root_path));
+                        if let (Some(alias), Some(target)) = (alias, target) {
+                            aliases.push((alias, target));
+                        }
+                    }
+                    // `[{find: '@', replacement: path.resolve(__dirname, 'src')}]`
+                    "object" => {
+                        let mut alias = None;
+                        let mut replacement = None;
+                        let mut entry_walker = entry.walk();
+                        for field in entry.named_children(&mut entry_walker).filter(|n| n.kind() == "pair") {
+                            let value = field.child_by_field_name("value");
+                            match key_text(field, source).as_deref() {
+                                Some("find") => {
+                                    alias = value.filter(|v| v.kind() == "string").and_then(|v| {
+                                        let mut parts = Vec::new();
+                                        strings(v, source, &mut parts);
+                                        parts.pop()
+                                    })
+                                }
+                                Some("replacement") => replacement = value.and_then(|v| target(v, source, root_path)),
+                                _ => {}
+                            }
+                        }
+                        if let (Some(alias), Some(target)) = (alias, replacement) {
+                            aliases.push((alias, target));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            return;
+        }
+        let mut walker = node.walk();
+        for child in node.named_children(&mut walker) {
+            collect(child, source, root_path, aliases);
+        }
+    }
+
+    let mut aliases = Vec::new();
+    for config in BUNDLER_CONFIG_FILES {
+        let Ok(source) = fs::read_to_string(root_path.join(config)) else {
+            continue;
+        };
+        let Some(tree) = parser.parse(source.as_bytes(), None) else {
+            continue;
+        };
+        let before = aliases.len();
+        collect(tree.root_node(), &source, root_path, &mut aliases);
+        debug!("Found {} aliases in '{}'.", aliases.len() - before, config);
+    }
+    aliases.sort_by_key(|(alias, _)| std::cmp::Reverse(alias.len()));
+    aliases
+}
+
+/// Rewrites an import that starts with a bundler alias to an absolute path under the alias'
+/// target. Webpack aliases ending in `$` only match the import exactly.
+fn apply_bundler_alias(import: &str, aliases: &[(String, PathBuf)]) -> Option<String> {
+    aliases.iter().find_map(|(alias, target)| {
+        let rest = match alias.strip_suffix('$') {
+            Some(exact) => (import == exact).then_some("")?,
+            None => {
+                let alias = alias.trim_end_matches('/');
+                let rest = import.strip_prefix(alias)?;
+                if !rest.is_empty() && !rest.starts_with('/') {
+                    return None;
+                }
+                rest.trim_start_matches('/')
+            }
+        };
+        let path = if rest.is_empty() { target.clone() } else { target.join(rest) };
+        Some(path.to_string_lossy().into_owned())
+    })
+}
+
+lazy_static! {
+    /// Whether bare JS/TS imports (`mypackage/utils`) are resolved through the `exports` of
+    /// `node_modules/<package>/package.json`, from the `RESOLVE_NODE_MODULES` env var.
+    /// Defaults to `false`, since it reads a `package.json` per import.
+    static ref RESOLVE_NODE_MODULES: bool = env::var("RESOLVE_NODE_MODULES")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(false);
+}
+
+/// Conditions an `exports` entry may be selected by, as Node.js does for both `import`
+/// and `require`.
+const EXPORTS_CONDITIONS: &[&str] = &["import", "require", "module", "node", "default"];
+
+/// Splits a bare import into its package name, including any `@scope/`, and the subpath
+/// within it in `exports` form (`.` or `./rest`). Relative and absolute imports yield `None`.
+fn split_package_import(import: &str) -> Option<(&str, String)> {
+    if import.is_empty() || import.starts_with('.') || import.starts_with('/') {
+        return None;
+    }
+    let name_segments = if import.starts_with('@') { 2 } else { 1 };
+    let end = import.match_indices('/').nth(name_segments - 1).map_or(import.len(), |(i, _)| i);
+    let (package, rest) = import.split_at(end);
+    if package.is_empty() || package.ends_with('/') {
+        return None;
+    }
+    Some((package, format!(".{}", rest)))
+}
+
+/// Resolves `subpath` (`.` or `./utils`) of `package` through the `exports` field of
+/// `node_modules/<package>/package.json` under `root`. Handles subpath maps, `*` patterns,
+/// condition objects and fallback arrays; a `null` target hides the subpath. The resolved
+/// file must exist.
+fn resolve_package_exports(root: &Path, package: &str, subpath: &str) -> Option<PathBuf> {
+    fn export_target(value: &serde_json::Value, wildcard: Option<&str>) -> Option<String> {
+        match value {
+            serde_json::Value::String(target) => {
+                let target = match wildcard {
+                    Some(matched) => target.replace('*', matched),
+                    None => target.clone(),
+                };
+                target.starts_with("./").then_some(target)
+            }
+            serde_json::Value::Array(targets) => targets.iter().find_map(|t| export_target(t, wildcard)),
+            serde_json::Value::Object(conditions) => conditions
+                .iter()
+                .find(|(condition, _)| EXPORTS_CONDITIONS.contains(&condition.as_str()))
+                .and_then(|(_, value)| export_target(value, wildcard)),
+            _ => None,
+        }
+    }
+
+    let package_dir = root.join("node_modules").join(package);
+    let manifest: serde_json::Value = serde_json::from_slice(&fs::read(package_dir.join("package.json")).ok()?).ok()?;
+    let exports = manifest.get("exports")?;
+    let resolved = match exports {
+        serde_json::Value::Object(subpaths) if subpaths.keys().any(|key| key.starts_with('.')) => {
+            match subpaths.get(subpath) {
+                Some(value) => export_target(value, None),
+                None => subpaths
+                    .iter()
+                    .filter_map(|(key, value)| {
+                        let (prefix, suffix) = key.split_once('*')?;
+                        let matched = subpath.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                        Some((prefix.len(), value, matched))
+                    })
+                    .max_by_key(|(prefix_len, _, _)| *prefix_len)
+                    .and_then(|(_, value, matched)| export_target(value, Some(matched))),
+            }
+        }
+        // Without `.`-prefixed keys, `exports` is the target of `.` itself.
+        _ if subpath == "." => export_target(exports, None),
+        _ => None,
+    }?;
+    let path = package_dir.join(resolved).clean();
+    path.is_file().then_some(path)
+}
+
+/// Helper function to resolve a relative import/module path to a file path.
+/// Tries appending possible suffixes and checks if the resolved path exists within the root.
+fn resolve_relative_path(
+    parent_dir: &Path,
+    import_str: &str,
+    root_path: &Path,
+    suffixes: &[&str],
+) -> Option<String> {
+    for suffix in suffixes {
+        let candidate = parent_dir.join(format!("{}{}", import_str, suffix)).clean();
+        // Import strings are usually NFC while names on disk may be NFD (or vice versa).
+        let Some(candidate) = resolve_unicode_path(&candidate) else {
+            continue;
+        };
+        if candidate.is_file() && candidate.starts_with(root_path) {
+            return Some(to_nfc(&candidate.to_string_lossy()));
+        }
+    }
+    None
+}
+
+/// Resolves a bare import through its package's `exports` when `RESOLVE_NODE_MODULES`
+/// is set, to a file under `root_path`.
+fn node_module_import(root_path: &Path, import: &str) -> Option<String> {
+    if !*RESOLVE_NODE_MODULES {
+        return None;
+    }
+    let (package, subpath) = split_package_import(import)?;
+    let resolved = resolve_package_exports(root_path, package, &subpath)?;
+    resolved
+        .starts_with(root_path)
+        .then(|| to_nfc(&resolved.to_string_lossy()))
+}
+
+/// Analyzes JavaScript and TypeScript files for dependencies.
+fn analyze_javascript_typescript(
+    root_path: &Path,
+    files_to_scan: &[String],
+    dependency_graph: &mut DependencyGraph,
+) {
+    let language = JAVASCRIPT.language();
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        warn!("Failed to set language for JavaScript: {}. JS/TS dependency analysis will be skipped.", e);
+        return;
+    }
+
+    let query_src = r#"
+(import_statement source: (string (string_fragment) @path))
+(call_expression
+  function: (identifier) @_fn
+  arguments: (arguments (string (string_fragment) @path))
+  (#eq? @_fn "require"))
+(call_expression
+  function: (import)
+  arguments: (arguments (string (string_fragment) @path)))
+(export_statement source: (string (string_fragment) @path))
+"#;
+    let query = match Query::new(&language, query_src) {
+        Ok(q) => q,
+        Err(e) => {
+            warn!("Failed to compile JavaScript tree-sitter query: {}. JS/TS dependency analysis will be skipped.", e);
+            return;
+        }
+    };
+
+    let js_like_files: Vec<_> = files_to_scan
+        .iter()
+        .filter(|file_path_str| {
+            let file_path = PathBuf::from(file_path_str);
+            file_path.extension().is_some_and(|e| {
+                e == "js" || e == "jsx" || e == "ts" || e == "tsx"
+            })
+        })
+        .collect();
+
+    debug!("Found {} JavaScript/TypeScript files to scan for dependencies.", js_like_files.len());
+    let aliases = if js_like_files.is_empty() { Vec::new() } else { load_bundler_aliases(&mut parser, root_path) };
+
+    for file_path_str in js_like_files {
+        let file_path = PathBuf::from(file_path_str);
+        
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        
+        let tree = match parser.parse(content.as_bytes(), None) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let mut cursor = QueryCursor::new();
+        let mut matches_iter = cursor.matches(&query, tree.root_node(), content.as_bytes());
+        let mut dependencies = Vec::new();
+
+        while let Some(mat) = matches_iter.next() {
+            // Pattern 1 is `require()` and 3 `export ... from`; the others are static and
+            // dynamic imports.
+            let kind = match mat.pattern_index {
+                1 => EdgeKind::Require,
+                3 => EdgeKind::ReExport,
+                _ => EdgeKind::Import,
+            };
+            for cap in mat.captures {
+                if query.capture_names()[cap.index as usize] != "path" {
+                    continue;
+                }
+
+                let path_node = cap.node;
+                let import_path_str = &content[path_node.byte_range()];
+                let clean_import = import_path_str.trim_matches('"').trim_matches('\'');
+                debug!("Found JS/TS import '{}' in '{}'", clean_import, file_path.display());
+
+                if let Some(parent_dir) = file_path.parent() {
+                    let possible_exts = [
+                        "", ".js", ".jsx", ".ts", ".tsx", "/index.js", "/index.jsx",
+                        "/index.ts", "/index.tsx",
+                    ];
+                    // Aliased imports resolve to absolute paths, which `join` uses as-is.
+                    let aliased = apply_bundler_alias(clean_import, &aliases);
+                    let import = aliased.as_deref().unwrap_or(clean_import);
+                    if let Some(resolved) = resolve_relative_path(parent_dir, import, root_path, &possible_exts) {
+                        dependencies.push(edge(resolved, kind, path_node));
+                    } else if let Some(resolved) = node_module_import(root_path, import) {
+                        dependencies.push(edge(resolved, kind, path_node));
+                    }
+                }
+            }
+        }
+
+        if !dependencies.is_empty() {
+            dependency_graph
+                .entry(file_path_str.clone())
+                .or_default()
+                .extend(dependencies);
+        }
+    }
+}
+
+/// Resolves a Python module name (e.g. `foo.bar` or `..foo`) imported by `file_path` to
+/// the file that defines it.
+fn resolve_python_module(module_str: &str, file_path: &Path, root_path: &Path) -> Option<String> {
+    let clean_import = if module_str.starts_with('.') {
+        // Relative import like 'from .foo import ...' or 'from ..foo.bar import ...'
+        let num_dots = module_str.find(|c| c != '.').unwrap_or(module_str.len());
+        let mut path_prefix = String::new();
+        if num_dots > 1 {
+            path_prefix.push_str(&"../".repeat(num_dots - 1));
+        }
+        let module_part = &module_str[num_dots..];
+        format!("{}{}", path_prefix, module_part.replace('.', "/"))
+    } else {
+        // Absolute import
+        module_str.replace('.', "/")
+    };
+
+    debug!("Found Python import '{}', processed to '{}' in '{}'", module_str, clean_import, file_path.display());
+    let parent_dir = file_path.parent()?;
+    let possible_exts = [".py", "/__init__.py"];
+    resolve_relative_path(parent_dir, &clean_import, root_path, &possible_exts)
+}
+
+fn process_python_module(
+    module_str: &str,
+    node: Node,
+    file_path_str: &str,
+    file_path: &Path,
+    root_path: &Path,
+    dependency_graph: &mut DependencyGraph,
+) {
+    if let Some(resolved) = resolve_python_module(module_str, file_path, root_path) {
+        dependency_graph
+            .entry(file_path_str.to_string())
+            .or_default()
+            .push(edge(resolved, EdgeKind::Import, node));
+    }
+}
+
+/// Tree-sitter query for the modules a Python file imports.
+const PYTHON_IMPORTS_QUERY: &str = r#"
+; Pattern 0: import foo
+(import_statement (dotted_name) @module)
+; Pattern 1a: from foo.bar import ...
+(import_from_statement
+  module_name: (dotted_name) @module
+)
+; Pattern 1b: from .foo import ...
+(import_from_statement
+  module_name: (relative_import) @module
+)
+; Pattern 2: from . import foo[, bar, ...]
+(import_from_statement
+  module_name: (relative_import) @dots
+  name: (dotted_name) @name
+  (#match? @dots "^\.+$")
+)
+(import_from_statement
+  module_name: (relative_import) @dots
+  name: (aliased_import
+    name: (dotted_name) @name
+  )
+  (#match? @dots "^\.+$")
+)
+"#;
+
+/// Lists the modules imported by a parsed Python file, each with the node it was read from.
+fn python_imports<'tree>(query: &Query, root: Node<'tree>, content: &str) -> Vec<(String, Node<'tree>)> {
+    let mut imports = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches_iter = cursor.matches(query, root, content.as_bytes());
+
+    while let Some(mat) = matches_iter.next() {
+        match mat.pattern_index {
+            0..=2 => { // import a.b, from a.b import c, from .a import c
+                for cap in mat.captures {
+                    if query.capture_names()[cap.index as usize] == "module" {
+                        imports.push((content[cap.node.byte_range()].to_string(), cap.node));
+                    }
+                }
+            },
+            3 | 4 => { // from . import a, from .. import b
+                let mut dots_opt = None;
+                let mut names = Vec::new();
+                for cap in mat.captures {
+                    let cap_name = query.capture_names()[cap.index as usize];
+                    let text = &content[cap.node.byte_range()];
+                    match cap_name {
+                        "dots" => dots_opt = Some(text),
+                        "name" => names.push((text, cap.node)),
+                        _ => {}
+                    }
+                }
+                if let Some(dots) = dots_opt {
+                    for (name, node) in names {
+                        imports.push((format!("{}{}", dots, name), node));
+                    }
+                }
+            },
+            _ => {} // Unhandled pattern
+        }
+    }
+    imports
+}
+
+/// Analyzes Python files for dependencies.
+fn analyze_python(
+    root_path: &Path,
+    files_to_scan: &[String],
+    dependency_graph: &mut DependencyGraph,
+) {
+    let language = PYTHON.language();
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        warn!("Failed to set language for Python: {}. Python dependency analysis will be skipped.", e);
+        return;
+    }
+
+    let query = match Query::new(&language, PYTHON_IMPORTS_QUERY) {
+        Ok(q) => q,
+        Err(e) => {
+            warn!("Failed to compile Python tree-sitter query: {}. Python dependency analysis will be skipped.", e);
+            return;
+        }
+    };
+
+    let py_files: Vec<_> = files_to_scan
+        .iter()
+        .filter(|file_path_str| {
+            PathBuf::from(file_path_str)
+                .extension()
+                .is_some_and(|e| e == "py")
+        })
+        .collect();
+    
+    debug!("Found {} Python files to scan for dependencies.", py_files.len());
+
+    for file_path_str in py_files {
+        let file_path = PathBuf::from(file_path_str);
+        
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        
+        let tree = match parser.parse(content.as_bytes(), None) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        for (module_str, node) in python_imports(&query, tree.root_node(), &content) {
+            process_python_module(&module_str, node, file_path_str, &file_path, root_path, dependency_graph);
+        }
+    }
+}
+
+/// Collects the dependencies each Python file only imports inside `if TYPE_CHECKING:` (or
+/// `if typing.TYPE_CHECKING:`), i.e. for type checking and not at runtime. Those edges are
+/// in the graph like any other; this marks them so clients can filter them out. Only files
+/// mentioning `TYPE_CHECKING` are parsed.
+fn analyze_python_type_checking(root_path: &Path, files_to_scan: &[String]) -> EdgeTargets {
+    let mut type_checking = HashMap::new();
+    let language = PYTHON.language();
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        warn!("Failed to set language for Python: {}. TYPE_CHECKING imports will be skipped.", e);
+        return type_checking;
+    }
+    let query = match Query::new(&language, PYTHON_IMPORTS_QUERY) {
+        Ok(q) => q,
+        Err(e) => {
+            warn!("Failed to compile Python tree-sitter query: {}. TYPE_CHECKING imports will be skipped.", e);
+            return type_checking;
+        }
+    };
+
+    for file_path_str in files_to_scan {
+        let file_path = Path::new(file_path_str);
+        if file_path.extension().is_none_or(|e| e != "py") {
+            continue;
+        }
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
+            Ok(c) if c.contains("TYPE_CHECKING") => c,
+            _ => continue,
+        };
+        let Some(tree) = parser.parse(content.as_bytes(), None) else {
+            continue;
+        };
+
+        let mut runtime = HashSet::new();
+        let mut guarded: Vec<String> = Vec::new();
+        for (module_str, node) in python_imports(&query, tree.root_node(), &content) {
+            let Some(resolved) = resolve_python_module(&module_str, file_path, root_path) else {
+                continue;
+            };
+            if in_type_checking_block(node, &content) {
+                if !guarded.contains(&resolved) {
+                    guarded.push(resolved);
+                }
+            } else {
+                runtime.insert(resolved);
+            }
+        }
+        guarded.retain(|dep| !runtime.contains(dep));
+        if !guarded.is_empty() {
+            debug!("Found {} TYPE_CHECKING-only imports in '{}'", guarded.len(), file_path_str);
+            type_checking.insert(file_path_str.clone(), guarded);
+        }
+    }
+    type_checking
+}
+
+/// Whether `node` is in the body (not an `else`/`elif` branch) of an `if TYPE_CHECKING:`.
+fn in_type_checking_block(node: Node, content: &str) -> bool {
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        if parent.kind() == "if_statement" && parent.child_by_field_name("consequence") == Some(current) {
+            let is_type_checking = parent.child_by_field_name("condition").is_some_and(|condition| {
+                let text = &content[condition.byte_range()];
+                text == "TYPE_CHECKING" || text.ends_with(".TYPE_CHECKING")
+            });
+            if is_type_checking {
+                return true;
+            }
+        }
+        current = parent;
+    }
+    false
+}
+
+/// Collects the names each Python file imports with `from module import *`, taken from the
+/// imported modules' `__all__` lists. Modules without `__all__` contribute nothing. Only
+/// files containing a `*` are parsed, and each imported module is parsed at most once.
+fn analyze_python_exports(root_path: &Path, files_to_scan: &[String]) -> ExportsMap {
+    let mut exports = HashMap::new();
+    let language = PYTHON.language();
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        warn!("Failed to set language for Python: {}. Python exports will be skipped.", e);
+        return exports;
+    }
+
+    let wildcard_src = r#"(import_from_statement module_name: (_) @module (wildcard_import))"#;
+    let all_src = r#"
+(assignment left: (identifier) @_target right: [(list) (tuple)] @names (#eq? @_target "__all__"))
+(augmented_assignment left: (identifier) @_target right: [(list) (tuple)] @names (#eq? @_target "__all__"))
+"#;
+    let (wildcard_query, all_query) = match (Query::new(&language, wildcard_src), Query::new(&language, all_src)) {
+        (Ok(wildcard), Ok(all)) => (wildcard, all),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Failed to compile Python exports tree-sitter query: {}. Python exports will be skipped.", e);
+            return exports;
+        }
+    };
+
+    let mut module_names: HashMap<String, Vec<String>> = HashMap::new();
+    for file_path_str in files_to_scan {
+        let file_path = Path::new(file_path_str);
+        if file_path.extension().is_none_or(|e| e != "py") {
+            continue;
+        }
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
+            Ok(c) if c.contains('*') => c,
+            _ => continue,
+        };
+        let Some(tree) = parser.parse(content.as_bytes(), None) else {
+            continue;
+        };
+
+        let mut modules = Vec::new();
+        let mut cursor = QueryCursor::new();
+        let mut matches_iter = cursor.matches(&wildcard_query, tree.root_node(), content.as_bytes());
+        while let Some(mat) = matches_iter.next() {
+            modules.extend(mat.captures.iter().map(|cap| content[cap.node.byte_range()].to_string()));
+        }
+
+        let mut names: Vec<String> = Vec::new();
+        for module in modules {
+            let Some(resolved) = resolve_python_module(&module, file_path, root_path) else {
+                continue;
+            };
+            debug!("Found wildcard import of '{}' in '{}'", resolved, file_path.display());
+            let exported = module_names
+                .entry(resolved)
+                .or_insert_with_key(|resolved| python_all_names(&mut parser, &all_query, resolved));
+            for name in exported.iter() {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+        }
+        if !names.is_empty() {
+            exports.insert(file_path_str.clone(), names);
+        }
+    }
+    exports
+}
+
+/// Reads the string literals assigned to (or added to) `__all__` in a Python module.
+fn python_all_names(parser: &mut Parser, query: &Query, module_path: &str) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(on_disk_path(module_path)) else {
+        return Vec::new();
+    };
+    let Some(tree) = parser.parse(content.as_bytes(), None) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches_iter = cursor.matches(query, tree.root_node(), content.as_bytes());
+    while let Some(mat) = matches_iter.next() {
+        for cap in mat.captures {
+            if query.capture_names()[cap.index as usize] != "names" {
+                continue;
+            }
+            let mut walker = cap.node.walk();
+            for string in cap.node.named_children(&mut walker).filter(|n| n.kind() == "string") {
+                let mut string_walker = string.walk();
+                let name = string
+                    .named_children(&mut string_walker)
+                    .find(|n| n.kind() == "string_content")
+                    .map(|n| content[n.byte_range()].to_string());
+                if let Some(name) = name.filter(|name| !names.contains(name)) {
+                    names.push(name);
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Analyzes Rust files for dependencies.
+fn analyze_rust(
+    root_path: &Path,
+    files_to_scan: &[String],
+    dependency_graph: &mut DependencyGraph,
+) {
+    let language = RUST.language();
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        warn!("Failed to set language for Rust: {}. Rust dependency analysis will be skipped.", e);
+        return;
+    }
+    let query_src = r#"
+(mod_item name: (identifier) @module)
+(use_declaration argument: [ (identifier) @module (scoped_identifier) @module ])
+(use_declaration argument: (scoped_use_list path: (_) @module))
+(use_declaration argument: (use_as_clause path: (_) @module))
+"#;
+    let query = match Query::new(&language, query_src) {
+        Ok(q) => q,
+        Err(e) => {
+            warn!("Failed to compile Rust tree-sitter query: {}. Rust dependency analysis will be skipped.", e);
+            return;
+        }
+    };
+
+    let workspace_crates: HashMap<String, String> = detect_cargo_workspace(root_path)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|member| cargo_crate_entry(member))
+        .collect();
+    if !workspace_crates.is_empty() {
+        debug!("Detected {} Cargo workspace crates in '{}'", workspace_crates.len(), root_path.display());
+    }
+    let rs_files: Vec<_> = files_to_scan
+        .iter()
+        .filter(|file_path_str| PathBuf::from(file_path_str).extension().is_some_and(|e| e == "rs"))
+        .collect();
+
+    debug!("Found {} Rust files to scan for dependencies.", rs_files.len());
+    
+    for file_path_str in rs_files {
+        let file_path = PathBuf::from(file_path_str);
+        
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
+            Ok(c) => c,
+            Err(_) => continue,
+        };
+        
+        let tree = match parser.parse(content.as_bytes(), None) {
+            Some(t) => t,
+            None => continue,
+        };
+
+        let mut cursor = QueryCursor::new();
+        let mut matches_iter = cursor.matches(&query, tree.root_node(), content.as_bytes());
+        let mut dependencies = Vec::new();
+
+        while let Some(mat) = matches_iter.next() {
+            // Pattern 0 is a `mod` declaration; the others are `use` declarations.
+            let kind = if mat.pattern_index == 0 { EdgeKind::ModDecl } else { EdgeKind::Import };
+            for cap in mat.captures {
+                if query.capture_names()[cap.index as usize] != "module" {
+                    continue;
+                }
+                
+                let path_node = cap.node;
+                let module_str = &content[path_node.byte_range()];
+                let mut clean_import = if let Some(stripped) = module_str.strip_prefix("self::") {
+                    stripped.to_string()
+                } else if let Some(stripped) = module_str.strip_prefix("super::") {
+                    format!("../{}", stripped)
+                } else {
+                    module_str.to_string()
+                };
+                clean_import = clean_import.replace("::", "/");
+
+                debug!("Found Rust module/use '{}', processed to '{}' in '{}'", module_str, clean_import, file_path.display());
+
+                // `use other_crate::...` of a workspace member depends on that crate's root.
+                let crate_name = module_str.split("::").next().unwrap_or(module_str);
+                if let Some(entry) = workspace_crates.get(crate_name) {
+                    if entry != file_path_str {
+                        dependencies.push(edge(entry.clone(), kind, path_node));
+                    }
+                    continue;
+                }
+
+                if let Some(parent_dir) = file_path.parent() {
+                    let possible_exts = [".rs", "/mod.rs"];
+                    if let Some(resolved) = resolve_relative_path(parent_dir, &clean_import, root_path, &possible_exts) {
+                        dependencies.push(edge(resolved, kind, path_node));
+                    }
+                }
+            }
+        }
+
+        if !dependencies.is_empty() {
+            dependency_graph
+                .entry(file_path_str.clone())
+                .or_default()
+                .extend(dependencies);
         }
     }
-    collect_files(tree, &mut files_to_scan);
+}
 
-    // Analyze each supported language
-    analyze_javascript_typescript(root_path, &files_to_scan, &mut dependency_graph);
-    analyze_python(root_path, &files_to_scan, &mut dependency_graph);
-    analyze_rust(root_path, &files_to_scan, &mut dependency_graph);
-    analyze_cpp(root_path, &files_to_scan, &mut dependency_graph);
+/// Reads the root `Cargo.toml` and returns the directories of its `[workspace] members`,
+/// expanding trailing `/*` globs and skipping `exclude`d ones. A root that is also a package
+/// is included. Returns `None` if the root isn't a Cargo workspace.
+fn detect_cargo_workspace(root: &Path) -> Option<Vec<PathBuf>> {
+    let content = fs::read_to_string(root.join("Cargo.toml")).ok()?;
+    let manifest: toml::Table = match content.parse() {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            warn!("Failed to parse '{}': {}", root.join("Cargo.toml").display(), e);
+            return None;
+        }
+    };
+    let workspace = manifest.get("workspace")?.as_table()?;
+    let patterns = |key: &str| -> Vec<String> {
+        workspace
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|list| list.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+            .unwrap_or_default()
+    };
+    let excluded: Vec<PathBuf> = patterns("exclude").iter().map(|p| root.join(p).clean()).collect();
 
-    let duration = start_time.elapsed();
-    info!(
-        "Dependency analysis for '{}' finished in {:.2?}. Found dependencies for {} files.",
-        root_path.display(),
-        duration,
-        dependency_graph.len()
-    );
-    Ok(dependency_graph)
+    let mut members = Vec::new();
+    if manifest.contains_key("package") {
+        members.push(root.to_path_buf());
+    }
+    for pattern in patterns("members") {
+        if let Some(parent) = pattern.strip_suffix("/*") {
+            let Ok(entries) = fs::read_dir(root.join(parent)) else {
+                continue;
+            };
+            let mut dirs: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect();
+            dirs.sort();
+            members.extend(dirs);
+        } else {
+            members.push(root.join(&pattern));
+        }
+    }
+    members.retain(|member| {
+        let member = member.clean();
+        !excluded.contains(&member) && member.join("Cargo.toml").is_file()
+    });
+    Some(members)
 }
 
-/// Expands dependencies for Python's `__init__.py` files.
-/// If a file depends on an `__init__.py`, it implicitly depends on everything
-/// that `__init__.py` file imports, transitively.
-pub fn expand_init_dependencies(dependency_graph: &DependencyGraph) -> DependencyGraph {
-    let mut expanded_graph = HashMap::new();
+/// Returns a Cargo package's crate name, as written in `use` paths, and its root source file
+/// (`src/lib.rs`, or `src/main.rs` for binaries).
+fn cargo_crate_entry(member: &Path) -> Option<(String, String)> {
+    let manifest: toml::Table = fs::read_to_string(member.join("Cargo.toml")).ok()?.parse().ok()?;
+    let lib = manifest.get("lib").and_then(|lib| lib.as_table());
+    let name = lib
+        .and_then(|lib| lib.get("name"))
+        .or_else(|| manifest.get("package")?.get("name"))?
+        .as_str()?
+        .replace('-', "_");
+    let lib_path = lib.and_then(|lib| lib.get("path")).and_then(|p| p.as_str()).unwrap_or("src/lib.rs");
+    let entry = [lib_path, "src/main.rs"].iter().map(|p| member.join(p).clean()).find(|p| p.is_file())?;
+    Some((name, to_nfc(&entry.to_string_lossy())))
+}
 
-    for (file, direct_deps) in dependency_graph {
-        let mut final_deps: HashSet<String> = direct_deps.iter().cloned().collect();
+/// Resolves a C++ include path against a set of search directories.
+fn resolve_cpp_path(
+    source_file: &Path,
+    include_str: &str,
+    is_quote_include: bool, // true for "...", false for <...>
+    search_paths: &[PathBuf],
+    root_path: &Path,
+) -> Option<String> {
+    let possible_exts = [
+        "", ".h", ".hpp", ".hxx", ".hh", ".c", ".cpp", ".cc",
+    ];
 
-        for dep in direct_deps {
-            if Path::new(dep).file_name().and_then(|s| s.to_str()) == Some("__init__.py") {
-                let mut visited = HashSet::new();
-                collect_transitive_init_deps(dep, dependency_graph, &mut final_deps, &mut visited);
-            }
+    let mut dirs_to_search: Vec<PathBuf> = Vec::new();
+
+    // For #include "...", the directory of the current file is searched first.
+    if is_quote_include {
+        if let Some(parent_dir) = source_file.parent() {
+            dirs_to_search.push(parent_dir.to_path_buf());
         }
+    }
+
+    // Add the provided search paths
+    dirs_to_search.extend(search_paths.iter().cloned());
+
+    for dir in dirs_to_search {
+        for ext in &possible_exts {
+            let path_with_ext = format!("{}{}", include_str, ext);
+            let candidate_path = dir.join(&path_with_ext).clean();
 
-        let mut sorted_deps: Vec<String> = final_deps.into_iter().collect();
-        sorted_deps.sort_by(|a, b| natord::compare(a, b));
-        expanded_graph.insert(file.clone(), sorted_deps);
+            if candidate_path.is_file() && candidate_path.starts_with(root_path) {
+                return Some(candidate_path.to_string_lossy().to_string());
+            }
+        }
     }
 
-    expanded_graph
+    None
 }
 
-fn collect_transitive_init_deps(
-    init_file: &str,
-    original_graph: &DependencyGraph,
-    final_deps: &mut HashSet<String>,
-    visited: &mut HashSet<String>,
-) {
-    if !visited.insert(init_file.to_string()) {
-        return; // Cycle detected or already visited
+lazy_static! {
+    /// Extra directories searched for C/C++ includes, from the colon-separated `INCLUDE_PATHS`
+    /// env var (e.g. `src/include:third_party/include`). Relative entries are resolved
+    /// against the analyzed root.
+    static ref INCLUDE_PATHS: Vec<PathBuf> = env::var("INCLUDE_PATHS")
+        .map(|paths| {
+            paths
+                .split(':')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    /// Whether includes inside `#if`/`#ifdef` blocks count as dependencies, from the
+    /// `INCLUDE_CONDITIONAL_DEPS` env var. Defaults to `true`.
+    static ref INCLUDE_CONDITIONAL_DEPS: bool = env::var("INCLUDE_CONDITIONAL_DEPS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(true);
+}
+
+/// Tree-sitter query for the paths of C/C++ `#include` directives.
+const CPP_INCLUDES_QUERY: &str = r#"
+(preproc_include
+  path: [
+    (string_literal) @path
+    (system_lib_string) @path
+  ]
+)"#;
+
+fn is_cpp_file(file_path_str: &str) -> bool {
+    let ext = Path::new(file_path_str).extension().and_then(|s| s.to_str());
+    matches!(ext, Some("cpp" | "c" | "h" | "hpp" | "hxx" | "cc" | "hh"))
+}
+
+/// Directories searched for C/C++ includes: those configured via `INCLUDE_PATHS`, in order,
+/// followed by common include paths. The latter is a heuristic since we don't have the
+/// build system's configuration.
+fn cpp_search_paths(root_path: &Path) -> Vec<PathBuf> {
+    let mut search_paths: Vec<PathBuf> = INCLUDE_PATHS
+        .iter()
+        .map(|dir| root_path.join(dir).clean())
+        .filter(|dir| dir.is_dir())
+        .collect();
+    search_paths.push(root_path.to_path_buf());
+    for subdir in &["include", "src", "inc"] {
+        let potential_path = root_path.join(subdir);
+        if potential_path.is_dir() {
+            search_paths.push(potential_path);
+        }
     }
+    search_paths
+}
 
-    if let Some(init_direct_deps) = original_graph.get(init_file) {
-        for dep in init_direct_deps {
-            final_deps.insert(dep.clone());
-            if Path::new(dep).file_name().and_then(|s| s.to_str()) == Some("__init__.py") {
-                collect_transitive_init_deps(dep, original_graph, final_deps, visited);
+/// Resolves the includes of a parsed C/C++ file, marking those inside an `#if`/`#ifdef`
+/// block other than an include guard as conditional.
+fn cpp_includes(
+    query: &Query,
+    root: Node,
+    content: &str,
+    file_path: &Path,
+    search_paths: &[PathBuf],
+    root_path: &Path,
+) -> Vec<DependencyEdge> {
+    let mut includes = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches_iter = cursor.matches(query, root, content.as_bytes());
+
+    while let Some(mat) = matches_iter.next() {
+        for cap in mat.captures {
+            if query.capture_names()[cap.index as usize] != "path" {
+                continue;
+            }
+
+            let path_node = cap.node;
+            let include_text = &content[path_node.byte_range()];
+
+            let (clean_import, is_quote_include) = if include_text.starts_with('"') {
+                (include_text.trim_matches('"'), true)
+            } else if include_text.starts_with('<') {
+                (include_text.trim_matches(|c| c == '<' || c == '>'), false)
+            } else {
+                // This case should not be reached with the current query
+                warn!("Unexpected include format in {}: {}", file_path.display(), include_text);
+                continue;
+            };
+
+            // Skip empty include paths
+            if clean_import.is_empty() {
+                continue;
+            }
+
+            debug!("Found C++ include '{}' ({}) in '{}'", clean_import, if is_quote_include { "quote" } else { "system" }, file_path.display());
+
+            if let Some(resolved) = resolve_cpp_path(
+                file_path,
+                clean_import,
+                is_quote_include,
+                search_paths,
+                root_path,
+            ) {
+                let conditional = in_preproc_conditional(path_node, content);
+                includes.push(DependencyEdge { conditional, ..edge(resolved, EdgeKind::Include, path_node) });
             }
         }
     }
+    includes
 }
 
-/// Helper function to resolve a relative import/module path to a file path.
-/// Tries appending possible suffixes and checks if the resolved path exists within the root.
-fn resolve_relative_path(
-    parent_dir: &Path,
-    import_str: &str,
-    root_path: &Path,
-    suffixes: &[&str],
-) -> Option<String> {
-    for suffix in suffixes {
-        let candidate = parent_dir.join(format!("{}{}", import_str, suffix)).clean();
-        if candidate.is_file() && candidate.starts_with(root_path) {
-            return Some(candidate.to_string_lossy().to_string());
+/// Whether `node` is inside a preprocessor conditional, not counting include guards.
+fn in_preproc_conditional(node: Node, content: &str) -> bool {
+    let mut current = node;
+    while let Some(parent) = current.parent() {
+        match parent.kind() {
+            "preproc_if" | "preproc_elif" | "preproc_elifdef" | "preproc_else" => return true,
+            "preproc_ifdef" if !is_include_guard(parent, content) => return true,
+            _ => {}
         }
+        current = parent;
     }
-    None
+    false
 }
 
-/// Analyzes JavaScript and TypeScript files for dependencies.
-fn analyze_javascript_typescript(
+/// Whether an `#ifdef`/`#ifndef` block is an include guard: `#ifndef X` followed directly
+/// by `#define X`.
+fn is_include_guard(node: Node, content: &str) -> bool {
+    if !content[node.byte_range()].starts_with("#ifndef") {
+        return false;
+    }
+    let Some(name) = node.child_by_field_name("name") else {
+        return false;
+    };
+    name.next_named_sibling().is_some_and(|define| {
+        define.kind() == "preproc_def"
+            && define
+                .child_by_field_name("name")
+                .is_some_and(|defined| content[defined.byte_range()] == content[name.byte_range()])
+    })
+}
+
+/// Analyzes C/C++ files for dependencies. Includes inside `#if`/`#ifdef` blocks are left
+/// out when `INCLUDE_CONDITIONAL_DEPS` is `false`.
+fn analyze_cpp(
     root_path: &Path,
     files_to_scan: &[String],
     dependency_graph: &mut DependencyGraph,
 ) {
-    let language: Language = tree_sitter_javascript::LANGUAGE.into();
+    let language = CPP.language();
     let mut parser = Parser::new();
     if let Err(e) = parser.set_language(&language) {
-        warn!("Failed to set language for JavaScript: {}. JS/TS dependency analysis will be skipped.", e);
+        warn!("Failed to set language for C++: {}. C++ dependency analysis will be skipped.", e);
         return;
     }
-
-    let query_src = r#"
-(import_statement source: (string (string_fragment) @path))
-(call_expression
-  function: (identifier) @_fn
-  arguments: (arguments (string (string_fragment) @path))
-  (#eq? @_fn "require"))
-"#;
-    let query = match Query::new(&language, query_src) {
+    let query = match Query::new(&language, CPP_INCLUDES_QUERY) {
         Ok(q) => q,
         Err(e) => {
-            warn!("Failed to compile JavaScript tree-sitter query: {}. JS/TS dependency analysis will be skipped.", e);
+            warn!("Failed to compile C++ tree-sitter query: {}. C++ dependency analysis will be skipped.", e);
             return;
         }
     };
+    let cpp_files: Vec<_> = files_to_scan.iter().filter(|file_path_str| is_cpp_file(file_path_str)).collect();
 
-    let js_like_files: Vec<_> = files_to_scan
-        .iter()
-        .filter(|file_path_str| {
-            let file_path = PathBuf::from(file_path_str);
-            file_path.extension().map_or(false, |e| {
-                e == "js" || e == "jsx" || e == "ts" || e == "tsx"
-            })
-        })
-        .collect();
-
-    debug!("Found {} JavaScript/TypeScript files to scan for dependencies.", js_like_files.len());
+    debug!("Found {} C++ files to scan for dependencies.", cpp_files.len());
+    
+    let search_paths = cpp_search_paths(root_path);
 
-    for file_path_str in js_like_files {
+    for file_path_str in cpp_files {
         let file_path = PathBuf::from(file_path_str);
         
-        let content = match fs::read_to_string(&file_path) {
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
             Ok(c) => c,
             Err(_) => continue,
         };
@@ -164,140 +2037,159 @@ fn analyze_javascript_typescript(
             None => continue,
         };
 
-        let mut cursor = QueryCursor::new();
-        let mut matches_iter = cursor.matches(&query, tree.root_node(), content.as_bytes());
-        let mut dependencies = Vec::new();
-
-        while let Some(mat) = matches_iter.next() {
-            for cap in mat.captures {
-                if query.capture_names()[cap.index as usize] != "path" {
-                    continue;
-                }
-
-                let path_node = cap.node;
-                let import_path_str = &content[path_node.byte_range()];
-                let clean_import = import_path_str.trim_matches('"').trim_matches('\'');
-                debug!("Found JS/TS import '{}' in '{}'", clean_import, file_path.display());
-
-                if let Some(parent_dir) = file_path.parent() {
-                    let possible_exts = [
-                        "", ".js", ".jsx", ".ts", ".tsx", "/index.js", "/index.jsx",
-                        "/index.ts", "/index.tsx",
-                    ];
-                    if let Some(resolved) = resolve_relative_path(parent_dir, clean_import, root_path, &possible_exts) {
-                        dependencies.push(resolved);
-                    }
-                }
-            }
-        }
+        let dependencies: Vec<DependencyEdge> = cpp_includes(&query, tree.root_node(), &content, &file_path, &search_paths, root_path)
+            .into_iter()
+            .filter(|include| *INCLUDE_CONDITIONAL_DEPS || !include.conditional)
+            .collect();
 
         if !dependencies.is_empty() {
             dependency_graph
                 .entry(file_path_str.clone())
                 .or_default()
-                .extend(dependencies);
+                .extend(sorted_unique(dependencies));
         }
     }
 }
 
-fn process_python_module(
-    module_str: &str,
-    file_path_str: &String,
-    file_path: &Path,
-    root_path: &Path,
-    dependency_graph: &mut DependencyGraph,
-) {
-    let clean_import = if module_str.starts_with('.') {
-        // Relative import like 'from .foo import ...' or 'from ..foo.bar import ...'
-        let num_dots = module_str.find(|c| c != '.').unwrap_or(module_str.len());
-        let mut path_prefix = String::new();
-        if num_dots > 1 {
-            path_prefix.push_str(&"../".repeat(num_dots - 1));
+/// Collects the includes each C/C++ file only makes inside `#if`/`#ifdef` blocks (include
+/// guards aside), e.g. platform-specific headers. Only files with a `#if` are parsed, and
+/// nothing is collected when `INCLUDE_CONDITIONAL_DEPS` is `false`, since those includes
+/// aren't in the graph then.
+fn analyze_cpp_conditional(root_path: &Path, files_to_scan: &[String]) -> EdgeTargets {
+    let mut conditional = HashMap::new();
+    if !*INCLUDE_CONDITIONAL_DEPS {
+        return conditional;
+    }
+    let language = CPP.language();
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&language) {
+        warn!("Failed to set language for C++: {}. Conditional includes will be skipped.", e);
+        return conditional;
+    }
+    let query = match Query::new(&language, CPP_INCLUDES_QUERY) {
+        Ok(q) => q,
+        Err(e) => {
+            warn!("Failed to compile C++ tree-sitter query: {}. Conditional includes will be skipped.", e);
+            return conditional;
         }
-        let module_part = &module_str[num_dots..];
-        format!("{}{}", path_prefix, module_part.replace('.', "/"))
-    } else {
-        // Absolute import
-        module_str.replace('.', "/")
     };
+    let search_paths = cpp_search_paths(root_path);
 
-    debug!("Found Python import '{}', processed to '{}' in '{}'", module_str, clean_import, file_path.display());
-    if let Some(parent_dir) = file_path.parent() {
-        let possible_exts = [".py", "/__init__.py"];
-        if let Some(resolved) = resolve_relative_path(parent_dir, &clean_import, root_path, &possible_exts) {
-            dependency_graph
-                .entry(file_path_str.clone())
-                .or_insert_with(Vec::new)
-                .push(resolved);
+    for file_path_str in files_to_scan.iter().filter(|file_path_str| is_cpp_file(file_path_str)) {
+        let file_path = Path::new(file_path_str);
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
+            Ok(c) if c.contains("#if") => c,
+            _ => continue,
+        };
+        let Some(tree) = parser.parse(content.as_bytes(), None) else {
+            continue;
+        };
+
+        let includes = cpp_includes(&query, tree.root_node(), &content, file_path, &search_paths, root_path);
+        let unconditional: HashSet<&String> =
+            includes.iter().filter(|include| !include.conditional).map(|include| &include.target).collect();
+        let mut deps: Vec<String> = includes
+            .iter()
+            .filter(|include| include.conditional && !unconditional.contains(&include.target))
+            .map(|include| include.target.clone())
+            .collect();
+        deps.sort_by(|a, b| natord::compare(a, b));
+        deps.dedup();
+        if !deps.is_empty() {
+            debug!("Found {} conditional includes in '{}'", deps.len(), file_path_str);
+            conditional.insert(file_path_str.clone(), deps);
         }
     }
+    conditional
 }
 
-/// Analyzes Python files for dependencies.
-fn analyze_python(
+/// Reads `go.mod` at the root and returns the module path from its `module` directive.
+fn detect_go_module(root: &Path) -> Option<String> {
+    let content = fs::read_to_string(root.join("go.mod")).ok()?;
+    content.lines().find_map(|line| {
+        let rest = line.trim().strip_prefix("module")?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        let module = rest.trim().trim_matches('"');
+        (!module.is_empty()).then(|| module.to_string())
+    })
+}
+
+/// Resolves a Go package directory to the non-test `.go` files it contains.
+fn resolve_go_package(package_dir: &Path, root_path: &Path) -> Vec<String> {
+    if !package_dir.is_dir() || !package_dir.starts_with(root_path) {
+        return Vec::new();
+    }
+    let entries = match fs::read_dir(package_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|e| e == "go")
+                && !path.to_string_lossy().ends_with("_test.go")
+        })
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    files.sort_by(|a, b| natord::compare(a, b));
+    files
+}
+
+/// Analyzes Go files for dependencies.
+fn analyze_go(
     root_path: &Path,
     files_to_scan: &[String],
     dependency_graph: &mut DependencyGraph,
 ) {
-    let language: Language = tree_sitter_python::LANGUAGE.into();
+    let go_files: Vec<_> = files_to_scan
+        .iter()
+        .filter(|file_path_str| PathBuf::from(file_path_str).extension().is_some_and(|e| e == "go"))
+        .collect();
+    if go_files.is_empty() {
+        return;
+    }
+
+    let language = GO.language();
     let mut parser = Parser::new();
     if let Err(e) = parser.set_language(&language) {
-        warn!("Failed to set language for Python: {}. Python dependency analysis will be skipped.", e);
+        warn!("Failed to set language for Go: {}. Go dependency analysis will be skipped.", e);
         return;
     }
-
     let query_src = r#"
-; Pattern 0: import foo
-(import_statement (dotted_name) @module)
-; Pattern 1a: from foo.bar import ...
-(import_from_statement
-  module_name: (dotted_name) @module
-)
-; Pattern 1b: from .foo import ...
-(import_from_statement
-  module_name: (relative_import) @module
-)
-; Pattern 2: from . import foo[, bar, ...]
-(import_from_statement
-  module_name: (relative_import) @dots
-  name: (dotted_name) @name
-  (#match? @dots "^\.+$")
-)
-(import_from_statement
-  module_name: (relative_import) @dots
-  name: (aliased_import
-    name: (dotted_name) @name
-  )
-  (#match? @dots "^\.+$")
-)
-"#;
+(import_spec
+  path: [
+    (interpreted_string_literal) @path
+    (raw_string_literal) @path
+  ]
+)"#;
     let query = match Query::new(&language, query_src) {
         Ok(q) => q,
         Err(e) => {
-            warn!("Failed to compile Python tree-sitter query: {}. Python dependency analysis will be skipped.", e);
+            warn!("Failed to compile Go tree-sitter query: {}. Go dependency analysis will be skipped.", e);
             return;
         }
     };
 
-    let py_files: Vec<_> = files_to_scan
-        .iter()
-        .filter(|file_path_str| {
-            PathBuf::from(file_path_str)
-                .extension()
-                .map_or(false, |e| e == "py")
-        })
-        .collect();
-    
-    debug!("Found {} Python files to scan for dependencies.", py_files.len());
+    let go_module = detect_go_module(root_path);
+    match &go_module {
+        Some(module) => debug!("Detected Go module '{}' in '{}'", module, root_path.display()),
+        None => debug!("No go.mod found in '{}'; only relative Go imports will be resolved.", root_path.display()),
+    }
+
+    debug!("Found {} Go files to scan for dependencies.", go_files.len());
 
-    for file_path_str in py_files {
+    for file_path_str in go_files {
         let file_path = PathBuf::from(file_path_str);
-        
-        let content = match fs::read_to_string(&file_path) {
+
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
             Ok(c) => c,
             Err(_) => continue,
         };
-        
+
         let tree = match parser.parse(content.as_bytes(), None) {
             Some(t) => t,
             None => continue,
@@ -305,81 +2197,179 @@ fn analyze_python(
 
         let mut cursor = QueryCursor::new();
         let mut matches_iter = cursor.matches(&query, tree.root_node(), content.as_bytes());
+        let mut dependencies = Vec::new();
 
         while let Some(mat) = matches_iter.next() {
-            match mat.pattern_index {
-                0 | 1 | 2 => { // import a.b, from a.b import c, from .a import c
-                    for cap in mat.captures {
-                        if query.capture_names()[cap.index as usize] == "module" {
-                            let module_str = &content[cap.node.byte_range()];
-                            process_python_module(module_str, file_path_str, &file_path, root_path, dependency_graph);
-                        }
-                    }
-                },
-                3 | 4 => { // from . import a, from .. import b
-                    let mut dots_opt = None;
-                    let mut names = Vec::new();
-                    for cap in mat.captures {
-                        let cap_name = query.capture_names()[cap.index as usize];
-                        let text = &content[cap.node.byte_range()];
-                        match cap_name {
-                            "dots" => dots_opt = Some(text),
-                            "name" => names.push(text),
-                            _ => {}
-                        }
+            for cap in mat.captures {
+                if query.capture_names()[cap.index as usize] != "path" {
+                    continue;
+                }
+
+                let import_text = &content[cap.node.byte_range()];
+                let clean_import = import_text.trim_matches(|c| c == '"' || c == '`');
+                debug!("Found Go import '{}' in '{}'", clean_import, file_path.display());
+
+                let package_dir = if clean_import.starts_with("./") || clean_import.starts_with("../") {
+                    match file_path.parent() {
+                        Some(parent_dir) => parent_dir.join(clean_import).clean(),
+                        None => continue,
                     }
-                    if let Some(dots) = dots_opt {
-                        for name in names {
-                            let combined_module = format!("{}{}", dots, name);
-                            process_python_module(&combined_module, file_path_str, &file_path, root_path, dependency_graph);
-                        }
+                } else if let Some(module) = &go_module {
+                    match clean_import.strip_prefix(module.as_str()) {
+                        Some("") => root_path.to_path_buf(),
+                        Some(rest) if rest.starts_with('/') => root_path.join(&rest[1..]).clean(),
+                        _ => continue, // Standard library or third-party package
                     }
-                },
-                _ => {} // Unhandled pattern
+                } else {
+                    continue;
+                };
+
+                dependencies.extend(
+                    resolve_go_package(&package_dir, root_path)
+                        .into_iter()
+                        .filter(|dep| dep != file_path_str)
+                        .map(|dep| edge(dep, EdgeKind::Import, cap.node)),
+                );
             }
         }
+
+        if !dependencies.is_empty() {
+            dependency_graph
+                .entry(file_path_str.clone())
+                .or_default()
+                .extend(sorted_unique(dependencies));
+        }
     }
 }
 
+/// Parses `Package.swift` at the root and maps each target name to its source directory.
+///
+/// This is a lightweight scan of the manifest rather than an evaluation of it: it picks up
+/// `.target`, `.executableTarget`, `.testTarget` and `.macro` declarations and honours an
+/// explicit `path:` argument, otherwise using SwiftPM's `Sources/<name>` / `Tests/<name>` defaults.
+fn detect_swift_targets(root: &Path) -> HashMap<String, PathBuf> {
+    let mut targets = HashMap::new();
+    let content = match fs::read_to_string(root.join("Package.swift")) {
+        Ok(c) => c,
+        Err(_) => return targets,
+    };
 
-/// Analyzes Rust files for dependencies.
-fn analyze_rust(
+    for (marker, default_dir) in [
+        (".target(", "Sources"),
+        (".executableTarget(", "Sources"),
+        (".macro(", "Sources"),
+        (".testTarget(", "Tests"),
+    ] {
+        let mut rest = content.as_str();
+        while let Some(start) = rest.find(marker) {
+            let args = &rest[start + marker.len()..];
+            let args = &args[..matching_paren(args).unwrap_or(args.len())];
+            rest = &rest[start + marker.len()..];
+
+            let Some(name) = swift_string_argument(args, "name:") else {
+                continue;
+            };
+            let dir = match swift_string_argument(args, "path:") {
+                Some(path) => root.join(path),
+                None => root.join(default_dir).join(&name),
+            };
+            targets.insert(name, dir.clean());
+        }
+    }
+    targets
+}
+
+/// Returns the index of the `)` closing an argument list that has already been opened.
+fn matching_paren(args: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' if !in_string => depth += 1,
+            ')' | ']' if !in_string => {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Extracts the string literal following a top-level `label` in a Swift argument list.
+fn swift_string_argument(args: &str, label: &str) -> Option<String> {
+    let after = &args[args.find(label)? + label.len()..];
+    let after = after.trim_start().strip_prefix('"')?;
+    Some(after[..after.find('"')?].to_string())
+}
+
+/// Recursively collects the `.swift` files under a target directory.
+fn collect_swift_files(dir: &Path, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_swift_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "swift") {
+            files.push(path.to_string_lossy().to_string());
+        }
+    }
+}
+
+/// Analyzes Swift files for dependencies on local SwiftPM targets.
+fn analyze_swift(
     root_path: &Path,
     files_to_scan: &[String],
     dependency_graph: &mut DependencyGraph,
 ) {
-    let language: Language = tree_sitter_rust::LANGUAGE.into();
+    let swift_files: Vec<_> = files_to_scan
+        .iter()
+        .filter(|file_path_str| PathBuf::from(file_path_str).extension().is_some_and(|e| e == "swift"))
+        .collect();
+    if swift_files.is_empty() {
+        return;
+    }
+
+    let targets = detect_swift_targets(root_path);
+    if targets.is_empty() {
+        debug!("No Package.swift targets found in '{}'; Swift dependency analysis will be skipped.", root_path.display());
+        return;
+    }
+    debug!("Detected {} Swift package targets in '{}'", targets.len(), root_path.display());
+
+    let language = SWIFT.language();
     let mut parser = Parser::new();
     if let Err(e) = parser.set_language(&language) {
-        warn!("Failed to set language for Rust: {}. Rust dependency analysis will be skipped.", e);
+        warn!("Failed to set language for Swift: {}. Swift dependency analysis will be skipped.", e);
         return;
     }
-    let query_src = r#"
-(mod_item name: (identifier) @module)
-(use_declaration argument: [ (identifier) @module (scoped_identifier) @module ])
-"#;
+    let query_src = "(import_declaration (identifier) @module)";
     let query = match Query::new(&language, query_src) {
         Ok(q) => q,
         Err(e) => {
-            warn!("Failed to compile Rust tree-sitter query: {}. Rust dependency analysis will be skipped.", e);
+            warn!("Failed to compile Swift tree-sitter query: {}. Swift dependency analysis will be skipped.", e);
             return;
         }
     };
-    let rs_files: Vec<_> = files_to_scan
-        .iter()
-        .filter(|file_path_str| PathBuf::from(file_path_str).extension().map_or(false, |e| e == "rs"))
-        .collect();
 
-    debug!("Found {} Rust files to scan for dependencies.", rs_files.len());
-    
-    for file_path_str in rs_files {
+    debug!("Found {} Swift files to scan for dependencies.", swift_files.len());
+
+    let mut target_files: HashMap<&str, Vec<String>> = HashMap::new();
+
+    for file_path_str in swift_files {
         let file_path = PathBuf::from(file_path_str);
-        
-        let content = match fs::read_to_string(&file_path) {
+
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
             Ok(c) => c,
             Err(_) => continue,
         };
-        
+
         let tree = match parser.parse(content.as_bytes(), None) {
             Some(t) => t,
             None => continue,
@@ -391,29 +2381,27 @@ fn analyze_rust(
 
         while let Some(mat) = matches_iter.next() {
             for cap in mat.captures {
-                if query.capture_names()[cap.index as usize] != "module" {
-                    continue;
-                }
-                
-                let path_node = cap.node;
-                let module_str = &content[path_node.byte_range()];
-                let mut clean_import = if let Some(stripped) = module_str.strip_prefix("self::") {
-                    stripped.to_string()
-                } else if let Some(stripped) = module_str.strip_prefix("super::") {
-                    format!("../{}", stripped)
-                } else {
-                    module_str.to_string()
-                };
-                clean_import = clean_import.replace("::", "/");
+                // `import Foo.Bar` imports a submodule of `Foo`.
+                let import_text = &content[cap.node.byte_range()];
+                let module = import_text.split('.').next().unwrap_or(import_text).trim();
+                debug!("Found Swift import '{}' in '{}'", import_text, file_path.display());
 
-                debug!("Found Rust module/use '{}', processed to '{}' in '{}'", module_str, clean_import, file_path.display());
-
-                if let Some(parent_dir) = file_path.parent() {
-                    let possible_exts = [".rs", "/mod.rs"];
-                    if let Some(resolved) = resolve_relative_path(parent_dir, &clean_import, root_path, &possible_exts) {
-                        dependencies.push(resolved);
+                let Some((name, dir)) = targets.get_key_value(module) else {
+                    continue; // System framework or external package
+                };
+                let files = target_files.entry(name.as_str()).or_insert_with(|| {
+                    let mut files = Vec::new();
+                    if dir.starts_with(root_path) {
+                        collect_swift_files(dir, &mut files);
                     }
-                }
+                    files
+                });
+                dependencies.extend(
+                    files
+                        .iter()
+                        .filter(|dep| *dep != file_path_str)
+                        .map(|dep| edge(dep.clone(), EdgeKind::Import, cap.node)),
+                );
             }
         }
 
@@ -421,104 +2409,91 @@ fn analyze_rust(
             dependency_graph
                 .entry(file_path_str.clone())
                 .or_default()
-                .extend(dependencies);
+                .extend(sorted_unique(dependencies));
         }
     }
 }
 
-/// Resolves a C++ include path against a set of search directories.
-fn resolve_cpp_path(
-    source_file: &Path,
-    include_str: &str,
-    is_quote_include: bool, // true for "...", false for <...>
-    search_paths: &[PathBuf],
-    root_path: &Path,
-) -> Option<String> {
-    let possible_exts = [
-        "", ".h", ".hpp", ".hxx", ".hh", ".c", ".cpp", ".cc",
-    ];
-
-    let mut dirs_to_search: Vec<PathBuf> = Vec::new();
-
-    // For #include "...", the directory of the current file is searched first.
-    if is_quote_include {
-        if let Some(parent_dir) = source_file.parent() {
-            dirs_to_search.push(parent_dir.to_path_buf());
-        }
-    }
-
-    // Add the provided search paths
-    dirs_to_search.extend(search_paths.iter().cloned());
-
-    for dir in dirs_to_search {
-        for ext in &possible_exts {
-            let path_with_ext = format!("{}{}", include_str, ext);
-            let candidate_path = dir.join(&path_with_ext).clean();
-
-            if candidate_path.is_file() && candidate_path.starts_with(root_path) {
-                return Some(candidate_path.to_string_lossy().to_string());
-            }
+/// Keeps only the R code chunks of an R Markdown document, blanking every other line
+/// so that byte offsets and line numbers still line up with the original file.
+fn extract_rmd_chunks(content: &str) -> String {
+    let mut in_chunk = false;
+    let mut out = String::with_capacity(content.len());
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let is_fence = trimmed.starts_with("```");
+        if is_fence && !in_chunk && trimmed[3..].trim_start().starts_with("{r") {
+            in_chunk = true;
+        } else if is_fence && in_chunk {
+            in_chunk = false;
+        } else if in_chunk {
+            out.push_str(line);
+            continue;
         }
+        out.extend(line.chars().map(|c| if c == '\n' { '\n' } else { ' ' }));
     }
-
-    None
+    out
 }
 
-/// Analyzes C/C++ files for dependencies.
-fn analyze_cpp(
+/// Analyzes R and R Markdown files for `source()` and local `library()`/`require()` dependencies.
+fn analyze_r(
     root_path: &Path,
     files_to_scan: &[String],
     dependency_graph: &mut DependencyGraph,
 ) {
-    let language: Language = tree_sitter_cpp::LANGUAGE.into();
+    let r_files: Vec<_> = files_to_scan
+        .iter()
+        .filter(|file_path_str| {
+            let path_buf = PathBuf::from(file_path_str);
+            let ext = path_buf.extension().and_then(|s| s.to_str());
+            matches!(ext, Some("R" | "r" | "Rmd" | "rmd"))
+        })
+        .collect();
+    if r_files.is_empty() {
+        return;
+    }
+
+    let language = R.language();
     let mut parser = Parser::new();
     if let Err(e) = parser.set_language(&language) {
-        warn!("Failed to set language for C++: {}. C++ dependency analysis will be skipped.", e);
+        warn!("Failed to set language for R: {}. R dependency analysis will be skipped.", e);
         return;
     }
     let query_src = r#"
-(preproc_include
-  path: [
-    (string_literal) @path
-    (system_lib_string) @path
-  ]
-)"#;
+; Pattern 0: source("file.R")
+(call
+  function: (identifier) @_fn
+  arguments: (arguments . (argument value: (string content: (string_content) @path)))
+  (#any-of? @_fn "source" "sys.source"))
+; Pattern 1: library(foo), require("foo")
+(call
+  function: (identifier) @_fn
+  arguments: (arguments . (argument value: [
+    (identifier) @package
+    (string content: (string_content) @package)
+  ]))
+  (#any-of? @_fn "library" "require" "requireNamespace"))
+"#;
     let query = match Query::new(&language, query_src) {
         Ok(q) => q,
         Err(e) => {
-            warn!("Failed to compile C++ tree-sitter query: {}. C++ dependency analysis will be skipped.", e);
+            warn!("Failed to compile R tree-sitter query: {}. R dependency analysis will be skipped.", e);
             return;
         }
     };
-    let cpp_files: Vec<_> = files_to_scan
-        .iter()
-        .filter(|file_path_str| {
-            let path_buf = PathBuf::from(file_path_str);
-            let ext = path_buf.extension().and_then(|s| s.to_str());
-            matches!(ext, Some("cpp" | "c" | "h" | "hpp" | "hxx" | "cc" | "hh"))
-        })
-        .collect();
 
-    debug!("Found {} C++ files to scan for dependencies.", cpp_files.len());
-    
-    // Build a list of common include paths to search.
-    // This is a heuristic since we don't have the build system's configuration.
-    let mut search_paths = vec![root_path.to_path_buf()];
-    for subdir in &["include", "src", "inc"] {
-        let potential_path = root_path.join(subdir);
-        if potential_path.is_dir() {
-            search_paths.push(potential_path);
-        }
-    }
+    debug!("Found {} R files to scan for dependencies.", r_files.len());
 
-    for file_path_str in cpp_files {
+    for file_path_str in r_files {
         let file_path = PathBuf::from(file_path_str);
-        
-        let content = match fs::read_to_string(&file_path) {
+
+        let content = match fs::read_to_string(on_disk_path(file_path_str)) {
             Ok(c) => c,
             Err(_) => continue,
         };
-        
+        let is_rmd = file_path.extension().is_some_and(|e| e.eq_ignore_ascii_case("rmd"));
+        let content = if is_rmd { extract_rmd_chunks(&content) } else { content };
+
         let tree = match parser.parse(content.as_bytes(), None) {
             Some(t) => t,
             None => continue,
@@ -526,53 +2501,385 @@ fn analyze_cpp(
 
         let mut cursor = QueryCursor::new();
         let mut matches_iter = cursor.matches(&query, tree.root_node(), content.as_bytes());
-        let mut dependencies = HashSet::new();
+        let mut dependencies = Vec::new();
 
         while let Some(mat) = matches_iter.next() {
             for cap in mat.captures {
-                if query.capture_names()[cap.index as usize] != "path" {
-                    continue;
-                }
-
-                let path_node = cap.node;
-                let include_text = &content[path_node.byte_range()];
-
-                let (clean_import, is_quote_include) = if include_text.starts_with('"') {
-                    (include_text.trim_matches('"'), true)
-                } else if include_text.starts_with('<') {
-                    (include_text.trim_matches(|c| c == '<' || c == '>'), false)
-                } else {
-                    // This case should not be reached with the current query
-                    warn!("Unexpected include format in {}: {}", file_path.display(), include_text);
-                    continue;
+                let cap_name = query.capture_names()[cap.index as usize];
+                let text = &content[cap.node.byte_range()];
+                let kind = if cap_name == "path" { EdgeKind::Include } else { EdgeKind::Require };
+                let resolved = match cap_name {
+                    "path" => {
+                        debug!("Found R source '{}' in '{}'", text, file_path.display());
+                        // `source()` paths are relative to the working directory, which is usually
+                        // either the script's own directory or the project root.
+                        file_path
+                            .parent()
+                            .and_then(|parent_dir| resolve_relative_path(parent_dir, text, root_path, &[""]))
+                            .or_else(|| resolve_relative_path(root_path, text, root_path, &[""]))
+                    }
+                    "package" => {
+                        debug!("Found R package '{}' in '{}'", text, file_path.display());
+                        // Most packages are installed from CRAN; only local scripts are tracked.
+                        let suffixes = [".R".to_string(), ".r".to_string(), format!("/{}.R", text)];
+                        let suffixes: Vec<&str> = suffixes.iter().map(|s| s.as_str()).collect();
+                        resolve_relative_path(root_path, text, root_path, &suffixes)
+                    }
+                    _ => None,
                 };
-
-                // Skip empty include paths
-                if clean_import.is_empty() {
-                    continue;
-                }
-
-                debug!("Found C++ include '{}' ({}) in '{}'", clean_import, if is_quote_include { "quote" } else { "system" }, file_path.display());
-
-                if let Some(resolved) = resolve_cpp_path(
-                    &file_path,
-                    clean_import,
-                    is_quote_include,
-                    &search_paths,
-                    root_path,
-                ) {
-                    dependencies.insert(resolved);
+                if let Some(resolved) = resolved.filter(|dep| dep != file_path_str) {
+                    dependencies.push(edge(resolved, kind, cap.node));
                 }
             }
         }
 
         if !dependencies.is_empty() {
-            let mut sorted_deps: Vec<_> = dependencies.into_iter().collect();
-            sorted_deps.sort_by(|a, b| natord::compare(a, b));
             dependency_graph
                 .entry(file_path_str.clone())
                 .or_default()
-                .extend(sorted_deps);
+                .extend(sorted_unique(dependencies));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn api(path: &Path) -> String {
+        to_nfc(&to_api_path(path))
+    }
+
+    fn targets(graph: &DependencyGraph, file: &Path) -> Vec<String> {
+        let mut targets: Vec<String> =
+            graph.get(&api(file)).into_iter().flatten().map(|edge| edge.target.clone()).collect();
+        targets.sort();
+        targets
+    }
+
+    /// A flat tree listing `files`, as `collect_files` sees a walked one.
+    fn tree_of(files: &[&Path]) -> IndexMap<String, TreeNode> {
+        files
+            .iter()
+            .map(|file| {
+                let node = TreeNode { node_type: "file".into(), path: api(file), ..Default::default() };
+                (api(file), node)
+            })
+            .collect()
+    }
+
+    /// A graph from (file, dependencies) pairs.
+    fn graph_of(files: &[(&str, &[&str])]) -> DependencyGraph {
+        files
+            .iter()
+            .map(|(file, deps)| {
+                let edges = deps
+                    .iter()
+                    .map(|dep| DependencyEdge { target: dep.to_string(), kind: EdgeKind::Import, conditional: false, line: None })
+                    .collect();
+                (file.to_string(), edges)
+            })
+            .collect()
+    }
+
+    fn strings(files: &[&str]) -> Vec<String> {
+        files.iter().map(|file| file.to_string()).collect()
+    }
+
+    #[test]
+    fn tarjan_finds_each_cycle_as_one_component() {
+        let graph = graph_of(&[
+            ("main", &["a1", "util"]),
+            ("a1", &["a2"]),
+            ("a2", &["a10"]),
+            ("a10", &["a1", "b"]),
+            ("b", &["c"]),
+            ("c", &["b", "util"]),
+            ("self", &["self"]),
+            ("util", &[]),
+        ]);
+        assert_eq!(tarjan_sccs(&graph), vec![strings(&["a1", "a2", "a10"]), strings(&["b", "c"])]);
+        assert!(!is_dag(&graph));
+
+        let graph = graph_of(&[("self", &["self"]), ("main", &["self"])]);
+        assert!(tarjan_sccs(&graph).is_empty());
+        assert!(!is_dag(&graph));
+        assert!(is_dag(&graph_of(&[("main", &["a", "b"]), ("a", &["b"])])));
+    }
+
+    #[test]
+    fn tarjan_handles_chains_deeper_than_the_stack() {
+        let files: Vec<String> = (0..50_000).map(|i| format!("f{}", i)).collect();
+        let mut graph: DependencyGraph = files
+            .windows(2)
+            .map(|pair| {
+                let edge = DependencyEdge { target: pair[1].clone(), kind: EdgeKind::Import, conditional: false, line: None };
+                (pair[0].clone(), vec![edge])
+            })
+            .collect();
+        assert!(tarjan_sccs(&graph).is_empty());
+
+        let back = DependencyEdge { target: files[0].clone(), kind: EdgeKind::Import, conditional: false, line: None };
+        graph.insert(files[files.len() - 1].clone(), vec![back]);
+        let sccs = tarjan_sccs(&graph);
+        assert_eq!(sccs.len(), 1);
+        assert_eq!(sccs[0].len(), files.len());
+    }
+
+    #[test]
+    fn topological_sort_puts_dependencies_first() {
+        let chain = graph_of(&[("app", &["lib"]), ("lib", &["core"])]);
+        assert_eq!(topological_sort(&chain), Ok(strings(&["core", "lib", "app"])));
+
+        // Files that become ready together are ordered naturally.
+        let diamond = graph_of(&[("app", &["left", "right"]), ("left", &["base"]), ("right", &["base"]), ("base", &[])]);
+        assert_eq!(topological_sort(&diamond), Ok(strings(&["base", "left", "right", "app"])));
+
+        let forest = graph_of(&[("b10", &[]), ("b2", &["x"]), ("a", &["x", "x"])]);
+        assert_eq!(topological_sort(&forest), Ok(strings(&["b10", "x", "a", "b2"])));
+        assert_eq!(topological_sort(&DependencyGraph::new()), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn topological_sort_fails_with_every_cycle() {
+        let graph = graph_of(&[
+            ("app", &["a", "leaf"]),
+            ("a", &["b"]),
+            ("b", &["a"]),
+            ("self", &["self"]),
+            ("leaf", &[]),
+        ]);
+        assert_eq!(topological_sort(&graph), Err(vec![strings(&["a", "b"]), strings(&["self"])]));
+    }
+
+    #[test]
+    fn cache_is_kept_outside_the_root_per_disabled_set() {
+        let root = TempDir::new();
+        let cache_dir = TempDir::new();
+        let main = root.write("main.py", "import util\n");
+        let util = root.write("util.py", "");
+        let cache = dependency_cache_path(cache_dir.path(), root.path(), DisabledLanguages::default());
+
+        let (graph, _, _) =
+            analyze_dependencies(root.path(), &tree_of(&[&main, &util]), DisabledLanguages::default(), Some(&cache))
+                .unwrap();
+        assert_eq!(targets(&graph, &main), vec![api(&util)]);
+        assert!(cache.starts_with(cache_dir.path()) && cache.is_file());
+        assert!(!root.path().join(DEPENDENCY_CACHE_FILE).exists());
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 2);
+
+        let python_off: DisabledLanguages = "py".parse().unwrap();
+        assert_ne!(dependency_cache_path(cache_dir.path(), root.path(), python_off), cache);
+        let other_root = TempDir::new();
+        assert_ne!(dependency_cache_path(cache_dir.path(), other_root.path(), DisabledLanguages::default()), cache);
+    }
+
+    #[test]
+    fn cached_analysis_picks_up_edges_to_added_files() {
+        let root = TempDir::new();
+        let cache_dir = TempDir::new();
+        let cache = dependency_cache_path(cache_dir.path(), root.path(), DisabledLanguages::default());
+        let main = root.write("main.py", "import util\n");
+
+        let (graph, _, _) =
+            analyze_dependencies(root.path(), &tree_of(&[&main]), DisabledLanguages::default(), Some(&cache)).unwrap();
+        assert!(targets(&graph, &main).is_empty());
+
+        // main.py is unchanged, but the module it imports now exists.
+        let util = root.write("util.py", "");
+        let (graph, _, _) =
+            analyze_dependencies(root.path(), &tree_of(&[&main, &util]), DisabledLanguages::default(), Some(&cache))
+                .unwrap();
+        assert_eq!(targets(&graph, &main), vec![api(&util)]);
+    }
+
+    #[test]
+    fn incremental_analysis_adds_edges_to_added_files() {
+        let root = TempDir::new();
+        let main = root.write("main.py", "import util\n");
+        let mut analyzer = IncrementalAnalyzer::default();
+        let (graph, _, _) = analyzer.analyze(root.path(), &tree_of(&[&main]), DisabledLanguages::default(), None).unwrap();
+        assert!(targets(&graph, &main).is_empty());
+
+        let util = root.write("util.py", "");
+        let (graph, _, _) =
+            analyzer.analyze(root.path(), &tree_of(&[&main, &util]), DisabledLanguages::default(), None).unwrap();
+        assert_eq!(targets(&graph, &main), vec![api(&util)]);
+    }
+
+    #[test]
+    fn incremental_analysis_reresolves_after_config_changes() {
+        let root = TempDir::new();
+        let go_mod = root.write("go.mod", "module example.com/app\n");
+        let main = root.write("main.go", "package main\n\nimport \"example.com/app/util\"\n");
+        let util = root.write("util/util.go", "package util\n");
+        let files = [go_mod.as_path(), main.as_path(), util.as_path()];
+        let mut analyzer = IncrementalAnalyzer::default();
+        let (graph, _, _) = analyzer.analyze(root.path(), &tree_of(&files), DisabledLanguages::default(), None).unwrap();
+        assert_eq!(targets(&graph, &main), vec![api(&util)]);
+
+        // main.go is unchanged, but its import no longer names this module.
+        fs::write(&go_mod, "module example.com/renamed\n").unwrap();
+        let (graph, _, _) = analyzer.analyze(root.path(), &tree_of(&files), DisabledLanguages::default(), None).unwrap();
+        assert!(targets(&graph, &main).is_empty());
+    }
+
+    #[test]
+    fn files_to_rescan_reparses_everything_on_additions_and_config_changes() {
+        let stamps = |entries: &[(&str, u64)]| -> HashMap<String, u64> {
+            entries.iter().map(|(file, stamp)| (file.to_string(), *stamp)).collect()
+        };
+        let before = stamps(&[("/r/a.rs", 1), ("/r/b.rs", 1), ("/r/Cargo.toml", 1)]);
+
+        let (changed, deleted) = files_to_rescan(&before, &stamps(&[("/r/a.rs", 2), ("/r/Cargo.toml", 1)])).unwrap();
+        assert_eq!(changed, vec!["/r/a.rs".to_string()]);
+        assert_eq!(deleted, HashSet::from(["/r/b.rs".to_string()]));
+
+        let added = stamps(&[("/r/a.rs", 1), ("/r/b.rs", 1), ("/r/c.rs", 1), ("/r/Cargo.toml", 1)]);
+        assert!(files_to_rescan(&before, &added).is_none());
+        let config_changed = stamps(&[("/r/a.rs", 1), ("/r/b.rs", 1), ("/r/Cargo.toml", 2)]);
+        assert!(files_to_rescan(&before, &config_changed).is_none());
+        let config_removed = stamps(&[("/r/a.rs", 1), ("/r/b.rs", 1)]);
+        assert!(files_to_rescan(&before, &config_removed).is_none());
+    }
+
+    #[test]
+    fn swift_imports_resolve_to_package_target_files() {
+        let dir = TempDir::new();
+        dir.write(
+            "Package.swift",
+            r#"let package = Package(
+    name: "App",
+    targets: [
+        .executableTarget(name: "App", dependencies: ["Core", "Utils"]),
+        .target(name: "Core"),
+        .target(name: "Utils", path: "Lib/Utils"),
+        .testTarget(name: "CoreTests", dependencies: ["Core"]),
+    ]
+)
+"#,
+        );
+        let main = dir.write("Sources/App/main.swift", "import Foundation\nimport Core\nimport Utils.Strings\n");
+        let model = dir.write("Sources/Core/Model.swift", "import Foundation\n");
+        let store = dir.write("Sources/Core/Storage/Store.swift", "import Core\n");
+        let strings = dir.write("Lib/Utils/Strings.swift", "");
+        let test = dir.write("Tests/CoreTests/ModelTests.swift", "import XCTest\n@testable import Core\n");
+        let files: Vec<String> = [&main, &model, &store, &strings, &test].iter().map(|f| api(f)).collect();
+
+        let (graph, _, _) = analyze_files(dir.path(), &files, DisabledLanguages::default());
+        let mut expected = vec![api(&model), api(&store), api(&strings)];
+        expected.sort();
+        assert_eq!(targets(&graph, &main), expected);
+        assert_eq!(targets(&graph, &store), vec![api(&model)]);
+        assert_eq!(targets(&graph, &test), vec![api(&model), api(&store)]);
+        assert!(targets(&graph, &model).is_empty());
+    }
+
+    #[test]
+    fn swift_imports_are_ignored_without_a_package_manifest() {
+        let dir = TempDir::new();
+        let main = dir.write("Sources/App/main.swift", "import Core\n");
+        let model = dir.write("Sources/Core/Model.swift", "");
+        let (graph, _, _) = analyze_files(dir.path(), &[api(&main), api(&model)], DisabledLanguages::default());
+        assert!(graph.is_empty());
+    }
+
+    #[test]
+    fn r_source_and_local_library_calls_resolve() {
+        let dir = TempDir::new();
+        let analysis = dir.write("scripts/analysis.R", "source(\"helpers.R\")\nsource(\"R/setup.r\")\nlibrary(mypkg)\nrequire(\"tools\")\nlibrary(ggplot2)\n");
+        let helpers = dir.write("scripts/helpers.R", "");
+        let setup = dir.write("R/setup.r", "");
+        let package = dir.write("mypkg/mypkg.R", "");
+        let tools = dir.write("tools.R", "");
+        let report = dir.write(
+            "report.Rmd",
+            "Run `source(\"tools.R\")` first.\n\n```{r}\nsource(\"scripts/helpers.R\")\n```\n",
+        );
+        let files: Vec<String> =
+            [&analysis, &helpers, &setup, &package, &tools, &report].iter().map(|f| api(f)).collect();
+
+        let (graph, _, _) = analyze_files(dir.path(), &files, DisabledLanguages::default());
+        let mut expected = vec![api(&helpers), api(&setup), api(&package), api(&tools)];
+        expected.sort();
+        assert_eq!(targets(&graph, &analysis), expected);
+        assert_eq!(targets(&graph, &report), vec![api(&helpers)]);
+        for edge in &graph[&api(&analysis)] {
+            let sourced = edge.target == api(&helpers) || edge.target == api(&setup);
+            assert_eq!(edge.kind, if sourced { EdgeKind::Include } else { EdgeKind::Require });
+        }
+    }
+
+    #[test]
+    fn dynamic_imports_are_import_edges() {
+        let dir = TempDir::new();
+        let main = dir.write(
+            "src/main.ts",
+            "async function load() {\n  const { a } = await import(\"./a\");\n  return a;\n}\n\
+             import('./lazy/b.js').then((b) => b.run());\nconst name = \"./c\";\nimport(name);\n",
+        );
+        let a = dir.write("src/a.ts", "");
+        let b = dir.write("src/lazy/b.js", "");
+        let c = dir.write("src/c.ts", "");
+        let files: Vec<String> = [&main, &a, &b, &c].iter().map(|f| api(f)).collect();
+
+        let (graph, _, _) = analyze_files(dir.path(), &files, DisabledLanguages::default());
+        let edges = &graph[&api(&main)];
+        let found: Vec<(&str, EdgeKind, Option<usize>)> =
+            edges.iter().map(|edge| (edge.target.as_str(), edge.kind, edge.line)).collect();
+        assert_eq!(
+            found,
+            vec![(api(&a).as_str(), EdgeKind::Import, Some(2)), (api(&b).as_str(), EdgeKind::Import, Some(5))]
+        );
+    }
+
+    #[test]
+    fn re_export_chains_are_followed_edge_by_edge() {
+        let dir = TempDir::new();
+        let app = dir.write("app.js", "import { User } from \"./lib\";\n");
+        let lib = dir.write("lib/index.ts", "export * from \"./models\";\nexport { format as fmt } from './format';\n");
+        let models = dir.write("lib/models/index.ts", "export { User } from \"./user\";\nexport const local = 1;\n");
+        let format = dir.write("lib/format.ts", "export function format() {}\n");
+        let user = dir.write("lib/models/user.ts", "export class User {}\n");
+        let files: Vec<String> = [&app, &lib, &models, &format, &user].iter().map(|f| api(f)).collect();
+
+        let (graph, _, _) = analyze_files(dir.path(), &files, DisabledLanguages::default());
+        let kinds = |file: &Path| -> Vec<(String, EdgeKind)> {
+            graph[&api(file)].iter().map(|edge| (edge.target.clone(), edge.kind)).collect()
+        };
+        assert_eq!(kinds(&app), vec![(api(&lib), EdgeKind::Import)]);
+        assert_eq!(kinds(&lib), vec![(api(&models), EdgeKind::ReExport), (api(&format), EdgeKind::ReExport)]);
+        assert_eq!(kinds(&models), vec![(api(&user), EdgeKind::ReExport)]);
+        assert!(!graph.contains_key(&api(&user)));
+
+        // The class app.js imports is reachable through the re-export chain.
+        let mut reachable = HashSet::new();
+        let mut pending = vec![api(&app)];
+        while let Some(file) = pending.pop() {
+            for edge in graph.get(&file).into_iter().flatten() {
+                if reachable.insert(edge.target.clone()) {
+                    pending.push(edge.target.clone());
+                }
+            }
         }
+        assert!(reachable.contains(&api(&user)));
+    }
+
+    #[test]
+    fn nfd_named_files_are_read_through_their_nfc_tree_paths() {
+        let dir = TempDir::new();
+        // "café" with a combining acute accent, as macOS stores names.
+        dir.write("cafe\u{301}.py", "import util\n");
+        let util = dir.write("util.py", "");
+        let main = dir.write("main.py", "import caf\u{e9}\n");
+        let nfc = dir.path().join("caf\u{e9}.py");
+        let files = vec![api(&main), api(&nfc), api(&util)];
+
+        let (graph, _, _) = analyze_files(dir.path(), &files, DisabledLanguages::default());
+        assert_eq!(targets(&graph, &main), vec![api(&nfc)]);
+        assert_eq!(targets(&graph, &nfc), vec![api(&util)]);
+        assert!(file_stamp(&api(&nfc)).is_some());
+        assert!(file_mtime(&api(&nfc)).is_some());
     }
 }
\ No newline at end of file
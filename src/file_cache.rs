@@ -0,0 +1,243 @@
+use indexmap::IndexMap;
+use log::debug;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A file's content as read from disk, valid while its modification time and size match.
+struct CachedFile {
+    modified: SystemTime,
+    bytes: Arc<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct CachedFiles {
+    /// Entries by canonical path, least recently used first.
+    files: IndexMap<PathBuf, CachedFile>,
+    bytes: u64,
+}
+
+impl CachedFiles {
+    fn remove(&mut self, path: &Path) {
+        if let Some(file) = self.files.shift_remove(path) {
+            self.bytes -= file.bytes.len() as u64;
+        }
+    }
+}
+
+/// Counters reported by `GET /api/cache/files`.
+#[derive(Serialize)]
+pub struct FileCacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub capacity: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Contents of recently read files, bounded in total bytes (`FILE_CACHE_BYTES`) and evicted
+/// least recently used first. A capacity of 0 disables it.
+pub struct FileCache {
+    capacity: u64,
+    files: Mutex<CachedFiles>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl FileCache {
+    pub fn new(capacity: u64) -> Self {
+        FileCache {
+            capacity,
+            files: Mutex::new(CachedFiles::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the cached content of `path` if the file on disk still has this modification
+    /// time and size, marking it as recently used. Entries of changed files are dropped.
+    pub fn get(&self, path: &Path, modified: SystemTime, size: u64) -> Option<Arc<Vec<u8>>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut cached = self.files.lock().unwrap();
+        let hit = match cached.files.get_index_of(path) {
+            Some(index) => {
+                let file = &cached.files[index];
+                if file.modified == modified && file.bytes.len() as u64 == size {
+                    let bytes = file.bytes.clone();
+                    let last = cached.files.len() - 1;
+                    cached.files.move_index(index, last);
+                    Some(bytes)
+                } else {
+                    debug!("Dropping stale cached content of '{}'", path.display());
+                    cached.remove(path);
+                    None
+                }
+            }
+            None => None,
+        };
+        let counter = if hit.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        hit
+    }
+
+    /// Caches the full content of `path` as of `modified`, evicting the least recently used
+    /// files to make room. Files larger than the whole cache aren't cached, nor is anything
+    /// when the cache is disabled.
+    pub fn insert(&self, path: &Path, modified: SystemTime, bytes: &[u8]) {
+        let size = bytes.len() as u64;
+        if !self.is_enabled() || size > self.capacity {
+            return;
+        }
+        let mut cached = self.files.lock().unwrap();
+        cached.remove(path);
+        while cached.bytes + size > self.capacity {
+            let Some((evicted, file)) = cached.files.shift_remove_index(0) else {
+                break;
+            };
+            debug!("Evicting cached content of '{}'", evicted.display());
+            cached.bytes -= file.bytes.len() as u64;
+        }
+        cached.bytes += size;
+        cached.files.insert(path.to_path_buf(), CachedFile { modified, bytes: Arc::new(bytes.to_vec()) });
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Drops every entry and returns how many there were. Counters are kept.
+    pub fn clear(&self) -> usize {
+        let mut cached = self.files.lock().unwrap();
+        let entries = cached.files.len();
+        *cached = CachedFiles::default();
+        entries
+    }
+
+    pub fn stats(&self) -> FileCacheStats {
+        let cached = self.files.lock().unwrap();
+        FileCacheStats {
+            entries: cached.files.len(),
+            bytes: cached.bytes,
+            capacity: self.capacity,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::{read_file_content, FileContent, Transport};
+    use crate::test_dir::TempDir;
+    use std::fs;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn cached_paths(cache: &FileCache) -> Vec<PathBuf> {
+        cache.files.lock().unwrap().files.keys().cloned().collect()
+    }
+
+    #[test]
+    fn least_recently_used_files_are_evicted_first() {
+        let cache = FileCache::new(10);
+        cache.insert(Path::new("/a"), at(1), b"aaaa");
+        cache.insert(Path::new("/b"), at(1), b"bbb");
+        cache.insert(Path::new("/c"), at(1), b"cc");
+        // Reading /a makes /b the least recently used.
+        assert!(cache.get(Path::new("/a"), at(1), 4).is_some());
+        cache.insert(Path::new("/d"), at(1), b"ddd");
+        assert_eq!(cached_paths(&cache), ["/c", "/a", "/d"].map(PathBuf::from));
+        assert_eq!(cache.stats().bytes, 9);
+
+        // Files larger than the cache are never cached, and evict nothing.
+        cache.insert(Path::new("/huge"), at(1), &[0; 11]);
+        assert_eq!(cached_paths(&cache), ["/c", "/a", "/d"].map(PathBuf::from));
+
+        // Replacing an entry frees its old size first.
+        cache.insert(Path::new("/a"), at(2), b"aaaaa");
+        assert_eq!(cached_paths(&cache), ["/c", "/d", "/a"].map(PathBuf::from));
+        assert_eq!(cache.stats().bytes, 10);
+    }
+
+    #[test]
+    fn changed_files_are_dropped_on_lookup() {
+        let cache = FileCache::new(100);
+        cache.insert(Path::new("/a"), at(1), b"old");
+        assert!(cache.get(Path::new("/a"), at(2), 3).is_none());
+        assert_eq!(cache.stats().entries, 0);
+
+        cache.insert(Path::new("/a"), at(1), b"old");
+        assert!(cache.get(Path::new("/a"), at(1), 4).is_none());
+        assert_eq!(cache.stats().bytes, 0);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (0, 2));
+    }
+
+    #[test]
+    fn clear_drops_entries_but_keeps_counters() {
+        let cache = FileCache::new(100);
+        cache.insert(Path::new("/a"), at(1), b"a");
+        cache.insert(Path::new("/b"), at(1), b"b");
+        assert!(cache.get(Path::new("/a"), at(1), 1).is_some());
+        assert!(cache.get(Path::new("/missing"), at(1), 1).is_none());
+        assert_eq!(cache.clear(), 2);
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.hits, stats.misses), (0, 0, 1, 1));
+    }
+
+    #[test]
+    fn disabled_cache_stores_nothing() {
+        let cache = FileCache::new(0);
+        cache.insert(Path::new("/a"), at(1), b"");
+        assert!(cache.get(Path::new("/a"), at(1), 0).is_none());
+        assert_eq!(cache.stats().entries, 0);
+        assert_eq!(cache.stats().misses, 0);
+    }
+
+    async fn read_text(path: &Path, cache: &FileCache) -> String {
+        match read_file_content(path, Transport::Text, u64::MAX, cache).await.unwrap().content {
+            FileContent::Text(text) => text.content,
+            FileContent::Binary { .. } => panic!("read as binary"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn writes_to_cached_files_are_read_fresh() {
+        let dir = TempDir::new();
+        let path = dir.write("config.json", "{\"v\": 1}");
+        let cache = FileCache::new(1024);
+        assert_eq!(read_text(&path, &cache).await, "{\"v\": 1}");
+        assert_eq!(read_text(&path, &cache).await, "{\"v\": 1}");
+        assert_eq!((cache.stats().hits, cache.stats().misses), (1, 1));
+
+        // Same size, so only the modification time gives the change away.
+        fs::write(&path, "{\"v\": 2}").unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(at(1)).unwrap();
+        assert_eq!(read_text(&path, &cache).await, "{\"v\": 2}");
+
+        fs::write(&path, "{\"v\": 30}").unwrap();
+        fs::File::options().write(true).open(&path).unwrap().set_modified(at(1)).unwrap();
+        assert_eq!(read_text(&path, &cache).await, "{\"v\": 30}");
+        assert_eq!(read_text(&path, &cache).await, "{\"v\": 30}");
+        assert_eq!((cache.stats().hits, cache.stats().misses, cache.stats().entries), (2, 3, 1));
+    }
+
+    #[actix_web::test]
+    async fn partial_reads_are_not_cached() {
+        let dir = TempDir::new();
+        let path = dir.write("big.txt", "0123456789");
+        let cache = FileCache::new(1024);
+        let read = read_file_content(&path, Transport::Text, 4, &cache).await.unwrap();
+        assert!(read.truncated);
+        assert_eq!(cache.stats().entries, 0);
+    }
+}
\ No newline at end of file
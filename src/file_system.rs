@@ -1,67 +1,2227 @@
-use crate::models::TreeNode;
-use crate::utils::natural_compare;
-use ignore::gitignore::Gitignore;
-use log::debug;
-use std::collections::HashMap;
+use crate::dependency_analyzer::DEPENDENCY_CACHE_FILE;
+use crate::file_cache::FileCache;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use crate::models::{Breadcrumb, DirectoryQuery, FileMetadata, FlatNode, LargeFile, LanguageLoc, LocReport, PathStat, TreeNode};
+use crate::utils::{
+    format_size, from_api_path, language_for_path, language_from_shebang, natural_compare, on_disk_path, resolve_unicode_path,
+    to_api_path, to_nfc,
+};
+use futures::stream::{self, StreamExt};
+use globset::{GlobBuilder, GlobMatcher, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use log::{debug, warn};
+use memmap2::Mmap;
+use serde::Serialize;
+use serde_json::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
 use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs as tokio_fs;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt};
+use xxhash_rust::xxh3::xxh3_128;
 
+/// Maximum number of files read concurrently when computing size estimates.
+const ESTIMATE_CONCURRENCY: usize = 32;
+/// Files larger than this are not read when computing size estimates.
+const ESTIMATE_MAX_FILE_SIZE: u64 = 5 * 1024 * 1024;
+
+/// Heavyweight directories skipped by default, extendable via the `DEFAULT_EXCLUDES` env var.
+const BUILTIN_DEFAULT_EXCLUDES: &[&str] = &[
+    ".git", "node_modules", "target", "venv", ".venv", "__pycache__", "dist",
+];
+
+lazy_static! {
+    static ref DEFAULT_EXCLUDES: Vec<String> = {
+        let mut excludes: Vec<String> = BUILTIN_DEFAULT_EXCLUDES.iter().map(|s| s.to_string()).collect();
+        if let Ok(extra) = env::var("DEFAULT_EXCLUDES") {
+            excludes.extend(extra.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()));
+        }
+        excludes
+    };
+}
+
+/// Upper bound on threads used to list directories in parallel.
+const MAX_WALK_THREADS: usize = 16;
+/// Number of queued directories listed per parallel batch.
+const WALK_BATCH_SIZE: usize = 256;
+
+/// Default cap on the number of entries returned in a tree.
+const DEFAULT_MAX_ENTRIES: usize = 50_000;
+
+/// Entries that never appear in the tree, regardless of options.
+fn is_always_excluded(name: &str) -> bool {
+    name == ".git" || name == DEPENDENCY_CACHE_FILE
+}
+
+/// Whether `name` is on the default exclude list (or always excluded).
+pub fn is_excluded_by_default(name: &str) -> bool {
+    is_always_excluded(name) || DEFAULT_EXCLUDES.iter().any(|s| s == name)
+}
+
+/// Extensions of formats that are always binary, used to flag files without reading them.
+const BINARY_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "webp", "tiff", "psd", "pdf", "zip", "gz", "tgz",
+    "bz2", "xz", "7z", "rar", "tar", "jar", "war", "wasm", "so", "dylib", "dll", "exe", "o", "a",
+    "lib", "class", "pyc", "pyo", "bin", "dat", "db", "sqlite", "woff", "woff2", "ttf", "otf",
+    "eot", "mp3", "mp4", "wav", "ogg", "flac", "mov", "avi", "mkv", "webm",
+];
+/// Number of leading bytes sniffed when detecting binary files by content.
+const BINARY_SNIFF_BYTES: usize = 8192;
+/// Size of the blocks `tail_file` reads backwards from the end of a file.
+const TAIL_BLOCK_BYTES: u64 = 64 * 1024;
+/// Text files `/api/file` sends at least this much of are mapped and streamed, not read.
+const MMAP_THRESHOLD_BYTES: u64 = 8 * 1024 * 1024;
+/// Bytes of a mapped file sent per chunk of a streamed response.
+const MMAP_CHUNK_BYTES: usize = 1024 * 1024;
+
+/// Compiles a comma-separated glob list into a set. Commas inside `{a,b}` alternations
+/// do not split the list.
+fn glob_set(list: &str) -> Result<GlobSet, String> {
+    let mut patterns = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in list.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                patterns.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    patterns.push(&list[start..]);
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns.into_iter().map(str::trim).filter(|p| !p.is_empty()) {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("Invalid glob '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder.build().map_err(|e| format!("Invalid glob list '{}': {}", list, e))
+}
+
+/// Dot-prefixed entries that stay visible when hidden files are hidden, unless overridden.
+const DEFAULT_ALWAYS_SHOW: &[&str] = &[".gitignore", ".github"];
+
+/// Options controlling which entries `build_tree` includes.
+pub struct TreeOptions {
+    pub hide_hidden: bool,
+    pub always_show: Vec<String>,
+    pub default_excludes: bool,
+    /// Lower-cased file extensions to keep, without the leading dot. Empty keeps all.
+    pub extensions: Vec<String>,
+    /// Glob matched against file paths relative to the root.
+    pub file_match: Option<GlobMatcher>,
+    /// Globs of which file paths relative to the root must match at least one.
+    pub include_globs: Option<GlobSet>,
+    /// Globs whose matching files are left out, on top of gitignore rules.
+    pub exclude_globs: Option<GlobSet>,
+    /// Maximum number of entries to list before the tree is truncated.
+    pub max_entries: usize,
+    /// Whether to roll up file counts and sizes onto folder nodes.
+    pub stats: bool,
+    /// How (if at all) files are flagged as binary.
+    pub detect_binary: BinaryDetection,
+    /// Number of levels to walk; folders below it are listed as truncated. `None` walks all.
+    pub max_depth: Option<usize>,
+}
+
+/// How `build_tree` decides whether a file is binary (`detect_binary` query parameter).
+#[derive(Clone, Copy, PartialEq)]
+pub enum BinaryDetection {
+    /// Files are not flagged (`false`, the default).
+    Off,
+    /// Known binary extensions only, without reading files (`extension`).
+    Extension,
+    /// Known binary extensions, then NUL bytes or invalid UTF-8 in the first 8KB (`true`).
+    Content,
+}
+
+impl BinaryDetection {
+    fn from_param(param: Option<&str>) -> Result<Self, String> {
+        match param.map(str::trim) {
+            None | Some("false") => Ok(BinaryDetection::Off),
+            Some("extension") => Ok(BinaryDetection::Extension),
+            Some("true") => Ok(BinaryDetection::Content),
+            Some(other) => Err(format!(
+                "Invalid detect_binary '{}': expected true, false or extension",
+                other
+            )),
+        }
+    }
+
+    /// Whether the file at `path` is binary, or `None` when detection is off.
+    fn check(self, path: &Path) -> Option<bool> {
+        match self {
+            BinaryDetection::Off => None,
+            BinaryDetection::Extension => Some(has_binary_extension(path)),
+            BinaryDetection::Content => Some(has_binary_extension(path) || sniff_binary(path)),
+        }
+    }
+}
+
+fn has_binary_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| BINARY_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Reads the start of the file and looks for NUL bytes or invalid UTF-8. A multi-byte
+/// character cut off by the read limit doesn't count. Unreadable files are not flagged.
+fn sniff_binary(path: &Path) -> bool {
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    let read = fs::File::open(path)
+        .and_then(|file| file.take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head));
+    read.is_ok() && is_binary_head(&head)
+}
+
+/// Whether the first (up to) `BINARY_SNIFF_BYTES` of a file look binary.
+fn is_binary_head(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => false,
+        Err(e) => e.error_len().is_some() || head.len() < BINARY_SNIFF_BYTES,
+    }
+}
+
+/// The language tag of a file, from its extension or else from the shebang on the first
+/// line of `head`, the start of the file.
+pub fn file_language(path: &Path, head: &[u8]) -> Option<&'static str> {
+    let language = language_for_path(&path.to_string_lossy());
+    if !language.is_empty() {
+        return Some(language);
+    }
+    if is_binary_head(head) {
+        return None;
+    }
+    let first_line = head.split(|&b| b == b'\n').next().unwrap_or_default();
+    language_from_shebang(&String::from_utf8_lossy(first_line))
+}
+
+/// Keeps the files whose language tag (see `file_language`) is one of `languages`, with
+/// `jsx` and `tsx` files also counting as `javascript` and `typescript`. Only files
+/// without a known extension are opened, to look for a shebang. Blocking.
+pub fn files_of_languages(files: Vec<String>, languages: &[String]) -> Vec<String> {
+    files
+        .into_iter()
+        .filter(|file| {
+            let mut language = language_for_path(file);
+            if language.is_empty() {
+                let path = from_api_path(file);
+                let path = resolve_unicode_path(&path).unwrap_or(path);
+                let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+                let read = fs::File::open(&path)
+                    .and_then(|file| file.take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head));
+                if read.is_err() {
+                    return false;
+                }
+                language = file_language(&path, &head).unwrap_or_default();
+            }
+            let family = match language {
+                "jsx" => "javascript",
+                "tsx" => "typescript",
+                other => other,
+            };
+            !language.is_empty() && languages.iter().any(|wanted| wanted == language || wanted == family)
+        })
+        .collect()
+}
+
+/// Stats a file for `/api/file/metadata` without loading it: binary detection and the
+/// shebang only need its start, and lines are counted in a streaming pass (for text files
+/// up to `max_line_count_bytes`).
+pub async fn file_metadata(path: &Path, max_line_count_bytes: u64) -> io::Result<FileMetadata> {
+    let metadata = tokio_fs::metadata(path).await?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a file"));
+    }
+    let modified = epoch_millis(metadata.modified());
+
+    let mut file = tokio_fs::File::open(path).await?;
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    (&mut file).take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head).await?;
+    let is_binary = has_binary_extension(path) || is_binary_head(&head);
+
+    let line_count = if is_binary || metadata.len() > max_line_count_bytes {
+        None
+    } else {
+        Some(count_lines(&head, file).await?)
+    };
+
+    let language = file_language(path, &head).unwrap_or_default();
+
+    Ok(FileMetadata {
+        path: to_nfc(&to_api_path(path)),
+        size: metadata.len(),
+        modified,
+        is_binary,
+        line_count,
+        language: (!language.is_empty()).then(|| language.to_string()),
+        permissions: unix_permissions(&metadata),
+    })
+}
+
+/// Counts the lines of a file whose first bytes (`head`) were already read from `rest`.
+/// A final line without a trailing newline counts too.
+async fn count_lines(head: &[u8], rest: tokio_fs::File) -> io::Result<usize> {
+    let mut lines = head.iter().filter(|&&b| b == b'\n').count();
+    let mut last = head.last().copied();
+    let mut reader = tokio::io::BufReader::new(rest);
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            break;
+        }
+        lines += buf.iter().filter(|&&b| b == b'\n').count();
+        last = buf.last().copied();
+        let len = buf.len();
+        reader.consume(len);
+    }
+    if last.is_some_and(|b| b != b'\n') {
+        lines += 1;
+    }
+    Ok(lines)
+}
+
+/// Stats a path for `/api/stat`. `requested` is the path as the client gave it, used to tell
+/// whether it is a symlink, and `path` is where it resolves to. Lines are counted for text
+/// files up to `max_line_count_bytes`, and `hash` streams files through BLAKE3.
+pub async fn stat_path(requested: &Path, path: &Path, max_line_count_bytes: u64, hash: bool) -> io::Result<PathStat> {
+    let is_symlink = tokio_fs::symlink_metadata(requested).await.is_ok_and(|m| m.file_type().is_symlink());
+    let metadata = tokio_fs::metadata(path).await?;
+    let mut stat = PathStat {
+        path: to_nfc(&to_api_path(path)),
+        size: metadata.len(),
+        modified: epoch_millis(metadata.modified()),
+        created: epoch_millis(metadata.created()),
+        is_dir: metadata.is_dir(),
+        is_symlink,
+        mime: None,
+        line_count: None,
+        hash: None,
+    };
+    if !metadata.is_file() {
+        return Ok(stat);
+    }
+    stat.mime = Some(mime_guess::from_path(path).first_or_octet_stream().to_string());
+
+    let mut file = tokio_fs::File::open(path).await?;
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    (&mut file).take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head).await?;
+    if !has_binary_extension(path) && !is_binary_head(&head) && metadata.len() <= max_line_count_bytes {
+        stat.line_count = Some(count_lines(&head, file).await?);
+    }
+    if hash {
+        stat.hash = Some(blake3_file(path).await?);
+    }
+    Ok(stat)
+}
+
+/// Hex BLAKE3 digest of a file, read in blocks rather than loaded whole.
+pub async fn blake3_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    digest_file(path, |block| {
+        hasher.update(block);
+    })
+    .await?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Feeds a file to `update` in blocks, so it is hashed without being loaded whole.
+async fn digest_file(path: &Path, update: impl FnMut(&[u8])) -> io::Result<()> {
+    digest_reader(tokio_fs::File::open(path).await?, update).await
+}
+
+/// Feeds the rest of an open file to `update` in blocks.
+async fn digest_reader(file: tokio_fs::File, mut update: impl FnMut(&[u8])) -> io::Result<()> {
+    let mut reader = tokio::io::BufReader::with_capacity(64 * 1024, file);
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            return Ok(());
+        }
+        update(buf);
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+/// Digests `/api/file/checksum` can compute (the `algorithm` parameter).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    #[default]
+    Sha256,
+    Sha1,
+    Blake3,
+}
+
+impl ChecksumAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "sha256",
+            ChecksumAlgorithm::Sha1 => "sha1",
+            ChecksumAlgorithm::Blake3 => "blake3",
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "sha256" | "sha-256" => Ok(ChecksumAlgorithm::Sha256),
+            "sha1" | "sha-1" => Ok(ChecksumAlgorithm::Sha1),
+            "blake3" => Ok(ChecksumAlgorithm::Blake3),
+            other => Err(format!("Invalid algorithm '{}': expected 'sha256', 'sha1' or 'blake3'", other)),
+        }
+    }
+}
+
+/// Hex digest of a file with the given algorithm, read in blocks rather than loaded whole.
+pub async fn checksum_file(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<String> {
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    match algorithm {
+        ChecksumAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            digest_file(path, |block| hasher.update(block)).await?;
+            Ok(hex(&hasher.finalize()))
+        }
+        ChecksumAlgorithm::Sha1 => {
+            let mut hasher = Sha1::new();
+            digest_file(path, |block| hasher.update(block)).await?;
+            Ok(hex(&hasher.finalize()))
+        }
+        ChecksumAlgorithm::Blake3 => blake3_file(path).await,
+    }
+}
+
+/// Reads a file as of a Git revision with `git show <rev>:./<file>`, run in the file's
+/// directory. Revisions starting with `-` are rejected so they can't pass options. Blocking.
+pub fn git_show(path: &Path, rev: &str) -> Result<String, String> {
+    if rev.is_empty() || rev.starts_with('-') {
+        return Err(format!("Invalid revision '{}'", rev));
+    }
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(format!("Not a file: {}", path.display()));
+    };
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .arg("show")
+        .arg(format!("{}:./{}", rev, name.to_string_lossy()))
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("git show failed: {}", stderr.trim()));
+    }
+    Ok(decode_text(output.stdout).content)
+}
+
+/// Suffixes of the siblings new content is staged in and `write_files_atomically` moves
+/// replaced files aside to.
+const WRITE_TMP_SUFFIX: &str = ".repoprompt.tmp";
+const WRITE_BACKUP_SUFFIX: &str = ".repoprompt.bak";
+
+/// A hidden sibling of `path` with a random component, so concurrent writes to the same
+/// file never share one.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let nonce = uuid::Uuid::new_v4().simple().to_string();
+    path.with_file_name(format!(".{}.{}{}", name, &nonce[..12], suffix))
+}
+
+/// Writes `content` to a fresh `.tmp` sibling of `path`, with the permissions of the file it
+/// will replace, and syncs it to disk. The sibling is created with `create_new`, so an
+/// existing file or symlink in its place is never written through. Returns the sibling; on
+/// failure it is removed.
+fn stage_write(path: &Path, content: &[u8]) -> io::Result<PathBuf> {
+    let tmp = sibling(path, WRITE_TMP_SUFFIX);
+    let created = fs::OpenOptions::new().write(true).create_new(true).open(&tmp);
+    let created_here = created.is_ok();
+    let written = created.and_then(|mut file| {
+        io::Write::write_all(&mut file, content)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()
+    });
+    match written {
+        Ok(()) => Ok(tmp),
+        Err(e) if !created_here => Err(e),
+        Err(e) => {
+            if let Err(e) = fs::remove_file(&tmp) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to remove '{}': {}", tmp.display(), e);
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Replaces a file's content atomically: the new content is staged in a `.tmp` sibling and
+/// renamed over the file, so readers see either the old content or the new. Blocking.
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let tmp = stage_write(path, content)?;
+    fs::rename(&tmp, path).inspect_err(|_| {
+        if let Err(e) = fs::remove_file(&tmp) {
+            warn!("Failed to remove '{}': {}", tmp.display(), e);
+        }
+    })
+}
+
+/// Writes several files so that either all of them get their new content or none do. Each
+/// file is first written and synced to a `.tmp` sibling; only once every one of them is on
+/// disk are they renamed into place, with the files they replace moved aside until the
+/// end. If anything fails, the renames done so far are rolled back and the originals
+/// restored. Returns the file that failed and why. Blocking.
+pub fn write_files_atomically(files: &[(PathBuf, String)]) -> Result<(), (PathBuf, io::Error)> {
+    write_files_with(files, |from, to| fs::rename(from, to))
+}
+
+/// `write_files_atomically`, moving each staged file into place with `commit`.
+fn write_files_with(
+    files: &[(PathBuf, String)],
+    commit: impl Fn(&Path, &Path) -> io::Result<()>,
+) -> Result<(), (PathBuf, io::Error)> {
+    fn remove_all(paths: &[PathBuf]) {
+        for path in paths {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => warn!("Failed to remove '{}': {}", path.display(), e),
+                _ => {}
+            }
+        }
+    }
+
+    let mut staged = Vec::with_capacity(files.len());
+    for (path, content) in files {
+        match stage_write(path, content.as_bytes()) {
+            Ok(tmp) => staged.push(tmp),
+            Err(e) => {
+                remove_all(&staged);
+                return Err((path.clone(), e));
+            }
+        }
+    }
+
+    // (target, backup of the file it replaced) for every rename done so far.
+    let mut done: Vec<(&Path, Option<PathBuf>)> = Vec::with_capacity(files.len());
+    for (i, (path, _)) in files.iter().enumerate() {
+        let backup = sibling(path, WRITE_BACKUP_SUFFIX);
+        let result = match fs::symlink_metadata(path) {
+            Ok(_) => fs::rename(path, &backup).map(|()| Some(backup)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+        .and_then(|backup| match commit(&staged[i], path) {
+            Ok(()) => Ok(backup),
+            Err(e) => {
+                if let Some(backup) = &backup {
+                    if let Err(e) = fs::rename(backup, path) {
+                        warn!("Failed to restore '{}': {}", path.display(), e);
+                    }
+                }
+                Err(e)
+            }
+        });
+        match result {
+            Ok(backup) => done.push((path, backup)),
+            Err(e) => {
+                warn!("Rolling back {} files after failing to write '{}': {}", done.len(), path.display(), e);
+                for (target, backup) in done.into_iter().rev() {
+                    let restored = match backup {
+                        Some(backup) => fs::rename(backup, target),
+                        None => fs::remove_file(target),
+                    };
+                    if let Err(e) = restored {
+                        warn!("Failed to roll back '{}': {}", target.display(), e);
+                    }
+                }
+                remove_all(&staged[i..]);
+                return Err((path.clone(), e));
+            }
+        }
+    }
+    remove_all(&done.into_iter().filter_map(|(_, backup)| backup).collect::<Vec<_>>());
+    Ok(())
+}
+
+/// Moves a file or directory to another filesystem, where `rename` can't: it is copied
+/// over, symlinks as symlinks, and then deleted. An empty directory at `to` is replaced, as
+/// `rename` would. If the copy fails, what was copied is removed again. Blocking.
+pub fn move_across_devices(from: &Path, to: &Path) -> io::Result<()> {
+    fn copy_recursively(from: &Path, to: &Path) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(from)?;
+        if metadata.file_type().is_symlink() {
+            return copy_symlink(from, to);
+        }
+        if !metadata.is_dir() {
+            return fs::copy(from, to).map(|_| ());
+        }
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        // Only now, in case the directory is read-only.
+        fs::set_permissions(to, metadata.permissions())
+    }
+
+    if fs::symlink_metadata(to).is_ok_and(|metadata| metadata.is_dir()) {
+        fs::remove_dir(to)?;
+    }
+    if let Err(e) = copy_recursively(from, to) {
+        let cleanup = match fs::symlink_metadata(to) {
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(to),
+            Ok(_) => fs::remove_file(to),
+            Err(_) => Ok(()),
+        };
+        if let Err(cleanup_error) = cleanup {
+            warn!("Failed to remove partial copy '{}': {}", to.display(), cleanup_error);
+        }
+        return Err(e);
+    }
+    if fs::symlink_metadata(from)?.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+/// A weak ETag for a file's current version, from its size and modification time, so it can
+/// be compared without reading the file.
+pub fn file_etag(metadata: &fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    format!("{:x}-{:x}", metadata.len(), modified)
+}
+
+/// A filesystem timestamp in milliseconds since the Unix epoch, if the platform has it.
+fn epoch_millis(time: io::Result<SystemTime>) -> Option<u64> {
+    time.ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+}
+
+#[cfg(unix)]
+fn unix_permissions(metadata: &fs::Metadata) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(format!("{:o}", metadata.permissions().mode() & 0o7777))
+}
+
+#[cfg(not(unix))]
+fn unix_permissions(_metadata: &fs::Metadata) -> Option<String> {
+    None
+}
+
+/// Non-fatal findings collected while walking the tree.
+#[derive(Default)]
+pub struct TreeReport {
+    /// Directories that could not be read.
+    pub warnings: Vec<String>,
+    /// Paths skipped because they matched a default exclude.
+    pub pruned: Vec<String>,
+    /// Number of nodes emitted in the tree.
+    pub entries: usize,
+    /// Whether the walk stopped early because it hit `max_entries`.
+    pub truncated: bool,
+}
+
+impl TreeOptions {
+    /// Builds tree options from query parameters. Fails if `match` or any of
+    /// `include_globs`/`exclude_globs` is not a valid glob.
+    pub fn from_query(query: &DirectoryQuery) -> Result<Self, String> {
+        let hide_hidden = query.hide_hidden.unwrap_or(false) || !query.show_hidden.unwrap_or(true);
+        let always_show = match &query.always_show {
+            Some(list) => list
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            None => DEFAULT_ALWAYS_SHOW.iter().map(|s| s.to_string()).collect(),
+        };
+        let extensions = query
+            .extensions
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(|s| s.trim().trim_start_matches('.').to_ascii_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        let file_match = match &query.file_match {
+            Some(pattern) => Some(
+                GlobBuilder::new(pattern)
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|e| format!("Invalid match glob '{}': {}", pattern, e))?
+                    .compile_matcher(),
+            ),
+            None => None,
+        };
+        let include_globs = query.include_globs.as_deref().map(glob_set).transpose()?;
+        let exclude_globs = query.exclude_globs.as_deref().map(glob_set).transpose()?;
+        Ok(TreeOptions {
+            hide_hidden,
+            always_show,
+            default_excludes: query.default_excludes.unwrap_or(true),
+            extensions,
+            file_match,
+            include_globs,
+            exclude_globs,
+            max_entries: query.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES),
+            stats: query.stats.unwrap_or(false),
+            detect_binary: BinaryDetection::from_param(query.detect_binary.as_deref())?,
+            max_depth: query.depth,
+        })
+    }
+
+    /// Whether file filters are active, in which case folders left empty are pruned.
+    fn filters_files(&self) -> bool {
+        !self.extensions.is_empty() || self.file_match.is_some() || self.include_globs.is_some()
+    }
+
+    fn keeps_file(&self, path: &Path, root: &Path) -> bool {
+        if !self.extensions.is_empty() {
+            let ext = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase())
+                .unwrap_or_default();
+            if !self.extensions.contains(&ext) {
+                return false;
+            }
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        if let Some(matcher) = &self.file_match {
+            if !matcher.is_match(relative) {
+                return false;
+            }
+        }
+        if let Some(include) = &self.include_globs {
+            if !include.is_match(relative) {
+                return false;
+            }
+        }
+        !self.exclude_globs.as_ref().is_some_and(|exclude| exclude.is_match(relative))
+    }
+
+    fn is_hidden(&self, name: &str) -> bool {
+        self.hide_hidden && name.starts_with('.') && !self.always_show.iter().any(|s| s == name)
+    }
+
+    /// `.git` and the dependency cache are always excluded; the rest of the default
+    /// list only when enabled.
+    fn is_default_excluded(&self, name: &str) -> bool {
+        if self.default_excludes {
+            is_excluded_by_default(name)
+        } else {
+            is_always_excluded(name)
+        }
+    }
+}
+
+/// Returns the file size if `path` is larger than `limit` bytes.
+/// Stat failures are ignored here and left for the subsequent read to report.
+pub async fn oversized_file(path: impl AsRef<Path>, limit: u64) -> Option<u64> {
+    let size = tokio_fs::metadata(path).await.ok()?.len();
+    (size > limit).then_some(size)
+}
+
+pub fn file_too_large_message(size: u64, limit: u64) -> String {
+    format!("File too large: {} exceeds limit of {}", format_size(size), format_size(limit))
+}
+
+/// Text decoded to UTF-8.
+pub struct DecodedText {
+    pub content: String,
+    /// The encoding the text was read as, e.g. `utf-8` or `Shift_JIS`.
+    pub encoding: &'static str,
+    /// Whether malformed byte sequences were replaced with U+FFFD.
+    pub lossy: bool,
+}
+
+/// Reads a text file as UTF-8, decoding it from another encoding if it isn't valid UTF-8.
+pub async fn read_text_file(path: impl AsRef<Path>) -> io::Result<DecodedText> {
+    Ok(decode_text(tokio_fs::read(path).await?))
+}
+
+fn decode_text(bytes: Vec<u8>) -> DecodedText {
+    match String::from_utf8(bytes) {
+        Ok(content) => DecodedText { content, encoding: "utf-8", lossy: false },
+        Err(e) => decode_non_utf8(e.as_bytes()),
+    }
+}
+
+/// How `/api/file` and `/api/files` send content (the `as` parameter).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Transport {
+    /// Text for text files, base64 for binary ones.
+    #[default]
+    Auto,
+    Text,
+    Base64,
+}
+
+impl FromStr for Transport {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auto" => Ok(Transport::Auto),
+            "text" => Ok(Transport::Text),
+            "base64" => Ok(Transport::Base64),
+            other => Err(format!("Invalid 'as' value '{}': expected 'auto', 'text' or 'base64'", other)),
+        }
+    }
+}
+
+/// A file's content as sent to clients.
+pub enum FileContent {
+    Text(DecodedText),
+    /// Raw bytes, sent base64-encoded with the MIME type guessed from the extension.
+    Binary { bytes: Vec<u8>, mime: String },
+}
+
+/// A file read by the file content endpoints, possibly cut short.
+pub struct FileRead {
+    pub content: FileContent,
+    /// The file's full size in bytes.
+    pub size: u64,
+    /// Whether only the start of the file was read.
+    pub truncated: bool,
+}
+
+/// Reads up to `max_bytes` of a file for the file content endpoints, so that huge files are
+/// never loaded whole. Files still unchanged in `cache` aren't read again, and files read
+/// whole are added to it. In `Auto` mode files with a binary extension, or with NUL bytes near
+/// the start and no UTF-16 byte order mark, are sent as binary; other files are decoded as
+/// text, which never fails thanks to the Latin-1 fallback.
+pub async fn read_file_content(
+    path: &Path,
+    transport: Transport,
+    max_bytes: u64,
+    cache: &FileCache,
+) -> io::Result<FileRead> {
+    read_content(path, transport, max_bytes, cache, None).await
+}
+
+/// `read_file_content`, also returning the hex BLAKE3 digest of the whole file. The digest
+/// is computed from the same read as the content (the rest of a truncated file is hashed
+/// without being kept), so it always describes the content returned.
+pub async fn read_hashed_file_content(
+    path: &Path,
+    transport: Transport,
+    max_bytes: u64,
+    cache: &FileCache,
+) -> io::Result<(FileRead, String)> {
+    let mut hasher = blake3::Hasher::new();
+    let read = read_content(path, transport, max_bytes, cache, Some(&mut hasher)).await?;
+    Ok((read, hasher.finalize().to_hex().to_string()))
+}
+
+async fn read_content(
+    path: &Path,
+    transport: Transport,
+    max_bytes: u64,
+    cache: &FileCache,
+    hasher: Option<&mut blake3::Hasher>,
+) -> io::Result<FileRead> {
+    let file = tokio_fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    let size = metadata.len();
+    let modified = metadata.modified().ok().filter(|_| cache.is_enabled());
+    let cached = modified.and_then(|modified| cache.get(path, modified, size));
+    let mut bytes = match cached {
+        Some(cached) => {
+            if let Some(hasher) = hasher {
+                hasher.update(&cached);
+            }
+            cached[..cached.len().min(max_bytes as usize)].to_vec()
+        }
+        None => {
+            let mut bytes = Vec::with_capacity(size.min(max_bytes) as usize);
+            let mut reader = file.take(max_bytes);
+            reader.read_to_end(&mut bytes).await?;
+            if let Some(hasher) = hasher {
+                hasher.update(&bytes);
+                digest_reader(reader.into_inner(), |block| {
+                    hasher.update(block);
+                })
+                .await?;
+            }
+            if let Some(modified) = modified.filter(|_| bytes.len() as u64 == size) {
+                cache.insert(path, modified, &bytes);
+            }
+            bytes
+        }
+    };
+    let truncated = size > bytes.len() as u64;
+
+    let binary = match transport {
+        Transport::Text => false,
+        Transport::Base64 => true,
+        Transport::Auto => {
+            let head = &bytes[..bytes.len().min(BINARY_SNIFF_BYTES)];
+            has_binary_extension(path) || (Encoding::for_bom(head).is_none() && head.contains(&0))
+        }
+    };
+    let content = if binary {
+        let mime = mime_guess::from_path(path).first_or_octet_stream().to_string();
+        FileContent::Binary { bytes, mime }
+    } else {
+        if truncated {
+            // Don't let a character cut in half at the limit look like a legacy encoding.
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+        }
+        FileContent::Text(decode_text(bytes))
+    };
+    Ok(FileRead { content, size, truncated })
+}
+
+/// The first `len` bytes of a large UTF-8 file, memory-mapped so it can be streamed without
+/// holding a copy of the content.
+pub struct MappedText {
+    map: Mmap,
+    len: usize,
+    /// The file's full size in bytes.
+    pub size: u64,
+}
+
+impl MappedText {
+    /// Whether only the start of the file is sent.
+    pub fn truncated(&self) -> bool {
+        (self.len as u64) < self.size
+    }
+
+    /// Returns the text from `offset` up to the next chunk boundary, and the offset after
+    /// it, or `None` at the end. Fails if the file changed since it was checked.
+    pub fn next_chunk(&self, offset: usize) -> Option<io::Result<(&str, usize)>> {
+        if offset >= self.len {
+            return None;
+        }
+        let mut end = (offset + MMAP_CHUNK_BYTES).min(self.len);
+        // Back up to the start of a character so no chunk splits one.
+        while end < self.len && (self.map[end] & 0b1100_0000) == 0b1000_0000 {
+            end -= 1;
+        }
+        Some(
+            std::str::from_utf8(&self.map[offset..end])
+                .map(|chunk| (chunk, end))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        )
+    }
+}
+
+/// Maps a text file of at least `MMAP_THRESHOLD_BYTES` whose first `max_bytes` are valid
+/// UTF-8, for `/api/file` to stream instead of reading it into memory (which, with the
+/// JSON response, held the content twice). Returns `None` for smaller files and for files
+/// `read_file_content` should handle: binary ones, and those needing another encoding.
+/// Blocking.
+pub fn map_text_file(path: &Path, transport: Transport, max_bytes: u64) -> io::Result<Option<MappedText>> {
+    let file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    if transport == Transport::Base64 || size.min(max_bytes) < MMAP_THRESHOLD_BYTES {
+        return Ok(None);
+    }
+    if transport == Transport::Auto && has_binary_extension(path) {
+        return Ok(None);
+    }
+    // SAFETY: the map is only read. If the file is truncated while mapped, reading past
+    // its new end raises SIGBUS; that is the usual caveat of mapping files, accepted here
+    // for files large enough that reading them whole is the bigger risk.
+    let map = unsafe { Mmap::map(&file)? };
+    let mut len = map.len().min(max_bytes as usize);
+    if transport == Transport::Auto && map[..len.min(BINARY_SNIFF_BYTES)].contains(&0) {
+        return Ok(None);
+    }
+    match std::str::from_utf8(&map[..len]) {
+        Ok(_) => {}
+        // A character cut in half at the limit.
+        Err(e) if e.error_len().is_none() && len < map.len() => len = e.valid_up_to(),
+        Err(_) => return Ok(None),
+    }
+    Ok(Some(MappedText { map, len, size }))
+}
+
+/// The last lines of a file, as returned by `/api/file/tail`.
+pub struct Tail {
+    /// Lines without their `\n` or `\r\n` endings.
+    pub lines: Vec<String>,
+    pub encoding: &'static str,
+    /// Byte offset where the first returned line starts.
+    pub offset: u64,
+    /// The file's size, which a follow-up call passes as `offset` to get only newer lines.
+    pub size: u64,
+    /// Whether fewer lines than requested were returned because of the byte limit.
+    pub truncated: bool,
+}
+
+/// Reads the last `lines` lines of a text file, scanning backwards from the end in blocks so
+/// only the tail is read. Nothing before byte `since` is returned, and at most `max_bytes`
+/// are read; a line cut by that limit is dropped. Binary files are refused with `InvalidInput`.
+pub async fn tail_file(path: &Path, lines: usize, since: u64, max_bytes: u64) -> io::Result<Tail> {
+    let mut file = tokio_fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a file"));
+    }
+    let size = metadata.len();
+    let mut head = Vec::with_capacity(BINARY_SNIFF_BYTES);
+    (&mut file).take(BINARY_SNIFF_BYTES as u64).read_to_end(&mut head).await?;
+    if has_binary_extension(path) || head.contains(&0) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Binary files can't be tailed"));
+    }
+
+    let since = since.min(size);
+    let floor = since.max(size.saturating_sub(max_bytes));
+    let mut offset = if lines == 0 { size } else { floor };
+    let mut wanted = lines;
+    let mut pos = size;
+    let mut block = vec![0; TAIL_BLOCK_BYTES.min(size) as usize];
+    'scan: while wanted > 0 && pos > floor {
+        let start = pos.saturating_sub(TAIL_BLOCK_BYTES).max(floor);
+        let block = &mut block[..(pos - start) as usize];
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(block).await?;
+        for (i, &byte) in block.iter().enumerate().rev() {
+            let at = start + i as u64;
+            // A newline ending the file ends the last line rather than starting an empty one.
+            if byte == b'\n' && at + 1 < size {
+                wanted -= 1;
+                if wanted == 0 {
+                    offset = at + 1;
+                    break 'scan;
+                }
+            }
+        }
+        pos = start;
+    }
+
+    let mut bytes = Vec::with_capacity((size - offset) as usize);
+    file.seek(SeekFrom::Start(offset)).await?;
+    file.take(size - offset).read_to_end(&mut bytes).await?;
+    let truncated = wanted > 0 && floor > since;
+    if truncated {
+        if let Some(newline) = bytes.iter().position(|&b| b == b'\n') {
+            bytes.drain(..=newline);
+            offset += newline as u64 + 1;
+        }
+    }
+    let text = decode_text(bytes);
+    Ok(Tail {
+        lines: text.content.lines().map(str::to_string).collect(),
+        encoding: text.encoding,
+        offset,
+        size,
+        truncated,
+    })
+}
+
+/// Decodes bytes that aren't valid UTF-8, honoring a UTF-16 byte order mark and otherwise
+/// guessing the encoding (e.g. Shift_JIS or windows-1252). Sequences that are malformed in
+/// the guessed encoding are replaced, and reported as lossy. When the guess is weak the bytes
+/// are read as Latin-1, which maps every byte to a character and so never fails.
+fn decode_non_utf8(bytes: &[u8]) -> DecodedText {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        let (content, lossy) = encoding.decode_with_bom_removal(bytes);
+        return DecodedText { content: content.into_owned(), encoding: encoding.name(), lossy };
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let (encoding, confident) = detector.guess_assess(None, false);
+    if confident {
+        let (content, _, lossy) = encoding.decode(bytes);
+        DecodedText { content: content.into_owned(), encoding: encoding.name(), lossy }
+    } else {
+        DecodedText { content: bytes.iter().map(|&b| char::from(b)).collect(), encoding: "ISO-8859-1", lossy: false }
+    }
+}
+
+/// Resolves and canonicalizes a requested path. Names are matched regardless of their
+/// Unicode normal form, so NFC paths from clients find NFD-named files and vice versa.
 pub fn validate_path(requested_path: &str) -> Result<PathBuf, String> {
-    let base_path = PathBuf::from(requested_path);
-    if !base_path.exists() {
+    let Some(base_path) = resolve_unicode_path(&from_api_path(requested_path)) else {
         return Err(format!("Path does not exist: {}", requested_path));
-    }
-    let resolved_path = base_path
-        .canonicalize()
-        .map_err(|e| format!("Failed to canonicalize path: {}", e))?;
+    };
+    let resolved_path =
+        canonicalize_path(&base_path).map_err(|e| format!("Failed to canonicalize path: {}", e))?;
 
     // For security, you might want to restrict access to certain directories.
     // This example allows access to any valid path on the system.
     Ok(resolved_path)
 }
 
-pub fn build_tree(path: &Path, ig: &Gitignore) -> Result<HashMap<String, TreeNode>, String> {
-    debug!("Building file tree for directory: {}", path.display());
-    let mut tree = HashMap::new();
-    let entries = fs::read_dir(path).map_err(|e| format!("Failed to read directory: {}", e))?;
-    let mut dirents = Vec::new();
+/// Canonicalizes `path`, resolving symlinks, without the Windows `\\?\` prefix so that the
+/// result compares equal to client-sent paths.
+pub fn canonicalize_path(path: &Path) -> io::Result<PathBuf> {
+    let resolved_path = path.canonicalize()?;
+    Ok(from_api_path(&to_api_path(&resolved_path)))
+}
 
-    for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
-        if ig.matched(&entry.path(), entry.path().is_dir()).is_ignore() {
+/// Splits `path` into one breadcrumb per ancestor, outermost first and ending with `path`
+/// itself. With a `root`, ancestors above it are left out; `path` must be inside it.
+pub fn breadcrumbs(path: &Path, root: Option<&Path>) -> Vec<Breadcrumb> {
+    let mut crumbs: Vec<Breadcrumb> = path
+        .ancestors()
+        .take_while(|ancestor| root.is_none_or(|root| ancestor.starts_with(root)))
+        .map(|ancestor| {
+            let api_path = to_nfc(&to_api_path(ancestor));
+            let name = match ancestor.file_name() {
+                Some(name) => to_nfc(&name.to_string_lossy()),
+                // The filesystem root (or a drive) is named after itself.
+                None => api_path.clone(),
+            };
+            Breadcrumb { name, path: api_path }
+        })
+        .collect();
+    crumbs.reverse();
+    crumbs
+}
+
+/// The `.gitignore` files that apply to a requested root: its own and those of its
+/// ancestors up to the enclosing repository root, deepest first.
+pub struct GitignoreStack {
+    matchers: Vec<Gitignore>,
+}
+
+impl GitignoreStack {
+    /// Collects `.gitignore` files from `path` upwards, stopping at the first directory
+    /// containing `.git` (the repository root) or at the filesystem root.
+    pub fn load(path: &Path) -> Self {
+        let mut matchers = Vec::new();
+        for dir in path.ancestors() {
+            let gitignore_path = dir.join(".gitignore");
+            if gitignore_path.is_file() {
+                let mut builder = GitignoreBuilder::new(dir);
+                if let Some(e) = builder.add(&gitignore_path) {
+                    warn!("Failed to parse '{}': {}", gitignore_path.display(), e);
+                }
+                match builder.build() {
+                    Ok(gitignore) => {
+                        debug!("Loaded gitignore rules from '{}'", gitignore_path.display());
+                        matchers.push(gitignore);
+                    }
+                    Err(e) => warn!("Failed to build gitignore from '{}': {}", gitignore_path.display(), e),
+                }
+            }
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        GitignoreStack { matchers }
+    }
+
+    /// Whether `path` is ignored. Rules in deeper `.gitignore` files take precedence,
+    /// so a whitelist (`!pattern`) in a subdirectory overrides its ancestors.
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in &self.matchers {
+            let matched = gitignore.matched(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+
+    /// Like `is_ignored`, but also checks the parent directories of `path`, for paths
+    /// that don't come from a top-down walk (e.g. filesystem events).
+    pub fn is_path_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for gitignore in &self.matchers {
+            let matched = gitignore.matched_path_or_any_parents(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+/// Builds the file tree rooted at `path`.
+///
+/// Only a failure to read `path` itself is an error. Subdirectories that cannot be
+/// read are emitted with empty children and an `error`, and their paths are pushed
+/// onto `report.warnings`.
+///
+/// Directories are listed breadth-first. Once listing the next directory would take the
+/// tree past `options.max_entries`, the walk stops: that directory and every directory
+/// still queued are emitted with empty children and `truncated: true`. Symlinked
+/// directories are followed, except links to a directory the link itself is in, which
+/// would make the walk loop; those are emitted like truncated folders.
+///
+/// This is a level-by-level walk rather than `ignore`'s parallel walker because the cap
+/// must cut the tree at the same breadth-first position on every run: each level is
+/// listed in parallel, but consumed in queue order, so the output is identical to a
+/// serial walk.
+pub fn build_tree(
+    path: &Path,
+    ig: &GitignoreStack,
+    options: &TreeOptions,
+    report: &mut TreeReport,
+) -> Result<IndexMap<String, TreeNode>, String> {
+    let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(MAX_WALK_THREADS);
+    walk_levels(path, ig, options, report, workers)
+}
+
+/// `build_tree`, listing each batch of directories on up to `workers` threads.
+fn walk_levels(
+    path: &Path,
+    ig: &GitignoreStack,
+    options: &TreeOptions,
+    report: &mut TreeReport,
+    workers: usize,
+) -> Result<IndexMap<String, TreeNode>, String> {
+    let mut listings: HashMap<PathBuf, io::Result<Vec<Listed>>> = HashMap::new();
+    let mut frontier = vec![path.to_path_buf()];
+    let mut listed_entries = 0;
+    let mut depth = 0;
+
+    // Each level of the walk is listed in parallel batches, but results are consumed in
+    // queue order so the output (including where truncation happens) is deterministic.
+    'walk: while !frontier.is_empty() {
+        let mut next_frontier = Vec::new();
+        for batch in frontier.chunks(WALK_BATCH_SIZE) {
+            for (dir, result) in batch.iter().zip(list_dirs_parallel(path, batch, ig, options, workers)) {
+                debug!("Building file tree for directory: {}", dir.display());
+                match result {
+                    Ok(listing) => {
+                        if listed_entries + listing.entries.len() > options.max_entries {
+                            debug!("Entry cap of {} reached at '{}'; truncating tree.", options.max_entries, dir.display());
+                            report.truncated = true;
+                            break 'walk;
+                        }
+                        listed_entries += listing.entries.len();
+                        report.pruned.extend(listing.pruned);
+                        next_frontier.extend(
+                            listing.entries.iter().filter(|e| e.is_dir && !e.loops).map(|e| e.path.clone()),
+                        );
+                        listings.insert(dir.clone(), Ok(listing.entries));
+                    }
+                    Err(e) if dir == path => return Err(format!("Failed to read directory: {}", e)),
+                    Err(e) => {
+                        warn!("Skipping unreadable directory '{}': {}", dir.display(), e);
+                        report.warnings.push(to_api_path(dir));
+                        listings.insert(dir.clone(), Err(e));
+                    }
+                }
+            }
+        }
+        depth += 1;
+        if options.max_depth.is_some_and(|max| depth >= max) {
+            break;
+        }
+        frontier = next_frontier;
+    }
+
+    let tree = match listings.remove(path) {
+        Some(Ok(entries)) => assemble_tree(entries, &mut listings, options, report).0,
+        _ => IndexMap::new(),
+    };
+    Ok(tree)
+}
+
+/// Lists several directories concurrently on up to `workers` scoped threads, returning
+/// results in input order.
+fn list_dirs_parallel(
+    root: &Path,
+    dirs: &[PathBuf],
+    ig: &GitignoreStack,
+    options: &TreeOptions,
+    workers: usize,
+) -> Vec<io::Result<DirListing>> {
+    let workers = workers.min(dirs.len());
+    if workers <= 1 {
+        return dirs.iter().map(|dir| list_dir(root, dir, ig, options)).collect();
+    }
+
+    let chunk_size = dirs.len().div_ceil(workers);
+    thread::scope(|scope| {
+        let handles: Vec<_> = dirs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|dir| list_dir(root, dir, ig, options))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("directory listing thread panicked"))
+            .collect()
+    })
+}
+
+/// The filtered contents of one directory.
+struct DirListing {
+    entries: Vec<Listed>,
+    /// Paths skipped because they matched a default exclude.
+    pruned: Vec<String>,
+}
+
+/// A directory entry that survived filtering.
+struct Listed {
+    name: String,
+    path: PathBuf,
+    /// Whether this is a directory, or a symlink to one.
+    is_dir: bool,
+    /// Whether this is a symlink to a directory it is in, which is not walked again.
+    loops: bool,
+    /// File size in bytes.
+    size: u64,
+    /// Modification time in milliseconds since the Unix epoch, 0 if unavailable.
+    modified: u64,
+    /// Whether the file is binary, when binary detection is enabled.
+    binary: Option<bool>,
+}
+
+/// Lists a single directory, applying every filter and the directories-first natural sort.
+fn list_dir(
+    root: &Path,
+    path: &Path,
+    ig: &GitignoreStack,
+    options: &TreeOptions,
+) -> io::Result<DirListing> {
+    let mut entries = Vec::new();
+    let mut pruned = Vec::new();
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = to_nfc(&entry.file_name().to_string_lossy());
+        let path = entry.path();
+        // Follows symlinks, so linked directories are walked like regular ones.
+        let metadata = fs::metadata(&path).ok();
+        let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+        if options.is_hidden(&name) {
+            continue;
+        }
+        if ig.is_ignored(&path, is_dir) {
             continue;
         }
-        dirents.push(entry);
+        if options.is_default_excluded(&name) {
+            if !is_always_excluded(&name) {
+                pruned.push(to_api_path(&path));
+            }
+            continue;
+        }
+        if !is_dir && !options.keeps_file(&path, root) {
+            continue;
+        }
+        let size = match &metadata {
+            Some(m) if !is_dir => m.len(),
+            _ => 0,
+        };
+        let modified = metadata
+            .and_then(|m| m.modified().ok())
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as u64);
+        let binary = if is_dir { None } else { options.detect_binary.check(&path) };
+        let loops = is_dir && entry.file_type().is_ok_and(|t| t.is_symlink()) && links_to_ancestor(&path);
+        entries.push(Listed { name, path, is_dir, loops, size, modified, binary });
     }
 
-    dirents.sort_by(|a, b| {
-        let a_is_dir = a.file_type().map_or(false, |ft| ft.is_dir());
-        let b_is_dir = b.file_type().map_or(false, |ft| ft.is_dir());
-        if a_is_dir != b_is_dir {
-            return b_is_dir.cmp(&a_is_dir);
+    entries.sort_by(|a, b| {
+        if a.is_dir != b.is_dir {
+            return b.is_dir.cmp(&a.is_dir);
         }
-        natural_compare(&a.file_name().to_string_lossy(), &b.file_name().to_string_lossy())
+        natural_compare(&a.name, &b.name)
     });
+    Ok(DirListing { entries, pruned })
+}
 
-    for entry in dirents {
-        let path = entry.path();
-        let name = entry.file_name().to_string_lossy().to_string();
-        if path.is_dir() {
-            let children = build_tree(&path, ig)?;
+/// Whether the symlink at `link` resolves to one of the directories it is in, as reached
+/// through the walk (possibly through other links), so following it would never end.
+fn links_to_ancestor(link: &Path) -> bool {
+    let Ok(target) = fs::canonicalize(link) else {
+        return false;
+    };
+    link.ancestors()
+        .skip(1)
+        .any(|ancestor| fs::canonicalize(ancestor).is_ok_and(|ancestor| ancestor == target))
+}
+
+/// File count, size and approximate token totals rolled up for a folder.
+#[derive(Default, Clone, Copy)]
+struct FolderStats {
+    file_count: usize,
+    total_size: u64,
+    total_approx_tokens: usize,
+}
+
+impl FolderStats {
+    fn add(&mut self, other: FolderStats) {
+        self.file_count += other.file_count;
+        self.total_size += other.total_size;
+        self.total_approx_tokens += other.total_approx_tokens;
+    }
+}
+
+/// Turns the directory listings gathered by `build_tree` into nested tree nodes,
+/// returning the stats rolled up from everything emitted.
+fn assemble_tree(
+    entries: Vec<Listed>,
+    listings: &mut HashMap<PathBuf, io::Result<Vec<Listed>>>,
+    options: &TreeOptions,
+    report: &mut TreeReport,
+) -> (IndexMap<String, TreeNode>, FolderStats) {
+    let mut tree = IndexMap::new();
+    let mut stats = FolderStats::default();
+
+    for entry in entries {
+        let path_str = to_nfc(&to_api_path(&entry.path));
+        if entry.is_dir {
+            let (children, child_stats, error, truncated) = match listings.remove(&entry.path) {
+                Some(Ok(entries)) => {
+                    let (children, child_stats) = assemble_tree(entries, listings, options, report);
+                    (children, child_stats, None, None)
+                }
+                Some(Err(e)) => (IndexMap::new(), FolderStats::default(), Some(describe_io_error(&e)), None),
+                None => (IndexMap::new(), FolderStats::default(), None, Some(true)),
+            };
+            if options.filters_files() && children.is_empty() && error.is_none() && truncated.is_none() {
+                continue;
+            }
+            stats.add(child_stats);
             let node = TreeNode {
                 node_type: "folder".to_string(),
-                path: path.to_string_lossy().to_string(),
+                path: path_str,
                 children: Some(children),
+                error,
+                truncated,
+                file_count: options.stats.then_some(child_stats.file_count),
+                total_size: options.stats.then_some(child_stats.total_size),
+                total_approx_tokens: options.stats.then_some(child_stats.total_approx_tokens),
+                ..Default::default()
             };
-            tree.insert(name, node);
+            tree.insert(entry.name, node);
         } else {
+            stats.add(FolderStats {
+                file_count: 1,
+                total_size: entry.size,
+                total_approx_tokens: (entry.size as usize).div_ceil(4),
+            });
             let node = TreeNode {
                 node_type: "file".to_string(),
-                path: path.to_string_lossy().to_string(),
-                children: None,
+                path: path_str,
+                modified: Some(entry.modified),
+                is_binary: entry.binary,
+                ..Default::default()
             };
-            tree.insert(name, node);
+            tree.insert(entry.name, node);
+        }
+        report.entries += 1;
+    }
+    (tree, stats)
+}
+
+/// Recomputes folder `totalApproxTokens` from per-file estimates, so binary files
+/// (which have no estimate) stop counting towards their folder's total.
+/// Returns the total for the given level.
+pub fn rollup_approx_tokens(tree: &mut IndexMap<String, TreeNode>) -> usize {
+    let mut total = 0;
+    for node in tree.values_mut() {
+        match node.children.as_mut() {
+            Some(children) => {
+                let folder_total = rollup_approx_tokens(children);
+                if node.total_approx_tokens.is_some() {
+                    node.total_approx_tokens = Some(folder_total);
+                }
+                total += folder_total;
+            }
+            None => total += node.approx_tokens.unwrap_or(0),
+        }
+    }
+    total
+}
+
+fn describe_io_error(e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => "permission denied".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+/// Flattens a tree in traversal order (each folder followed by its contents) and returns
+/// the `limit` nodes after `cursor`, plus the cursor of the next page if any remain.
+/// Cursors encode the path of the last node returned; one whose path is no longer in the
+/// tree is rejected.
+pub fn paginate_tree(
+    tree: IndexMap<String, TreeNode>,
+    cursor: Option<&str>,
+    limit: usize,
+) -> Result<(Vec<FlatNode>, Option<String>), String> {
+    fn flatten(tree: IndexMap<String, TreeNode>, depth: usize, nodes: &mut Vec<FlatNode>) {
+        for (name, mut node) in tree {
+            let children = node.children.take();
+            nodes.push(FlatNode { name, depth, node });
+            if let Some(children) = children {
+                flatten(children, depth + 1, nodes);
+            }
+        }
+    }
+    let mut nodes = Vec::new();
+    flatten(tree, 0, &mut nodes);
+
+    let start = match cursor {
+        None => 0,
+        Some(cursor) => {
+            let last = URL_SAFE_NO_PAD
+                .decode(cursor)
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(|| format!("Invalid cursor '{}'", cursor))?;
+            let index = nodes
+                .iter()
+                .position(|n| n.node.path == last)
+                .ok_or_else(|| format!("Cursor no longer matches the tree: '{}' is gone; start over without a cursor", last))?;
+            index + 1
+        }
+    };
+    let end = start.saturating_add(limit).min(nodes.len());
+    let next_cursor = (end < nodes.len()).then(|| URL_SAFE_NO_PAD.encode(&nodes[end - 1].node.path));
+    let page = nodes.drain(start.min(end)..end).collect();
+    Ok((page, next_cursor))
+}
+
+/// Converts a serialized tree into the `format=ordered` shape, where every level is an
+/// array of nodes carrying their `name`, kept in directories-first natural order.
+pub fn into_ordered_tree(tree: Value) -> Value {
+    let Value::Object(entries) = tree else {
+        return tree;
+    };
+    Value::Array(
+        entries
+            .into_iter()
+            .map(|(name, mut node)| {
+                if let Value::Object(fields) = &mut node {
+                    if let Some(children) = fields.get_mut("children") {
+                        *children = into_ordered_tree(children.take());
+                    }
+                    fields.insert("name".to_string(), Value::String(name));
+                }
+                node
+            })
+            .collect(),
+    )
+}
+
+/// Relative path -> modification time (ms since the Unix epoch) for every entry in a tree.
+/// Folders are keyed with a trailing `/` and a modification time of 0.
+pub type TreeSnapshot = BTreeMap<String, u64>;
+
+/// Snapshots the tree's paths (relative to `root`), types and the mtimes recorded
+/// during the walk, without touching the filesystem again.
+pub fn tree_snapshot(root: &Path, tree: &IndexMap<String, TreeNode>) -> TreeSnapshot {
+    // Tree paths are NFC API paths, so the root must be too for prefixes to match.
+    let root = PathBuf::from(to_nfc(&to_api_path(root)));
+    fn walk(root: &Path, node: &IndexMap<String, TreeNode>, snapshot: &mut TreeSnapshot) {
+        for child in node.values() {
+            let path = Path::new(&child.path);
+            let relative = to_api_path(path.strip_prefix(root).unwrap_or(path));
+            if let Some(children) = &child.children {
+                snapshot.insert(format!("{}/", relative), 0);
+                walk(root, children, snapshot);
+            } else {
+                snapshot.insert(relative, child.modified.unwrap_or(0));
+            }
+        }
+    }
+    let mut snapshot = TreeSnapshot::new();
+    walk(&root, tree, &mut snapshot);
+    snapshot
+}
+
+/// A digest over the sorted paths, types and mtimes of a snapshot, used as the tree's etag.
+pub fn snapshot_etag(snapshot: &TreeSnapshot) -> String {
+    let mut hasher = DefaultHasher::new();
+    snapshot.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Files added, removed and modified between two snapshots.
+#[derive(Serialize)]
+pub struct TreeDelta {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+}
+
+pub fn diff_snapshots(old: &TreeSnapshot, new: &TreeSnapshot) -> TreeDelta {
+    let mut delta = TreeDelta {
+        added: Vec::new(),
+        removed: Vec::new(),
+        modified: Vec::new(),
+    };
+    for (path, mtime) in new {
+        match old.get(path) {
+            None => delta.added.push(path.clone()),
+            Some(old_mtime) if old_mtime != mtime => delta.modified.push(path.clone()),
+            Some(_) => {}
+        }
+    }
+    delta.removed = old.keys().filter(|path| !new.contains_key(*path)).cloned().collect();
+    delta
+}
+
+/// Groups the text files of a walked tree by identical content, returning groups of two or
+/// more paths. Files are bucketed by size first, so only files sharing a size are read and
+/// hashed; binary files and files larger than `max_bytes` are skipped. Blocking.
+pub fn find_duplicates(tree: &IndexMap<String, TreeNode>, max_bytes: u64) -> Vec<Vec<String>> {
+    fn collect_sizes(node: &IndexMap<String, TreeNode>, max_bytes: u64, by_size: &mut HashMap<u64, Vec<String>>) {
+        for child in node.values() {
+            if let Some(children) = &child.children {
+                collect_sizes(children, max_bytes, by_size);
+            } else if child.node_type == "file" && child.is_binary != Some(true) {
+                match fs::metadata(on_disk_path(&child.path)) {
+                    // Empty files are trivially identical and not worth reporting.
+                    Ok(m) if m.len() > 0 && m.len() <= max_bytes => {
+                        by_size.entry(m.len()).or_default().push(child.path.clone())
+                    }
+                    Ok(_) => {}
+                    Err(e) => debug!("Failed to stat '{}' for duplicates: {}", child.path, e),
+                }
+            }
+        }
+    }
+    let mut by_size = HashMap::new();
+    collect_sizes(tree, max_bytes, &mut by_size);
+
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    for candidates in by_size.into_values().filter(|paths| paths.len() > 1) {
+        let mut by_hash: HashMap<u128, Vec<String>> = HashMap::new();
+        for path in candidates {
+            match fs::read(on_disk_path(&path)) {
+                Ok(bytes) if !is_binary_head(&bytes[..bytes.len().min(BINARY_SNIFF_BYTES)]) => {
+                    by_hash.entry(xxh3_128(&bytes)).or_default().push(path)
+                }
+                Ok(_) => {}
+                Err(e) => debug!("Failed to read '{}' for duplicates: {}", path, e),
+            }
+        }
+        groups.extend(by_hash.into_values().filter(|paths| paths.len() > 1));
+    }
+
+    for group in &mut groups {
+        group.sort_by(|a, b| natural_compare(a, b));
+    }
+    groups.sort_by(|a, b| natural_compare(&a[0], &b[0]));
+    groups
+}
+
+/// Returns the `n` largest files of a walked tree, largest first. Blocking.
+pub fn largest_files(tree: &IndexMap<String, TreeNode>, n: usize) -> Vec<LargeFile> {
+    fn collect(node: &IndexMap<String, TreeNode>, files: &mut Vec<LargeFile>) {
+        for child in node.values() {
+            if let Some(children) = &child.children {
+                collect(children, files);
+            } else if child.node_type == "file" {
+                let path = from_api_path(&child.path);
+                match fs::metadata(resolve_unicode_path(&path).unwrap_or(path)) {
+                    Ok(m) => {
+                        let language = language_for_path(&child.path);
+                        files.push(LargeFile {
+                            path: child.path.clone(),
+                            size: m.len(),
+                            language: (!language.is_empty()).then(|| language.to_string()),
+                        });
+                    }
+                    Err(e) => debug!("Failed to stat '{}' for largest files: {}", child.path, e),
+                }
+            }
+        }
+    }
+    let mut files = Vec::new();
+    collect(tree, &mut files);
+    files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| natural_compare(&a.path, &b.path)));
+    files.truncate(n);
+    files
+}
+
+/// Returns the `n` files of a walked tree most recently modified after `since`, newest
+/// first, with their modification times in milliseconds since the Unix epoch.
+pub fn recently_modified(tree: &IndexMap<String, TreeNode>, since: SystemTime, n: usize) -> Vec<(String, u64)> {
+    fn collect(node: &IndexMap<String, TreeNode>, since: u64, files: &mut Vec<(String, u64)>) {
+        for child in node.values() {
+            if let Some(children) = &child.children {
+                collect(children, since, files);
+            } else if child.node_type == "file" {
+                if let Some(modified) = child.modified.filter(|&modified| modified > since) {
+                    files.push((child.path.clone(), modified));
+                }
+            }
+        }
+    }
+    let since = epoch_millis(Ok(since)).unwrap_or(0);
+    let mut files = Vec::new();
+    collect(tree, since, &mut files);
+    files.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| natural_compare(&a.0, &b.0)));
+    files.truncate(n);
+    files
+}
+
+/// Counts lines of code per language over the text files of a walked tree. Files in
+/// languages `language_for_path` doesn't know, binary files and files larger than
+/// `max_bytes` are skipped. Blocking.
+pub fn compute_loc(tree: &IndexMap<String, TreeNode>, max_bytes: u64) -> LocReport {
+    fn walk(node: &IndexMap<String, TreeNode>, max_bytes: u64, report: &mut LocReport) {
+        for child in node.values() {
+            if let Some(children) = &child.children {
+                walk(children, max_bytes, report);
+                continue;
+            }
+            if child.node_type != "file" || child.is_binary == Some(true) {
+                continue;
+            }
+            let language = language_for_path(&child.path);
+            if language.is_empty() {
+                continue;
+            }
+            let path = from_api_path(&child.path);
+            let path = resolve_unicode_path(&path).unwrap_or(path);
+            if fs::metadata(&path).map_or(true, |m| m.len() > max_bytes) {
+                continue;
+            }
+            // Invalid UTF-8 means the file isn't text.
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let loc = count_loc(&content, comment_syntax(language));
+            report.languages.entry(language.to_string()).or_default().add(loc);
+            report.total.add(loc);
+        }
+    }
+    let mut report = LocReport::default();
+    walk(tree, max_bytes, &mut report);
+    report
+}
+
+/// How comments are written in a language, for `compute_loc`.
+struct CommentSyntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+}
+
+fn comment_syntax(language: &str) -> CommentSyntax {
+    const C_BLOCK: Option<(&str, &str)> = Some(("/*", "*/"));
+    match language {
+        "python" | "ruby" | "bash" | "r" | "yaml" | "toml" => CommentSyntax { line: &["#"], block: None },
+        "php" => CommentSyntax { line: &["//", "#"], block: C_BLOCK },
+        "css" => CommentSyntax { line: &[], block: C_BLOCK },
+        "sql" => CommentSyntax { line: &["--"], block: C_BLOCK },
+        "html" | "xml" | "markdown" => CommentSyntax { line: &[], block: Some(("<!--", "-->")) },
+        "json" => CommentSyntax { line: &[], block: None },
+        // Rust, JS/TS, Go, C/C++, Java, Kotlin, Swift, C#, SCSS
+        _ => CommentSyntax { line: &["//"], block: C_BLOCK },
+    }
+}
+
+/// Classifies each line as blank, comment or code. A line counts as a comment only if it
+/// holds nothing but comment text, so code followed by a trailing comment is code.
+fn count_loc(content: &str, syntax: CommentSyntax) -> LanguageLoc {
+    let mut loc = LanguageLoc { files: 1, ..Default::default() };
+    let mut in_block = false;
+    for line in content.lines() {
+        loc.lines += 1;
+        let trimmed = line.trim();
+        if in_block {
+            loc.comment += 1;
+            if let Some((_, end)) = syntax.block {
+                in_block = !trimmed.contains(end);
+            }
+        } else if trimmed.is_empty() {
+            loc.blank += 1;
+        } else if syntax.line.iter().any(|marker| trimmed.starts_with(marker)) {
+            loc.comment += 1;
+        } else if let Some((start, end)) = syntax.block.filter(|(start, _)| trimmed.starts_with(start)) {
+            loc.comment += 1;
+            in_block = !trimmed[start.len()..].contains(end);
+        }
+    }
+    loc.code = loc.lines - loc.blank - loc.comment;
+    loc
+}
+
+/// Annotates every file node in the tree with its line count and an approximate
+/// token count (bytes / 4). Binary files get a line count of `None` and no token estimate.
+pub async fn annotate_estimates(tree: &mut IndexMap<String, TreeNode>) {
+    fn collect_files<'a>(node: &'a mut IndexMap<String, TreeNode>, files: &mut Vec<&'a mut TreeNode>) {
+        for child in node.values_mut() {
+            if child.node_type == "file" {
+                files.push(child);
+            } else if let Some(children) = child.children.as_mut() {
+                collect_files(children, files);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    collect_files(tree, &mut files);
+    debug!("Computing size estimates for {} files.", files.len());
+
+    let paths: Vec<PathBuf> = files.iter().map(|node| on_disk_path(&node.path)).collect();
+    let estimates: Vec<Option<(usize, usize)>> = stream::iter(paths)
+        .map(|path| async move { estimate_file(&path).await })
+        .buffered(ESTIMATE_CONCURRENCY)
+        .collect()
+        .await;
+
+    for (node, estimate) in files.into_iter().zip(estimates) {
+        if let Some((lines, approx_tokens)) = estimate {
+            node.lines = Some(lines);
+            node.approx_tokens = Some(approx_tokens);
+        }
+    }
+}
+
+/// Returns `(lines, approx_tokens)` for a text file, or `None` if the file is
+/// binary, too large, or unreadable.
+async fn estimate_file(path: &Path) -> Option<(usize, usize)> {
+    let metadata = match tokio_fs::metadata(path).await {
+        Ok(m) => m,
+        Err(e) => {
+            warn!("Failed to stat '{}' for estimates: {}", path.display(), e);
+            return None;
+        }
+    };
+    if metadata.len() > ESTIMATE_MAX_FILE_SIZE {
+        debug!("Skipping estimates for '{}': {} bytes exceeds the size cap.", path.display(), metadata.len());
+        return None;
+    }
+
+    let bytes = match tokio_fs::read(path).await {
+        Ok(b) => b,
+        Err(e) => {
+            warn!("Failed to read '{}' for estimates: {}", path.display(), e);
+            return None;
+        }
+    };
+    if is_binary(&bytes) {
+        return None;
+    }
+
+    Some(text_estimates(&bytes))
+}
+
+/// Returns `(lines, approx_tokens)` for text: a final line without a trailing newline
+/// counts too, and tokens are estimated as bytes / 4.
+pub fn text_estimates(bytes: &[u8]) -> (usize, usize) {
+    let mut lines = bytes.iter().filter(|&&b| b == b'\n').count();
+    if bytes.last().is_some_and(|&b| b != b'\n') {
+        lines += 1;
+    }
+    (lines, bytes.len().div_ceil(4))
+}
+
+/// Heuristic binary check: a NUL byte in the first 8KB or invalid UTF-8.
+fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(8192)];
+    head.contains(&0) || std::str::from_utf8(bytes).is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn leftovers(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".repoprompt."))
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn write_file_atomically_replaces_content_and_cleans_up() {
+        let dir = TempDir::new();
+        let path = dir.write("a.txt", "old");
+        write_file_atomically(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_atomically_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new();
+        let path = dir.write("run.sh", "echo old");
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        write_file_atomically(&path, b"echo new").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o750);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stage_write_never_writes_through_a_planted_symlink() {
+        let dir = TempDir::new();
+        let outside = TempDir::new();
+        let target = outside.write("victim.txt", "untouched");
+        let path = dir.path().join("a.txt");
+        // The name the staging file had before it got a random component.
+        std::os::unix::fs::symlink(&target, dir.path().join(".a.txt.repoprompt.tmp")).unwrap();
+
+        write_file_atomically(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+    }
+
+    #[test]
+    fn write_files_atomically_writes_every_file() {
+        let dir = TempDir::new();
+        let a = dir.write("a.txt", "old a");
+        let b = dir.path().join("b.txt");
+        write_files_atomically(&[(a.clone(), "new a".into()), (b.clone(), "new b".into())]).unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "new a");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "new b");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn write_files_rolls_back_when_a_rename_fails_partway() {
+        let dir = TempDir::new();
+        let a = dir.write("a.txt", "old a");
+        let b = dir.path().join("b.txt");
+        let c = dir.write("c.txt", "old c");
+        let files = [(a.clone(), "new a".into()), (b.clone(), "new b".into()), (c.clone(), "new c".into())];
+
+        let (failed, _) = write_files_with(&files, |from, to| {
+            if to == c {
+                return Err(io::Error::other("injected failure"));
+            }
+            fs::rename(from, to)
+        })
+        .unwrap_err();
+
+        assert_eq!(failed, c);
+        assert_eq!(fs::read_to_string(&a).unwrap(), "old a");
+        assert!(!b.exists(), "a file created by the batch is removed again");
+        assert_eq!(fs::read_to_string(&c).unwrap(), "old c");
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+    #[actix_web::test]
+    async fn estimates_read_nfd_named_files_through_nfc_tree_paths() {
+        let dir = TempDir::new();
+        dir.write("cafe\u{301}.txt", "one\ntwo\n");
+        let mut tree = IndexMap::new();
+        let node = TreeNode {
+            node_type: "file".into(),
+            path: to_api_path(dir.path().join("caf\u{e9}.txt")),
+            ..Default::default()
+        };
+        tree.insert("caf\u{e9}.txt".to_string(), node);
+
+        annotate_estimates(&mut tree).await;
+        assert_eq!(tree["caf\u{e9}.txt"].lines, Some(2));
+        assert_eq!(tree["caf\u{e9}.txt"].approx_tokens, Some(2));
+    }
+
+    /// Walks `root` on `workers` threads and serializes everything the walk produces.
+    fn walk_output(root: &Path, query: &DirectoryQuery, workers: usize) -> String {
+        let options = TreeOptions::from_query(query).unwrap();
+        let mut report = TreeReport::default();
+        let tree = walk_levels(root, &GitignoreStack::load(root), &options, &mut report, workers).unwrap();
+        serde_json::to_string(&serde_json::json!({
+            "tree": tree,
+            "warnings": report.warnings,
+            "pruned": report.pruned,
+            "entries": report.entries,
+            "truncated": report.truncated,
+        }))
+        .unwrap()
+    }
+
+    /// More directories per level than `WALK_BATCH_SIZE`, so levels span several batches.
+    fn wide_fixture() -> TempDir {
+        let dir = TempDir::new();
+        dir.write(".gitignore", "*.log\n");
+        for i in 0..WALK_BATCH_SIZE + 40 {
+            dir.write(&format!("pkg{}/mod{}.rs", i, i), "fn main() {}\n");
+            dir.write(&format!("pkg{}/nested/data.log", i), "ignored");
+            dir.write(&format!("pkg{}/nested/deeper/file10.txt", i), "ten");
+            dir.write(&format!("pkg{}/nested/deeper/file9.txt", i), "nine");
+        }
+        dir.write("node_modules/dep/index.js", "");
+        dir
+    }
+
+    #[test]
+    fn parallel_walk_output_matches_the_serial_walk() {
+        let dir = wide_fixture();
+        let queries = [
+            DirectoryQuery::default(),
+            DirectoryQuery { stats: Some(true), ..Default::default() },
+            DirectoryQuery { max_entries: Some(500), ..Default::default() },
+            DirectoryQuery { depth: Some(2), extensions: Some("rs".into()), ..Default::default() },
+        ];
+        for query in &queries {
+            let serial = walk_output(dir.path(), query, 1);
+            assert_eq!(walk_output(dir.path(), query, MAX_WALK_THREADS), serial);
+            assert_eq!(walk_output(dir.path(), query, 3), serial);
+        }
+    }
+
+    /// The tree `build_tree` produces for `query`, one line per node with paths relative to
+    /// `root`, followed by the walk report.
+    #[cfg(unix)]
+    fn walk_listing(root: &Path, query: serde_json::Value) -> String {
+        let query: DirectoryQuery = serde_json::from_value(query).unwrap();
+        let options = TreeOptions::from_query(&query).unwrap();
+        let mut report = TreeReport::default();
+        let tree = build_tree(root, &GitignoreStack::load(root), &options, &mut report).unwrap();
+        let prefix = format!("{}/", to_api_path(root));
+        let mut listing = String::new();
+        render_nodes(&tree, &prefix, &mut listing);
+        let pruned: Vec<&str> = report.pruned.iter().map(|path| path.strip_prefix(&prefix).unwrap_or(path)).collect();
+        listing.push_str(&format!(
+            "pruned {:?} warnings {:?} entries {} truncated {}\n",
+            pruned, report.warnings, report.entries, report.truncated
+        ));
+        listing
+    }
+
+    #[cfg(unix)]
+    fn render_nodes(nodes: &IndexMap<String, TreeNode>, prefix: &str, listing: &mut String) {
+        for node in nodes.values() {
+            listing.push_str(&format!("{} {}", node.node_type, node.path.strip_prefix(prefix).unwrap_or(&node.path)));
+            if node.truncated == Some(true) {
+                listing.push_str(" truncated");
+            }
+            if let (Some(files), Some(bytes)) = (node.file_count, node.total_size) {
+                listing.push_str(&format!(" files={} bytes={}", files, bytes));
+            }
+            listing.push('\n');
+            if let Some(children) = &node.children {
+                render_nodes(children, prefix, listing);
+            }
+        }
+    }
+
+    /// A small tree with ignored, hidden, excluded and symlinked entries.
+    #[cfg(unix)]
+    fn walk_fixture() -> TempDir {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new();
+        dir.write(".gitignore", "*.log\nbuild/\n");
+        dir.write(".env", "SECRET=1\n");
+        dir.write(".git/HEAD", "ref: refs/heads/main\n");
+        dir.write("build/out.bin", "binary");
+        dir.write("Cargo.toml", "[package]\n");
+        dir.write("data/a.txt", "alpha");
+        dir.write("data/b10.txt", "b10");
+        dir.write("data/b9.txt", "b9");
+        dir.write("docs/guide/intro.md", "# Intro\n");
+        dir.write("docs/readme.md", "# Docs\n");
+        dir.write("node_modules/dep/index.js", "module.exports = 1;\n");
+        dir.write("src/lib.rs", "pub mod util;\n");
+        dir.write("src/main.rs", "fn main() {}\n");
+        dir.write("src/notes.log", "ignored");
+        dir.write("src/util/file10.rs", "// ten\n");
+        dir.write("src/util/file9.rs", "// nine\n");
+        dir.write("src/util/mod.rs", "");
+        symlink(dir.path().join("src/util"), dir.path().join("linked")).unwrap();
+        symlink(dir.path().join("src/main.rs"), dir.path().join("main-link.rs")).unwrap();
+        dir
+    }
+    /// What `build_tree` returned for `walk_fixture` before directories were listed in
+    /// parallel, for each query in `the_walk_matches_the_serial_walker_it_replaced`.
+    #[cfg(unix)]
+    const SERIAL_WALK_OUTPUT: [&str; 4] = [
+        "folder data
+file data/a.txt
+file data/b9.txt
+file data/b10.txt
+folder docs
+folder docs/guide
+file docs/guide/intro.md
+file docs/readme.md
+folder linked
+file linked/file9.rs
+file linked/file10.rs
+file linked/mod.rs
+folder src
+folder src/util
+file src/util/file9.rs
+file src/util/file10.rs
+file src/util/mod.rs
+file src/lib.rs
+file src/main.rs
+file .env
+file .gitignore
+file Cargo.toml
+file main-link.rs
+pruned [\"node_modules\"] warnings [] entries 23 truncated false
+",
+        "folder data files=3 bytes=10
+file data/a.txt
+file data/b9.txt
+file data/b10.txt
+folder docs files=2 bytes=15
+folder docs/guide files=1 bytes=8
+file docs/guide/intro.md
+file docs/readme.md
+folder linked files=3 bytes=15
+file linked/file9.rs
+file linked/file10.rs
+file linked/mod.rs
+folder src files=5 bytes=42
+folder src/util files=3 bytes=15
+file src/util/file9.rs
+file src/util/file10.rs
+file src/util/mod.rs
+file src/lib.rs
+file src/main.rs
+file .env
+file .gitignore
+file Cargo.toml
+file main-link.rs
+pruned [\"node_modules\"] warnings [] entries 23 truncated false
+",
+        "folder data truncated
+folder docs truncated
+folder linked truncated
+folder src truncated
+file .env
+file .gitignore
+file Cargo.toml
+file main-link.rs
+pruned [\"node_modules\"] warnings [] entries 8 truncated true
+",
+        "folder linked
+file linked/file9.rs
+file linked/file10.rs
+file linked/mod.rs
+folder src
+folder src/util
+file src/util/file9.rs
+file src/util/file10.rs
+file src/util/mod.rs
+file src/lib.rs
+file src/main.rs
+file main-link.rs
+pruned [\"node_modules\"] warnings [] entries 12 truncated false
+",
+    ];
+
+    #[cfg(unix)]
+    #[test]
+    fn the_walk_matches_the_serial_walker_it_replaced() {
+        use serde_json::json;
+
+        let dir = walk_fixture();
+        let queries = [
+            json!({}),
+            json!({ "stats": true }),
+            json!({ "max_entries": 8 }),
+            json!({ "extensions": "rs", "hide_hidden": true }),
+        ];
+        for (query, expected) in queries.into_iter().zip(SERIAL_WALK_OUTPUT) {
+            assert_eq!(walk_listing(dir.path(), query), expected);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_back_into_the_walk_are_not_followed() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new();
+        let outside = TempDir::new();
+        outside.write("shared.txt", "s");
+        dir.write("src/main.rs", "");
+        dir.write("a/a.txt", "");
+        dir.write("b/b.txt", "");
+        symlink(dir.path(), dir.path().join("src/root")).unwrap();
+        symlink(dir.path().join("b"), dir.path().join("a/to_b")).unwrap();
+        symlink(dir.path().join("a"), dir.path().join("b/to_a")).unwrap();
+        symlink(outside.path(), dir.path().join("shared")).unwrap();
+
+        let listing = walk_listing(dir.path(), serde_json::json!({}));
+        for looping in ["src/root", "a/to_b/to_a", "b/to_a/to_b"] {
+            assert!(listing.contains(&format!("folder {} truncated\n", looping)), "{}", listing);
+        }
+        for followed in ["file a/to_b/b.txt", "file b/to_a/a.txt", "file shared/shared.txt"] {
+            assert!(listing.contains(&format!("{}\n", followed)), "{}", listing);
+        }
+        assert!(listing.ends_with("truncated false\n"), "{}", listing);
+    }
+
+    #[test]
+    fn concurrent_writes_stage_in_separate_files() {
+        let dir = TempDir::new();
+        let path = dir.write("a.txt", "old");
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || write_file_atomically(path, format!("writer {}", i).as_bytes()).unwrap());
+            }
+        });
+        assert!(fs::read_to_string(&path).unwrap().starts_with("writer "));
+        assert!(leftovers(dir.path()).is_empty());
+    }
+
+
+    #[actix_web::test]
+    async fn hashed_reads_digest_the_whole_file() {
+        let dir = TempDir::new();
+        let path = dir.write("a.txt", "0123456789");
+        let whole = blake3::hash(b"0123456789").to_hex().to_string();
+        let cache = FileCache::new(1024);
+        // Uncached and truncated, then cached by the first whole read.
+        for max_bytes in [4, u64::MAX, 4] {
+            let (read, hash) = read_hashed_file_content(&path, Transport::Text, max_bytes, &cache).await.unwrap();
+            assert_eq!(read.truncated, max_bytes == 4);
+            assert_eq!(hash, whole, "max_bytes={}", max_bytes);
         }
     }
-    Ok(tree)
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+use crate::dependency_analyzer::DependencyGraph;
+use crate::models::TreeNode;
+use crate::utils::{natural_compare, to_api_path};
+use indexmap::IndexMap;
+use similar::TextDiff;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Graphs with more nodes than this are clustered into one subgraph per top-level directory.
+const MERMAID_CLUSTER_THRESHOLD: usize = 50;
+
+/// Renders the dependency graph as a Mermaid `flowchart LR` diagram, with nodes labeled
+/// by their path relative to `root`.
+pub fn to_mermaid(graph: &DependencyGraph, root: &Path) -> String {
+    let mut files: Vec<&String> = graph
+        .iter()
+        .flat_map(|(file, deps)| std::iter::once(file).chain(deps.iter().map(|dep| &dep.target)))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    files.sort_by(|a, b| natural_compare(a, b));
+
+    let ids: HashMap<&str, String> = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (file.as_str(), format!("n{}", i)))
+        .collect();
+    let label = |file: &str| -> String {
+        Path::new(file)
+            .strip_prefix(root)
+            .map(to_api_path)
+            .unwrap_or_else(|_| file.to_string())
+    };
+    let node = |file: &str| format!("{}[\"{}\"]", ids[file], escape_mermaid(&label(file)));
+
+    let mut out = String::from("flowchart LR\n");
+
+    if files.len() > MERMAID_CLUSTER_THRESHOLD {
+        // Group files by the first component of their relative path; root-level files stay unclustered.
+        let mut clusters: Vec<(String, Vec<&str>)> = Vec::new();
+        for file in &files {
+            let relative = label(file);
+            match relative.split_once('/') {
+                Some((dir, _)) => match clusters.iter_mut().find(|(name, _)| name == dir) {
+                    Some((_, members)) => members.push(file),
+                    None => clusters.push((dir.to_string(), vec![file])),
+                },
+                None => out.push_str(&format!("    {}\n", node(file))),
+            }
+        }
+        clusters.sort_by(|(a, _), (b, _)| natural_compare(a, b));
+        for (i, (dir, members)) in clusters.iter().enumerate() {
+            out.push_str(&format!("    subgraph c{}[\"{}\"]\n", i, escape_mermaid(dir)));
+            for file in members {
+                out.push_str(&format!("        {}\n", node(file)));
+            }
+            out.push_str("    end\n");
+        }
+    } else {
+        for file in &files {
+            out.push_str(&format!("    {}\n", node(file)));
+        }
+    }
+
+    let mut sources: Vec<&String> = graph.keys().collect();
+    sources.sort_by(|a, b| natural_compare(a, b));
+    for source in sources {
+        for dep in &graph[source] {
+            out.push_str(&format!("    {} --> {}\n", ids[source.as_str()], ids[dep.target.as_str()]));
+        }
+    }
+
+    out
+}
+
+/// Escapes characters that would terminate a quoted Mermaid label.
+fn escape_mermaid(label: &str) -> String {
+    label.replace('"', "#quot;")
+}
+
+/// Renders a file tree like the Unix `tree` command, with directories suffixed by `/`.
+/// Levels deeper than `max_depth` (if given) are omitted.
+pub fn to_tree_text(tree: &IndexMap<String, TreeNode>, max_depth: Option<usize>) -> String {
+    fn walk(nodes: &IndexMap<String, TreeNode>, prefix: &str, depth: usize, max_depth: Option<usize>, out: &mut String) {
+        for (index, (name, node)) in nodes.iter().enumerate() {
+            let is_last = index == nodes.len() - 1;
+            let suffix = if node.node_type == "folder" { "/" } else { "" };
+            out.push_str(&format!("{}{}{}{}\n", prefix, if is_last { "└── " } else { "├── " }, name, suffix));
+            if let Some(children) = &node.children {
+                if max_depth.is_none_or(|max| depth < max) {
+                    let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+                    walk(children, &child_prefix, depth + 1, max_depth, out);
+                }
+            }
+        }
+    }
+
+    let mut out = String::new();
+    walk(tree, "", 1, max_depth, &mut out);
+    out
+}
+
+/// Renders a unified diff (`--- a/path`, `+++ b/path`, hunks with three lines of context)
+/// from `old` to `new`. Empty if they are identical.
+pub fn to_unified_diff(old: &str, new: &str, label: &str) -> String {
+    let label = label.trim_start_matches('/');
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", label), &format!("b/{}", label))
+        .to_string()
+}
\ No newline at end of file
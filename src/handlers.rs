@@ -1,43 +1,600 @@
-use crate::dependency_analyzer::{analyze_dependencies, expand_init_dependencies};
-use crate::file_system::{build_tree, validate_path};
-use crate::models::{DirectoryQuery, FileResult, FilesRequest};
-use actix_web::{get, post, web, HttpRequest, HttpResponse};
-use futures::stream::{self, StreamExt};
-use ignore::gitignore::Gitignore;
+use crate::config::ServerMode;
+use crate::dependency_analyzer::{
+    analyze_files, dependency_cache_path, expand_init_dependencies, external_dependencies, is_dag, tarjan_sccs, topological_sort,
+    DependencyGraph, DisabledLanguages, ExportsMap, EdgeFlags,
+};
+use crate::file_system::{
+    annotate_estimates, breadcrumbs, build_tree, compute_loc, file_metadata, find_duplicates, diff_snapshots, file_too_large_message, into_ordered_tree,
+    blake3_file, canonicalize_path, checksum_file, file_etag, files_of_languages, git_show, largest_files, move_across_devices, recently_modified, oversized_file, paginate_tree, write_file_atomically, write_files_atomically, stat_path, tail_file, text_estimates, read_file_content, read_hashed_file_content, map_text_file, read_text_file, rollup_approx_tokens, snapshot_etag, tree_snapshot, validate_path,
+    ChecksumAlgorithm, FileContent, GitignoreStack, MappedText, TreeOptions, TreeReport, TreeSnapshot, Transport,
+};
+use crate::formatters::{to_mermaid, to_tree_text, to_unified_diff};
+use crate::markdown::render_context_markdown;
+use crate::middleware::block;
+use crate::models::{
+    BatchDependenciesRequest, BreadcrumbsQuery, CallgraphQuery, ChecksumQuery, ChecksumResponse, CodemapFile, CodemapRequest, DiffQuery, DiffRequest, DirectoriesRequest, CustomQueryRequest, DirectoryQuery, EditsRequest, FileCounts, FileEdits, FileRequest, FileResult, FilesRequest, MarkdownContextRequest, MkdirRequest,
+    PathStat, RecentlyModifiedQuery, RenameRequest, RootRequest, SearchFileMatches, SearchQuery, StatQuery, Symbol, SymbolsQuery, StatRequest, StatResult, TailQuery, TodoFile, TodosQuery, TreeNode, WriteFileRequest, WriteFilesRequest,
+};
+use crate::search::{build_matcher, find_todos, fuzzy_find, rank_symbols, search_file, CaseMode, FileList, FileListKey};
+use crate::state::AppState;
+use crate::syntax::{code_map, file_outline, find_callers, run_query, Grammar};
+use crate::utils::{apply_line_edits, normalize_content, on_disk_path, to_api_path, to_nfc};
+use crate::watcher::WatchEvent;
+use actix_files::NamedFile;
+use actix_web::error::{InternalError, JsonPayloadError};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use actix_web::http::{header, StatusCode};
+use actix_web::{delete, get, post, web, HttpRequest, HttpResponse};
+use futures::future::join_all;
+use futures::stream::{self, Stream, StreamExt};
+use indexmap::IndexMap;
 use log::{debug, info, warn};
+use path_clean::PathClean;
 use rust_embed::RustEmbed;
-use serde_json::json;
-use std::collections::HashMap;
-use std::time::Instant;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::fs as tokio_fs;
+use tokio::time::interval;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+
+/// How often `/api/watch` and `/api/events` streams send a keep-alive comment.
+const WATCH_PING_INTERVAL: Duration = Duration::from_secs(15);
+/// Number of files `/api/files/largest` and `/api/directory/recently-modified` return by
+/// default, and the most they return.
+const DEFAULT_LARGEST_FILES: usize = 20;
+const MAX_LARGEST_FILES: usize = 100;
+/// How far back `/api/directory/recently-modified` looks by default.
+const DEFAULT_RECENT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+/// Nodes per page of a paginated `/api/directory` listing by default, and the most allowed.
+const DEFAULT_PAGE_LIMIT: usize = 500;
+const MAX_PAGE_LIMIT: usize = 10_000;
+/// Prefix naming the algorithm of the content hashes `/api/files` returns and compares.
+const HASH_PREFIX: &str = "blake3:";
+/// Number of lines `/api/file/tail` returns by default.
+const DEFAULT_TAIL_LINES: usize = 200;
+/// Results `/api/search/files` and `/api/symbols/search` return by default, and the most
+/// they return.
+const DEFAULT_FUZZY_RESULTS: usize = 50;
+const MAX_FUZZY_RESULTS: usize = 1000;
+/// Matches `/api/search` and `/api/todos` return by default, and the most they return.
+const DEFAULT_SEARCH_RESULTS: usize = 500;
+const MAX_SEARCH_RESULTS: usize = 10_000;
+/// Most lines of context `/api/search` sends on each side of a match.
+const MAX_SEARCH_CONTEXT: usize = 10;
+/// Files `/api/search` and `/api/todos` scan at once on the blocking thread pool.
+const SCAN_CONCURRENCY: usize = 16;
+/// Roots `/api/directories` walks at once.
+const ROOT_CONCURRENCY: usize = 4;
+/// Symbols per page of `/api/symbols/index` by default, and the most allowed.
+const DEFAULT_SYMBOL_PAGE: usize = 500;
+const MAX_SYMBOL_PAGE: usize = 10_000;
+/// Most files `/api/callgraph` scans for calls in one request.
+const MAX_CALLGRAPH_FILES: usize = 10_000;
+/// Longest tree-sitter query `/api/analyze/custom-query` compiles.
+const MAX_CUSTOM_QUERY_BYTES: usize = 2 * 1024;
 
 #[derive(RustEmbed)]
 #[folder = "public/"]
 struct Asset;
 
+/// Confirms the server is reachable and reports the request limits, so that clients can
+/// split large batches before sending them, and the mode, so that read-only servers can hide
+/// editing UI.
 #[get("/api/connect")]
-pub async fn connect() -> HttpResponse {
-    HttpResponse::Ok().json(json!({ "success": true, "message": "Connection successful" }))
+pub async fn connect(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": "Connection successful",
+        "capabilities": {
+            "max_body_bytes": state.config.max_body_bytes,
+            "max_batch_paths": state.config.max_batch_paths,
+            "max_file_read_bytes": state.config.max_file_read_bytes,
+            "max_file_bytes": state.config.max_file_bytes,
+            "max_batch_bytes": state.config.max_batch_bytes,
+            "mode": state.config.mode.name(),
+        },
+    }))
+}
+
+#[get("/api/version")]
+pub async fn version() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "build_date": env!("BUILD_DATE"),
+        "git_sha": env!("GIT_SHA"),
+        "rust_version": env!("RUST_VERSION"),
+    }))
+}
+
+/// A failed request: the HTTP status to answer with and the error message.
+pub struct ApiError {
+    pub status: StatusCode,
+    pub message: String,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError { status, message: message.into() }
+    }
+
+    /// The `{"success": false, "error": ...}` response sent to HTTP clients.
+    fn response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(json!({ "success": false, "error": self.message }))
+    }
+}
+
+/// Turns a JSON body that couldn't be read into a JSON error: 413 naming the limit if it
+/// was too large, 400 otherwise.
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let error = match &err {
+        JsonPayloadError::OverflowKnownLength { limit, .. } | JsonPayloadError::Overflow { limit } => ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Request body exceeds the limit of {} bytes (MAX_BODY_BYTES)", limit),
+        ),
+        _ => ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid request body: {}", err)),
+    };
+    warn!("Rejected request body: {}", err);
+    InternalError::from_response(err, error.response()).into()
+}
+
+/// Rejects batches of more than `MAX_BATCH_PATHS` paths with 413.
+pub fn check_batch_size<T>(paths: &[T], state: &AppState) -> Result<(), ApiError> {
+    let limit = state.config.max_batch_paths;
+    if paths.len() > limit {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("Too many paths: {} requested, the limit is {} (MAX_BATCH_PATHS)", paths.len(), limit),
+        ));
+    }
+    Ok(())
+}
+
+/// Validates the requested root against the allowed roots, defaulting to the working directory.
+fn resolve_root(query: &DirectoryQuery, state: &AppState) -> Result<PathBuf, ApiError> {
+    let base_path_str = query.path.as_deref().unwrap_or(".");
+    state.allowed_roots.validate_root(base_path_str).map_err(|e| {
+        warn!("Path validation failed for '{}': {}", base_path_str, e);
+        ApiError::new(StatusCode::OK, e)
+    })
+}
+
+/// Loads the applicable gitignore rules and builds the tree for `path` on actix's
+/// blocking thread pool, so slow filesystems don't stall the async executor.
+async fn walk_tree(
+    path: PathBuf,
+    options: TreeOptions,
+) -> Result<(IndexMap<String, TreeNode>, TreeReport), String> {
+    block(move || {
+        #[cfg(test)]
+        tests::wait_at_walk_gate(&path);
+        let gitignore = GitignoreStack::load(&path);
+        let mut report = TreeReport::default();
+        build_tree(&path, &gitignore, &options, &mut report).map(|tree| (tree, report))
+    })
+    .await
+    .map_err(|e| format!("Tree walk failed: {}", e))?
+}
+
+/// A walked tree together with the snapshot its etag was computed from.
+struct WalkedTree {
+    tree: IndexMap<String, TreeNode>,
+    report: TreeReport,
+    snapshot: TreeSnapshot,
+    etag: String,
+}
+
+/// Parses the tree options from the query, walks `path` and snapshots the file mtimes,
+/// both on the blocking thread pool. Fails if the options are invalid or the walk fails.
+async fn walk_with_snapshot(
+    path: &Path,
+    query: &DirectoryQuery,
+) -> Result<WalkedTree, ApiError> {
+    let options = TreeOptions::from_query(query).map_err(|e| {
+        warn!("Invalid tree options for '{}': {}", path.display(), e);
+        ApiError::new(StatusCode::BAD_REQUEST, e)
+    })?;
+
+    let (tree, report) = walk_tree(path.to_path_buf(), options).await.map_err(|e| {
+        warn!("Failed to build tree for '{}': {}", path.display(), e);
+        ApiError::new(StatusCode::OK, e)
+    })?;
+
+    let root = path.to_path_buf();
+    let (tree, snapshot, etag) = block(move || {
+        let snapshot = tree_snapshot(&root, &tree);
+        let etag = snapshot_etag(&snapshot);
+        (tree, snapshot, etag)
+    })
+    .await
+    .map_err(|e| {
+        warn!("Failed to snapshot tree for '{}': {}", path.display(), e);
+        ApiError::new(StatusCode::OK, format!("Tree walk failed: {}", e))
+    })?;
+
+    Ok(WalkedTree { tree, report, snapshot, etag })
+}
+
+/// Builds the full `/api/directory` body, remembers its snapshot for later deltas and
+/// allows the file endpoints to serve files under the root.
+async fn directory_listing(
+    path: &Path,
+    query: &DirectoryQuery,
+    state: &AppState,
+    walked: WalkedTree,
+) -> Value {
+    let WalkedTree { mut tree, report, snapshot, etag } = walked;
+    state.remember_snapshot(path, &etag, snapshot);
+    state.allowed_roots.allow(path);
+
+    if query.estimates.unwrap_or(false) {
+        annotate_estimates(&mut tree).await;
+        if query.stats.unwrap_or(false) {
+            rollup_approx_tokens(&mut tree);
+        }
+    }
+
+    if query.cursor.is_some() || query.limit.is_some() {
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+        let (nodes, next_cursor) = match paginate_tree(tree, query.cursor.as_deref(), limit) {
+            Ok(page) => page,
+            Err(e) => {
+                warn!("Rejected directory page for '{}': {}", path.display(), e);
+                return json!({ "success": false, "error": e });
+            }
+        };
+        return json!({
+            "success": true,
+            "root": to_api_path(path),
+            "treeHash": etag,
+            "nodes": nodes,
+            "next_cursor": next_cursor,
+            "warnings": report.warnings,
+            "pruned": report.pruned,
+            "truncated": report.truncated,
+            "entries": report.entries,
+        });
+    }
+
+    // Serializing a large tree takes long enough to stall the worker's other requests.
+    let ordered = query.format.as_deref() == Some("ordered");
+    let tree_json = block(move || {
+        let tree_json = json!(tree);
+        if ordered { into_ordered_tree(tree_json) } else { tree_json }
+    })
+    .await;
+    let tree_json = match tree_json {
+        Ok(tree_json) => tree_json,
+        Err(e) => {
+            warn!("Failed to serialize tree for '{}': {}", path.display(), e);
+            return json!({ "success": false, "error": format!("Tree walk failed: {}", e) });
+        }
+    };
+
+    json!({
+        "success": true,
+        "root": to_api_path(path),
+        "treeHash": etag,
+        "tree": tree_json,
+        "warnings": report.warnings,
+        "pruned": report.pruned,
+        "truncated": report.truncated,
+        "entries": report.entries,
+    })
+}
+
+/// Validates and walks the requested root, returning the `/api/directory` body.
+/// Shared by the HTTP and WebSocket APIs.
+pub async fn list_directory(query: &DirectoryQuery, state: &AppState) -> Result<Value, ApiError> {
+    let path = resolve_root(query, state)?;
+    info!("Processing canonicalized path: {}", path.display());
+    let walked = walk_with_snapshot(&path, query).await?;
+    Ok(directory_listing(&path, query, state, walked).await)
+}
+
+/// Whether the request's `If-None-Match` header lists `tree_hash` (or `*`).
+fn matches_if_none_match(req: &HttpRequest, tree_hash: &str) -> bool {
+    req.headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').map(etag_value).any(|tag| tag == "*" || tag == tree_hash))
+}
+
+/// An ETag without its weak prefix and quotes, for comparison.
+fn etag_value(tag: &str) -> &str {
+    tag.trim().trim_start_matches("W/").trim_matches('"')
+}
+
+/// Whether a file modified at `modified` is unchanged according to the request's
+/// conditional headers. `If-None-Match` takes precedence over `If-Modified-Since`, which
+/// only has second precision.
+fn file_not_modified(req: &HttpRequest, etag: &str, modified: Option<SystemTime>) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return matches_if_none_match(req, etag);
+    }
+    let since = req
+        .headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<header::HttpDate>().ok())
+        .map(SystemTime::from);
+    let secs = |time: SystemTime| time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    matches!((since, modified), (Some(since), Some(modified)) if secs(modified) <= secs(since))
+}
+
+/// Whether the request's `Accept` header asks for `text/plain` rather than JSON, so file
+/// content can be sent raw instead of wrapped in a JSON envelope.
+fn prefers_plain_text(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            let types: Vec<&str> = value
+                .split(',')
+                .map(|media| media.split(';').next().unwrap_or_default().trim())
+                .collect();
+            types.contains(&"text/plain") && !types.contains(&"application/json")
+        })
+}
+
+fn etag_header(tree_hash: &str) -> (header::HeaderName, String) {
+    (header::ETAG, format!("\"{}\"", tree_hash))
+}
+
+fn weak_etag_header(etag: &str) -> (header::HeaderName, String) {
+    (header::ETAG, format!("W/\"{}\"", etag))
 }
 
 #[get("/api/directory")]
-pub async fn get_directory_contents(query: web::Query<DirectoryQuery>) -> HttpResponse {
+pub async fn get_directory_contents(
+    req: HttpRequest,
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received request for directory contents: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let path = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    info!("Processing canonicalized path: {}", path.display());
+
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
+
+    record_recent_root(&state, &path).await;
+
+    let etag = etag_header(&walked.etag);
+    if matches_if_none_match(&req, &walked.etag) {
+        debug!("Directory '{}' not modified.", path.display());
+        state.remember_snapshot(&path, &walked.etag, walked.snapshot);
+        state.allowed_roots.allow(&path);
+        return HttpResponse::NotModified().insert_header(etag).finish();
+    }
+    let listing = directory_listing(&path, &query, &state, walked).await;
+
+    let duration = start_time.elapsed();
+    info!("Successfully processed directory '{}' in {:.2?}.", path.display(), duration);
+    HttpResponse::Ok().insert_header(etag).json(listing)
+}
+
+/// Adds a successfully listed root to the recent roots, off the async executor.
+async fn record_recent_root(state: &web::Data<AppState>, path: &Path) {
+    let state = state.clone();
+    let root = to_api_path(path);
+    if let Err(e) = block(move || state.recent.record(&root)).await {
+        warn!("Failed to record recent root '{}': {}", path.display(), e);
+    }
+}
+
+/// Lists the roots configured with `ALLOWED_ROOTS` or `--root`, so clients can offer them
+/// in a root picker. `restricted` is false (and `roots` empty) when any directory may be browsed.
+#[get("/api/roots")]
+pub async fn get_allowed_roots(state: web::Data<AppState>) -> HttpResponse {
+    let roots: Vec<Value> = state
+        .allowed_roots
+        .configured()
+        .iter()
+        .map(|root| {
+            let name = root.file_name().map_or_else(|| to_api_path(root), |n| n.to_string_lossy().into_owned());
+            json!({ "path": to_api_path(root), "name": name })
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "restricted": state.allowed_roots.is_restricted(),
+        "roots": roots,
+    }))
+}
+
+/// Returns the roots most recently opened through `/api/directory` on this server, most
+/// recent first. Roots that no longer exist are dropped.
+#[get("/api/recent")]
+pub async fn get_recent_roots(state: web::Data<AppState>) -> HttpResponse {
+    let recent = state.clone();
+    match block(move || recent.recent.list()).await {
+        Ok(roots) => HttpResponse::Ok().json(json!({ "success": true, "roots": roots })),
+        Err(e) => {
+            warn!("Failed to read recent roots: {}", e);
+            HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Failed to read recent roots: {}", e) }))
+        }
+    }
+}
+
+/// Removes a root from the recent roots.
+#[delete("/api/recent")]
+pub async fn delete_recent_root(query: web::Query<DirectoryQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let Some(path_str) = query.path.clone() else {
+        return HttpResponse::BadRequest().json(json!({"success": false, "error": "Path is required"}));
+    };
+    let recent = state.clone();
+    match block(move || {
+        // Entries are stored canonicalized, but roots that no longer exist can't be.
+        let canonical = validate_path(&path_str).map(to_api_path).unwrap_or_else(|_| path_str.clone());
+        recent.recent.remove(&canonical) || recent.recent.remove(&path_str)
+    })
+    .await
+    {
+        Ok(removed) => HttpResponse::Ok().json(json!({ "success": true, "removed": removed })),
+        Err(e) => {
+            warn!("Failed to update recent roots: {}", e);
+            HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Failed to update recent roots: {}", e) }))
+        }
+    }
+}
+
+/// Reports the file content cache's size and hit/miss counters.
+#[get("/api/cache/files")]
+pub async fn get_file_cache_stats(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "success": true, "cache": state.file_cache.stats() }))
+}
+
+/// Empties the file content cache. Requires `MODE=readwrite`.
+#[delete("/api/cache/files")]
+pub async fn flush_file_cache(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    if let Some(response) = writes_disabled(&state) {
+        warn!("Rejected file cache flush from {}: the server is read-only.", client_ip(&req));
+        return response;
+    }
+    let flushed = state.file_cache.clear();
+    info!("Flushed {} files from the file content cache.", flushed);
+    HttpResponse::Ok().json(json!({ "success": true, "flushed": flushed }))
+}
+
+/// Lists several roots in one call, each with its own options, walking up to
+/// `ROOT_CONCURRENCY` of them at once. Responds with a map keyed by canonical root (or the
+/// requested path if it is invalid), in request order; a failure on one root is reported in
+/// its entry only. Batches of more than `MAX_BATCH_PATHS` roots are rejected with 413.
+#[post("/api/directories")]
+pub async fn get_directories(req: web::Json<DirectoriesRequest>, state: web::Data<AppState>) -> HttpResponse {
+    let DirectoriesRequest { paths, include_deps } = req.into_inner();
+    info!("Received request for {} directories.", paths.len());
+    let start_time = Instant::now();
+    if let Err(e) = check_batch_size(&paths, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+
+    let include_deps = include_deps.unwrap_or(false);
+    let queries: Vec<DirectoryQuery> = paths.into_iter().map(RootRequest::into_query).collect();
+    let roots: IndexMap<String, Value> = stream::iter(&queries)
+        .map(|query| list_root(query, include_deps, &state))
+        .buffered(ROOT_CONCURRENCY)
+        .collect()
+        .await;
+
+    let duration = start_time.elapsed();
+    info!("Processed {} directories in {:.2?}.", roots.len(), duration);
+    HttpResponse::Ok().json(json!({ "success": true, "roots": roots }))
+}
+
+/// One `/api/directories` entry: the `/api/directory` body, plus the dependency graph if
+/// requested, or `{"success": false, "error": ...}`.
+async fn list_root(query: &DirectoryQuery, include_deps: bool, state: &web::Data<AppState>) -> (String, Value) {
+    let requested = query.path.clone().unwrap_or_else(|| ".".to_string());
+    let path = match resolve_root(query, state) {
+        Ok(p) => p,
+        Err(e) => return (requested, json!({ "success": false, "error": e.message })),
+    };
+    let key = to_api_path(&path);
+    let walked = match walk_with_snapshot(&path, query).await {
+        Ok(w) => w,
+        Err(e) => return (key, json!({ "success": false, "error": e.message })),
+    };
+
+    let graph = if include_deps {
+        match disabled_languages(query.disable.as_deref(), state) {
+            Ok(disabled) => Some(analyze_tree(&path, walked.tree.clone(), disabled, state).await),
+            Err(e) => Some(Err(e)),
+        }
+    } else {
+        None
+    };
+    let mut listing = directory_listing(&path, query, state, walked).await;
+    match graph {
+        Some(Ok((graph, exports, edge_flags))) => {
+            listing["dependencyGraph"] = json!(graph);
+            listing["exports"] = json!(exports);
+            listing["typeCheckingOnly"] = json!(edge_flags.type_checking_only);
+            listing["conditionalIncludes"] = json!(edge_flags.conditional);
+        }
+        Some(Err(e)) => listing["error"] = json!(e.message),
+        None => {}
+    }
+    (key, listing)
+}
+
+/// Decomposes a path into breadcrumbs for navigation, e.g. `[{"name": "src", "path":
+/// "/abs/root/src"}, ...]`, omitting the directories above `root` if one is given.
+#[get("/api/path/breadcrumbs")]
+pub async fn get_path_breadcrumbs(
+    query: web::Query<BreadcrumbsQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let path = match state.allowed_roots.validate(&query.path) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Path validation failed for '{}': {}", query.path, e);
+            return HttpResponse::Ok().json(json!({ "success": false, "error": e }));
+        }
+    };
+    let root = match query.root.as_deref().map(|root| state.allowed_roots.validate(root)).transpose() {
+        Ok(root) => root,
+        Err(e) => {
+            warn!("Root validation failed for '{}': {}", query.root.as_deref().unwrap_or(""), e);
+            return HttpResponse::Ok().json(json!({ "success": false, "error": e }));
+        }
+    };
+    if let Some(root) = &root {
+        if !path.starts_with(root) {
+            return HttpResponse::Ok().json(json!({
+                "success": false,
+                "error": format!("Path '{}' is outside the root '{}'", to_api_path(&path), to_api_path(root)),
+            }));
+        }
+    }
+
+    HttpResponse::Ok().json(json!({ "success": true, "breadcrumbs": breadcrumbs(&path, root.as_deref()) }))
+}
+
+/// Renders the tree as plain text in the style of the Unix `tree` command.
+#[get("/api/directory/tree-text")]
+pub async fn get_directory_tree_text(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
     let base_path_str = query.path.clone().unwrap_or_else(|| ".".to_string());
-    info!("Received request for directory contents: {}", base_path_str);
+    info!("Received request for text tree: {}", base_path_str);
     let start_time = Instant::now();
 
-    let path = match validate_path(&base_path_str) {
+    let path = match state.allowed_roots.validate_root(&base_path_str) {
         Ok(p) => p,
         Err(e) => {
             warn!("Path validation failed for '{}': {}", base_path_str, e);
             return HttpResponse::Ok().json(json!({ "success": false, "error": e }));
         }
     };
-    info!("Processing canonicalized path: {}", path.display());
 
-    let (gitignore, _) = Gitignore::new(&path.join(".gitignore"));
+    let options = match TreeOptions::from_query(&query) {
+        Ok(o) => o,
+        Err(e) => {
+            warn!("Invalid tree options for '{}': {}", path.display(), e);
+            return HttpResponse::BadRequest().json(json!({ "success": false, "error": e }));
+        }
+    };
 
-    let tree = match build_tree(&path, &gitignore) {
+    let (tree, _) = match walk_tree(path.clone(), options).await {
         Ok(t) => t,
         Err(e) => {
             warn!("Failed to build tree for '{}': {}", path.display(), e);
@@ -45,130 +602,3230 @@ pub async fn get_directory_contents(query: web::Query<DirectoryQuery>) -> HttpRe
         }
     };
 
+    let text = to_tree_text(&tree, query.depth);
+
     let duration = start_time.elapsed();
-    info!("Successfully processed directory '{}' in {:.2?}.", path.display(), duration);
-    HttpResponse::Ok().json(json!({
-        "success": true,
-        "root": path.to_str().unwrap_or(""),
-        "tree": tree,
-    }))
+    info!("Rendered text tree for '{}' in {:.2?}.", path.display(), duration);
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(text)
 }
 
-#[get("/api/dependencies")]
-pub async fn get_dependencies(query: web::Query<DirectoryQuery>) -> HttpResponse {
+/// Reports which files were added, removed or modified since the tree identified by `etag`.
+/// Falls back to the full tree (with a fresh etag) when the etag is unknown.
+#[get("/api/directory/delta")]
+pub async fn get_directory_delta(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
     let base_path_str = query.path.clone().unwrap_or_else(|| ".".to_string());
-    info!("Received request for dependencies: {}", base_path_str);
-    let start_time = Instant::now();
+    info!("Received request for directory delta: {}", base_path_str);
 
-    let path = match validate_path(&base_path_str) {
+    let path = match state.allowed_roots.validate_root(&base_path_str) {
         Ok(p) => p,
         Err(e) => {
             warn!("Path validation failed for '{}': {}", base_path_str, e);
             return HttpResponse::Ok().json(json!({ "success": false, "error": e }));
         }
     };
-    info!("Processing dependency analysis for: {}", path.display());
 
-    let (gitignore, _) = Gitignore::new(&path.join(".gitignore"));
+    let previous = query
+        .etag
+        .as_deref()
+        .and_then(|etag| state.find_snapshot(&path, etag));
 
-    let tree = match build_tree(&path, &gitignore) {
-        Ok(t) => t,
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
+
+    let Some(previous) = previous else {
+        debug!("Unknown etag for '{}', returning full tree.", path.display());
+        return HttpResponse::Ok().json(directory_listing(&path, &query, &state, walked).await);
+    };
+
+    if query.etag.as_deref() == Some(walked.etag.as_str()) {
+        return HttpResponse::Ok().json(json!({
+            "success": true,
+            "unchanged": true,
+            "treeHash": walked.etag,
+        }));
+    }
+
+    let delta = diff_snapshots(&previous, &walked.snapshot);
+    info!(
+        "Directory '{}' changed: {} added, {} removed, {} modified.",
+        path.display(),
+        delta.added.len(),
+        delta.removed.len(),
+        delta.modified.len()
+    );
+    state.remember_snapshot(&path, &walked.etag, walked.snapshot);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "unchanged": false,
+        "treeHash": walked.etag,
+        "added": delta.added,
+        "removed": delta.removed,
+        "modified": delta.modified,
+    }))
+}
+
+/// Streams debounced filesystem events under `path` as server-sent events.
+/// Comment pings are sent periodically so disconnected clients are noticed and unsubscribed.
+#[get("/api/watch")]
+pub async fn watch_directory(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    stream_watch_events(query.path.as_deref(), &state, |item| {
+        let payload = match item {
+            Ok(event) => json!(event),
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => json!({ "event": "lagged", "skipped": skipped }),
+        };
+        format!("data: {}\n\n", payload)
+    })
+    .await
+}
+
+/// Like `/api/watch`, but sends each change as a named `change` event, e.g.
+/// `event: change` / `data: {"type":"modified","path":"src/app.py"}`, so `EventSource`
+/// clients can listen for it with `addEventListener("change", ...)`.
+#[get("/api/events")]
+pub async fn stream_events(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    stream_watch_events(query.path.as_deref(), &state, |item| match item {
+        Ok(event) => {
+            let payload = json!({ "type": event.event, "path": event.path });
+            format!("event: change\ndata: {}\n\n", payload)
+        }
+        Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+            format!("event: lagged\ndata: {}\n\n", json!({ "skipped": skipped }))
+        }
+    })
+    .await
+}
+
+/// Subscribes to the watcher of a root (shared with other subscribers) and streams its
+/// events, formatted by `frame`, as server-sent events with periodic pings. The subscription
+/// is dropped with the stream, so the watcher stops once its last client disconnects.
+async fn stream_watch_events(
+    path: Option<&str>,
+    state: &web::Data<AppState>,
+    frame: fn(Result<WatchEvent, BroadcastStreamRecvError>) -> String,
+) -> HttpResponse {
+    let base_path_str = path.unwrap_or(".");
+    info!("Received watch request for: {}", base_path_str);
+
+    let path = match state.allowed_roots.validate_root(base_path_str) {
+        Ok(p) => p,
         Err(e) => {
-            warn!("Failed to build tree for '{}': {}", path.display(), e);
+            warn!("Path validation failed for '{}': {}", base_path_str, e);
             return HttpResponse::Ok().json(json!({ "success": false, "error": e }));
         }
     };
 
-    let dependency_graph = match analyze_dependencies(&path, &tree) {
-        Ok(deps) => deps,
+    let registry = state.watchers.clone();
+    let max_watchers = state.config.max_watchers;
+    let watch_path = path.clone();
+    let subscribed = block(move || registry.subscribe(&watch_path, max_watchers))
+        .await
+        .map_err(|e| format!("Watch failed: {}", e))
+        .and_then(|result| result);
+    let (subscription, receiver) = match subscribed {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Failed to watch '{}': {}", path.display(), e);
+            return HttpResponse::ServiceUnavailable().json(json!({ "success": false, "error": e }));
+        }
+    };
+
+    let events = BroadcastStream::new(receiver).map(move |item| {
+        // Keeps the subscription alive for as long as the client is connected.
+        let _ = &subscription;
+        Ok::<_, actix_web::Error>(web::Bytes::from(frame(item)))
+    });
+    let pings = stream::unfold(interval(WATCH_PING_INTERVAL), |mut ticker| async move {
+        ticker.tick().await;
+        Some((Ok(web::Bytes::from_static(b": ping\n\n")), ticker))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream::select(events, pings))
+}
+
+/// Runs (incremental) dependency analysis over a walked tree and expands `__init__.py` edges.
+/// Returns the graph, the names Python files import with `*` and the edge flags. Parsing
+/// reads every changed file, so it runs on actix's blocking thread pool like the tree walk.
+async fn analyze_tree(
+    path: &Path,
+    tree: IndexMap<String, TreeNode>,
+    disabled: DisabledLanguages,
+    state: &web::Data<AppState>,
+) -> Result<(DependencyGraph, ExportsMap, EdgeFlags), ApiError> {
+    let root = path.to_path_buf();
+    let state = state.clone();
+    let analysis = block(move || {
+        let cache = dependency_cache(&root, disabled, &state);
+        let analyzer = state.analyzer(&root, disabled);
+        let mut analyzer = analyzer.lock().unwrap();
+        analyzer.analyze(&root, &tree, disabled, cache.as_deref()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| {
+        warn!("Dependency analysis failed for path '{}': {}", path.display(), e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Dependency analysis failed: {}", e))
+    })?;
+    let (dependency_graph, exports, edge_flags) = match analysis {
+        Ok(analysis) => analysis,
         Err(e) => {
             warn!("Dependency analysis failed for path '{}': {}", path.display(), e);
-            HashMap::new()
+            Default::default()
         }
     };
+    Ok((expand_init_dependencies(&dependency_graph), exports, edge_flags))
+}
+
+/// Where the dependency graph of `root` is cached, or `None` to not persist it: read-only
+/// servers never write into the analyzed root or the allowed roots, even if `CACHE_DIR`
+/// points there.
+fn dependency_cache(root: &Path, disabled: DisabledLanguages, state: &AppState) -> Option<PathBuf> {
+    let cache_dir = canonicalize_path(&state.config.cache_dir).unwrap_or_else(|_| state.config.cache_dir.clean());
+    let cache = dependency_cache_path(&cache_dir, root, disabled);
+    let in_roots = cache.starts_with(root) || state.allowed_roots.configured().iter().any(|r| cache.starts_with(r));
+    if state.config.mode == ServerMode::ReadOnly && in_roots {
+        debug!("Not caching dependencies of '{}': CACHE_DIR is inside a browsed root.", root.display());
+        return None;
+    }
+    Some(cache)
+}
+
+/// The languages to skip: those disabled by `DISABLE_LANGUAGES` plus the request's own.
+fn disabled_languages(disable: Option<&str>, state: &AppState) -> Result<DisabledLanguages, ApiError> {
+    let requested: DisabledLanguages = disable.unwrap_or("").parse().map_err(|e: String| {
+        warn!("Invalid disable list '{}': {}", disable.unwrap_or(""), e);
+        ApiError::new(StatusCode::BAD_REQUEST, e)
+    })?;
+    Ok(state.config.disabled_languages.union(requested))
+}
+
+/// Validates the requested root, builds its tree and runs dependency analysis.
+/// Returns the canonicalized root, the expanded graph, the Python exports, the edge flags
+/// and the languages skipped.
+#[allow(clippy::type_complexity)]
+pub async fn load_dependency_graph(
+    query: &DirectoryQuery,
+    state: &web::Data<AppState>,
+) -> Result<(PathBuf, DependencyGraph, ExportsMap, EdgeFlags, DisabledLanguages), ApiError> {
+    let path = resolve_root(query, state)?;
+    let disabled = disabled_languages(query.disable.as_deref(), state)?;
+    info!("Processing dependency analysis for: {}", path.display());
+    let walked = walk_with_snapshot(&path, query).await?;
+    let (graph, exports, edge_flags) = analyze_tree(&path, walked.tree, disabled, state).await?;
+    Ok((path, graph, exports, edge_flags, disabled))
+}
+
+/// Responds with `304 Not Modified` when no file in the analyzed tree changed since the
+/// `treeHash` the client sent in `If-None-Match`.
+#[get("/api/dependencies")]
+pub async fn get_dependencies(
+    req: HttpRequest,
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received request for dependencies: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let path = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    let disabled = match disabled_languages(query.disable.as_deref(), &state) {
+        Ok(d) => d,
+        Err(e) => return e.response(),
+    };
+    info!("Processing dependency analysis for: {}", path.display());
+
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
 
-    let expanded_graph = expand_init_dependencies(&dependency_graph);
+    let etag = etag_header(&walked.etag);
+    if matches_if_none_match(&req, &walked.etag) {
+        debug!("Dependencies for '{}' not modified.", path.display());
+        return HttpResponse::NotModified().insert_header(etag).finish();
+    }
+    let (expanded_graph, exports, edge_flags) = match analyze_tree(&path, walked.tree, disabled, &state).await {
+        Ok(analysis) => analysis,
+        Err(e) => return e.response(),
+    };
+    let manifest_root = path.clone();
+    let external = match block(move || external_dependencies(&manifest_root, disabled)).await {
+        Ok(external) => external,
+        Err(e) => {
+            warn!("Failed to read package manifests in '{}': {}", path.display(), e);
+            Default::default()
+        }
+    };
 
     let duration = start_time.elapsed();
     info!("Successfully processed dependencies for '{}' in {:.2?}.", path.display(), duration);
-    HttpResponse::Ok().json(json!({
+    HttpResponse::Ok().insert_header(etag).json(json!({
         "success": true,
-        "root": path.to_str().unwrap_or(""),
+        "root": to_api_path(&path),
+        "treeHash": walked.etag,
         "dependencyGraph": expanded_graph,
+        "exports": exports,
+        "typeCheckingOnly": edge_flags.type_checking_only,
+        "conditionalIncludes": edge_flags.conditional,
+        "external_dependencies": external,
+        "disabled_languages": disabled.names(),
     }))
 }
 
-#[get("/api/file")]
-pub async fn get_file_content(query: web::Query<DirectoryQuery>) -> HttpResponse {
-    let path_str = match &query.path {
-        Some(p) => p,
-        None => {
-            warn!("Received file content request with no path.");
-            return HttpResponse::BadRequest()
-                .json(json!({"success": false, "error": "Path is required"}));
+/// Analyzes only the listed files and returns their direct dependencies, for clients that
+/// already know which files changed. Files outside the root, missing or of disabled
+/// languages are skipped.
+#[post("/api/dependencies/batch")]
+pub async fn get_dependencies_batch(
+    req: web::Json<BatchDependenciesRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received batch dependency request for {} files in '{}'.", req.files.len(), req.root);
+    let start_time = Instant::now();
+
+    let root = match state.allowed_roots.validate_root(&req.root) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Path validation failed for '{}': {}", req.root, e);
+            return HttpResponse::Ok().json(json!({ "success": false, "error": e }));
         }
     };
-    debug!("Reading file: {}", path_str);
-    match tokio_fs::read_to_string(path_str).await {
-        Ok(content) => {
-            debug!("Successfully read file: {}", path_str);
-            HttpResponse::Ok().json(json!({"success": true, "content": content}))
+    let disabled = match disabled_languages(req.disable.as_deref(), &state) {
+        Ok(d) => d,
+        Err(e) => return e.response(),
+    };
+
+    // Checking that each file exists stats the filesystem, so it runs with the analysis.
+    let analyze_root = root.clone();
+    let requested = req.files.clone();
+    let analysis = block(move || {
+        let mut files = Vec::new();
+        let mut skipped = Vec::new();
+        for file in requested {
+            let full_path = analyze_root.join(&file).clean();
+            let api_path = to_nfc(&to_api_path(&full_path));
+            // Resolved through symlinks, so a link inside the root can't point the analysis
+            // at a file outside it.
+            let inside = canonicalize_path(&full_path)
+                .is_ok_and(|real| real.starts_with(&analyze_root) && real.is_file());
+            if inside && disabled.allows_file(&api_path) {
+                files.push(api_path);
+            } else {
+                skipped.push(file);
+            }
         }
+        let analysis = analyze_files(&analyze_root, &files, disabled);
+        (files, skipped, analysis)
+    })
+    .await;
+    let (files, skipped, (mut graph, exports, edge_flags)) = match analysis {
+        Ok(analysis) => analysis,
         Err(e) => {
-            warn!("Failed to read file '{}': {}", path_str, e);
-            HttpResponse::InternalServerError()
-                .json(json!({"success": false, "error": e.to_string()}))
+            warn!("Batch dependency analysis failed for '{}': {}", root.display(), e);
+            return HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Dependency analysis failed: {}", e) }));
         }
+    };
+    // Files without dependencies are reported with an empty list rather than omitted.
+    for file in files {
+        graph.entry(file).or_default();
     }
+
+    let duration = start_time.elapsed();
+    info!("Analyzed dependencies of {} files in '{}' in {:.2?}.", graph.len(), root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "dependencyGraph": graph,
+        "exports": exports,
+        "typeCheckingOnly": edge_flags.type_checking_only,
+        "conditionalIncludes": edge_flags.conditional,
+        "skipped": skipped,
+        "disabled_languages": disabled.names(),
+    }))
 }
 
-#[post("/api/files")]
-pub async fn get_files_content(req: web::Json<FilesRequest>) -> HttpResponse {
-    info!("Received batch request for {} files.", req.paths.len());
+/// Lists the dependency cycles under a root: groups of files that (transitively) import
+/// each other, e.g. `{"cycles": [["/abs/a.py", "/abs/b.py"]], "isDag": false}`.
+#[get("/api/dependencies/cycles")]
+pub async fn get_dependency_cycles(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received request for dependency cycles: {}", query.path.as_deref().unwrap_or("."));
     let start_time = Instant::now();
-    let results: HashMap<String, FileResult> = stream::iter(&req.paths)
-        .then(|path_str| async move {
-            debug!("Reading file in batch: {}", path_str);
-            let result = match tokio_fs::read_to_string(path_str).await {
-                Ok(content) => FileResult {
-                    success: true,
-                    content: Some(content),
-                    error: None,
-                },
-                Err(e) => {
-                    warn!("Failed to read file '{}' in batch: {}", path_str, e);
-                    FileResult {
-                        success: false,
-                        content: None,
-                        error: Some(e.to_string()),
-                    }
-                }
-            };
-            (path_str.clone(), result)
-        })
-        .collect()
-        .await;
+
+    let (path, graph, _, _, disabled) = match load_dependency_graph(&query, &state).await {
+        Ok(result) => result,
+        Err(e) => return e.response(),
+    };
+    let cycles = tarjan_sccs(&graph);
 
     let duration = start_time.elapsed();
-    info!("Batch file request processed in {:.2?}.", duration);
+    info!("Found {} dependency cycles in '{}' in {:.2?}.", cycles.len(), path.display(), duration);
     HttpResponse::Ok().json(json!({
         "success": true,
-        "files": results
+        "root": to_api_path(&path),
+        "cycles": cycles,
+        "isDag": is_dag(&graph),
+        "disabled_languages": disabled.names(),
     }))
 }
 
-pub async fn static_handler(req: HttpRequest) -> HttpResponse {
-    let path = req.path().trim_start_matches('/');
-    let path = if path.is_empty() { "index.html" } else { path };
-    debug!("Serving static asset: {}", path);
+/// Lists the files under a root in dependency order, each after the files it imports, e.g.
+/// `{"order": ["/abs/util.py", "/abs/main.py"], "cycles": []}`. If the imports form cycles
+/// there is no such order: `order` is empty and `cycles` lists them.
+#[get("/api/dependencies/order")]
+pub async fn get_dependency_order(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received request for dependency order: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
 
-    match Asset::get(path) {
-        Some(content) => {
-            let mime = mime_guess::from_path(path).first_or_octet_stream();
-            HttpResponse::Ok()
-                .content_type(mime.as_ref())
-                .body(content.data.into_owned())
+    let (path, graph, _, _, disabled) = match load_dependency_graph(&query, &state).await {
+        Ok(result) => result,
+        Err(e) => return e.response(),
+    };
+    let (order, cycles) = match topological_sort(&graph) {
+        Ok(order) => (order, Vec::new()),
+        Err(cycles) => {
+            warn!("Dependencies of '{}' contain {} cycles; no order exists.", path.display(), cycles.len());
+            (Vec::new(), cycles)
         }
-        None => HttpResponse::NotFound().body("404 Not Found"),
+    };
+
+    let duration = start_time.elapsed();
+    info!("Ordered {} files in '{}' in {:.2?}.", order.len(), path.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&path),
+        "order": order,
+        "cycles": cycles,
+        "disabled_languages": disabled.names(),
+    }))
+}
+
+#[get("/api/dependencies/mermaid")]
+pub async fn get_dependencies_mermaid(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received request for Mermaid dependency graph: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let (path, expanded_graph, _, _, _) = match load_dependency_graph(&query, &state).await {
+        Ok(result) => result,
+        Err(e) => return e.response(),
+    };
+
+    let diagram = to_mermaid(&expanded_graph, &path);
+
+    let duration = start_time.elapsed();
+    info!("Generated Mermaid dependency graph for '{}' in {:.2?}.", path.display(), duration);
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(diagram)
+}
+
+#[get("/api/file")]
+pub async fn get_file_content(
+    req: HttpRequest,
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let path_str = match &query.path {
+        Some(p) => p,
+        None => {
+            warn!("Received file content request with no path.");
+            return HttpResponse::BadRequest()
+                .json(json!({"success": false, "error": "Path is required"}));
+        }
+    };
+    let transport = match transport(query.transport.as_deref()) {
+        Ok(t) => t,
+        Err(e) => return e.response(),
+    };
+    debug!("Reading file: {}", path_str);
+    let path = match state.allowed_roots.check(&on_disk_path(path_str)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing to read '{}': {}", path_str, e);
+            return HttpResponse::Forbidden().json(json!({"success": false, "error": e}));
+        }
+    };
+    let metadata = tokio_fs::metadata(&path).await.ok();
+    let mut ok = HttpResponse::Ok();
+    if let Some(metadata) = &metadata {
+        let etag = file_etag(metadata);
+        let modified = metadata.modified().ok();
+        ok.insert_header(weak_etag_header(&etag));
+        if let Some(modified) = modified {
+            ok.insert_header((header::LAST_MODIFIED, header::HttpDate::from(modified).to_string()));
+        }
+        if file_not_modified(&req, &etag, modified) {
+            debug!("File unchanged: {}", path_str);
+            return HttpResponse::NotModified().insert_header(weak_etag_header(&etag)).finish();
+        }
+    }
+    let limit = file_byte_limit(query.max_bytes, &state);
+    let normalize = query.normalize.unwrap_or(false);
+    let plain_text = prefers_plain_text(&req);
+    if !normalize && !query.counts.unwrap_or(false) {
+        let map_path = path.clone();
+        match block(move || map_text_file(&map_path, transport, limit)).await {
+            Ok(Ok(Some(mapped))) => {
+                if mapped.truncated() {
+                    info!("Truncated '{}' at {} of {} bytes.", path_str, limit, mapped.size);
+                }
+                debug!("Streaming mapped file: {} ({} bytes)", path_str, mapped.size);
+                let content_type = if plain_text { "text/plain; charset=utf-8" } else { "application/json" };
+                return ok.content_type(content_type).streaming(stream_mapped_text(mapped, !plain_text));
+            }
+            Ok(Ok(None)) => {}
+            Ok(Err(e)) => debug!("Not mapping '{}': {}", path_str, e),
+            Err(e) => warn!("Mapping '{}' was cancelled: {}", path_str, e),
+        }
+    }
+    let read = match read_file_content(&path, transport, limit, &state.file_cache).await {
+        Ok(read) => read,
+        Err(e) => {
+            warn!("Failed to read file '{}': {}", path_str, e);
+            return HttpResponse::InternalServerError()
+                .json(json!({"success": false, "error": e.to_string()}));
+        }
+    };
+    if read.truncated {
+        info!("Truncated '{}' at {} of {} bytes.", path_str, limit, read.size);
+    }
+    match read.content {
+        FileContent::Text(text) if plain_text => {
+            debug!("Successfully read file as plain text: {}", path_str);
+            let content = if normalize { normalize_content(&text.content).into_owned() } else { text.content };
+            ok.content_type("text/plain; charset=utf-8").body(content)
+        }
+        FileContent::Binary { .. } if plain_text => {
+            warn!("Refusing to send binary file '{}' as text/plain.", path_str);
+            HttpResponse::NotAcceptable().json(json!({
+                "success": false,
+                "error": "Binary files can't be sent as text/plain; use /api/raw instead",
+            }))
+        }
+        FileContent::Text(text) => {
+            debug!("Successfully read file: {} ({}{})", path_str, text.encoding, if text.lossy { ", lossy" } else { "" });
+            let (content, normalized) = if !normalize {
+                (text.content, None)
+            } else if let Cow::Owned(normalized) = normalize_content(&text.content) {
+                (normalized, Some(true))
+            } else {
+                (text.content, Some(false))
+            };
+            let counts = query.counts.unwrap_or(false).then(|| text_counts(&content));
+            let mut body = json!({
+                "success": true,
+                "content": content,
+                "encoding": text.encoding,
+                "lossy": text.lossy,
+                "size": read.size,
+                "truncated": read.truncated,
+            });
+            if let Some(normalized) = normalized {
+                body["normalized"] = json!(normalized);
+            }
+            if let Some(counts) = counts {
+                body["lines"] = json!(counts.lines);
+                body["bytes"] = json!(counts.bytes);
+                body["approxTokens"] = json!(counts.approx_tokens);
+            }
+            ok.json(body)
+        }
+        FileContent::Binary { bytes, mime } => {
+            debug!("Successfully read binary file: {} ({})", path_str, mime);
+            ok.json(json!({
+                "success": true,
+                "contentBase64": BASE64.encode(&bytes),
+                "encoding": "base64",
+                "mime": mime,
+                "size": read.size,
+                "truncated": read.truncated,
+            }))
+        }
+    }
+}
+
+/// Streams a mapped text file in chunks, either as is or as the JSON body `/api/file` sends
+/// for text, with the content escaped chunk by chunk.
+fn stream_mapped_text(
+    mapped: MappedText,
+    json: bool,
+) -> impl futures::Stream<Item = Result<web::Bytes, io::Error>> {
+    let prefix = json.then(|| web::Bytes::from_static(br#"{"success":true,"content":""#));
+    let suffix = json.then(|| {
+        web::Bytes::from(format!(
+            r#"","encoding":"utf-8","lossy":false,"size":{},"truncated":{}}}"#,
+            mapped.size,
+            mapped.truncated()
+        ))
+    });
+    let chunks = stream::unfold((mapped, 0), move |(mapped, offset)| async move {
+        let chunk = match mapped.next_chunk(offset)? {
+            Ok((text, end)) => {
+                let bytes = if json {
+                    let escaped = serde_json::to_string(text).expect("strings serialize to JSON");
+                    web::Bytes::from(escaped[1..escaped.len() - 1].to_owned())
+                } else {
+                    web::Bytes::copy_from_slice(text.as_bytes())
+                };
+                (Ok(bytes), end)
+            }
+            // Stop after reporting the error.
+            Err(e) => (Err(e), usize::MAX),
+        };
+        Some((chunk.0, (mapped, chunk.1)))
+    });
+    stream::iter(prefix.map(Ok)).chain(chunks).chain(stream::iter(suffix.map(Ok)))
+}
+
+/// Streams a file's raw bytes with its guessed `Content-Type`, `Content-Length`,
+/// `Content-Disposition` and `Range` support, for images in the UI and downloads.
+#[get("/api/raw")]
+pub async fn get_raw_file(
+    req: HttpRequest,
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(path_str) = &query.path else {
+        warn!("Received raw file request with no path.");
+        return HttpResponse::BadRequest().json(json!({"success": false, "error": "Path is required"}));
+    };
+    let path = match state.allowed_roots.check(&on_disk_path(path_str)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing to serve '{}': {}", path_str, e);
+            return HttpResponse::Forbidden().json(json!({"success": false, "error": e}));
+        }
+    };
+    if tokio_fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+        return HttpResponse::BadRequest()
+            .json(json!({"success": false, "error": format!("'{}' is a directory", path_str)}));
+    }
+    match NamedFile::open_async(&path).await {
+        Ok(file) => {
+            debug!("Serving raw file: {}", path_str);
+            file.into_response(&req)
+        }
+        Err(e) => {
+            warn!("Failed to open file '{}': {}", path_str, e);
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            HttpResponse::build(status).json(json!({"success": false, "error": e.to_string()}))
+        }
+    }
+}
+
+/// Returns the last `lines` lines of a text file (default 200) and the byte offset where they
+/// start, reading backwards from the end so large logs aren't transferred whole.
+#[get("/api/file/tail")]
+pub async fn get_file_tail(query: web::Query<TailQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let path_str = &query.path;
+    let path = match state.allowed_roots.check(&on_disk_path(path_str)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing to tail '{}': {}", path_str, e);
+            return HttpResponse::Forbidden().json(json!({"success": false, "error": e}));
+        }
+    };
+    let lines = query.lines.unwrap_or(DEFAULT_TAIL_LINES);
+    let max_bytes = file_byte_limit(query.max_bytes, &state);
+    match tail_file(&path, lines, query.offset.unwrap_or(0), max_bytes).await {
+        Ok(tail) => {
+            debug!("Read the last {} lines of '{}' from offset {}.", tail.lines.len(), path_str, tail.offset);
+            HttpResponse::Ok().json(json!({
+                "success": true,
+                "lines": tail.lines,
+                "encoding": tail.encoding,
+                "offset": tail.offset,
+                "size": tail.size,
+                "truncated": tail.truncated,
+            }))
+        }
+        Err(e) => {
+            warn!("Failed to tail '{}': {}", path_str, e);
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            HttpResponse::build(status).json(json!({"success": false, "error": e.to_string()}))
+        }
+    }
+}
+
+/// Returns the digest of a file without its content (`algorithm=sha256|sha1|blake3`, default
+/// `sha256`), so clients can check that a cached copy is still current.
+#[get("/api/file/checksum")]
+pub async fn get_file_checksum(query: web::Query<ChecksumQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let algorithm: ChecksumAlgorithm = match query.algorithm.as_deref().map_or(Ok(Default::default()), str::parse) {
+        Ok(algorithm) => algorithm,
+        Err(e) => {
+            warn!("Invalid checksum request for '{}': {}", query.path, e);
+            return HttpResponse::BadRequest().json(json!({"success": false, "error": e}));
+        }
+    };
+    let path = match state.allowed_roots.check(&on_disk_path(&query.path)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing to checksum '{}': {}", query.path, e);
+            return HttpResponse::Forbidden().json(json!({"success": false, "error": e}));
+        }
+    };
+    match checksum_file(&path, algorithm).await {
+        Ok(hash) => {
+            debug!("Computed the {} checksum of '{}'.", algorithm.name(), query.path);
+            HttpResponse::Ok().json(ChecksumResponse {
+                success: true,
+                path: to_api_path(&path),
+                algorithm: algorithm.name(),
+                hash,
+            })
+        }
+        Err(e) => {
+            warn!("Failed to checksum '{}': {}", query.path, e);
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::IsADirectory => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            HttpResponse::build(status).json(json!({"success": false, "error": e.to_string()}))
+        }
+    }
+}
+
+/// Parses the `hash` parameter of `/api/stat`: whether to compute a BLAKE3 digest.
+fn stat_hash(value: Option<&str>) -> Result<bool, ApiError> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(false),
+        Some(hash) if hash.eq_ignore_ascii_case("blake3") => Ok(true),
+        Some(other) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Unsupported hash '{}': expected 'blake3'", other),
+        )),
+    }
+}
+
+/// Stats one path for `/api/stat`: 403 outside the allowed roots, 404 if it doesn't exist.
+async fn stat_one(path_str: &str, hash: bool, state: &AppState) -> Result<PathStat, ApiError> {
+    let requested = on_disk_path(path_str);
+    let path = state
+        .allowed_roots
+        .check(&requested)
+        .map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+    stat_path(&requested, &path, state.config.max_file_read_bytes, hash)
+        .await
+        .map_err(|e| {
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            ApiError::new(status, e.to_string())
+        })
+}
+
+/// Returns the size, timestamps, type, MIME type and line count of a file or directory,
+/// plus a BLAKE3 digest of a file with `hash=blake3`, so clients can tell whether to re-fetch.
+#[get("/api/stat")]
+pub async fn get_stat(query: web::Query<StatQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let hash = match stat_hash(query.hash.as_deref()) {
+        Ok(hash) => hash,
+        Err(e) => return e.response(),
+    };
+    match stat_one(&query.path, hash, &state).await {
+        Ok(stat) => HttpResponse::Ok().json(json!({"success": true, "stat": stat})),
+        Err(e) => {
+            warn!("Failed to stat '{}': {}", query.path, e.message);
+            e.response()
+        }
+    }
+}
+
+/// Stats many paths at once. Results are keyed by path, and a path that can't be stat'ed
+/// gets an error entry rather than failing the request.
+#[post("/api/stat")]
+pub async fn post_stat(req: web::Json<StatRequest>, state: web::Data<AppState>) -> HttpResponse {
+    info!("Received bulk stat request for {} paths.", req.paths.len());
+    if let Err(e) = check_batch_size(&req.paths, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+    let hash = match stat_hash(req.hash.as_deref()) {
+        Ok(hash) => hash,
+        Err(e) => return e.response(),
+    };
+    let results: HashMap<String, StatResult> = stream::iter(&req.paths)
+        .then(|path_str| {
+            let state = &state;
+            async move {
+                let result = match stat_one(path_str, hash, state).await {
+                    Ok(stat) => StatResult { success: true, stat: Some(stat), error: None, status: None },
+                    Err(e) => {
+                        debug!("Failed to stat '{}' in bulk: {}", path_str, e.message);
+                        StatResult { success: false, stat: None, error: Some(e.message), status: Some(e.status.as_u16()) }
+                    }
+                };
+                (path_str.clone(), result)
+            }
+        })
+        .collect()
+        .await;
+    HttpResponse::Ok().json(json!({"success": true, "stats": results}))
+}
+
+/// Returns a file's size, mtime, permissions, line count, language and whether it is
+/// binary, without sending (or fully loading) its content.
+#[get("/api/file/metadata")]
+pub async fn get_file_metadata(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let Some(path_str) = &query.path else {
+        warn!("Received file metadata request with no path.");
+        return HttpResponse::BadRequest().json(json!({"success": false, "error": "Path is required"}));
+    };
+    let path = match state.allowed_roots.check(&on_disk_path(path_str)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing to stat '{}': {}", path_str, e);
+            return HttpResponse::Forbidden().json(json!({"success": false, "error": e}));
+        }
+    };
+    match file_metadata(&path, state.config.max_file_read_bytes).await {
+        Ok(metadata) => HttpResponse::Ok().json(json!({"success": true, "metadata": metadata})),
+        Err(e) => {
+            warn!("Failed to read metadata of '{}': {}", path_str, e);
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::InvalidInput => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            HttpResponse::build(status).json(json!({"success": false, "error": e.to_string()}))
+        }
+    }
+}
+
+/// Returns a 403 response with the `read_only` error code unless the server runs with
+/// `MODE=readwrite`. Every endpoint that modifies the filesystem must check this first.
+fn writes_disabled(state: &AppState) -> Option<HttpResponse> {
+    if state.config.mode == ServerMode::ReadWrite {
+        return None;
+    }
+    Some(HttpResponse::Forbidden().json(json!({
+        "success": false,
+        "error": "The server is read-only",
+        "code": "read_only",
+    })))
+}
+
+#[delete("/api/file")]
+pub async fn delete_file(
+    req: HttpRequest,
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(response) = writes_disabled(&state) {
+        warn!("Rejected file deletion from {}: the server is read-only.", client_ip(&req));
+        return response;
+    }
+    let path_str = match &query.path {
+        Some(p) => p,
+        None => {
+            warn!("Received file deletion request with no path.");
+            return HttpResponse::BadRequest()
+                .json(json!({"success": false, "error": "Path is required"}));
+        }
+    };
+    let path = match deletable_path(path_str, &state).await {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("Refusing to delete '{}': {}", path_str, e.message);
+            return e.response();
+        }
+    };
+
+    warn!("Deleting file '{}' at the request of {}.", path.display(), client_ip(&req));
+    match tokio_fs::remove_file(&path).await {
+        Ok(()) => HttpResponse::Ok().json(json!({"success": true})),
+        Err(e) => {
+            warn!("Failed to delete file '{}': {}", path.display(), e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "error": format!("Failed to delete '{}': {}", path.display(), e),
+            }))
+        }
+    }
+}
+
+/// Resolves a file to delete. Only its directory is resolved through symlinks before the
+/// allowed roots are checked, so a symlink is deleted itself rather than the file it points to.
+async fn deletable_path(path_str: &str, state: &AppState) -> Result<PathBuf, ApiError> {
+    let path = on_disk_path(path_str);
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, format!("Not a file path: {}", path_str)));
+    };
+    let parent = state.allowed_roots.check(parent).map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+    let path = parent.join(name);
+    match tokio_fs::symlink_metadata(&path).await {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(ApiError::new(StatusCode::BAD_REQUEST, format!("Not a file: {}", path_str)))
+        }
+        Ok(_) => Ok(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(ApiError::new(StatusCode::NOT_FOUND, format!("File not found: {}", path_str)))
+        }
+        Err(e) => Err(ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read metadata of '{}': {}", path_str, e),
+        )),
+    }
+}
+
+/// Creates a directory and any missing parents. Fails with 409 if something already
+/// exists at the path.
+#[post("/api/directory/mkdir")]
+pub async fn make_directory(
+    req: HttpRequest,
+    body: web::Json<MkdirRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(response) = writes_disabled(&state) {
+        warn!("Rejected directory creation from {}: the server is read-only.", client_ip(&req));
+        return response;
+    }
+    let path = on_disk_path(&body.path).clean();
+    if let Err(e) = state.allowed_roots.check(&path) {
+        warn!("Refusing to create directory '{}': {}", body.path, e);
+        return ApiError::new(StatusCode::FORBIDDEN, e).response();
+    }
+    if path.exists() {
+        let error = if path.is_dir() {
+            format!("Directory already exists: {}", body.path)
+        } else {
+            format!("A file already exists at: {}", body.path)
+        };
+        return ApiError::new(StatusCode::CONFLICT, error).response();
+    }
+
+    info!("Creating directory '{}' at the request of {}.", path.display(), client_ip(&req));
+    match create_dir_within_roots(&path, &state).await {
+        Ok(()) => HttpResponse::Ok().json(json!({ "success": true, "path": to_api_path(&path) })),
+        Err(e) => {
+            warn!("Failed to create directory '{}': {}", body.path, e.message);
+            e.response()
+        }
+    }
+}
+
+/// Resolves one side of a rename: its directory through symlinks, but not the entry itself,
+/// so that a symlink is moved rather than its target.
+fn entry_path(path_str: &str, state: &AppState) -> Result<PathBuf, ApiError> {
+    let path = on_disk_path(path_str).clean();
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, format!("Not a file or directory path: {}", path_str)));
+    };
+    let parent = canonicalize_path(parent).map_err(|e| {
+        ApiError::new(StatusCode::BAD_REQUEST, format!("Directory of '{}' is not accessible: {}", path_str, e))
+    })?;
+    let parent = state.allowed_roots.check(&parent).map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+    Ok(parent.join(name))
+}
+
+/// Whether `to` can't take the place of `from`: a directory only replaces an empty
+/// directory, and a file only a file.
+fn rename_conflict(from: &Path, to: &Path, to_str: &str) -> Option<String> {
+    let to_metadata = to.symlink_metadata().ok()?;
+    match (from.symlink_metadata().is_ok_and(|m| m.is_dir()), to_metadata.is_dir()) {
+        (true, true) if std::fs::read_dir(to).map_or(true, |mut entries| entries.next().is_some()) => {
+            Some(format!("Cannot move a directory onto '{}': it is a directory that is not empty", to_str))
+        }
+        (true, false) => Some(format!("Cannot move a directory onto '{}': it is a file", to_str)),
+        (false, true) => Some(format!("Cannot move a file onto '{}': it is a directory", to_str)),
+        _ => None,
+    }
+}
+
+/// Renames or moves a file or directory within the allowed roots. An existing file at the
+/// destination is replaced, as is an empty directory when moving a directory. Moves to
+/// another filesystem fall back to copying and deleting.
+#[post("/api/directory/rename")]
+pub async fn rename_path(
+    req: HttpRequest,
+    body: web::Json<RenameRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(response) = writes_disabled(&state) {
+        warn!("Rejected rename from {}: the server is read-only.", client_ip(&req));
+        return response;
+    }
+    let (from, to) = match (entry_path(&body.from, &state), entry_path(&body.to, &state)) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => {
+            warn!("Refusing to rename '{}' to '{}': {}", body.from, body.to, e.message);
+            return e.response();
+        }
+    };
+    if from.symlink_metadata().is_err() {
+        return ApiError::new(StatusCode::NOT_FOUND, format!("Not found: {}", body.from)).response();
+    }
+    if from == to {
+        return HttpResponse::Ok().json(json!({ "success": true, "from": to_api_path(&from), "to": to_api_path(&to) }));
+    }
+    if from.is_dir() && to.starts_with(&from) {
+        let error = format!("Cannot move '{}' into itself", body.from);
+        return ApiError::new(StatusCode::BAD_REQUEST, error).response();
+    }
+    if let Some(error) = rename_conflict(&from, &to, &body.to) {
+        return ApiError::new(StatusCode::CONFLICT, error).response();
+    }
+
+    warn!("Renaming '{}' to '{}' at the request of {}.", from.display(), to.display(), client_ip(&req));
+    let result = match tokio_fs::rename(&from, &to).await {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            warn!("'{}' and '{}' are on different filesystems; copying and deleting instead.", from.display(), to.display());
+            let (from, to) = (from.clone(), to.clone());
+            block(move || move_across_devices(&from, &to))
+                .await
+                .unwrap_or_else(|e| Err(io::Error::other(e.to_string())))
+        }
+        result => result,
+    };
+    match result {
+        Ok(()) => HttpResponse::Ok().json(json!({ "success": true, "from": to_api_path(&from), "to": to_api_path(&to) })),
+        Err(e) => {
+            warn!("Failed to rename '{}' to '{}': {}", from.display(), to.display(), e);
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            ApiError::new(status, format!("Failed to rename '{}' to '{}': {}", body.from, body.to, e)).response()
+        }
+    }
+}
+
+/// Reads the on-disk side of a `/api/file/diff`, refusing files outside the allowed roots
+/// or larger than `MAX_FILE_READ_BYTES`.
+async fn read_for_diff(path_str: &str, state: &AppState) -> Result<(PathBuf, String), ApiError> {
+    let path = state
+        .allowed_roots
+        .check(&on_disk_path(path_str))
+        .map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+    let limit = state.config.max_file_read_bytes;
+    if let Some(size) = oversized_file(&path, limit).await {
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, file_too_large_message(size, limit)));
+    }
+    match read_text_file(&path).await {
+        Ok(text) => Ok((path, text.content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(ApiError::new(StatusCode::NOT_FOUND, e.to_string())),
+        Err(e) => Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+fn diff_response(diff: String) -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain; charset=utf-8").body(diff)
+}
+
+/// Returns a unified diff, as `text/plain`, from the file on disk to the content a client
+/// has, e.g. unsaved edits.
+#[post("/api/file/diff")]
+pub async fn diff_file_with_content(req: web::Json<DiffRequest>, state: web::Data<AppState>) -> HttpResponse {
+    match read_for_diff(&req.path, &state).await {
+        Ok((_, on_disk)) => diff_response(to_unified_diff(&on_disk, &req.content, &req.path)),
+        Err(e) => {
+            warn!("Failed to diff '{}': {}", req.path, e.message);
+            e.response()
+        }
+    }
+}
+
+/// Returns a unified diff, as `text/plain`, from a file as of a Git revision (`rev`, e.g.
+/// `HEAD`) to the file on disk.
+#[get("/api/file/diff")]
+pub async fn diff_file_with_revision(query: web::Query<DiffQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let (path, on_disk) = match read_for_diff(&query.path, &state).await {
+        Ok(file) => file,
+        Err(e) => {
+            warn!("Failed to diff '{}': {}", query.path, e.message);
+            return e.response();
+        }
+    };
+    let rev = query.rev.trim().to_string();
+    let at_rev = match block(move || git_show(&path, &rev)).await {
+        Ok(Ok(content)) => content,
+        Ok(Err(e)) => {
+            warn!("Failed to read '{}' at {}: {}", query.path, query.rev, e);
+            return HttpResponse::BadRequest().json(json!({ "success": false, "error": e }));
+        }
+        Err(e) => {
+            warn!("Failed to read '{}' at {}: {}", query.path, query.rev, e);
+            return HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Failed to read the revision: {}", e) }));
+        }
+    };
+    diff_response(to_unified_diff(&at_rev, &on_disk, &query.path))
+}
+
+/// Resolves a path to write to. Its directory must exist and is resolved through symlinks
+/// before the allowed roots are checked, so a new file can't be created through a link
+/// that points outside them.
+fn writable_path(path_str: &str, state: &AppState) -> Result<PathBuf, String> {
+    let path = on_disk_path(path_str);
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(format!("Not a file path: {}", path_str));
+    };
+    let parent = canonicalize_path(parent).map_err(|e| format!("Directory of '{}' is not accessible: {}", path_str, e))?;
+    let path = state.allowed_roots.check(&parent.join(name))?;
+    if path.is_dir() {
+        return Err(format!("Not a file: {}", path_str));
+    }
+    Ok(path)
+}
+
+/// Creates the missing directories above a file to write.
+async fn create_parent_dirs(path_str: &str, state: &AppState) -> Result<(), String> {
+    let path = on_disk_path(path_str).clean();
+    let Some(parent) = path.parent() else {
+        return Err(format!("Not a file path: {}", path_str));
+    };
+    create_dir_within_roots(parent, state).await.map_err(|e| e.message)
+}
+
+/// Creates a directory and its missing parents. The deepest existing ancestor is resolved
+/// through symlinks first, and the directories are only created if they end up under an
+/// allowed root.
+async fn create_dir_within_roots(dir: &Path, state: &AppState) -> Result<(), ApiError> {
+    let Some(existing) = dir.ancestors().find(|dir| dir.exists()) else {
+        return Err(ApiError::new(StatusCode::BAD_REQUEST, format!("'{}' is not accessible", to_api_path(dir))));
+    };
+    let missing = dir.strip_prefix(existing).unwrap_or(Path::new(""));
+    if missing.as_os_str().is_empty() {
+        return Ok(());
+    }
+    let forbidden = |e| ApiError::new(StatusCode::FORBIDDEN, e);
+    let dir = state.allowed_roots.check(existing).map_err(forbidden)?.join(missing);
+    let dir = state.allowed_roots.check(&dir).map_err(forbidden)?;
+    info!("Creating directory '{}'.", dir.display());
+    tokio_fs::create_dir_all(&dir).await.map_err(|e| {
+        let status = match e.kind() {
+            io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, format!("Failed to create directory '{}': {}", to_api_path(&dir), e))
+    })
+}
+
+/// The hex BLAKE3 digest of the file a write would replace, or `None` if it doesn't exist,
+/// which includes files whose directory doesn't exist yet.
+async fn current_hash(path_str: &str, state: &AppState) -> Result<Option<String>, ApiError> {
+    let invalid = |e| ApiError::new(StatusCode::BAD_REQUEST, e);
+    let path = on_disk_path(path_str).clean();
+    if path.parent().is_some_and(|parent| !parent.exists()) {
+        state.allowed_roots.check(&path).map_err(invalid)?;
+        return Ok(None);
+    }
+    let path = writable_path(path_str, state).map_err(invalid)?;
+    match blake3_file(&path).await {
+        Ok(hex) => Ok(Some(hex)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => {
+            Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read '{}': {}", path_str, e)))
+        }
+    }
+}
+
+/// Whether a file's hex BLAKE3 digest (`None` if the file doesn't exist) is the
+/// `expected_hash` a client sent, with or without the `blake3:` prefix.
+fn hash_matches(expected: &str, current: Option<&str>) -> bool {
+    let expected = expected.strip_prefix(HASH_PREFIX).unwrap_or(expected);
+    current.is_some_and(|current| current.eq_ignore_ascii_case(expected))
+}
+
+/// Reads one file of a `/api/edits` request, checks its `expected_hash` and applies its
+/// edits. Returns the file's path, its current content and its edited content.
+async fn prepare_edits(file: &FileEdits, state: &AppState) -> Result<(PathBuf, String, String), ApiError> {
+    let path = writable_path(&file.path, state).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let limit = state.config.max_file_read_bytes;
+    if let Some(size) = oversized_file(&path, limit).await {
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, file_too_large_message(size, limit)));
+    }
+    let bytes = tokio_fs::read(&path).await.map_err(|e| {
+        let status = match e.kind() {
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, format!("Failed to read '{}': {}", file.path, e))
+    })?;
+    if let Some(expected) = &file.expected_hash {
+        if !hash_matches(expected, Some(blake3::hash(&bytes).to_hex().as_str())) {
+            return Err(ApiError::new(StatusCode::CONFLICT, format!("'{}' has changed since it was read", file.path)));
+        }
+    }
+    let content = String::from_utf8(bytes)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, format!("'{}' is not UTF-8 text", file.path)))?;
+    let edited = apply_line_edits(&content, &file.edits)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("Can't edit '{}': {}", file.path, e)))?;
+    Ok((path, content, edited))
+}
+
+/// Applies line edits to several files, all or nothing, and returns a unified diff of each
+/// file's changes. Edits are checked against the files' current content and must not
+/// overlap. With `dry_run` nothing is written, so previews also work in read-only mode;
+/// otherwise this requires `MODE=readwrite`.
+#[post("/api/edits")]
+pub async fn apply_edits(
+    http_req: HttpRequest,
+    req: web::Json<EditsRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let dry_run = req.dry_run.unwrap_or(false);
+    if !dry_run {
+        if let Some(response) = writes_disabled(&state) {
+            warn!("Rejected edits from {}: the server is read-only.", client_ip(&http_req));
+            return response;
+        }
+    }
+    if let Err(e) = check_batch_size(&req.files, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+
+    let prepared = join_all(req.files.iter().map(|file| prepare_edits(file, &state))).await;
+    let mut results = Vec::with_capacity(prepared.len());
+    let mut seen: Vec<PathBuf> = Vec::new();
+    let mut writes: Vec<(PathBuf, String)> = Vec::new();
+    let mut failure: Option<StatusCode> = None;
+    for (file, prepared) in req.files.iter().zip(prepared) {
+        let prepared = prepared.and_then(|(path, content, edited)| {
+            if seen.contains(&path) {
+                return Err(ApiError::new(StatusCode::BAD_REQUEST, "The same file is listed more than once"));
+            }
+            seen.push(path.clone());
+            Ok((path, content, edited))
+        });
+        match prepared {
+            Ok((path, content, edited)) => {
+                results.push(json!({
+                    "path": file.path,
+                    "success": true,
+                    "changed": content != edited,
+                    "diff": to_unified_diff(&content, &edited, &file.path),
+                    "hash": format!("{}{}", HASH_PREFIX, blake3::hash(edited.as_bytes()).to_hex()),
+                }));
+                // Unchanged files are left alone, keeping their modification times.
+                if content != edited {
+                    writes.push((path, edited));
+                }
+            }
+            Err(e) => {
+                warn!("Rejected edits to '{}': {}", file.path, e.message);
+                let mut result = json!({ "path": file.path, "success": false, "error": e.message });
+                if e.status == StatusCode::CONFLICT {
+                    result["code"] = json!("conflict");
+                }
+                results.push(result);
+                failure.get_or_insert(e.status);
+            }
+        }
+    }
+    if let Some(status) = failure {
+        return HttpResponse::build(status).json(json!({ "success": false, "dryRun": dry_run, "files": results }));
+    }
+    if dry_run {
+        return HttpResponse::Ok().json(json!({ "success": true, "dryRun": true, "files": results }));
+    }
+
+    warn!("Editing {} files at the request of {}.", writes.len(), client_ip(&http_req));
+    match block(move || write_files_atomically(&writes)).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(json!({ "success": true, "dryRun": false, "files": results })),
+        Ok(Err((culprit, e))) => {
+            warn!("Failed to write '{}', no files were changed: {}", culprit.display(), e);
+            HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "error": format!("Failed to write '{}', no files were changed: {}", to_api_path(&culprit), e),
+            }))
+        }
+        Err(e) => {
+            warn!("Edits failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "success": false, "error": format!("Edits failed: {}", e) }))
+        }
+    }
+}
+
+/// Writes one file atomically: the content is staged in a temporary sibling and renamed
+/// over the file. With `expected_hash`, the write is refused with 409 if the file no longer
+/// has that content, so concurrent edits aren't clobbered. Requires `MODE=readwrite`.
+#[post("/api/file/write")]
+pub async fn write_file(
+    http_req: HttpRequest,
+    req: web::Json<WriteFileRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(response) = writes_disabled(&state) {
+        warn!("Rejected file write from {}: the server is read-only.", client_ip(&http_req));
+        return response;
+    }
+    let WriteFileRequest { path: path_str, content, create_dirs, expected_hash } = req.into_inner();
+    // Checked before any directories are created, so that a refused write leaves nothing behind.
+    if let Some(expected) = expected_hash {
+        let current = match current_hash(&path_str, &state).await {
+            Ok(current) => current,
+            Err(e) => {
+                warn!("Refusing to write '{}': {}", path_str, e.message);
+                return e.response();
+            }
+        };
+        if !hash_matches(&expected, current.as_deref()) {
+            warn!("Refusing to write '{}': it changed since the client read it.", path_str);
+            return HttpResponse::Conflict().json(json!({
+                "success": false,
+                "error": format!("'{}' has changed since it was read", path_str),
+                "code": "conflict",
+                "hash": current.map(|hex| format!("{}{}", HASH_PREFIX, hex)),
+            }));
+        }
+    }
+    if create_dirs.unwrap_or(false) {
+        if let Err(e) = create_parent_dirs(&path_str, &state).await {
+            warn!("Refusing to write '{}': {}", path_str, e);
+            return HttpResponse::BadRequest().json(json!({ "success": false, "error": e }));
+        }
+    }
+    let path = match writable_path(&path_str, &state) {
+        Ok(path) => path,
+        Err(e) => {
+            warn!("Refusing to write '{}': {}", path_str, e);
+            return HttpResponse::BadRequest().json(json!({ "success": false, "error": e }));
+        }
+    };
+
+    warn!("Writing '{}' at the request of {}.", path.display(), client_ip(&http_req));
+    let hash = format!("{}{}", HASH_PREFIX, blake3::hash(content.as_bytes()).to_hex());
+    let target = path.clone();
+    let written = block(move || {
+        write_file_atomically(&target, content.as_bytes())?;
+        std::fs::metadata(&target)
+    })
+    .await;
+    match written.unwrap_or_else(|e| Err(io::Error::other(e.to_string()))) {
+        Ok(metadata) => {
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64);
+            HttpResponse::Ok().json(json!({
+                "success": true,
+                "path": to_api_path(&path),
+                "size": metadata.len(),
+                "modified": modified,
+                "hash": hash,
+            }))
+        }
+        Err(e) => {
+            warn!("Failed to write '{}': {}", path.display(), e);
+            let status = if e.kind() == io::ErrorKind::PermissionDenied {
+                StatusCode::FORBIDDEN
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            };
+            HttpResponse::build(status)
+                .json(json!({ "success": false, "error": format!("Failed to write '{}': {}", path_str, e) }))
+        }
+    }
+}
+
+/// Writes several files at once, all or nothing: each is staged in a temporary sibling and
+/// they are only renamed into place once all are staged, and renames are rolled back if
+/// one fails. Requires `MODE=readwrite`.
+#[post("/api/files/write")]
+pub async fn write_files(
+    http_req: HttpRequest,
+    req: web::Json<WriteFilesRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Some(response) = writes_disabled(&state) {
+        warn!("Rejected file write from {}: the server is read-only.", client_ip(&http_req));
+        return response;
+    }
+    let paths: Vec<&String> = req.files.keys().collect();
+    if let Err(e) = check_batch_size(&paths, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+
+    let mut files = Vec::with_capacity(req.files.len());
+    let mut failed = Vec::new();
+    for (path_str, content) in &req.files {
+        match writable_path(path_str, &state) {
+            Ok(path) if files.iter().any(|(p, _): &(PathBuf, String)| *p == path) => {
+                failed.push(json!({ "path": path_str, "error": "The same file is listed more than once" }))
+            }
+            Ok(path) => files.push((path, content.clone())),
+            Err(e) => failed.push(json!({ "path": path_str, "error": e })),
+        }
+    }
+    if !failed.is_empty() {
+        warn!("Refusing to write {} files: {} paths are invalid.", req.files.len(), failed.len());
+        return HttpResponse::BadRequest().json(json!({ "success": false, "written": [], "failed": failed }));
+    }
+
+    warn!("Writing {} files at the request of {}.", files.len(), client_ip(&http_req));
+    let paths: Vec<String> = files.iter().map(|(path, _)| to_api_path(path)).collect();
+    match block(move || write_files_atomically(&files)).await {
+        Ok(Ok(())) => HttpResponse::Ok().json(json!({ "success": true, "written": paths, "failed": [] })),
+        Ok(Err((culprit, e))) => {
+            let culprit = to_api_path(&culprit);
+            warn!("Failed to write '{}', no files were changed: {}", culprit, e);
+            let failed: Vec<Value> = paths
+                .iter()
+                .map(|path| {
+                    let error = if *path == culprit {
+                        format!("Failed to write '{}': {}", path, e)
+                    } else {
+                        format!("Not written: '{}' could not be written", culprit)
+                    };
+                    json!({ "path": path, "error": error })
+                })
+                .collect();
+            HttpResponse::InternalServerError().json(json!({ "success": false, "written": [], "failed": failed }))
+        }
+        Err(e) => {
+            warn!("File write failed: {}", e);
+            HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("File write failed: {}", e) }))
+        }
+    }
+}
+
+/// Parses the `as` parameter of the file content endpoints.
+pub fn transport(value: Option<&str>) -> Result<Transport, ApiError> {
+    value.map_or(Ok(Transport::Auto), |value| value.parse().map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e)))
+}
+
+/// Bytes of each file the file content endpoints send: `MAX_FILE_BYTES`, or the request's
+/// `max_bytes`, which may not exceed `MAX_FILE_READ_BYTES`.
+fn file_byte_limit(max_bytes: Option<u64>, state: &AppState) -> u64 {
+    max_bytes.unwrap_or(state.config.max_file_bytes).min(state.config.max_file_read_bytes)
+}
+
+fn failed_file(error: String, status: Option<StatusCode>) -> FileResult {
+    FileResult {
+        error: Some(error),
+        status: status.map(|s| s.as_u16()),
+        ..FileResult::default()
+    }
+}
+
+/// A file to read in a `/api/files` batch, once globs are expanded.
+struct BatchFile<'a> {
+    path: String,
+    etag: Option<&'a str>,
+    hash: Option<&'a str>,
+    /// The glob that matched the file, if it wasn't named explicitly.
+    glob: Option<&'a str>,
+}
+
+/// Appends the paths of the files in a walked tree to `paths`, in tree order.
+fn collect_files(tree: IndexMap<String, TreeNode>, paths: &mut Vec<String>) {
+    for node in tree.into_values() {
+        match node.children {
+            Some(children) => collect_files(children, paths),
+            None if node.node_type == "file" => paths.push(node.path),
+            None => {}
+        }
+    }
+}
+
+/// Expands the globs of a `/api/files` request into the files they match under their roots,
+/// walking each root like `/api/directory` so gitignored files are skipped. A glob that
+/// fails, matches nothing or matches more than `MAX_GLOB_MATCHES` files also gets an entry
+/// of its own, keyed by the glob, so it doesn't silently vanish from the response.
+async fn expand_globs<'a>(
+    files: &'a [FileRequest],
+    state: &AppState,
+) -> (Vec<BatchFile<'a>>, Vec<(String, FileResult)>) {
+    fn notice(glob: &str, error: String, status: StatusCode) -> (String, FileResult) {
+        let mut result = failed_file(error, Some(status));
+        result.matched_by = Some(glob.to_string());
+        (glob.to_string(), result)
+    }
+    let mut batch = Vec::with_capacity(files.len());
+    let mut notices = Vec::new();
+    for file in files {
+        let (glob, root) = match file {
+            FileRequest::Path(path) => {
+                batch.push(BatchFile { path: path.clone(), etag: None, hash: None, glob: None });
+                continue;
+            }
+            FileRequest::Cached { path, etag, hash } => {
+                let (etag, hash) = (etag.as_deref(), hash.as_deref());
+                batch.push(BatchFile { path: path.clone(), etag, hash, glob: None });
+                continue;
+            }
+            FileRequest::Glob { glob, root } => (glob, root),
+        };
+        let root_path = match state.allowed_roots.check(&on_disk_path(root)) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Refusing to expand '{}' under '{}': {}", glob, root, e);
+                notices.push(notice(glob, e, StatusCode::FORBIDDEN));
+                continue;
+            }
+        };
+        let query = DirectoryQuery { file_match: Some(glob.clone()), ..Default::default() };
+        let options = match TreeOptions::from_query(&query) {
+            Ok(options) => options,
+            Err(e) => {
+                notices.push(notice(glob, e, StatusCode::BAD_REQUEST));
+                continue;
+            }
+        };
+        let tree = match walk_tree(root_path, options).await {
+            Ok((tree, _)) => tree,
+            Err(e) => {
+                warn!("Failed to expand '{}' under '{}': {}", glob, root, e);
+                notices.push(notice(glob, e, StatusCode::INTERNAL_SERVER_ERROR));
+                continue;
+            }
+        };
+        let mut paths = Vec::new();
+        collect_files(tree, &mut paths);
+        debug!("Glob '{}' under '{}' matched {} files.", glob, root, paths.len());
+        let limit = state.config.max_glob_matches;
+        if paths.is_empty() {
+            let error = format!("No files under '{}' match '{}'", root, glob);
+            notices.push(notice(glob, error, StatusCode::NOT_FOUND));
+        } else if paths.len() > limit {
+            let error = format!(
+                "'{}' matched {} files; only the first {} were read (MAX_GLOB_MATCHES)",
+                glob,
+                paths.len(),
+                limit
+            );
+            let (key, mut result) = notice(glob, error, StatusCode::PAYLOAD_TOO_LARGE);
+            result.truncated = Some(true);
+            notices.push((key, result));
+            paths.truncate(limit);
+        }
+        batch.extend(paths.into_iter().map(|path| BatchFile { path, etag: None, hash: None, glob: Some(glob) }));
+    }
+    (batch, notices)
+}
+
+/// Reads a batch of files, expanding globs and refusing files outside the allowed roots.
+/// Files that still match the ETag or content hash the client sent are reported as
+/// unchanged instead of read. Each file is cut at the per-file limit, and once
+/// `MAX_BATCH_BYTES` of content has been read the file that crossed it is cut short and the
+/// rest are skipped; the returned flag says whether that happened. Shared by the HTTP and WebSocket APIs.
+pub async fn read_files(
+    files: &[FileRequest],
+    transport: Transport,
+    max_bytes: Option<u64>,
+    state: &AppState,
+) -> (HashMap<String, FileResult>, bool) {
+    let (batch, notices) = expand_globs(files, state).await;
+    let per_file = file_byte_limit(max_bytes, state);
+    let batch_limit = state.config.max_batch_bytes;
+    let mut remaining = batch_limit;
+    let mut batch_truncated = false;
+    let mut results: HashMap<String, FileResult> = notices.into_iter().collect();
+    for file in batch {
+        let path_str = file.path.as_str();
+        let mut result = 'read: {
+            if remaining == 0 {
+                let error = format!("Skipped: the batch reached its limit of {} bytes (MAX_BATCH_BYTES)", batch_limit);
+                batch_truncated = true;
+                break 'read failed_file(error, Some(StatusCode::PAYLOAD_TOO_LARGE));
+            }
+            debug!("Reading file in batch: {}", path_str);
+            let path = match state.allowed_roots.check(&on_disk_path(path_str)) {
+                Ok(p) => p,
+                Err(e) => {
+                    warn!("Refusing to read '{}' in batch: {}", path_str, e);
+                    break 'read failed_file(e, Some(StatusCode::FORBIDDEN));
+                }
+            };
+            let etag = tokio_fs::metadata(&path).await.ok().map(|m| file_etag(&m));
+            if let (Some(etag), Some(cached)) = (&etag, file.etag) {
+                if etag_value(cached) == etag {
+                    debug!("File unchanged in batch: {}", path_str);
+                    break 'read FileResult {
+                        success: true,
+                        etag: Some(etag.clone()),
+                        unchanged: Some(true),
+                        ..FileResult::default()
+                    };
+                }
+            }
+            let limit = per_file.min(remaining);
+            let (read, hash) = match read_hashed_file_content(&path, transport, limit, &state.file_cache).await {
+                Ok((read, hex)) => (read, format!("{}{}", HASH_PREFIX, hex)),
+                Err(e) => {
+                    warn!("Failed to read file '{}' in batch: {}", path_str, e);
+                    break 'read failed_file(e.to_string(), None);
+                }
+            };
+            if file.hash.is_some_and(|cached| cached.eq_ignore_ascii_case(&hash)) {
+                debug!("File unchanged in batch: {}", path_str);
+                break 'read FileResult {
+                    success: true,
+                    etag,
+                    hash: Some(hash),
+                    unchanged: Some(true),
+                    ..FileResult::default()
+                };
+            }
+            if read.truncated {
+                debug!("Truncated '{}' in batch at {} of {} bytes.", path_str, limit, read.size);
+                if limit < per_file {
+                    batch_truncated = true;
+                }
+            }
+            match read.content {
+                FileContent::Text(text) => {
+                    remaining = remaining.saturating_sub(text.content.len() as u64);
+                    FileResult {
+                        success: true,
+                        content: Some(text.content),
+                        file_size: Some(read.size),
+                        encoding: Some(text.encoding.to_string()),
+                        lossy: Some(text.lossy),
+                        is_binary: Some(false),
+                        truncated: Some(read.truncated),
+                        etag,
+                        hash: Some(hash),
+                        ..FileResult::default()
+                    }
+                }
+                FileContent::Binary { bytes, mime } => {
+                    remaining = remaining.saturating_sub(bytes.len() as u64);
+                    FileResult {
+                        success: true,
+                        content_base64: Some(BASE64.encode(&bytes)),
+                        file_size: Some(read.size),
+                        encoding: Some("base64".to_string()),
+                        is_binary: Some(true),
+                        mime: Some(mime),
+                        truncated: Some(read.truncated),
+                        etag,
+                        hash: Some(hash),
+                        ..FileResult::default()
+                    }
+                }
+            }
+        };
+        result.matched_by = file.glob.map(str::to_string);
+        results.insert(file.path, result);
+    }
+    (results, batch_truncated)
+}
+
+/// Line, byte and token counts of text sent to the client, estimated like the tree's.
+fn text_counts(text: &str) -> FileCounts {
+    let (lines, approx_tokens) = text_estimates(text.as_bytes());
+    FileCounts { lines, bytes: text.len(), approx_tokens }
+}
+
+/// Strips BOMs and CRLF line endings from the text files of a batch, flagging each with
+/// whether anything was removed.
+pub fn normalize_files(results: &mut HashMap<String, FileResult>) {
+    for result in results.values_mut() {
+        if let Some(content) = &mut result.content {
+            let normalized = match normalize_content(content) {
+                Cow::Owned(normalized) => Some(normalized),
+                Cow::Borrowed(_) => None,
+            };
+            result.normalized = Some(normalized.is_some());
+            if let Some(normalized) = normalized {
+                *content = normalized;
+            }
+        }
+    }
+}
+
+/// Adds counts to the text files of a batch and returns their totals. Binary, unchanged
+/// and failed files are left without counts.
+pub fn count_files(results: &mut HashMap<String, FileResult>) -> FileCounts {
+    let mut totals = FileCounts::default();
+    for result in results.values_mut() {
+        if let Some(content) = &result.content {
+            let counts = text_counts(content);
+            totals.add(counts);
+            result.counts = Some(counts);
+        }
+    }
+    totals
+}
+
+#[post("/api/files")]
+pub async fn get_files_content(
+    req: web::Json<FilesRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received batch request for {} files.", req.paths.len());
+    if let Err(e) = check_batch_size(&req.paths, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+    let start_time = Instant::now();
+    let transport = match transport(req.transport.as_deref()) {
+        Ok(t) => t,
+        Err(e) => return e.response(),
+    };
+    let (mut results, truncated) = read_files(&req.paths, transport, req.max_bytes, &state).await;
+    if truncated {
+        warn!("Batch file request hit the limit of {} bytes; returning partial results.", state.config.max_batch_bytes);
+    }
+    if req.normalize.unwrap_or(false) {
+        normalize_files(&mut results);
+    }
+    let totals = req.counts.unwrap_or(false).then(|| count_files(&mut results));
+
+    let duration = start_time.elapsed();
+    info!("Batch file request processed in {:.2?}.", duration);
+    let mut body = json!({
+        "success": true,
+        "files": results,
+        "truncated": truncated,
+    });
+    if let Some(totals) = totals {
+        body["totals"] = json!(totals);
+    }
+    HttpResponse::Ok().json(body)
+}
+
+/// Finds text files with identical content under a root, honoring the same tree options
+/// as `/api/directory`. Responds with `{"groups": [[path, path, ...], ...]}`.
+#[get("/api/files/duplicates")]
+pub async fn get_duplicate_files(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received duplicate files request for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let path = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
+
+    let max_bytes = state.config.max_file_read_bytes;
+    let tree = walked.tree;
+    let groups = match block(move || find_duplicates(&tree, max_bytes)).await {
+        Ok(groups) => groups,
+        Err(e) => {
+            warn!("Duplicate detection failed for '{}': {}", path.display(), e);
+            return HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Duplicate detection failed: {}", e) }));
+        }
+    };
+
+    let duration = start_time.elapsed();
+    info!("Found {} groups of duplicate files in '{}' in {:.2?}.", groups.len(), path.display(), duration);
+    HttpResponse::Ok().json(json!({ "success": true, "root": to_api_path(&path), "groups": groups }))
+}
+
+/// Lists the `n` largest files under a root (default 20, at most 100), honoring the same
+/// tree options as `/api/directory`, so gitignored files are skipped.
+#[get("/api/files/largest")]
+pub async fn get_largest_files(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received largest files request for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let path = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
+
+    let n = query.n.unwrap_or(DEFAULT_LARGEST_FILES).min(MAX_LARGEST_FILES);
+    let tree = walked.tree;
+    let files = match block(move || largest_files(&tree, n)).await {
+        Ok(files) => files,
+        Err(e) => {
+            warn!("Listing largest files failed for '{}': {}", path.display(), e);
+            return HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Listing largest files failed: {}", e) }));
+        }
+    };
+
+    let duration = start_time.elapsed();
+    info!("Listed the {} largest files in '{}' in {:.2?}.", files.len(), path.display(), duration);
+    HttpResponse::Ok().json(json!({ "success": true, "root": to_api_path(&path), "files": files }))
+}
+
+/// Lists the `limit` files under a root modified most recently after `since_secs` (a Unix timestamp
+/// in seconds, 24 hours ago by default), newest first. Takes the same tree options as
+/// `/api/directory`, so gitignored files are skipped.
+#[get("/api/directory/recently-modified")]
+pub async fn get_recently_modified(
+    query: web::Query<DirectoryQuery>,
+    recent: web::Query<RecentlyModifiedQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received recently modified files request for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let since = match recent.since_secs {
+        Some(secs) => match UNIX_EPOCH.checked_add(Duration::from_secs(secs)) {
+            Some(since) => since,
+            None => {
+                warn!("Rejected recently modified files request: since_secs {} is out of range.", secs);
+                return ApiError::new(StatusCode::BAD_REQUEST, format!("since_secs {} is out of range", secs)).response();
+            }
+        },
+        None => SystemTime::now() - DEFAULT_RECENT_WINDOW,
+    };
+    let path = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
+
+    let n = recent.limit.unwrap_or(DEFAULT_LARGEST_FILES).min(MAX_LARGEST_FILES);
+    let files: Vec<_> = recently_modified(&walked.tree, since, n)
+        .into_iter()
+        .map(|(path, modified)| json!({ "path": path, "modified": modified }))
+        .collect();
+
+    let duration = start_time.elapsed();
+    info!("Listed {} recently modified files in '{}' in {:.2?}.", files.len(), path.display(), duration);
+    HttpResponse::Ok().json(json!({ "success": true, "root": to_api_path(&path), "files": files }))
+}
+
+/// Counts lines of code per language under a root, split into blank, comment and code
+/// lines, honoring the same tree options as `/api/directory`.
+#[get("/api/metrics/loc")]
+pub async fn get_loc_metrics(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received lines-of-code request for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let path = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    let walked = match walk_with_snapshot(&path, &query).await {
+        Ok(w) => w,
+        Err(e) => return e.response(),
+    };
+
+    let max_bytes = state.config.max_file_read_bytes;
+    let tree = walked.tree;
+    let report = match block(move || compute_loc(&tree, max_bytes)).await {
+        Ok(report) => report,
+        Err(e) => {
+            warn!("Line counting failed for '{}': {}", path.display(), e);
+            return HttpResponse::InternalServerError()
+                .json(json!({ "success": false, "error": format!("Line counting failed: {}", e) }));
+        }
+    };
+
+    let duration = start_time.elapsed();
+    info!("Counted {} lines in {} files in '{}' in {:.2?}.", report.total.lines, report.total.files, path.display(), duration);
+    HttpResponse::Ok().json(json!({ "success": true, "root": to_api_path(&path), "loc": report }))
+}
+
+/// Runs a blocking `scan` over each file on the blocking thread pool, `SCAN_CONCURRENCY`
+/// files at a time, yielding the results in file order.
+fn scan_files<T, F>(files: Vec<String>, scan: F) -> impl Stream<Item = io::Result<(String, T)>>
+where
+    T: Send + 'static,
+    F: Fn(&Path) -> io::Result<T> + Clone + Send + 'static,
+{
+    stream::iter(files)
+        .map(move |file| {
+            let scan = scan.clone();
+            async move {
+                let scanned = block(move || scan(Path::new(&file)).map(|found| (file, found))).await;
+                scanned.map_err(|e| io::Error::other(e.to_string())).and_then(|result| result)
+            }
+        })
+        .buffered(SCAN_CONCURRENCY)
+}
+
+/// Walks a root like `/api/directory` with default options, so gitignored files are
+/// skipped, and lists its files.
+async fn root_files(path: Option<String>, state: &AppState) -> Result<(PathBuf, Vec<String>), ApiError> {
+    let root_query = DirectoryQuery { path, ..Default::default() };
+    let root = resolve_root(&root_query, state)?;
+    let options = TreeOptions::from_query(&root_query).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let tree = match walk_tree(root.clone(), options).await {
+        Ok((tree, _)) => tree,
+        Err(e) => {
+            warn!("Failed to walk '{}': {}", root.display(), e);
+            return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e));
+        }
+    };
+    let mut files = Vec::new();
+    collect_files(tree, &mut files);
+    Ok((root, files))
+}
+
+/// Searches the content of the files under a root, walked like `/api/directory` so
+/// gitignored files are skipped. Binary files are skipped as well.
+#[get("/api/search")]
+pub async fn search_files(
+    query: web::Query<SearchQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received search for '{}' in: {}", query.q, query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    if query.q.is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "Missing search pattern 'q'").response();
+    }
+    let case = match query.case.as_deref().map(str::parse::<CaseMode>).transpose() {
+        Ok(case) => case.unwrap_or_default(),
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, e).response(),
+    };
+    let matcher = match build_matcher(&query.q, query.regex.unwrap_or(false), case) {
+        Ok(m) => m,
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, format!("Invalid pattern: {}", e)).response(),
+    };
+    let (root, files) = match root_files(query.path.clone(), &state).await {
+        Ok(listed) => listed,
+        Err(e) => return e.response(),
+    };
+
+    let max_results = query.max_results.unwrap_or(DEFAULT_SEARCH_RESULTS).clamp(1, MAX_SEARCH_RESULTS);
+    let context = query.context.unwrap_or(0).min(MAX_SEARCH_CONTEXT);
+    let file_count = files.len();
+    let mut searches = scan_files(files, move |path| search_file(&matcher, path, context, max_results));
+
+    let mut results: Vec<SearchFileMatches> = Vec::new();
+    let mut total = 0;
+    let mut truncated = false;
+    while let Some(searched) = searches.next().await {
+        let (file, mut matches) = match searched {
+            Ok(found) => found,
+            Err(e) => {
+                debug!("Skipping file in search: {}", e);
+                continue;
+            }
+        };
+        if matches.is_empty() {
+            continue;
+        }
+        if total + matches.len() >= max_results {
+            truncated = true;
+            matches.truncate(max_results - total);
+            total = max_results;
+            results.push(SearchFileMatches { file, matches });
+            break;
+        }
+        total += matches.len();
+        results.push(SearchFileMatches { file, matches });
+    }
+
+    let duration = start_time.elapsed();
+    info!("Search for '{}' found {} matches in {} of {} files under '{}' in {:.2?}.", query.q, total, results.len(), file_count, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "files": results,
+        "totalMatches": total,
+        "truncated": truncated,
+    }))
+}
+
+/// Lists the comments tagged `TODO`, `FIXME` or `HACK` (or the given `tags`) in the text
+/// files under a root, walked like `/api/search`, with how many each folder has. In
+/// languages without a grammar, tags anywhere on a line count.
+#[get("/api/todos")]
+pub async fn get_todos(query: web::Query<TodosQuery>, state: web::Data<AppState>) -> HttpResponse {
+    info!("Received TODO request for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let tags: Vec<String> = query
+        .tags
+        .as_deref()
+        .unwrap_or("TODO,FIXME,HACK")
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect();
+    if tags.is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "No tags to look for").response();
+    }
+    let (root, files) = match root_files(query.path.clone(), &state).await {
+        Ok(listed) => listed,
+        Err(e) => return e.response(),
+    };
+
+    let max_results = query.max_results.unwrap_or(DEFAULT_SEARCH_RESULTS).clamp(1, MAX_SEARCH_RESULTS);
+    let max_bytes = state.config.max_file_read_bytes;
+    let file_count = files.len();
+    let mut scans = scan_files(files, move |path| find_todos(path, &tags, max_bytes));
+
+    let root_str = to_nfc(&to_api_path(&root));
+    let mut results: Vec<TodoFile> = Vec::new();
+    // Tagged comments per folder relative to the root (`.`), including subfolders.
+    let mut folders: BTreeMap<String, usize> = BTreeMap::new();
+    let mut total = 0;
+    let mut truncated = false;
+    while let Some(scanned) = scans.next().await {
+        let (file, mut todos) = match scanned {
+            Ok(found) => found,
+            Err(e) => {
+                debug!("Skipping file in TODO scan: {}", e);
+                continue;
+            }
+        };
+        if todos.is_empty() {
+            continue;
+        }
+        if total + todos.len() >= max_results {
+            truncated = true;
+            todos.truncate(max_results - total);
+        }
+        total += todos.len();
+        let relative = file.strip_prefix(&root_str).unwrap_or(&file).trim_start_matches('/');
+        let mut folder = ".".to_string();
+        *folders.entry(folder.clone()).or_default() += todos.len();
+        if let Some((dir, _)) = relative.rsplit_once('/') {
+            for part in dir.split('/') {
+                folder = if folder == "." { part.to_string() } else { format!("{}/{}", folder, part) };
+                *folders.entry(folder.clone()).or_default() += todos.len();
+            }
+        }
+        results.push(TodoFile { file, todos });
+        if truncated {
+            break;
+        }
+    }
+
+    let duration = start_time.elapsed();
+    info!("Found {} TODOs in {} of {} files under '{}' in {:.2?}.", total, results.len(), file_count, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "files": results,
+        "folders": folders,
+        "total": total,
+        "truncated": truncated,
+    }))
+}
+
+/// Lists the files of the given languages under a root, walked like `/api/directory`, as a
+/// flat list. Languages are detected as for `/api/file/metadata`: by extension, or by
+/// shebang for files without a known one.
+#[get("/api/directory/search-by-language")]
+pub async fn search_by_language(query: web::Query<DirectoryQuery>, state: web::Data<AppState>) -> HttpResponse {
+    info!("Received search by language for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let languages: Vec<String> = query
+        .language
+        .as_deref()
+        .unwrap_or("")
+        .split(',')
+        .map(|language| language.trim().to_ascii_lowercase())
+        .filter(|language| !language.is_empty())
+        .collect();
+    if languages.is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "Missing 'language', e.g. language=python,rust").response();
+    }
+    let root = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    let options = match TreeOptions::from_query(&query) {
+        Ok(options) => options,
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, e).response(),
+    };
+    let tree = match walk_tree(root.clone(), options).await {
+        Ok((tree, _)) => tree,
+        Err(e) => {
+            warn!("Failed to walk '{}' for search by language: {}", root.display(), e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e).response();
+        }
+    };
+    let mut files = Vec::new();
+    collect_files(tree, &mut files);
+    let file_count = files.len();
+
+    let files = match block(move || files_of_languages(files, &languages)).await {
+        Ok(files) => files,
+        Err(e) => {
+            let error = format!("Search by language failed: {}", e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+        }
+    };
+
+    let duration = start_time.elapsed();
+    info!("Found {} of {} files in the requested languages under '{}' in {:.2?}.", files.len(), file_count, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "count": files.len(),
+        "files": files,
+    }))
+}
+
+/// Fuzzy-matches a pattern against the paths of the files under a root, listed like
+/// `/api/directory`. The file list is cached per root and reused while no folder in it
+/// has changed, so that a search per keystroke stays cheap.
+#[get("/api/search/files")]
+pub async fn search_file_names(
+    query: web::Query<DirectoryQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pattern = query.q.as_deref().unwrap_or("").to_string();
+    debug!("Received file name search for '{}' in: {}", pattern, query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    if pattern.trim().is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "Missing search pattern 'q'").response();
+    }
+    let root = match resolve_root(&query, &state) {
+        Ok(p) => p,
+        Err(e) => return e.response(),
+    };
+    // Only the visibility rules apply; the file filters of `/api/directory` do not.
+    let list_query = DirectoryQuery {
+        show_hidden: query.show_hidden,
+        hide_hidden: query.hide_hidden,
+        always_show: query.always_show.clone(),
+        default_excludes: query.default_excludes,
+        ..Default::default()
+    };
+    let options = match TreeOptions::from_query(&list_query) {
+        Ok(options) => options,
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, e).response(),
+    };
+    let key = FileListKey::new(&root, &options);
+
+    let cached = match state.find_file_list(&key) {
+        Some(list) => block(move || list.is_fresh().then_some(list)).await.ok().flatten(),
+        None => None,
+    };
+    let list = match cached {
+        Some(list) => list,
+        None => {
+            let (tree, report) = match walk_tree(root.clone(), options).await {
+                Ok(walked) => walked,
+                Err(e) => {
+                    warn!("Failed to walk '{}' for file name search: {}", root.display(), e);
+                    return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e).response();
+                }
+            };
+            let list_root = root.clone();
+            let list = match block(move || FileList::new(&list_root, &tree, report.truncated)).await {
+                Ok(list) => Arc::new(list),
+                Err(e) => {
+                    let error = format!("File listing failed: {}", e);
+                    return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+                }
+            };
+            debug!("Cached {} file names for '{}'.", list.files.len(), root.display());
+            state.remember_file_list(key, list.clone());
+            list
+        }
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_FUZZY_RESULTS).clamp(1, MAX_FUZZY_RESULTS);
+    let truncated = list.truncated;
+    let file_count = list.files.len();
+    let results = match block(move || fuzzy_find(&list, &pattern, limit)).await {
+        Ok(results) => results,
+        Err(e) => {
+            let error = format!("File name search failed: {}", e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+        }
+    };
+
+    let duration = start_time.elapsed();
+    debug!("File name search ranked {} of {} files under '{}' in {:.2?}.", results.len(), file_count, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "results": results,
+        "truncated": truncated,
+    }))
+}
+
+/// The grammars named by a comma-separated `language` parameter, or all of them if it is
+/// absent or empty. Fails with 400 on an unknown name.
+fn requested_grammars(language: Option<&str>) -> Result<Vec<&'static Grammar>, ApiError> {
+    let mut grammars = Vec::new();
+    for name in language.unwrap_or("").split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        match Grammar::by_name(name) {
+            Some(grammar) => grammars.push(grammar),
+            None => {
+                let error = format!("Unknown language '{}'; expected one of: {}", name, Grammar::names().join(", "));
+                return Err(ApiError::new(StatusCode::BAD_REQUEST, error));
+            }
+        }
+    }
+    if grammars.is_empty() {
+        grammars.extend(Grammar::all());
+    }
+    Ok(grammars)
+}
+
+/// Brings the root's symbol index up to date with a fresh walk (only changed files are
+/// re-parsed) and returns the root, the files walked and the symbols of files of the given
+/// languages, in tree order. The walk and parsing run on the blocking thread pool.
+async fn indexed_symbols(
+    path: Option<&str>,
+    grammars: &[&'static Grammar],
+    state: &web::Data<AppState>,
+) -> Result<(PathBuf, Vec<String>, Vec<Symbol>), ApiError> {
+    let extensions: Vec<&str> = Grammar::all().iter().flat_map(|g| g.extensions.iter().copied()).collect();
+    let root_query = DirectoryQuery {
+        path: path.map(str::to_string),
+        extensions: Some(extensions.join(",")),
+        ..Default::default()
+    };
+    let root = resolve_root(&root_query, state)?;
+    let options = TreeOptions::from_query(&root_query).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let (tree, _) = walk_tree(root.clone(), options).await.map_err(|e| {
+        warn!("Failed to walk '{}' for symbols: {}", root.display(), e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e)
+    })?;
+    let mut files = Vec::new();
+    collect_files(tree, &mut files);
+
+    let index = state.symbol_index(&root);
+    let root_str = to_nfc(&to_api_path(&root));
+    let max_bytes = state.config.max_file_read_bytes;
+    let (files, symbols) = block(move || {
+        let symbols = index.lock().unwrap().update(&root_str, &files, max_bytes);
+        (files, symbols)
+    })
+    .await
+    .map_err(|e| {
+        warn!("Symbol extraction failed under '{}': {}", root.display(), e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Symbol extraction failed: {}", e))
+    })?;
+    let symbols = if grammars.len() == Grammar::all().len() {
+        symbols
+    } else {
+        symbols
+            .into_iter()
+            .filter(|symbol| Grammar::for_path(Path::new(&symbol.file)).is_some_and(|g| grammars.iter().any(|r| r.name == g.name)))
+            .collect()
+    };
+    Ok((root, files, symbols))
+}
+
+/// Lists the definitions (functions, classes, structs, ...) in the files of the given
+/// languages under a root, walked like `/api/directory`, one page at a time.
+#[get("/api/symbols/index")]
+pub async fn get_symbol_index(
+    query: web::Query<SymbolsQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received symbol index request for: {}", query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    let grammars = match requested_grammars(query.language.as_deref()) {
+        Ok(grammars) => grammars,
+        Err(e) => return e.response(),
+    };
+    let (root, _, mut symbols) = match indexed_symbols(query.path.as_deref(), &grammars, &state).await {
+        Ok(indexed) => indexed,
+        Err(e) => return e.response(),
+    };
+    if let Some(q) = query.q.as_deref().map(str::to_lowercase).filter(|q| !q.is_empty()) {
+        symbols.retain(|symbol| symbol.name.to_lowercase().contains(&q));
+    }
+
+    let total = symbols.len();
+    let offset = query.offset.unwrap_or(0).min(total);
+    let limit = query.limit.unwrap_or(DEFAULT_SYMBOL_PAGE).clamp(1, MAX_SYMBOL_PAGE);
+    let page: Vec<_> = symbols.into_iter().skip(offset).take(limit).collect();
+    let next_offset = (offset + page.len() < total).then_some(offset + page.len());
+
+    let duration = start_time.elapsed();
+    info!("Listed {} symbols under '{}' in {:.2?}.", total, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "symbols": page,
+        "total": total,
+        "next_offset": next_offset,
+    }))
+}
+
+/// Finds the definitions whose names match `q` under a root, best matches first.
+#[get("/api/symbols/search")]
+pub async fn search_symbols(
+    query: web::Query<SymbolsQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let pattern = query.q.as_deref().unwrap_or("").trim().to_string();
+    debug!("Received symbol search for '{}' in: {}", pattern, query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    if pattern.is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "Missing search pattern 'q'").response();
+    }
+    let grammars = match requested_grammars(query.language.as_deref()) {
+        Ok(grammars) => grammars,
+        Err(e) => return e.response(),
+    };
+    let (root, _, mut symbols) = match indexed_symbols(query.path.as_deref(), &grammars, &state).await {
+        Ok(indexed) => indexed,
+        Err(e) => return e.response(),
+    };
+    let kinds: Vec<&str> = query.kind.as_deref().unwrap_or("").split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+    if !kinds.is_empty() {
+        symbols.retain(|symbol| kinds.contains(&symbol.kind.as_str()));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_FUZZY_RESULTS).clamp(1, MAX_FUZZY_RESULTS);
+    let symbol_count = symbols.len();
+    let results = match block(move || rank_symbols(symbols, &pattern, limit)).await {
+        Ok(results) => results,
+        Err(e) => {
+            let error = format!("Symbol search failed: {}", e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+        }
+    };
+
+    let duration = start_time.elapsed();
+    debug!("Symbol search ranked {} of {} symbols under '{}' in {:.2?}.", results.len(), symbol_count, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "results": results,
+    }))
+}
+
+/// Outlines one file: its definitions nested as in the source, with their signatures and
+/// docstrings or leading comments. Files without a grammar get an empty outline with
+/// `supported: false`.
+#[get("/api/outline")]
+pub async fn get_outline(query: web::Query<DirectoryQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let Some(path_str) = &query.path else {
+        return ApiError::new(StatusCode::BAD_REQUEST, "Path is required").response();
+    };
+    debug!("Received outline request for: {}", path_str);
+    let start_time = Instant::now();
+
+    let path = match state.allowed_roots.check(&on_disk_path(path_str)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing to outline '{}': {}", path_str, e);
+            return ApiError::new(StatusCode::FORBIDDEN, e).response();
+        }
+    };
+    let Some(grammar) = Grammar::for_path(&path) else {
+        return HttpResponse::Ok().json(json!({
+            "success": true,
+            "path": to_api_path(&path),
+            "supported": false,
+            "outline": [],
+        }));
+    };
+    let limit = state.config.max_file_read_bytes;
+    if let Some(size) = oversized_file(&path, limit).await {
+        return ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, file_too_large_message(size, limit)).response();
+    }
+    let content = match read_text_file(&path).await {
+        Ok(text) => text.content,
+        Err(e) => {
+            let status = match e.kind() {
+                io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+                io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            return ApiError::new(status, format!("Failed to read '{}': {}", path_str, e)).response();
+        }
+    };
+
+    let outline = block(move || {
+        let (mut parser, query) = grammar.symbol_parser()?;
+        Some(file_outline(&mut parser, &query, &content))
+    })
+    .await;
+    let outline = match outline {
+        Ok(Some(outline)) => outline,
+        Ok(None) => {
+            let error = format!("The {} grammar failed to load", grammar.name);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+        }
+        Err(e) => {
+            let error = format!("Outline failed: {}", e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+        }
+    };
+
+    debug!("Outlined '{}' in {:.2?}.", path.display(), start_time.elapsed());
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "path": to_api_path(&path),
+        "supported": true,
+        "language": grammar.name,
+        "outline": outline,
+    }))
+}
+
+/// Renders one file of a `/api/codemap` request. Files in languages without a grammar are
+/// returned in full.
+async fn code_map_file(path_str: &str, state: &AppState) -> Result<CodemapFile, ApiError> {
+    let path = state
+        .allowed_roots
+        .check(&on_disk_path(path_str))
+        .map_err(|e| ApiError::new(StatusCode::FORBIDDEN, e))?;
+    let limit = state.config.max_file_read_bytes;
+    if let Some(size) = oversized_file(&path, limit).await {
+        return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, file_too_large_message(size, limit)));
+    }
+    let content = read_text_file(&path).await.map_err(|e| {
+        let status = match e.kind() {
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError::new(status, format!("Failed to read '{}': {}", path_str, e))
+    })?;
+    let content = content.content;
+    let original_tokens = text_estimates(content.as_bytes()).1;
+
+    let Some(grammar) = Grammar::for_path(&path) else {
+        return Ok(CodemapFile {
+            success: true,
+            content: Some(content),
+            original_tokens,
+            condensed_tokens: original_tokens,
+            ..Default::default()
+        });
+    };
+    let map = block(move || {
+        let (mut parser, query) = grammar.symbol_parser()?;
+        Some(code_map(grammar, &mut parser, &query, &content))
+    })
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("Code map failed: {}", e)))?
+    .ok_or_else(|| {
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, format!("The {} grammar failed to load", grammar.name))
+    })?;
+    Ok(CodemapFile {
+        success: true,
+        condensed_tokens: text_estimates(map.as_bytes()).1,
+        content: Some(map),
+        supported: true,
+        language: Some(grammar.name.to_string()),
+        original_tokens,
+        ..Default::default()
+    })
+}
+
+/// Renders files as code maps: their source with function and method bodies elided, so
+/// that more of a repository fits in a context window. Files stop being rendered once the
+/// maps reach `MAX_BATCH_BYTES`.
+#[post("/api/codemap")]
+pub async fn get_codemap(req: web::Json<CodemapRequest>, state: web::Data<AppState>) -> HttpResponse {
+    info!("Received code map request for {} files.", req.paths.len());
+    if let Err(e) = check_batch_size(&req.paths, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+    let start_time = Instant::now();
+
+    let batch_limit = state.config.max_batch_bytes;
+    let mut remaining = batch_limit;
+    let mut truncated = false;
+    let (mut original_tokens, mut condensed_tokens) = (0, 0);
+    let mut files = IndexMap::new();
+    for path_str in &req.paths {
+        let file = if truncated {
+            Err(ApiError::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Skipped: the batch reached its limit of {} bytes (MAX_BATCH_BYTES)", batch_limit),
+            ))
+        } else {
+            code_map_file(path_str, &state).await
+        };
+        let file = match file {
+            Ok(file) if file.content.as_ref().map_or(0, |c| c.len() as u64) > remaining => {
+                truncated = true;
+                CodemapFile {
+                    error: Some(format!("Skipped: the batch reached its limit of {} bytes (MAX_BATCH_BYTES)", batch_limit)),
+                    status: Some(StatusCode::PAYLOAD_TOO_LARGE.as_u16()),
+                    ..Default::default()
+                }
+            }
+            Ok(file) => {
+                remaining -= file.content.as_ref().map_or(0, |c| c.len() as u64);
+                original_tokens += file.original_tokens;
+                condensed_tokens += file.condensed_tokens;
+                file
+            }
+            Err(e) => {
+                debug!("Skipping '{}' in code map: {}", path_str, e.message);
+                CodemapFile { error: Some(e.message), status: Some(e.status.as_u16()), ..Default::default() }
+            }
+        };
+        files.insert(path_str.clone(), file);
+    }
+
+    info!(
+        "Rendered code maps of {} files in {:.2?}: ~{} tokens down to ~{}.",
+        files.len(),
+        start_time.elapsed(),
+        original_tokens,
+        condensed_tokens
+    );
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "files": files,
+        "truncated": truncated,
+        "totals": { "original_tokens": original_tokens, "condensed_tokens": condensed_tokens },
+    }))
+}
+
+/// Finds the callers of a function or method under a root: its definitions, from the
+/// shared symbol index, and the calls of that name in files of the language, each with the
+/// function making it. Matching is by name only, so results are heuristic.
+#[get("/api/callgraph")]
+pub async fn get_callgraph(query: web::Query<CallgraphQuery>, state: web::Data<AppState>) -> HttpResponse {
+    let symbol = query.symbol.trim().to_string();
+    info!("Received call graph request for '{}' in: {}", symbol, query.path.as_deref().unwrap_or("."));
+    let start_time = Instant::now();
+
+    if symbol.is_empty() {
+        return ApiError::new(StatusCode::BAD_REQUEST, "Missing 'symbol'").response();
+    }
+    let grammars = match requested_grammars(query.lang.as_deref()) {
+        Ok(grammars) => grammars,
+        Err(e) => return e.response(),
+    };
+    let grammars: Vec<&'static Grammar> = grammars.into_iter().filter(|grammar| grammar.calls.is_some()).collect();
+    if grammars.is_empty() {
+        let supported: Vec<&str> = Grammar::all().iter().filter(|g| g.calls.is_some()).map(|g| g.name).collect();
+        let error = format!("Call graphs are only supported for: {}", supported.join(", "));
+        return ApiError::new(StatusCode::BAD_REQUEST, error).response();
+    }
+    let (root, files, symbols) = match indexed_symbols(query.path.as_deref(), &grammars, &state).await {
+        Ok(indexed) => indexed,
+        Err(e) => return e.response(),
+    };
+    let definitions: Vec<Symbol> = symbols
+        .into_iter()
+        .filter(|s| s.name == symbol && matches!(s.kind.as_str(), "function" | "method"))
+        .collect();
+
+    let mut files: Vec<String> = files
+        .into_iter()
+        .filter(|file| Grammar::for_path(Path::new(file)).is_some_and(|g| grammars.iter().any(|r| r.name == g.name)))
+        .collect();
+    let mut truncated = files.len() > MAX_CALLGRAPH_FILES;
+    files.truncate(MAX_CALLGRAPH_FILES);
+    let file_count = files.len();
+
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_RESULTS).clamp(1, MAX_SEARCH_RESULTS);
+    let root_str = to_nfc(&to_api_path(&root));
+    let max_bytes = state.config.max_file_read_bytes;
+    let callee = symbol.clone();
+    let mut scans = scan_files(files, move |path| match Grammar::for_path(path) {
+        Some(grammar) => find_callers(grammar, path, &root_str, &callee, max_bytes),
+        None => Ok(Vec::new()),
+    });
+    let mut callers = Vec::new();
+    while let Some(scanned) = scans.next().await {
+        let mut found = match scanned {
+            Ok((_, found)) => found,
+            Err(e) => {
+                debug!("Skipping file in call graph: {}", e);
+                continue;
+            }
+        };
+        if callers.len() + found.len() >= limit {
+            truncated = true;
+            found.truncate(limit - callers.len());
+            callers.extend(found);
+            break;
+        }
+        callers.extend(found);
+    }
+
+    let duration = start_time.elapsed();
+    info!(
+        "Found {} definitions and {} callers of '{}' in {} files under '{}' in {:.2?}.",
+        definitions.len(),
+        callers.len(),
+        symbol,
+        file_count,
+        root.display(),
+        duration
+    );
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "symbol": symbol,
+        "heuristic": true,
+        "definitions": definitions,
+        "callers": callers,
+        "truncated": truncated,
+    }))
+}
+
+/// Runs a client-supplied tree-sitter query over the files of one language under a root,
+/// walked like `/api/directory` so gitignored files are skipped.
+#[post("/api/analyze/custom-query")]
+pub async fn run_custom_query(
+    req: web::Json<CustomQueryRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received custom {} query for '{}'.", req.language, req.root);
+    let start_time = Instant::now();
+
+    let Some(grammar) = Grammar::by_name(&req.language) else {
+        let error = format!("Unknown language '{}'; expected one of: {}", req.language, Grammar::names().join(", "));
+        return ApiError::new(StatusCode::BAD_REQUEST, error).response();
+    };
+    if req.query.len() > MAX_CUSTOM_QUERY_BYTES {
+        let error = format!("Query is {} bytes; the limit is {}", req.query.len(), MAX_CUSTOM_QUERY_BYTES);
+        return ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, error).response();
+    }
+    let query = match grammar.query(&req.query) {
+        Ok(q) => q,
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, e).response(),
+    };
+    let root = match state.allowed_roots.check(&on_disk_path(&req.root)) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Refusing custom query under '{}': {}", req.root, e);
+            return ApiError::new(StatusCode::FORBIDDEN, e).response();
+        }
+    };
+
+    let extensions = match &req.extensions {
+        Some(extensions) => extensions.join(","),
+        None => grammar.extensions.join(","),
+    };
+    let query_options = DirectoryQuery { extensions: Some(extensions), ..Default::default() };
+    let options = match TreeOptions::from_query(&query_options) {
+        Ok(options) => options,
+        Err(e) => return ApiError::new(StatusCode::BAD_REQUEST, e).response(),
+    };
+    let tree = match walk_tree(root.clone(), options).await {
+        Ok((tree, _)) => tree,
+        Err(e) => {
+            warn!("Failed to walk '{}' for custom query: {}", root.display(), e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e).response();
+        }
+    };
+    let mut files = Vec::new();
+    collect_files(tree, &mut files);
+
+    let limit = state.config.max_custom_query_results;
+    let max_bytes = state.config.max_file_read_bytes;
+    let file_count = files.len();
+    let (matches, truncated) = match block(move || run_query(grammar, &query, &files, limit, max_bytes)).await {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Custom query failed under '{}': {}", root.display(), e);
+            let error = format!("Custom query failed: {}", e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, error).response();
+        }
+    };
+
+    let duration = start_time.elapsed();
+    info!("Custom query found {} matches in {} files under '{}' in {:.2?}.", matches.len(), file_count, root.display(), duration);
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "root": to_api_path(&root),
+        "language": grammar.name,
+        "matches": matches,
+        "truncated": truncated,
+    }))
+}
+
+/// Renders files under a root (paths relative to it) as one Markdown document: an optional
+/// tree of the files, then each file in a fenced code block. Like `/api/files`, a request
+/// takes at most `MAX_BATCH_PATHS` files, and files past `MAX_BATCH_BYTES` of content are
+/// skipped. Files that can't be read are rendered with their error.
+#[post("/api/context/markdown")]
+pub async fn get_context_markdown(
+    req: web::Json<MarkdownContextRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    info!("Received Markdown context request for {} files in '{}'.", req.files.len(), req.root);
+    let start_time = Instant::now();
+    if let Err(e) = check_batch_size(&req.files, &state) {
+        warn!("{}", e.message);
+        return e.response();
+    }
+
+    let root = match state.allowed_roots.validate_root(&req.root) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Path validation failed for '{}': {}", req.root, e);
+            return ApiError::new(StatusCode::BAD_REQUEST, e).response();
+        }
+    };
+    if let Err(e) = state.allowed_roots.check(&root) {
+        warn!("Refusing Markdown context for '{}': {}", req.root, e);
+        return ApiError::new(StatusCode::FORBIDDEN, e).response();
+    }
+
+    let limit = state.config.max_file_read_bytes;
+    let batch_limit = state.config.max_batch_bytes;
+    let mut remaining = batch_limit;
+    let mut truncated = false;
+    let mut files: Vec<(String, Result<String, String>)> = Vec::with_capacity(req.files.len());
+    for file in &req.files {
+        let full_path = on_disk_path(&root.join(file).to_string_lossy());
+        let display_path = full_path.strip_prefix(&root).map(to_api_path).unwrap_or_else(|_| file.clone());
+        let skipped = || format!("Skipped: the batch reached its limit of {} bytes (MAX_BATCH_BYTES)", batch_limit);
+        if truncated {
+            files.push((display_path, Err(skipped())));
+            continue;
+        }
+        // Files may escape the root through `..` or symlinks.
+        let full_path = match state.allowed_roots.check(&full_path) {
+            Ok(p) => p,
+            Err(e) => {
+                files.push((display_path, Err(e)));
+                continue;
+            }
+        };
+        if let Some(size) = oversized_file(&full_path, limit).await {
+            files.push((display_path, Err(file_too_large_message(size, limit))));
+            continue;
+        }
+        let content = match read_text_file(&full_path).await {
+            Ok(text) if text.content.len() as u64 > remaining => {
+                truncated = true;
+                Err(skipped())
+            }
+            Ok(text) => {
+                remaining -= text.content.len() as u64;
+                Ok(text.content)
+            }
+            Err(e) => {
+                warn!("Failed to read file '{}' for Markdown context: {}", full_path.display(), e);
+                Err(e.to_string())
+            }
+        };
+        files.push((display_path, content));
+    }
+    if truncated {
+        warn!("Markdown context for '{}' hit the limit of {} bytes; later files were skipped.", req.root, batch_limit);
+    }
+
+    let markdown = render_context_markdown(
+        &to_api_path(&root),
+        &files,
+        req.include_tree.unwrap_or(true),
+    );
+
+    let duration = start_time.elapsed();
+    info!("Markdown context for '{}' generated in {:.2?}.", root.display(), duration);
+    HttpResponse::Ok()
+        .content_type("text/markdown; charset=utf-8")
+        .body(markdown)
+}
+
+/// The requesting client's IP address, for audit logging.
+fn client_ip(req: &HttpRequest) -> String {
+    req.peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub async fn static_handler(req: HttpRequest) -> HttpResponse {
+    let path = req.path().trim_start_matches('/');
+    let path = if path.is_empty() { "index.html" } else { path };
+    debug!("Serving static asset: {}", path);
+
+    match Asset::get(path) {
+        Some(content) => {
+            let mime = mime_guess::from_path(path).first_or_octet_stream();
+            HttpResponse::Ok()
+                .content_type(mime.as_ref())
+                .body(content.data.into_owned())
+        }
+        None => HttpResponse::NotFound().body("404 Not Found"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::test_dir::TempDir;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Mutex;
+
+    fn state_with(config: ServerConfig, data_dir: &Path) -> AppState {
+        AppState::new(ServerConfig { data_dir: data_dir.to_path_buf(), ..config })
+    }
+
+    #[test]
+    fn read_only_servers_never_cache_into_the_root() {
+        let root = TempDir::new();
+        let inside = ServerConfig { cache_dir: root.path().join(".cache"), ..Default::default() };
+        let state = state_with(inside, root.path());
+        assert_eq!(dependency_cache(root.path(), DisabledLanguages::default(), &state), None);
+
+        let writable = ServerConfig { cache_dir: root.path().join(".cache"), mode: ServerMode::ReadWrite, ..Default::default() };
+        let state = state_with(writable, root.path());
+        assert!(dependency_cache(root.path(), DisabledLanguages::default(), &state).is_some());
+
+        let elsewhere = TempDir::new();
+        let outside = ServerConfig { cache_dir: elsewhere.path().to_path_buf(), ..Default::default() };
+        let state = state_with(outside, elsewhere.path());
+        let cache = dependency_cache(root.path(), DisabledLanguages::default(), &state).unwrap();
+        assert!(cache.starts_with(elsewhere.path()));
+    }
+
+    #[actix_web::test]
+    async fn batches_skip_files_whose_hash_still_matches() {
+        let root = TempDir::new();
+        let same = root.write("same.txt", "unchanged");
+        let edited = root.write("edited.txt", "new content");
+        let plain = root.write("plain.txt", "plain");
+        let missing = root.path().join("missing.txt");
+        let hash_of = |content: &str| format!("{}{}", HASH_PREFIX, blake3::hash(content.as_bytes()).to_hex());
+        let config = ServerConfig { allowed_roots: vec![root.path().to_path_buf()], ..Default::default() };
+        let state = state_with(config, root.path());
+
+        let cached = |path: &Path, hash: String| FileRequest::Cached {
+            path: to_api_path(path),
+            etag: None,
+            hash: Some(hash),
+        };
+        let files = [
+            // Hex digits may come back in either case.
+            cached(&same, format!("{}{}", HASH_PREFIX, blake3::hash(b"unchanged").to_hex().to_uppercase())),
+            cached(&edited, hash_of("old content")),
+            FileRequest::Path(to_api_path(&plain)),
+            cached(&missing, hash_of("")),
+        ];
+        let (results, truncated) = read_files(&files, Transport::Auto, None, &state).await;
+        assert!(!truncated);
+
+        let same = &results[&to_api_path(&same)];
+        assert_eq!((same.unchanged, same.content.as_deref()), (Some(true), None));
+        assert_eq!(same.hash, Some(hash_of("unchanged")));
+
+        let edited = &results[&to_api_path(&edited)];
+        assert_eq!((edited.unchanged, edited.content.as_deref()), (None, Some("new content")));
+        assert_eq!(edited.hash, Some(hash_of("new content")));
+
+        let plain = &results[&to_api_path(&plain)];
+        assert_eq!((plain.content.as_deref(), plain.hash.clone()), (Some("plain"), Some(hash_of("plain"))));
+
+        let missing = &results[&to_api_path(&missing)];
+        assert!(!missing.success);
+        assert_eq!((missing.unchanged, missing.hash.as_deref()), (None, None));
+    }
+
+    /// Held walks: a walk of the root signals `started`, then waits for `release`.
+    struct WalkGate {
+        started: Sender<()>,
+        release: Receiver<()>,
+    }
+
+    static WALK_GATES: Mutex<Vec<(PathBuf, WalkGate)>> = Mutex::new(Vec::new());
+
+    /// Holds the next walk of `root` once it has started, until the returned sender is used.
+    fn gate_next_walk(root: &Path) -> (Receiver<()>, Sender<()>) {
+        let (started, on_started) = mpsc::channel();
+        let (release, on_release) = mpsc::channel();
+        WALK_GATES.lock().unwrap().push((root.to_path_buf(), WalkGate { started, release: on_release }));
+        (on_started, release)
+    }
+
+    /// Called by every walk; waits if a test gated walks of `root`. Gives up after a while
+    /// so that a walk run on the wrong thread fails its test instead of hanging it.
+    pub(super) fn wait_at_walk_gate(root: &Path) {
+        let gate = {
+            let mut gates = WALK_GATES.lock().unwrap();
+            gates.iter().position(|(gated, _)| gated == root).map(|index| gates.remove(index).1)
+        };
+        if let Some(gate) = gate {
+            let _ = gate.started.send(());
+            let _ = gate.release.recv_timeout(std::time::Duration::from_secs(10));
+        }
+    }
+
+    #[actix_web::test]
+    async fn connect_stays_responsive_during_a_walk() {
+        use actix_web::{test, App};
+        use std::rc::Rc;
+
+        let root = TempDir::new();
+        root.write("src/main.rs", "fn main() {}\n");
+        let state = state_with(ServerConfig::default(), root.path());
+        let app = Rc::new(
+            test::init_service(App::new().app_data(web::Data::new(state)).service(connect).service(get_directory_contents))
+                .await,
+        );
+
+        let (started, release) = gate_next_walk(&canonicalize_path(root.path()).unwrap());
+        let uri = format!("/api/directory?path={}", to_api_path(root.path()));
+        let walking = actix_web::rt::spawn({
+            let app = Rc::clone(&app);
+            async move { test::call_service(&*app, test::TestRequest::get().uri(&uri).to_request()).await.status() }
+        });
+        block(move || started.recv()).await.unwrap().unwrap();
+        // The walk is now held mid-way. Had it run on this thread, it would only have let go
+        // after timing out at the gate, and would be finished by now.
+        let res = test::call_service(&*app, test::TestRequest::get().uri("/api/connect").to_request()).await;
+        assert!(res.status().is_success());
+        assert!(!walking.is_finished(), "the walk held up the server's thread");
+        release.send(()).unwrap();
+        assert!(walking.await.unwrap().is_success());
+    }
+
+    /// Anonymous (non-file-backed) resident memory of this process, in bytes.
+    #[cfg(target_os = "linux")]
+    fn anonymous_rss() -> u64 {
+        let statm = std::fs::read_to_string("/proc/self/statm").unwrap();
+        let pages: Vec<u64> = statm.split_whitespace().map(|n| n.parse().unwrap()).collect();
+        (pages[1] - pages[2]) * 4096
+    }
+
+    /// Measures the memory of the whole process, so other tests running alongside would
+    /// skew it. Run on its own with `cargo test -- --ignored large_files_are_streamed`.
+    #[cfg(target_os = "linux")]
+    #[ignore = "measures process-wide memory; run on its own"]
+    #[actix_web::test]
+    async fn large_files_are_streamed_without_being_copied() {
+        use actix_web::body::MessageBody;
+        use actix_web::{test, App};
+        use std::io::Write;
+        use std::pin::Pin;
+
+        const SIZE: u64 = 200 * 1024 * 1024;
+        let root = TempDir::new();
+        let path = root.path().join("huge.log");
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path).unwrap());
+        let line = [b"0123456789abcdef".repeat(4).as_slice(), b"\n"].concat();
+        for _ in 0..SIZE / line.len() as u64 {
+            file.write_all(&line).unwrap();
+        }
+        file.write_all(&line[..(SIZE % line.len() as u64) as usize]).unwrap();
+        drop(file);
+
+        let config = ServerConfig { max_file_bytes: SIZE, max_file_read_bytes: SIZE, ..Default::default() };
+        let state = state_with(config, root.path());
+        state.allowed_roots.allow(root.path());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(get_file_content)).await;
+        let uri = format!("/api/file?path={}", to_api_path(&path));
+        let baseline = anonymous_rss();
+        let res = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert!(res.status().is_success());
+
+        let mut peak = anonymous_rss();
+        let mut body = res.into_body();
+        let (mut received, mut tail) = (0u64, Vec::new());
+        while let Some(chunk) = std::future::poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await {
+            let chunk = chunk.unwrap();
+            received += chunk.len() as u64;
+            tail = chunk.to_vec();
+            peak = peak.max(anonymous_rss());
+        }
+        // Each newline is escaped as two bytes in the JSON string.
+        assert!(received > SIZE);
+        assert!(String::from_utf8(tail).unwrap().ends_with(&format!(r#""size":{},"truncated":false}}"#, SIZE)));
+        let growth = peak.saturating_sub(baseline);
+        assert!(growth < SIZE / 4, "memory grew by {} MB while streaming", growth / (1024 * 1024));
+    }
+
+    fn write_request(body: &Value) -> actix_web::test::TestRequest {
+        actix_web::test::TestRequest::post().uri("/api/file/write").set_json(body)
+    }
+
+    /// A read-write server's state with `root` opened.
+    fn read_write_state(root: &TempDir) -> AppState {
+        let state = state_with(ServerConfig { mode: ServerMode::ReadWrite, ..Default::default() }, root.path());
+        state.allowed_roots.allow(root.path());
+        state
+    }
+
+    #[actix_web::test]
+    async fn deletes_outside_the_allowed_roots_are_forbidden() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        let outside = TempDir::new();
+        let victim = outside.write("victim.txt", "keep");
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(delete_file)).await;
+        let outside_name = outside.path().file_name().unwrap().to_string_lossy();
+        let escape = format!("{}/../{}/victim.txt", to_api_path(root.path()), outside_name);
+        let missing = to_api_path(outside.path().join("missing/file.txt"));
+        for path in [to_api_path(&victim), escape, missing] {
+            let req = test::TestRequest::delete().uri(&format!("/api/file?path={}", path)).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN, "{}", path);
+        }
+        assert!(victim.exists());
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn deleting_a_symlink_keeps_its_target() {
+        use actix_web::{test, App};
+        use std::os::unix::fs::symlink;
+
+        let root = TempDir::new();
+        let outside = TempDir::new();
+        let external = outside.write("external.txt", "keep");
+        let internal = root.write("internal.txt", "keep too");
+        let (to_external, to_internal) = (root.path().join("external.txt"), root.path().join("internal-link.txt"));
+        symlink(&external, &to_external).unwrap();
+        symlink(&internal, &to_internal).unwrap();
+
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(delete_file)).await;
+        for link in [&to_external, &to_internal] {
+            let req = test::TestRequest::delete().uri(&format!("/api/file?path={}", to_api_path(link))).to_request();
+            assert!(test::call_service(&app, req).await.status().is_success());
+            assert!(std::fs::symlink_metadata(link).is_err());
+        }
+        assert_eq!(std::fs::read_to_string(&external).unwrap(), "keep");
+        assert_eq!(std::fs::read_to_string(&internal).unwrap(), "keep too");
+    }
+
+    #[actix_web::test]
+    async fn recently_modified_rejects_out_of_range_timestamps() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        root.write("a.txt", "a");
+        let state = state_with(ServerConfig::default(), root.path());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(get_recently_modified)).await;
+        for (since_secs, status) in [(u64::MAX, StatusCode::BAD_REQUEST), (0, StatusCode::OK)] {
+            let uri = format!("/api/directory/recently-modified?path={}&since_secs={}", to_api_path(root.path()), since_secs);
+            let res = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(res.status(), status, "since_secs={}", since_secs);
+        }
+    }
+
+    #[actix_web::test]
+    async fn flushing_the_file_cache_needs_read_write_mode() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        for (mode, status) in [(ServerMode::ReadOnly, StatusCode::FORBIDDEN), (ServerMode::ReadWrite, StatusCode::OK)] {
+            let state = state_with(ServerConfig { mode, file_cache_bytes: 1024, ..Default::default() }, root.path());
+            state.file_cache.insert(Path::new("/a"), SystemTime::now(), b"a");
+            let state = web::Data::new(state);
+            let app = test::init_service(App::new().app_data(state.clone()).service(flush_file_cache)).await;
+            let res = test::call_service(&app, test::TestRequest::delete().uri("/api/cache/files").to_request()).await;
+            assert_eq!(res.status(), status);
+            assert_eq!(state.file_cache.stats().entries, usize::from(mode == ServerMode::ReadOnly));
+        }
+    }
+
+    #[actix_web::test]
+    async fn directories_rejects_batches_over_the_path_limit() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        root.write("a.txt", "a");
+        let state = state_with(ServerConfig { max_batch_paths: 2, ..Default::default() }, root.path());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(get_directories)).await;
+        let path = to_api_path(root.path());
+        for (paths, status) in [(vec![path.clone(); 3], StatusCode::PAYLOAD_TOO_LARGE), (vec![path; 2], StatusCode::OK)] {
+            let req = test::TestRequest::post().uri("/api/directories").set_json(json!({ "paths": paths })).to_request();
+            assert_eq!(test::call_service(&app, req).await.status(), status);
+        }
+    }
+
+
+    #[actix_web::test]
+    async fn writes_create_and_overwrite_files() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(write_file)).await;
+        let path = root.path().join("new/a.txt");
+        let body = json!({ "path": to_api_path(&path), "content": "one", "create_dirs": true });
+        let res: Value = test::call_and_read_body_json(&app, write_request(&body).to_request()).await;
+        assert_eq!(res["success"], true, "{}", res);
+        assert_eq!(res["size"], 3);
+
+        let body = json!({ "path": to_api_path(&path), "content": "two", "expected_hash": res["hash"] });
+        let res: Value = test::call_and_read_body_json(&app, write_request(&body).to_request()).await;
+        assert_eq!(res["success"], true, "{}", res);
+        assert_eq!(res["hash"], format!("{}{}", HASH_PREFIX, blake3::hash(b"two").to_hex()));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+    }
+
+    #[actix_web::test]
+    async fn stale_writes_conflict_and_report_the_current_hash() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        let file = root.write("a.txt", "edited elsewhere");
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(write_file)).await;
+        let stale = format!("{}{}", HASH_PREFIX, blake3::hash(b"original").to_hex());
+        let body = json!({ "path": to_api_path(&file), "content": "mine", "expected_hash": stale });
+        let res = test::call_service(&app, write_request(&body).to_request()).await;
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let res: Value = test::read_body_json(res).await;
+        assert_eq!(res["code"], "conflict");
+        assert_eq!(res["hash"], format!("{}{}", HASH_PREFIX, blake3::hash(b"edited elsewhere").to_hex()));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "edited elsewhere");
+    }
+
+    #[actix_web::test]
+    async fn expected_hash_of_a_missing_file_conflicts_before_creating_directories() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(write_file)).await;
+        let hash = format!("{}{}", HASH_PREFIX, blake3::hash(b"deleted").to_hex());
+        for path in [root.path().join("gone.txt"), root.path().join("new/dir/gone.txt")] {
+            let body = json!({ "path": to_api_path(&path), "content": "x", "create_dirs": true, "expected_hash": hash });
+            let res = test::call_service(&app, write_request(&body).to_request()).await;
+            assert_eq!(res.status(), StatusCode::CONFLICT, "{}", path.display());
+            let res: Value = test::read_body_json(res).await;
+            assert_eq!(res["code"], "conflict");
+            assert!(res["hash"].is_null());
+            assert!(!path.exists());
+        }
+        assert!(!root.path().join("new").exists());
+    }
+
+    #[actix_web::test]
+    async fn writes_outside_the_allowed_roots_are_refused() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        let outside = TempDir::new();
+        let victim = outside.write("victim.txt", "keep");
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(write_file)).await;
+        let outside_name = outside.path().file_name().unwrap().to_string_lossy();
+        let escape = format!("{}/../{}/victim.txt", to_api_path(root.path()), outside_name);
+        let new_dir = to_api_path(outside.path().join("new/file.txt"));
+        for path in [to_api_path(&victim), escape, new_dir] {
+            let body = json!({ "path": path, "content": "pwned", "create_dirs": true });
+            let res = test::call_service(&app, write_request(&body).to_request()).await;
+            assert!(res.status().is_client_error(), "{}: {}", path, res.status());
+        }
+        assert_eq!(std::fs::read_to_string(&victim).unwrap(), "keep");
+        assert!(!outside.path().join("new").exists());
+    }
+
+    #[actix_web::test]
+    async fn writes_are_forbidden_on_read_only_servers() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        let state = state_with(ServerConfig::default(), root.path());
+        state.allowed_roots.allow(root.path());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(write_file)).await;
+        let body = json!({ "path": to_api_path(root.path().join("a.txt")), "content": "a" });
+        let res = test::call_service(&app, write_request(&body).to_request()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let res: Value = test::read_body_json(res).await;
+        assert_eq!(res["code"], "read_only");
+        assert!(!root.path().join("a.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[actix_web::test]
+    async fn writes_into_a_read_only_directory_are_forbidden() {
+        use actix_web::{test, App};
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = TempDir::new();
+        let file = root.write("locked/a.txt", "keep");
+        let locked = root.path().join("locked");
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // Privileged users (e.g. root in a container) write regardless of permissions.
+        if std::fs::write(locked.join("probe"), "").is_ok() {
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+            return;
+        }
+        let app = test::init_service(App::new().app_data(web::Data::new(read_write_state(&root))).service(write_file)).await;
+        let body = json!({ "path": to_api_path(&file), "content": "overwritten" });
+        let status = test::call_service(&app, write_request(&body).to_request()).await.status();
+        std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+    }
+
+
+    #[actix_web::test]
+    async fn markdown_context_rejects_invalid_requests() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        root.write("a.txt", "a");
+        let state = state_with(ServerConfig { max_batch_paths: 1, ..Default::default() }, root.path());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(get_context_markdown)).await;
+        let requests = [
+            (json!({ "root": to_api_path(root.path()), "files": ["a.txt", "b.txt"] }), StatusCode::PAYLOAD_TOO_LARGE),
+            (json!({ "root": to_api_path(root.path().join("missing")), "files": ["a.txt"] }), StatusCode::BAD_REQUEST),
+        ];
+        for (body, status) in requests {
+            let req = test::TestRequest::post().uri("/api/context/markdown").set_json(&body).to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), status, "{}", body);
+            let res: Value = test::read_body_json(res).await;
+            assert_eq!(res["success"], false);
+        }
+    }
+
+    #[actix_web::test]
+    async fn markdown_context_skips_files_past_the_batch_byte_limit() {
+        use actix_web::{test, App};
+
+        let root = TempDir::new();
+        root.write("a.txt", "first");
+        root.write("b.txt", "second file");
+        root.write("c.txt", "3");
+        let state = state_with(ServerConfig { max_batch_bytes: 10, ..Default::default() }, root.path());
+        state.allowed_roots.allow(root.path());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(get_context_markdown)).await;
+        let body = json!({ "root": to_api_path(root.path()), "files": ["a.txt", "b.txt", "c.txt"], "include_tree": false });
+        let req = test::TestRequest::post().uri("/api/context/markdown").set_json(&body).to_request();
+        let markdown = String::from_utf8(test::call_and_read_body(&app, req).await.to_vec()).unwrap();
+        assert!(markdown.contains("first"), "{}", markdown);
+        assert!(!markdown.contains("second file"), "{}", markdown);
+        assert!(!markdown.contains("\n3\n"), "{}", markdown);
+        assert_eq!(markdown.matches("MAX_BATCH_BYTES").count(), 2, "{}", markdown);
     }
 }
\ No newline at end of file
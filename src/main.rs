@@ -1,20 +1,41 @@
 use actix_cors::Cors;
-use actix_web::{web, App, HttpServer, middleware};
+use actix_web::middleware::{from_fn, Compress, Logger};
+use actix_web::dev::ServerHandle;
+use actix_web::{rt, web, App, HttpServer};
+use futures::future::Either;
 use actix_web::http::header::{self, HeaderName};
 use log::{info, warn};
 use rustls::ServerConfig;
 use rustls_pemfile::{certs, pkcs8_private_keys};
 use std::env;
 use std::fs::File as FsFile;
-use std::io::BufReader;
-use std::path::Path;
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::state::AppState;
 
 // Declare application modules
+mod allowed_roots;
+mod config;
 mod dependency_analyzer;
+mod file_cache;
 mod file_system;
+mod formatters;
 mod handlers;
+mod markdown;
+mod middleware;
 mod models;
+mod rate_limit;
+mod recent;
+mod search;
+mod state;
+mod syntax;
+#[cfg(test)]
+mod test_dir;
 mod utils;
+mod watcher;
+mod ws;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -23,39 +44,154 @@ async fn main() -> std::io::Result<()> {
     unsafe {
         env::set_var("RUST_LOG", env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string()));
     }
-    env_logger::init();
+    // Log lines emitted while handling a request are tagged with its `X-Request-ID`.
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let request_id = middleware::current_request_id()
+                .map(|id| format!(" req={}", id))
+                .unwrap_or_default();
+            writeln!(
+                buf,
+                "[{} {:<5} {}{}] {}",
+                buf.timestamp(),
+                record.level(),
+                record.target(),
+                request_id,
+                record.args()
+            )
+        })
+        .init();
 
     let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string());
     let addr = format!("0.0.0.0:{}", port);
     info!("Server starting at http://{}", addr);
 
-    let mut http_server = HttpServer::new(|| {
+    let mut config = config::ServerConfig::from_env();
+    config.apply_args(env::args().skip(1));
+    if config.allowed_roots.is_empty() {
+        warn!("*** No ALLOWED_ROOTS or --root configured: every directory on this machine can be browsed. ***");
+        warn!("*** Restrict access before exposing the server beyond localhost (e.g. through ngrok). ***");
+    } else {
+        let roots: Vec<_> = config.allowed_roots.iter().map(|root| root.display().to_string()).collect();
+        info!("Restricting access to: {}", roots.join(", "));
+    }
+    match config.mode {
+        config::ServerMode::ReadOnly => info!("Running in read-only mode."),
+        config::ServerMode::ReadWrite => warn!("Running in read-write mode: clients may modify files."),
+    }
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    let (workers, blocking_threads) = (config.worker_threads, config.blocking_threads);
+    info!("Using {} workers with up to {} blocking threads each.", workers, blocking_threads);
+    let max_body_bytes = config.max_body_bytes;
+    let app_state = web::Data::new(AppState::new(config));
+
+    let mut http_server = HttpServer::new(move || {
         let cors = Cors::default()
             .allow_any_origin()
-            .allowed_methods(vec!["GET", "POST"])
+            .allowed_methods(vec!["GET", "POST", "DELETE"])
             .allowed_headers(vec![
                 header::AUTHORIZATION,
                 header::ACCEPT,
                 header::CONTENT_TYPE,
                 HeaderName::from_static("ngrok-skip-browser-warning"),
+                middleware::REQUEST_ID_HEADER,
+            ])
+            .expose_headers(vec![
+                middleware::REQUEST_ID_HEADER,
+                header::RETRY_AFTER,
+                header::CONTENT_DISPOSITION,
+                header::CONTENT_RANGE,
+                header::ACCEPT_RANGES,
+                header::ETAG,
+                header::LAST_MODIFIED,
             ])
             .supports_credentials()
             .max_age(3600);
 
         App::new()
+            .app_data(app_state.clone())
+            .app_data(web::JsonConfig::default().limit(max_body_bytes).error_handler(handlers::json_error))
+            .wrap(Compress::default())
+            .wrap(from_fn(middleware::negotiate_compression))
+            .wrap(from_fn(middleware::rate_limit))
             .wrap(cors)
-            .wrap(middleware::Logger::default())
+            .wrap(from_fn(middleware::track_in_flight))
+            .wrap(from_fn(middleware::request_id))
+            .wrap(Logger::new(r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T req=%{x-request-id}o"#))
             .service(handlers::connect)
+            .service(handlers::version)
             .service(handlers::get_directory_contents)
+            .service(handlers::get_directories)
+            .service(handlers::get_directory_delta)
+            .service(handlers::get_directory_tree_text)
+            .service(handlers::get_path_breadcrumbs)
+            .service(handlers::get_allowed_roots)
+            .service(handlers::get_recent_roots)
+            .service(handlers::delete_recent_root)
+            .service(handlers::get_file_cache_stats)
+            .service(handlers::flush_file_cache)
+            .service(handlers::watch_directory)
+            .service(handlers::stream_events)
+            .service(ws::websocket)
             .service(handlers::get_dependencies)
+            .service(handlers::get_dependencies_batch)
+            .service(handlers::get_dependencies_mermaid)
+            .service(handlers::get_dependency_cycles)
+            .service(handlers::get_dependency_order)
             .service(handlers::get_file_content)
+            .service(handlers::get_file_metadata)
+            .service(handlers::get_file_tail)
+            .service(handlers::get_file_checksum)
+            .service(handlers::diff_file_with_content)
+            .service(handlers::diff_file_with_revision)
+            .service(handlers::get_stat)
+            .service(handlers::post_stat)
+            .service(handlers::get_raw_file)
+            .service(handlers::delete_file)
+            .service(handlers::make_directory)
+            .service(handlers::rename_path)
             .service(handlers::get_files_content)
+            .service(handlers::write_file)
+            .service(handlers::apply_edits)
+            .service(handlers::write_files)
+            .service(handlers::get_duplicate_files)
+            .service(handlers::get_largest_files)
+            .service(handlers::get_recently_modified)
+            .service(handlers::get_loc_metrics)
+            .service(handlers::search_files)
+            .service(handlers::search_file_names)
+            .service(handlers::get_symbol_index)
+            .service(handlers::search_symbols)
+            .service(handlers::get_outline)
+            .service(handlers::get_codemap)
+            .service(handlers::get_todos)
+            .service(handlers::search_by_language)
+            .service(handlers::get_callgraph)
+            .service(handlers::run_custom_query)
+            .service(handlers::get_context_markdown)
             .default_service(web::to(handlers::static_handler))
-    });
+    })
+    // Signals are handled below so that shutdown can be logged and bounded.
+    .workers(workers)
+    .worker_max_blocking_threads(blocking_threads)
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout.as_secs());
+
+    let tls_paths = match (env::var("CERT_PATH"), env::var("KEY_PATH")) {
+        (Ok(cert_path), Ok(key_path)) => Some((PathBuf::from(cert_path), PathBuf::from(key_path))),
+        _ if env::var("HTTPS").is_ok_and(|v| v.trim().parse().unwrap_or(false)) => {
+            let paths = generate_self_signed_cert()?;
+            warn!("*** HTTPS=true without CERT_PATH and KEY_PATH: serving a generated self-signed certificate ({}). ***", paths.0.display());
+            warn!("*** It is only valid for localhost and 127.0.0.1, browsers won't trust it, and it must not be used in production. ***");
+            Some(paths)
+        }
+        _ => None,
+    };
 
-    if let (Ok(cert_path), Ok(key_path)) = (env::var("CERT_PATH"), env::var("KEY_PATH")) {
-        if !Path::new(&cert_path).exists() || !Path::new(&key_path).exists() {
+    if let Some((cert_path, key_path)) = tls_paths {
+        if !cert_path.exists() || !key_path.exists() {
             warn!("Warning: CERT_PATH or KEY_PATH points to a non-existent file. Starting without HTTPS.");
+            http_server = http_server.bind(addr)?;
         } else {
             info!("Attempting to start HTTPS server...");
             let cert_file = &mut BufReader::new(FsFile::open(cert_path)?);
@@ -64,8 +200,7 @@ async fn main() -> std::io::Result<()> {
             let mut keys = pkcs8_private_keys(key_file).map(|r| r.unwrap()).collect::<Vec<_>>();
 
             if keys.is_empty() {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                return Err(std::io::Error::other(
                     "No private keys found in key file",
                 ));
             }
@@ -73,7 +208,7 @@ async fn main() -> std::io::Result<()> {
             let config = ServerConfig::builder()
                 .with_no_client_auth()
                 .with_single_cert(cert_chain, keys.remove(0).into())
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+                .map_err(std::io::Error::other)?;
 
             info!("Successfully configured TLS. Binding to https://{}", addr);
             http_server = http_server.bind_rustls_0_23(addr, config)?;
@@ -83,5 +218,75 @@ async fn main() -> std::io::Result<()> {
         http_server = http_server.bind(addr)?;
     }
 
-    http_server.run().await
+    let server = http_server.run();
+    rt::spawn(shutdown_on_signal(server.handle(), shutdown_timeout));
+    let result = server.await;
+    info!("Server stopped.");
+    result
+}
+
+/// Generates a self-signed certificate for `localhost` and `127.0.0.1`, valid for a year,
+/// and writes it and its key as PEM files to the temp directory. Returns their paths.
+fn generate_self_signed_cert() -> std::io::Result<(PathBuf, PathBuf)> {
+    let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string(), "127.0.0.1".to_string()])
+        .map_err(std::io::Error::other)?;
+    let now = time::OffsetDateTime::now_utc();
+    params.not_before = now;
+    params.not_after = now + time::Duration::days(365);
+    let key_pair = rcgen::KeyPair::generate().map_err(std::io::Error::other)?;
+    let cert = params.self_signed(&key_pair).map_err(std::io::Error::other)?;
+
+    let dir = env::temp_dir();
+    let cert_path = dir.join("repoprompt-self-signed-cert.pem");
+    let key_path = dir.join("repoprompt-self-signed-key.pem");
+    std::fs::write(&cert_path, cert.pem())?;
+    let mut key_options = std::fs::OpenOptions::new();
+    key_options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut key_options, 0o600);
+    key_options.open(&key_path)?.write_all(key_pair.serialize_pem().as_bytes())?;
+    Ok((cert_path, key_path))
+}
+
+/// Waits for SIGTERM or SIGINT, then stops accepting connections and lets in-flight
+/// requests finish. Exits the process if draining takes longer than `timeout`.
+async fn shutdown_on_signal(handle: ServerHandle, timeout: Duration) {
+    let signal = wait_for_signal().await;
+    info!(
+        "Received {}, shutting down. Draining {} in-flight requests (timeout {}s).",
+        signal,
+        middleware::in_flight_requests(),
+        timeout.as_secs()
+    );
+    if tokio::time::timeout(timeout, handle.stop(true)).await.is_err() {
+        warn!(
+            "Shutdown timed out with {} requests still in flight; exiting.",
+            middleware::in_flight_requests()
+        );
+        std::process::exit(1);
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+    let (Ok(mut terminate), Ok(mut interrupt)) =
+        (signal(SignalKind::terminate()), signal(SignalKind::interrupt()))
+    else {
+        warn!("Failed to install signal handlers; falling back to Ctrl-C only.");
+        let _ = tokio::signal::ctrl_c().await;
+        return "SIGINT";
+    };
+    let terminated = Box::pin(async move { terminate.recv().await });
+    let interrupted = Box::pin(async move { interrupt.recv().await });
+    match futures::future::select(terminated, interrupted).await {
+        Either::Left(_) => "SIGTERM",
+        Either::Right(_) => "SIGINT",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
 }
\ No newline at end of file
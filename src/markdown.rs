@@ -0,0 +1,93 @@
+use crate::utils::{language_for_path, natural_compare};
+use std::collections::BTreeMap;
+
+/// Renders the given files as a Markdown document suitable for pasting into an LLM chat.
+///
+/// `files` holds `(display_path, content)` pairs, where a failed read is carried as `Err`.
+pub fn render_context_markdown(
+    root: &str,
+    files: &[(String, Result<String, String>)],
+    include_tree: bool,
+) -> String {
+    let mut out = String::new();
+
+    if include_tree {
+        let paths: Vec<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        out.push_str("```\n");
+        out.push_str(root);
+        out.push('\n');
+        for line in format_path_tree(&paths) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out.push_str("```\n\n");
+    }
+
+    for (path, content) in files {
+        out.push_str(&format!("## {}\n\n", path));
+        match content {
+            Ok(content) => {
+                let fence = code_fence(content);
+                out.push_str(&format!("{}{}\n", fence, language_for_path(path)));
+                out.push_str(content);
+                if !content.ends_with('\n') {
+                    out.push('\n');
+                }
+                out.push_str(&format!("{}\n\n", fence));
+            }
+            Err(e) => out.push_str(&format!("_Failed to read file: {}_\n\n", e)),
+        }
+    }
+
+    out
+}
+
+/// Picks a backtick fence longer than any backtick run inside `content`.
+fn code_fence(content: &str) -> String {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in content.chars() {
+        if c == '`' {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+#[derive(Default)]
+struct PathTree {
+    children: BTreeMap<String, PathTree>,
+}
+
+/// Formats a list of `/`-separated paths as an ASCII tree, folders first.
+fn format_path_tree(paths: &[&str]) -> Vec<String> {
+    let mut tree = PathTree::default();
+    for path in paths {
+        let mut node = &mut tree;
+        for part in path.split('/').filter(|p| !p.is_empty()) {
+            node = node.children.entry(part.to_string()).or_default();
+        }
+    }
+
+    fn walk(node: &PathTree, prefix: &str, lines: &mut Vec<String>) {
+        let mut entries: Vec<_> = node.children.iter().collect();
+        entries.sort_by(|(a_name, a), (b_name, b)| {
+            let a_is_dir = !a.children.is_empty();
+            let b_is_dir = !b.children.is_empty();
+            b_is_dir.cmp(&a_is_dir).then_with(|| natural_compare(a_name, b_name))
+        });
+        for (index, (name, child)) in entries.iter().enumerate() {
+            let is_last = index == entries.len() - 1;
+            lines.push(format!("{}{}{}", prefix, if is_last { "└── " } else { "├── " }, name));
+            let child_prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+            walk(child, &child_prefix, lines);
+        }
+    }
+
+    let mut lines = Vec::new();
+    walk(&tree, "", &mut lines);
+    lines
+}
\ No newline at end of file
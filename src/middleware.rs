@@ -0,0 +1,268 @@
+use crate::config::Compression;
+use crate::rate_limit::{retry_after_secs, EndpointClass};
+use crate::state::AppState;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::BlockingError;
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpMessage, HttpResponse};
+use log::warn;
+use serde_json::json;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use uuid::Uuid;
+
+/// Header carrying the request correlation ID, read from clients and echoed in responses.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Streaming endpoints whose responses must not be compressed, since buffering in the
+/// encoder would hold back events.
+const UNCOMPRESSED_PATHS: &[&str] = &["/api/watch", "/api/events", "/api/ws"];
+
+/// Longest client-provided request ID that is accepted; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The correlation ID of the current request, stored in the request's extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Requests currently being handled, reported while draining on shutdown.
+static IN_FLIGHT_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// The ID of the request being handled on this task, for log formatting.
+/// `None` outside of request handling, and on threads not entered through `block`.
+pub fn current_request_id() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Runs `future` with `id` as the current request ID, for work that outlives the
+/// request's own task (e.g. WebSocket sessions).
+pub async fn with_request_id<F: Future>(id: String, future: F) -> F::Output {
+    CURRENT_REQUEST_ID.scope(id, future).await
+}
+
+/// `web::block`, with the current request ID carried over to the blocking thread so that
+/// log lines written there are tagged with it too. Use it instead of `web::block`.
+pub async fn block<F, R>(f: F) -> Result<R, BlockingError>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    match current_request_id() {
+        Some(id) => web::block(move || CURRENT_REQUEST_ID.sync_scope(id, f)).await,
+        None => web::block(f).await,
+    }
+}
+
+/// Assigns every request a correlation ID (the client's `X-Request-ID` if it sent a
+/// usable one, otherwise a fresh UUID v4), makes it available to handlers and log
+/// lines, and echoes it in the `X-Request-ID` response header.
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut res = CURRENT_REQUEST_ID.scope(id.clone(), next.call(req)).await?;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        res.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(res)
+}
+
+/// Number of requests whose handlers have not yet produced a response.
+pub fn in_flight_requests() -> usize {
+    IN_FLIGHT_REQUESTS.load(Ordering::SeqCst)
+}
+
+/// Decrements the in-flight count when the request finishes, even if its handler fails.
+struct InFlightGuard;
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT_REQUESTS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Counts in-flight requests so that shutdown can report how many it is draining.
+pub async fn track_in_flight(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    IN_FLIGHT_REQUESTS.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlightGuard;
+    next.call(req).await
+}
+
+/// Rejects requests from clients over their rate limit with `429 Too Many Requests` and a
+/// `Retry-After` header. Clients are identified by IP address.
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let class = EndpointClass::of(req.path());
+    let client = req.peer_addr().map(|addr| addr.ip());
+    let state = req.app_data::<web::Data<AppState>>();
+    if let (Some(class), Some(client), Some(state)) = (class, client, state) {
+        if let Err(retry_after) = state.rate_limiter.check(client, class) {
+            let retry_after = retry_after_secs(retry_after);
+            warn!("Rate limit exceeded by {} for {}; retry after {}s.", client, req.path(), retry_after);
+            let response = HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .json(json!({"success": false, "error": "Too many requests"}));
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+    next.call(req).await.map(ServiceResponse::map_into_left_body)
+}
+
+/// Narrows the request's `Accept-Encoding` to what `COMPRESSION` allows before it reaches
+/// the `Compress` middleware: nothing when compression is off or for streaming endpoints,
+/// and only gzip in `gzip` mode.
+pub async fn negotiate_compression(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let compression = req
+        .app_data::<web::Data<AppState>>()
+        .map_or(Compression::Auto, |state| state.config.compression);
+    let streaming = UNCOMPRESSED_PATHS.contains(&req.path());
+    match compression {
+        Compression::Auto if !streaming => {}
+        Compression::Gzip if !streaming && accepts_gzip(&req) => {
+            req.headers_mut().insert(header::ACCEPT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        _ => {
+            req.headers_mut().remove(header::ACCEPT_ENCODING);
+        }
+    }
+    next.call(req).await
+}
+
+/// Whether the client's `Accept-Encoding` lists gzip (or `*`) with a non-zero quality.
+fn accepts_gzip(req: &ServiceRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.split(',').any(|coding| {
+                let mut parts = coding.split(';').map(str::trim);
+                let name = parts.next().unwrap_or_default();
+                let refused = parts.any(|param| {
+                    param.strip_prefix("q=").and_then(|q| q.trim().parse::<f32>().ok()) == Some(0.0)
+                });
+                (name.eq_ignore_ascii_case("gzip") || name == "*") && !refused
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::test_dir::TempDir;
+    use actix_web::dev::Decompress;
+    use actix_web::error::PayloadError;
+    use actix_web::middleware::{from_fn, Compress};
+    use actix_web::web::Bytes;
+    use actix_web::{test, App};
+    use futures::StreamExt;
+
+    /// Fetches `uri` through the compression middleware, returning the response's
+    /// `Content-Encoding` and its decoded body.
+    async fn fetch(compression: Compression, uri: &str, accept_encoding: Option<&str>) -> (Option<String>, Bytes) {
+        let data_dir = TempDir::new();
+        let state = AppState::new(ServerConfig { compression, data_dir: data_dir.path().to_path_buf(), ..Default::default() });
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .wrap(Compress::default())
+                .wrap(from_fn(negotiate_compression))
+                .service(crate::handlers::get_directory_contents)
+                .route("/api/watch", web::get().to(|| async { "data: event\n\n".repeat(1000) })),
+        )
+        .await;
+        let mut req = test::TestRequest::get().uri(uri);
+        if let Some(accept_encoding) = accept_encoding {
+            req = req.insert_header((header::ACCEPT_ENCODING, accept_encoding));
+        }
+        let res = test::call_service(&app, req.to_request()).await;
+        assert!(res.status().is_success());
+        let encoding = res.headers().get(header::CONTENT_ENCODING).map(|v| v.to_str().unwrap().to_string());
+        let headers = res.headers().clone();
+        let body = test::read_body(res).await;
+        let stream = futures::stream::iter([Ok::<_, PayloadError>(body)]);
+        let mut decoded = Vec::new();
+        let mut decoder = Decompress::from_headers(stream, &headers);
+        while let Some(chunk) = decoder.next().await {
+            decoded.extend_from_slice(&chunk.unwrap());
+        }
+        (encoding, Bytes::from(decoded))
+    }
+
+    /// A directory whose tree is a large JSON document.
+    fn large_tree() -> (TempDir, String) {
+        let dir = TempDir::new();
+        for i in 0..500 {
+            dir.write(&format!("src/module_{}/component_{}.ts", i % 20, i), "");
+        }
+        let uri = format!("/api/directory?path={}", crate::utils::to_api_path(dir.path()));
+        (dir, uri)
+    }
+
+    #[actix_web::test]
+    async fn large_trees_are_compressed_only_when_accepted() {
+        let (_dir, uri) = large_tree();
+        let (encoding, plain) = fetch(Compression::Auto, &uri, None).await;
+        assert_eq!(encoding, None);
+        assert!(plain.len() > 10_000);
+
+        for (compression, accept, expected) in [
+            (Compression::Auto, "gzip", "gzip"),
+            (Compression::Auto, "br;q=1.0, gzip;q=0.5", "br"),
+            (Compression::Gzip, "br, gzip", "gzip"),
+        ] {
+            let (encoding, body) = fetch(compression, &uri, Some(accept)).await;
+            assert_eq!(encoding.as_deref(), Some(expected), "{:?} with '{}'", compression, accept);
+            assert_eq!(body, plain);
+        }
+    }
+
+    #[actix_web::test]
+    async fn compression_is_skipped_when_off_refused_or_streaming() {
+        let (_dir, uri) = large_tree();
+        for (compression, uri, accept) in [
+            (Compression::Off, uri.as_str(), "gzip, br"),
+            (Compression::Gzip, uri.as_str(), "br"),
+            (Compression::Gzip, uri.as_str(), "gzip;q=0, br"),
+            (Compression::Auto, "/api/watch", "gzip, br"),
+        ] {
+            let (encoding, _) = fetch(compression, uri, Some(accept)).await;
+            assert_eq!(encoding, None, "{:?} {} with '{}'", compression, uri, accept);
+        }
+    }
+
+    #[actix_web::test]
+    async fn block_carries_the_request_id_to_the_blocking_thread() {
+        let seen = with_request_id("req-42".to_string(), block(current_request_id)).await.unwrap();
+        assert_eq!(seen.as_deref(), Some("req-42"));
+    }
+
+    #[actix_web::test]
+    async fn block_outside_a_request_has_no_id() {
+        assert_eq!(block(current_request_id).await.unwrap(), None);
+    }
+}
\ No newline at end of file
@@ -1,27 +1,677 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use indexmap::IndexMap;
+use std::collections::BTreeMap;
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, Default)]
 pub struct TreeNode {
     #[serde(rename = "type")]
     pub node_type: String,
     pub path: String,
-    pub children: Option<HashMap<String, TreeNode>>,
+    pub children: Option<IndexMap<String, TreeNode>>,
+    /// Line and token estimates, populated on files when `estimates=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<usize>,
+    #[serde(rename = "approxTokens", skip_serializing_if = "Option::is_none")]
+    pub approx_tokens: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Set on folders left unexpanded because the tree hit its entry cap or depth limit, and
+    /// on symlinks to a directory they are in, which aren't followed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    /// Folder roll-ups, populated when `stats=true`.
+    #[serde(rename = "fileCount", skip_serializing_if = "Option::is_none")]
+    pub file_count: Option<usize>,
+    #[serde(rename = "totalSize", skip_serializing_if = "Option::is_none")]
+    pub total_size: Option<u64>,
+    #[serde(rename = "totalApproxTokens", skip_serializing_if = "Option::is_none")]
+    pub total_approx_tokens: Option<usize>,
+    /// Set on files when `detect_binary` is enabled.
+    #[serde(rename = "isBinary", skip_serializing_if = "Option::is_none")]
+    pub is_binary: Option<bool>,
+    /// File modification time (ms since the Unix epoch) recorded during the walk, for tree hashes.
+    #[serde(skip)]
+    pub modified: Option<u64>,
 }
 
-#[derive(Deserialize)]
+/// A tree node without its children, as listed by a paginated `/api/directory`.
+#[derive(Serialize)]
+pub struct FlatNode {
+    pub name: String,
+    /// Nesting level below the root, starting at 0 for the root's entries.
+    pub depth: usize,
+    #[serde(flatten)]
+    pub node: TreeNode,
+}
+
+#[derive(Default, Deserialize)]
 pub struct DirectoryQuery {
     pub path: Option<String>,
+    pub estimates: Option<bool>,
+    pub show_hidden: Option<bool>,
+    pub hide_hidden: Option<bool>,
+    pub always_show: Option<String>,
+    pub default_excludes: Option<bool>,
+    /// `ordered` returns children as arrays of named nodes instead of maps keyed by name.
+    pub format: Option<String>,
+    /// Comma-separated list of file extensions to keep, e.g. `py,rs,ts`.
+    pub extensions: Option<String>,
+    /// Glob that file paths relative to the root must match, e.g. `src/**/*.rs`.
+    #[serde(rename = "match")]
+    pub file_match: Option<String>,
+    /// Comma-separated globs; only files matching at least one are listed, e.g. `src/**,*.md`.
+    pub include_globs: Option<String>,
+    /// Comma-separated globs of files to leave out, on top of gitignore rules.
+    pub exclude_globs: Option<String>,
+    pub max_entries: Option<usize>,
+    pub stats: Option<bool>,
+    /// Etag of a previously served tree, used by `/api/directory/delta`.
+    pub etag: Option<String>,
+    /// `true` flags binary files by extension and content, `extension` by extension only.
+    pub detect_binary: Option<String>,
+    /// Number of levels to walk (and render, for `/api/directory/tree-text`).
+    pub depth: Option<usize>,
+    /// Comma-separated languages to skip in dependency analysis, e.g. `js,ts,cpp`.
+    pub disable: Option<String>,
+    /// How `/api/file` sends content: `auto` (default), `text` or `base64`.
+    #[serde(rename = "as")]
+    pub transport: Option<String>,
+    /// Bytes of the file `/api/file` sends, overriding `MAX_FILE_BYTES`.
+    pub max_bytes: Option<u64>,
+    /// Number of files `/api/files/largest` returns.
+    pub n: Option<usize>,
+    /// `next_cursor` of the previous page of a paginated `/api/directory` listing.
+    pub cursor: Option<String>,
+    /// Number of nodes per page; setting it (or `cursor`) paginates `/api/directory`.
+    pub limit: Option<usize>,
+    /// Whether `/api/file` adds line, byte and token counts of the text it sends.
+    pub counts: Option<bool>,
+    /// Whether `/api/file` strips a UTF-8 BOM and converts CRLF to LF in text content.
+    pub normalize: Option<bool>,
+    /// Fuzzy pattern matched against file paths by `/api/search/files`.
+    pub q: Option<String>,
+    /// Comma-separated language tags whose files `/api/directory/search-by-language` lists.
+    pub language: Option<String>,
+}
+
+/// Query for `/api/path/breadcrumbs`.
+#[derive(Deserialize)]
+pub struct BreadcrumbsQuery {
+    pub path: String,
+    /// Breadcrumbs above this directory are omitted.
+    pub root: Option<String>,
+}
+
+/// One component of a path, with the full path up to and including it.
+#[derive(Serialize)]
+pub struct Breadcrumb {
+    pub name: String,
+    pub path: String,
+}
+
+/// Stat-only information about a file, returned by `/api/file/metadata`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMetadata {
+    pub path: String,
+    pub size: u64,
+    /// Modification time in milliseconds since the Unix epoch.
+    pub modified: Option<u64>,
+    pub is_binary: bool,
+    /// Number of lines; `None` for binary files and files too large to count.
+    pub line_count: Option<usize>,
+    /// Language tag, as used for fenced code blocks, from the extension or shebang.
+    pub language: Option<String>,
+    /// Permission bits as an octal string, e.g. `644`. Unix only.
+    pub permissions: Option<String>,
+}
+
+/// Query for `GET /api/stat`.
+#[derive(Deserialize)]
+pub struct StatQuery {
+    pub path: String,
+    /// `blake3` to include a digest of the file's content.
+    pub hash: Option<String>,
 }
 
+/// Body of `POST /api/stat`.
+#[derive(Deserialize)]
+pub struct StatRequest {
+    pub paths: Vec<String>,
+    pub hash: Option<String>,
+}
+
+/// What `/api/stat` reports about a file or directory, without reading its content (unless
+/// a hash is requested).
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathStat {
+    pub path: String,
+    pub size: u64,
+    /// Modification time in milliseconds since the Unix epoch.
+    pub modified: Option<u64>,
+    /// Creation time in milliseconds since the Unix epoch, where the platform records it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    /// MIME type guessed from the extension; files only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Number of lines, for text files small enough to count.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_count: Option<usize>,
+    /// Hex BLAKE3 digest of the content, when requested with `hash=blake3`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// One entry of a bulk `/api/stat` response: the stat, or why it failed.
+#[derive(Serialize)]
+pub struct StatResult {
+    pub success: bool,
+    #[serde(flatten)]
+    pub stat: Option<PathStat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+}
+
+/// A file and its size, as listed by `/api/files/largest`.
 #[derive(Serialize)]
+pub struct LargeFile {
+    pub path: String,
+    pub size: u64,
+    /// Language tag, as used for fenced code blocks; `None` if the extension is unknown.
+    pub language: Option<String>,
+}
+
+/// Line counts for one language (or all of them), as returned by `/api/metrics/loc`.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct LanguageLoc {
+    pub files: usize,
+    pub lines: usize,
+    pub blank: usize,
+    pub comment: usize,
+    pub code: usize,
+}
+
+impl LanguageLoc {
+    pub fn add(&mut self, other: LanguageLoc) {
+        self.files += other.files;
+        self.lines += other.lines;
+        self.blank += other.blank;
+        self.comment += other.comment;
+        self.code += other.code;
+    }
+}
+
+/// Lines of code per language, keyed by language tag, plus the totals.
+#[derive(Default, Serialize)]
+pub struct LocReport {
+    pub languages: BTreeMap<String, LanguageLoc>,
+    pub total: LanguageLoc,
+}
+
+/// Size of the text a file read sent, for clients budgeting a prompt, with `counts=true`.
+#[derive(Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileCounts {
+    pub lines: usize,
+    pub bytes: usize,
+    /// Approximate tokens (bytes / 4).
+    pub approx_tokens: usize,
+}
+
+impl FileCounts {
+    pub fn add(&mut self, other: FileCounts) {
+        self.lines += other.lines;
+        self.bytes += other.bytes;
+        self.approx_tokens += other.approx_tokens;
+    }
+}
+
+#[derive(Default, Serialize)]
 pub struct FileResult {
     pub success: bool,
     pub content: Option<String>,
+    /// Content of binary files (or with `as=base64`), base64-encoded.
+    #[serde(rename = "contentBase64", skip_serializing_if = "Option::is_none")]
+    pub content_base64: Option<String>,
     pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    /// HTTP status the file would have been refused with on its own, e.g. 403.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Encoding the content was read as, e.g. `utf-8`, `windows-1252`, or `base64` for
+    /// `contentBase64`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// Whether malformed byte sequences in the text were replaced with U+FFFD.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lossy: Option<bool>,
+    #[serde(rename = "isBinary", skip_serializing_if = "Option::is_none")]
+    pub is_binary: Option<bool>,
+    /// MIME type guessed from the extension, for binary content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime: Option<String>,
+    /// Whether only the start of the file was sent; `file_size` has its full size.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    /// The file's current ETag, to send back on the next request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// The file's current content hash, `blake3:<hex>`, to send back on the next request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Set instead of content when the file still matches the ETag or hash the client sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unchanged: Option<bool>,
+    /// The glob of the request that matched this file.
+    #[serde(rename = "matchedBy", skip_serializing_if = "Option::is_none")]
+    pub matched_by: Option<String>,
+    /// Set on text files with `counts=true`.
+    #[serde(flatten)]
+    pub counts: Option<FileCounts>,
+    /// With `normalize=true`, whether a BOM or CRLF line endings were removed from the content.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized: Option<bool>,
 }
 
 #[derive(Deserialize)]
 pub struct FilesRequest {
+    pub paths: Vec<FileRequest>,
+    /// `auto` (default), `text` or `base64`; see `/api/file`.
+    #[serde(rename = "as")]
+    pub transport: Option<String>,
+    /// Bytes to send of each file, overriding `MAX_FILE_BYTES` (up to `MAX_FILE_READ_BYTES`).
+    pub max_bytes: Option<u64>,
+    /// Whether to add line, byte and token counts to each text file and the batch totals.
+    pub counts: Option<bool>,
+    /// Whether to strip a UTF-8 BOM and convert CRLF to LF in text content.
+    pub normalize: Option<bool>,
+}
+
+/// Files in a `/api/files` request: a bare path, a path with the ETag or content hash
+/// (`blake3:<hex>`) of a copy the client already has, or a glob (e.g. `src/**/*.rs`) matched
+/// against the files under `root`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum FileRequest {
+    Path(String),
+    Cached { path: String, etag: Option<String>, hash: Option<String> },
+    Glob { glob: String, root: String },
+}
+
+/// Body of `POST /api/file/diff`: content to compare with the file on disk.
+#[derive(Deserialize)]
+pub struct DiffRequest {
+    pub path: String,
+    pub content: String,
+}
+
+/// Query for `GET /api/directory/recently-modified`, alongside the usual tree options.
+#[derive(Deserialize)]
+pub struct RecentlyModifiedQuery {
+    /// Unix timestamp in seconds; only files modified after it are listed.
+    pub since_secs: Option<u64>,
+    /// Number of files to return.
+    pub limit: Option<usize>,
+}
+
+/// Query for `GET /api/file/tail`.
+#[derive(Deserialize)]
+pub struct TailQuery {
+    pub path: String,
+    /// Number of lines to return.
+    pub lines: Option<usize>,
+    /// Byte offset before which nothing is returned, e.g. the `size` of an earlier call,
+    /// to fetch only the lines added since.
+    pub offset: Option<u64>,
+    /// Bytes to read at most, overriding `MAX_FILE_BYTES`.
+    pub max_bytes: Option<u64>,
+}
+
+/// Query for `GET /api/file/diff`: the Git revision to compare the file on disk with.
+#[derive(Deserialize)]
+pub struct DiffQuery {
+    pub path: String,
+    pub rev: String,
+}
+
+/// Body of `POST /api/files/write`: new contents keyed by path, written in this order.
+#[derive(Deserialize)]
+pub struct WriteFilesRequest {
+    pub files: IndexMap<String, String>,
+}
+
+/// Query for `GET /api/file/checksum`.
+#[derive(Deserialize)]
+pub struct ChecksumQuery {
+    pub path: String,
+    /// `sha256` (default), `sha1` or `blake3`.
+    pub algorithm: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ChecksumResponse {
+    pub success: bool,
+    pub path: String,
+    pub algorithm: &'static str,
+    /// Hex digest of the file's content.
+    pub hash: String,
+}
+
+/// Body of `POST /api/directory/mkdir`.
+#[derive(Deserialize)]
+pub struct MkdirRequest {
+    pub path: String,
+}
+
+/// Body of `POST /api/directory/rename`.
+#[derive(Deserialize)]
+pub struct RenameRequest {
+    pub from: String,
+    pub to: String,
+}
+
+/// Body of `POST /api/codemap`.
+#[derive(Deserialize)]
+pub struct CodemapRequest {
     pub paths: Vec<String>,
+}
+
+/// One file of a `/api/codemap` response.
+#[derive(Default, Serialize)]
+pub struct CodemapFile {
+    pub success: bool,
+    /// The code map, or the full content if the language is not supported.
+    pub content: Option<String>,
+    pub error: Option<String>,
+    /// HTTP status the file would have been refused with on its own, e.g. 403.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Whether function bodies could be elided; `false` if the language has no grammar.
+    pub supported: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Approximate tokens (bytes / 4) of the file and of its code map.
+    pub original_tokens: usize,
+    pub condensed_tokens: usize,
+}
+
+/// Body of `POST /api/file/write`.
+#[derive(Deserialize)]
+pub struct WriteFileRequest {
+    pub path: String,
+    pub content: String,
+    /// Create the file's missing parent directories.
+    pub create_dirs: Option<bool>,
+    /// The hash (as returned by `/api/files`) the file must still have, or the write is
+    /// refused with 409.
+    pub expected_hash: Option<String>,
+}
+
+/// Body of `POST /api/edits`: line edits to several files, applied all or nothing.
+#[derive(Deserialize)]
+pub struct EditsRequest {
+    pub files: Vec<FileEdits>,
+    /// Only report what would change.
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Deserialize)]
+pub struct FileEdits {
+    pub path: String,
+    pub edits: Vec<LineEdit>,
+    /// The hash (as returned by `/api/files`) the file must still have.
+    pub expected_hash: Option<String>,
+}
+
+/// Replaces lines `start_line..=end_line` (1-based) with `replacement`. An `end_line` of
+/// `start_line - 1` inserts before `start_line` without replacing anything.
+#[derive(Clone, Deserialize)]
+pub struct LineEdit {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub replacement: String,
+}
+
+/// Roots to list in one `/api/directories` call, each optionally with its own tree options.
+#[derive(Deserialize)]
+pub struct DirectoriesRequest {
+    pub paths: Vec<RootRequest>,
+    pub include_deps: Option<bool>,
+}
+
+/// A root given either as a bare path or as an object taking the `/api/directory` parameters.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RootRequest {
+    Path(String),
+    Query(Box<DirectoryQuery>),
+}
+
+impl RootRequest {
+    pub fn into_query(self) -> DirectoryQuery {
+        match self {
+            RootRequest::Path(path) => DirectoryQuery { path: Some(path), ..Default::default() },
+            RootRequest::Query(query) => *query,
+        }
+    }
+}
+
+/// How a file refers to one of its dependencies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EdgeKind {
+    /// `import` statements and expressions (JS/TS, Python, Go, Swift) and Rust `use`.
+    Import,
+    /// `require()` in JS and `library()`/`require()` in R.
+    Require,
+    /// C/C++ `#include` and R `source()`.
+    Include,
+    /// A Rust `mod` declaration.
+    ModDecl,
+    /// JS/TS `export { ... } from` and `export * from`.
+    ReExport,
+}
+
+/// One dependency of a file in the dependency graph.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DependencyEdge {
+    /// The file depended on.
+    pub target: String,
+    pub kind: EdgeKind,
+    /// Whether the dependency only holds in some builds, e.g. an include inside `#ifdef`.
+    #[serde(default)]
+    pub conditional: bool,
+    /// The 1-based line of the import, include or declaration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+/// Files (relative to `root`) whose direct dependencies should be analyzed.
+#[derive(Deserialize)]
+pub struct BatchDependenciesRequest {
+    pub root: String,
+    pub files: Vec<String>,
+    /// Comma-separated languages to skip, as for `/api/dependencies`.
+    pub disable: Option<String>,
+}
+
+/// Query for `/api/search`.
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub path: Option<String>,
+    /// The pattern to search for, a literal string unless `regex` is set.
+    pub q: String,
+    pub regex: Option<bool>,
+    /// `sensitive` (default), `insensitive` or `smart`.
+    pub case: Option<String>,
+    pub max_results: Option<usize>,
+    /// Lines of context sent before and after each match.
+    pub context: Option<usize>,
+}
+
+/// Query for `/api/todos`.
+#[derive(Deserialize)]
+pub struct TodosQuery {
+    pub path: Option<String>,
+    /// Comma-separated tags to look for; `TODO,FIXME,HACK` by default.
+    pub tags: Option<String>,
+    pub max_results: Option<usize>,
+}
+
+/// A comment line with a `/api/todos` tag, from the tag to the end of the line.
+#[derive(Serialize)]
+pub struct Todo {
+    pub line: usize,
+    pub tag: String,
+    pub text: String,
+}
+
+/// The tagged comments of one file, as returned by `/api/todos`.
+#[derive(Serialize)]
+pub struct TodoFile {
+    pub file: String,
+    pub todos: Vec<Todo>,
+}
+
+/// A line matching a `/api/search` pattern, with 1-based line and column.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub line: usize,
+    pub column: usize,
+    pub line_text: String,
+    pub context_before: Vec<String>,
+    pub context_after: Vec<String>,
+}
+
+/// Query for `/api/symbols/index` and `/api/symbols/search`.
+#[derive(Deserialize)]
+pub struct SymbolsQuery {
+    pub path: Option<String>,
+    /// Comma-separated languages to index, e.g. `rust,python`. Defaults to all.
+    pub language: Option<String>,
+    /// For `/api/symbols/index`, a case-insensitive substring symbol names must contain;
+    /// for `/api/symbols/search`, the name to search for.
+    pub q: Option<String>,
+    /// Comma-separated kinds of symbols `/api/symbols/search` returns, e.g. `function,method`.
+    pub kind: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// A definition listed by the symbol endpoints.
+#[derive(Clone, Serialize)]
+pub struct Symbol {
+    pub name: String,
+    /// e.g. `function`, `method`, `class`, `struct`, `enum`, `trait` or `impl`.
+    pub kind: String,
+    /// The defining file, relative to the root.
+    pub file: String,
+    /// The 1-based line the definition starts on.
+    pub line: usize,
+    /// Name of the definition enclosing this one, e.g. a method's class.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+}
+
+/// A definition in the outline of a file, with the definitions nested inside it.
+#[derive(Serialize)]
+pub struct OutlineItem {
+    pub name: String,
+    /// e.g. `function`, `method`, `class`, `struct`, `enum`, `trait` or `impl`.
+    pub kind: String,
+    /// The declaration up to its body, on one line, e.g. `fn parse(input: &str) -> Ast`.
+    pub signature: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    /// The docstring or leading comment, without comment markers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+    pub children: Vec<OutlineItem>,
+}
+
+/// Query for `/api/callgraph`.
+#[derive(Deserialize)]
+pub struct CallgraphQuery {
+    pub path: Option<String>,
+    /// The function or method whose callers to find.
+    pub symbol: String,
+    /// `rust` or `python`; both by default.
+    pub lang: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// A call of the `/api/callgraph` symbol, found by name.
+#[derive(Serialize)]
+pub struct Caller {
+    /// The calling file, relative to the root.
+    pub file: String,
+    /// The 1-based line of the call.
+    pub line: usize,
+    /// The function or method making the call; `None` at the top level of a file.
+    pub function: Option<String>,
+    /// The definition enclosing the calling function, e.g. its class or `impl`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    /// The line of the call, trimmed.
+    pub text: String,
+}
+
+/// A file matched by `/api/search/files`.
+#[derive(Serialize)]
+pub struct FuzzyMatch {
+    /// The file's path relative to the root.
+    pub path: String,
+    pub score: u32,
+    /// Character indices into `path` of the matched characters, for highlighting.
+    pub positions: Vec<u32>,
+}
+
+/// The matches of a `/api/search` pattern in one file.
+#[derive(Serialize)]
+pub struct SearchFileMatches {
+    pub file: String,
+    pub matches: Vec<SearchMatch>,
+}
+
+/// Body of `POST /api/analyze/custom-query`.
+#[derive(Deserialize)]
+pub struct CustomQueryRequest {
+    pub root: String,
+    /// Grammar the query is written for, e.g. `javascript` or `rust`.
+    pub language: String,
+    /// Tree-sitter query source, e.g. `(function_declaration name: (identifier) @name)`.
+    pub query: String,
+    /// Extensions of the files to run the query on; defaults to those of `language`.
+    pub extensions: Option<Vec<String>>,
+}
+
+/// One match of a custom tree-sitter query.
+#[derive(Serialize)]
+pub struct QueryMatch {
+    pub file: String,
+    pub captures: Vec<QueryCapture>,
+}
+
+/// A node captured by a custom query match, with 1-based lines.
+#[derive(Serialize)]
+pub struct QueryCapture {
+    pub name: String,
+    pub text: String,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Deserialize)]
+pub struct MarkdownContextRequest {
+    pub root: String,
+    pub files: Vec<String>,
+    pub include_tree: Option<bool>,
 }
\ No newline at end of file
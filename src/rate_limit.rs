@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets refill completely within this long, so clients idle longer than it are
+/// forgotten. It is also how often the limiter sweeps for them.
+const REFILL_PERIOD: Duration = Duration::from_secs(60);
+
+/// Rate-limited endpoints are split by how expensive they are to serve.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EndpointClass {
+    /// Endpoints that walk or analyze a whole tree.
+    Expensive,
+    /// Everything else under `/api/`.
+    Cheap,
+}
+
+impl EndpointClass {
+    /// Classifies a request path. Static assets aren't rate limited.
+    pub fn of(path: &str) -> Option<EndpointClass> {
+        const EXPENSIVE_PREFIXES: &[&str] = &[
+            "/api/directory",
+            "/api/directories",
+            "/api/dependencies",
+            "/api/files/duplicates",
+            "/api/files/largest",
+            "/api/metrics/",
+            "/api/analyze/",
+            "/api/search",
+            "/api/symbols/",
+            "/api/todos",
+            "/api/callgraph",
+        ];
+        if !path.starts_with("/api/") {
+            None
+        } else if EXPENSIVE_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+            Some(EndpointClass::Expensive)
+        } else {
+            Some(EndpointClass::Cheap)
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Buckets {
+    clients: HashMap<(IpAddr, EndpointClass), Bucket>,
+    last_sweep: Instant,
+}
+
+/// Per-client token buckets: each client may make up to `limit` requests per minute of each
+/// endpoint class, in bursts of up to `limit`. A limit of 0 disables limiting for the class.
+pub struct RateLimiter {
+    expensive_per_minute: u32,
+    cheap_per_minute: u32,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(expensive_per_minute: u32, cheap_per_minute: u32) -> Self {
+        RateLimiter {
+            expensive_per_minute,
+            cheap_per_minute,
+            buckets: Mutex::new(Buckets { clients: HashMap::new(), last_sweep: Instant::now() }),
+        }
+    }
+
+    /// Takes a token for `client`. Returns `Err` with the time until a token is available
+    /// if the client is over its limit.
+    pub fn check(&self, client: IpAddr, class: EndpointClass) -> Result<(), Duration> {
+        self.check_at(client, class, Instant::now())
+    }
+
+    fn check_at(&self, client: IpAddr, class: EndpointClass, now: Instant) -> Result<(), Duration> {
+        let limit = match class {
+            EndpointClass::Expensive => self.expensive_per_minute,
+            EndpointClass::Cheap => self.cheap_per_minute,
+        };
+        if limit == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(limit);
+        let per_second = capacity / REFILL_PERIOD.as_secs_f64();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.last_sweep) >= REFILL_PERIOD {
+            buckets.last_sweep = now;
+            buckets.clients.retain(|_, bucket| now.duration_since(bucket.updated) < REFILL_PERIOD);
+        }
+
+        let bucket = buckets
+            .clients
+            .entry((client, class))
+            .or_insert(Bucket { tokens: capacity, updated: now });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+/// Whole seconds to send in `Retry-After` for a wait returned by `RateLimiter::check`,
+/// rounded up and at least 1.
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    (wait.as_secs_f64().ceil() as u64).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    #[test]
+    fn buckets_refill_at_the_limit_per_minute() {
+        let limiter = RateLimiter::new(6, 0);
+        let start = Instant::now();
+        for _ in 0..6 {
+            assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, start).is_ok());
+        }
+        assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, start).is_err());
+        // Other clients have their own bucket.
+        assert!(limiter.check_at(OTHER, EndpointClass::Expensive, start).is_ok());
+
+        // Six per minute is one token every ten seconds.
+        assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, start + Duration::from_secs(9)).is_err());
+        assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, start + Duration::from_secs(10)).is_ok());
+        assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, start + Duration::from_secs(10)).is_err());
+
+        // Refills stop at the burst size.
+        let later = start + Duration::from_secs(3600);
+        for _ in 0..6 {
+            assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, later).is_ok());
+        }
+        assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, later).is_err());
+    }
+
+    #[test]
+    fn retry_after_is_the_time_until_the_next_token() {
+        let limiter = RateLimiter::new(0, 2);
+        let start = Instant::now();
+        limiter.check_at(CLIENT, EndpointClass::Cheap, start).unwrap();
+        limiter.check_at(CLIENT, EndpointClass::Cheap, start).unwrap();
+        let wait = limiter.check_at(CLIENT, EndpointClass::Cheap, start).unwrap_err();
+        assert!((wait.as_secs_f64() - 30.0).abs() < 0.001, "{:?}", wait);
+
+        let wait = limiter.check_at(CLIENT, EndpointClass::Cheap, start + Duration::from_secs(20)).unwrap_err();
+        assert!((wait.as_secs_f64() - 10.0).abs() < 0.001, "{:?}", wait);
+        assert_eq!(retry_after_secs(wait), 10);
+        assert_eq!(retry_after_secs(Duration::from_millis(9_200)), 10);
+        assert_eq!(retry_after_secs(Duration::from_millis(1)), 1);
+    }
+
+    #[test]
+    fn a_limit_of_zero_disables_the_class() {
+        let limiter = RateLimiter::new(0, 1);
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert!(limiter.check_at(CLIENT, EndpointClass::Expensive, now).is_ok());
+        }
+        assert!(limiter.check_at(CLIENT, EndpointClass::Cheap, now).is_ok());
+        assert!(limiter.check_at(CLIENT, EndpointClass::Cheap, now).is_err());
+        assert_eq!(limiter.buckets.lock().unwrap().clients.len(), 1);
+    }
+
+    #[test]
+    fn idle_clients_are_forgotten() {
+        let limiter = RateLimiter::new(10, 10);
+        let start = Instant::now();
+        limiter.check_at(CLIENT, EndpointClass::Expensive, start).unwrap();
+        limiter.check_at(OTHER, EndpointClass::Cheap, start + Duration::from_secs(30)).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().clients.len(), 2);
+
+        // The sweep at the next request drops buckets idle for a whole refill period.
+        limiter.check_at(OTHER, EndpointClass::Cheap, start + REFILL_PERIOD + Duration::from_secs(1)).unwrap();
+        let buckets = limiter.buckets.lock().unwrap();
+        let clients: Vec<_> = buckets.clients.keys().copied().collect();
+        assert_eq!(clients, vec![(OTHER, EndpointClass::Cheap)]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,94 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File under the data directory holding the recently opened roots.
+const RECENT_ROOTS_FILE: &str = "recent.json";
+
+/// A root served by `/api/directory`, with when it was last opened.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentRoot {
+    pub path: String,
+    /// Milliseconds since the Unix epoch.
+    pub last_opened: u64,
+}
+
+/// Recently opened roots, most recent first, persisted as JSON so that the history is
+/// shared by every client of the server and survives restarts.
+pub struct RecentRoots {
+    file: PathBuf,
+    limit: usize,
+    entries: Mutex<Vec<RecentRoot>>,
+}
+
+impl RecentRoots {
+    /// Loads the history from `data_dir`, starting empty if it is missing or malformed.
+    pub fn load(data_dir: &Path, limit: usize) -> Self {
+        let file = data_dir.join(RECENT_ROOTS_FILE);
+        let entries = match fs::read(&file) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                warn!("Ignoring malformed recent roots file '{}': {}", file.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        RecentRoots { file, limit, entries: Mutex::new(entries) }
+    }
+
+    /// Moves `root` to the front of the history, dropping the oldest entries past the limit.
+    pub fn record(&self, root: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| entry.path != root);
+        entries.insert(0, RecentRoot { path: root.to_string(), last_opened: now_millis() });
+        entries.truncate(self.limit);
+        self.save(&entries);
+    }
+
+    /// Returns the history, first pruning roots that no longer exist.
+    pub fn list(&self) -> Vec<RecentRoot> {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|entry| Path::new(&entry.path).is_dir());
+        if entries.len() != before {
+            debug!("Pruned {} missing roots from the recent roots.", before - entries.len());
+            self.save(&entries);
+        }
+        entries.clone()
+    }
+
+    /// Removes `root` from the history. Returns whether it was present.
+    pub fn remove(&self, root: &str) -> bool {
+        let mut entries = self.entries.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|entry| entry.path != root);
+        let removed = entries.len() != before;
+        if removed {
+            self.save(&entries);
+        }
+        removed
+    }
+
+    /// Writes the history to a temporary file and renames it into place, so that a crash
+    /// mid-write can't leave a truncated file behind.
+    fn save(&self, entries: &[RecentRoot]) {
+        let result = (|| -> std::io::Result<()> {
+            if let Some(dir) = self.file.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let tmp = self.file.with_extension("json.tmp");
+            fs::write(&tmp, serde_json::to_vec_pretty(entries)?)?;
+            fs::rename(&tmp, &self.file)
+        })();
+        if let Err(e) = result {
+            warn!("Failed to save recent roots to '{}': {}", self.file.display(), e);
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
\ No newline at end of file
@@ -0,0 +1,321 @@
+use crate::file_system::TreeOptions;
+use crate::models::{FuzzyMatch, SearchMatch, Symbol, Todo, TreeNode};
+use crate::syntax::{comment_ranges, Grammar};
+use crate::utils::{from_api_path, natural_compare, resolve_unicode_path, to_api_path, to_nfc};
+use grep_matcher::Matcher;
+use grep_regex::{RegexMatcher, RegexMatcherBuilder};
+use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkContextKind, SinkMatch};
+use indexmap::IndexMap;
+use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Utf32Str};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// How `/api/search` compares letter case (`case` query parameter).
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum CaseMode {
+    /// Case must match (`sensitive`, the default).
+    #[default]
+    Sensitive,
+    /// Case is ignored (`insensitive`).
+    Insensitive,
+    /// Case is ignored unless the pattern has an uppercase letter (`smart`).
+    Smart,
+}
+
+impl FromStr for CaseMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode.trim().to_ascii_lowercase().as_str() {
+            "sensitive" => Ok(CaseMode::Sensitive),
+            "insensitive" => Ok(CaseMode::Insensitive),
+            "smart" => Ok(CaseMode::Smart),
+            other => Err(format!("Unknown case mode '{}'; expected sensitive, insensitive or smart", other)),
+        }
+    }
+}
+
+/// Compiles a search pattern, as a regex or (with `regex` false) a literal string. Fails
+/// with the parse error if the regex is invalid.
+pub fn build_matcher(pattern: &str, regex: bool, case: CaseMode) -> Result<RegexMatcher, String> {
+    RegexMatcherBuilder::new()
+        .fixed_strings(!regex)
+        .case_insensitive(case == CaseMode::Insensitive)
+        .case_smart(case == CaseMode::Smart)
+        .line_terminator(Some(b'\n'))
+        .build(pattern)
+        .map_err(|e| e.to_string())
+}
+
+/// Searches one file line by line, returning up to `limit` matching lines with `context`
+/// lines around each. Files with a NUL byte are treated as binary and yield no matches.
+pub fn search_file(matcher: &RegexMatcher, path: &Path, context: usize, limit: usize) -> io::Result<Vec<SearchMatch>> {
+    let mut searcher = SearcherBuilder::new()
+        .line_number(true)
+        .before_context(context)
+        .after_context(context)
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .build();
+    let mut sink = MatchSink { matcher, limit, matches: Vec::new(), before: Vec::new(), binary: false };
+    searcher.search_path(matcher, path, &mut sink)?;
+    if sink.binary {
+        return Ok(Vec::new());
+    }
+    Ok(sink.matches)
+}
+
+/// Collects matching lines, attaching the context lines the searcher reports around them.
+struct MatchSink<'a> {
+    matcher: &'a RegexMatcher,
+    limit: usize,
+    matches: Vec<SearchMatch>,
+    /// Context lines seen since the last match, to become the next match's `context_before`.
+    before: Vec<String>,
+    binary: bool,
+}
+
+fn line_text(bytes: &[u8]) -> String {
+    let line = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    String::from_utf8_lossy(line).into_owned()
+}
+
+impl Sink for MatchSink<'_> {
+    type Error = io::Error;
+
+    fn matched(&mut self, _searcher: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, io::Error> {
+        let bytes = mat.bytes();
+        let start = self
+            .matcher
+            .find(bytes)
+            .map_err(io::Error::other)?
+            .map_or(0, |m| m.start());
+        self.matches.push(SearchMatch {
+            line: mat.line_number().unwrap_or(0) as usize,
+            // 1-based, in characters rather than bytes.
+            column: String::from_utf8_lossy(&bytes[..start]).chars().count() + 1,
+            line_text: line_text(bytes),
+            context_before: std::mem::take(&mut self.before),
+            context_after: Vec::new(),
+        });
+        Ok(self.matches.len() < self.limit)
+    }
+
+    fn context(&mut self, _searcher: &Searcher, context: &SinkContext<'_>) -> Result<bool, io::Error> {
+        let text = line_text(context.bytes());
+        match (context.kind(), self.matches.last_mut()) {
+            (SinkContextKind::After, Some(last)) => last.context_after.push(text),
+            _ => self.before.push(text),
+        }
+        Ok(true)
+    }
+
+    fn context_break(&mut self, _searcher: &Searcher) -> Result<bool, io::Error> {
+        self.before.clear();
+        Ok(true)
+    }
+
+    fn binary_data(&mut self, _searcher: &Searcher, _offset: u64) -> Result<bool, io::Error> {
+        self.binary = true;
+        Ok(false)
+    }
+}
+
+/// Identifies a cached file list: the root and the tree options that decide which files
+/// `/api/search/files` sees.
+#[derive(Clone, PartialEq, Eq)]
+pub struct FileListKey {
+    root: PathBuf,
+    hide_hidden: bool,
+    always_show: Vec<String>,
+    default_excludes: bool,
+}
+
+impl FileListKey {
+    pub fn new(root: &Path, options: &TreeOptions) -> Self {
+        FileListKey {
+            root: root.to_path_buf(),
+            hide_hidden: options.hide_hidden,
+            always_show: options.always_show.clone(),
+            default_excludes: options.default_excludes,
+        }
+    }
+}
+
+/// The files of a walked tree, kept between `/api/search/files` requests together with
+/// the mtimes of the folders walked, since adding, removing or renaming a file changes
+/// its folder's mtime.
+pub struct FileList {
+    /// File paths relative to the root, in tree order.
+    pub files: Vec<String>,
+    /// Whether the walk stopped at its entry limit, so some files are missing.
+    pub truncated: bool,
+    folders: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl FileList {
+    /// Collects the files of `tree` and stats the root and every folder in it.
+    pub fn new(root: &Path, tree: &IndexMap<String, TreeNode>, truncated: bool) -> Self {
+        fn walk(root: &str, node: &IndexMap<String, TreeNode>, list: &mut FileList) {
+            for child in node.values() {
+                match &child.children {
+                    Some(children) => {
+                        // Names on disk may be NFD while tree paths are NFC.
+                        let folder = from_api_path(&child.path);
+                        list.folders.push((resolve_unicode_path(&folder).unwrap_or(folder), None));
+                        walk(root, children, list);
+                    }
+                    None if child.node_type == "file" => {
+                        let relative = child.path.strip_prefix(root).unwrap_or(&child.path);
+                        list.files.push(relative.trim_start_matches('/').to_string());
+                    }
+                    None => {}
+                }
+            }
+        }
+        // Tree paths are NFC API paths, so the root must be too for prefixes to match.
+        let root_str = to_nfc(&to_api_path(root));
+        let mut list = FileList { files: Vec::new(), truncated, folders: vec![(root.to_path_buf(), None)] };
+        walk(&root_str, tree, &mut list);
+        for (folder, mtime) in &mut list.folders {
+            *mtime = folder_mtime(folder);
+        }
+        list
+    }
+
+    /// Whether no folder has been added, removed or changed since the list was built.
+    /// New folders always change their parent's mtime, so checking known ones suffices.
+    pub fn is_fresh(&self) -> bool {
+        self.folders.iter().all(|(folder, mtime)| folder_mtime(folder) == *mtime)
+    }
+}
+
+fn folder_mtime(folder: &Path) -> Option<SystemTime> {
+    fs::metadata(folder).and_then(|m| m.modified()).ok()
+}
+
+/// Ranks the files of `list` against an fzf-style `query` (space-separated terms matched
+/// as subsequences, with `'`, `^`, `$` and `!` as in fzf), returning the best `limit`
+/// matches with the character positions each matched at. Ties go to shorter paths.
+pub fn fuzzy_find(list: &FileList, query: &str, limit: usize) -> Vec<FuzzyMatch> {
+    let mut matcher = nucleo_matcher::Matcher::new(Config::DEFAULT.match_paths());
+    let pattern = Pattern::parse(query, CaseMatching::Smart, Normalization::Smart);
+    let mut buf = Vec::new();
+    let mut matches: Vec<FuzzyMatch> = list
+        .files
+        .iter()
+        .filter_map(|path| {
+            let mut positions = Vec::new();
+            let score = pattern.indices(Utf32Str::new(path, &mut buf), &mut matcher, &mut positions)?;
+            positions.sort_unstable();
+            positions.dedup();
+            Some(FuzzyMatch { path: path.clone(), score, positions })
+        })
+        .collect();
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.path.len().cmp(&b.path.len()))
+            .then_with(|| natural_compare(&a.path, &b.path))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+/// Ranks symbols by how well their names match `query`: exact matches first, then case-
+/// insensitive ones, prefixes, substrings and finally fuzzy (subsequence) matches, each
+/// ordered by fuzzy score and then by shorter name. Returns the best `limit`.
+pub fn rank_symbols(symbols: Vec<Symbol>, query: &str, limit: usize) -> Vec<Symbol> {
+    let mut matcher = nucleo_matcher::Matcher::new(Config::DEFAULT);
+    let pattern = Pattern::parse(query, CaseMatching::Smart, Normalization::Smart);
+    let lower_query = query.to_lowercase();
+    let mut buf = Vec::new();
+    let mut ranked: Vec<(u8, u32, Symbol)> = symbols
+        .into_iter()
+        .filter_map(|symbol| {
+            let score = pattern.score(Utf32Str::new(&symbol.name, &mut buf), &mut matcher)?;
+            let lower_name = symbol.name.to_lowercase();
+            let tier = if symbol.name == query {
+                0
+            } else if lower_name == lower_query {
+                1
+            } else if lower_name.starts_with(&lower_query) {
+                2
+            } else if lower_name.contains(&lower_query) {
+                3
+            } else {
+                4
+            };
+            Some((tier, score, symbol))
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        a.0.cmp(&b.0)
+            .then(b.1.cmp(&a.1))
+            .then(a.2.name.len().cmp(&b.2.name.len()))
+            .then_with(|| natural_compare(&a.2.file, &b.2.file))
+            .then(a.2.line.cmp(&b.2.line))
+    });
+    ranked.into_iter().take(limit).map(|(_, _, symbol)| symbol).collect()
+}
+
+/// Finds the lines of a file with one of `tags` (as a whole word) in a comment, or on any
+/// line in languages without a grammar. Files larger than `max_bytes` or with a NUL byte
+/// are skipped.
+pub fn find_todos(path: &Path, tags: &[String], max_bytes: u64) -> io::Result<Vec<Todo>> {
+    if fs::metadata(path)?.len() > max_bytes {
+        return Ok(Vec::new());
+    }
+    let bytes = fs::read(path)?;
+    if bytes.contains(&0) {
+        return Ok(Vec::new());
+    }
+    let content = String::from_utf8_lossy(&bytes);
+    if !tags.iter().any(|tag| content.contains(tag.as_str())) {
+        return Ok(Vec::new());
+    }
+    let ranges = Grammar::for_path(path)
+        .and_then(|grammar| comment_ranges(grammar, &content))
+        // Without a grammar the whole file is one range.
+        .unwrap_or_else(|| std::iter::once(0..content.len()).collect());
+
+    let mut todos: Vec<Todo> = Vec::new();
+    // The 1-based line of byte `counted`.
+    let (mut line, mut counted) = (1, 0);
+    for range in ranges {
+        line += content[counted..range.start].matches('\n').count();
+        counted = range.start;
+        for (i, text) in content[range].lines().enumerate() {
+            let Some((start, tag)) = find_tag(text, tags) else { continue };
+            if todos.last().is_some_and(|todo| todo.line == line + i) {
+                continue;
+            }
+            let text = text[start..].trim_end();
+            todos.push(Todo {
+                line: line + i,
+                tag: tag.to_string(),
+                text: text.strip_suffix("*/").unwrap_or(text).trim_end().to_string(),
+            });
+        }
+    }
+    Ok(todos)
+}
+
+/// The first of `tags` to appear in `text` as a whole word, with its byte offset.
+fn find_tag<'t>(text: &str, tags: &'t [String]) -> Option<(usize, &'t str)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    tags.iter()
+        .filter_map(|tag| {
+            let (start, _) = text.match_indices(tag.as_str()).find(|(start, _)| {
+                let before = text[..*start].chars().next_back();
+                let after = text[start + tag.len()..].chars().next();
+                !before.is_some_and(is_word) && !after.is_some_and(is_word)
+            })?;
+            Some((start, tag.as_str()))
+        })
+        .min_by_key(|(start, _)| *start)
+}
\ No newline at end of file
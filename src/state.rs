@@ -0,0 +1,218 @@
+use crate::allowed_roots::AllowedRoots;
+use crate::config::ServerConfig;
+use crate::dependency_analyzer::{DisabledLanguages, IncrementalAnalyzer};
+use crate::file_cache::FileCache;
+use crate::file_system::TreeSnapshot;
+use crate::rate_limit::RateLimiter;
+use crate::recent::RecentRoots;
+use crate::search::{FileList, FileListKey};
+use crate::syntax::SymbolIndex;
+use crate::watcher::WatchRegistry;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Number of recent tree snapshots kept per root for `/api/directory/delta`.
+const SNAPSHOTS_PER_ROOT: usize = 4;
+/// Number of file lists kept for `/api/search/files`.
+const CACHED_FILE_LISTS: usize = 8;
+/// Number of incremental dependency analyzers kept, one per root and set of disabled languages.
+const CACHED_ANALYZERS: usize = 8;
+/// Number of symbol indexes kept for `/api/symbols/*`, one per root.
+const CACHED_SYMBOL_INDEXES: usize = 8;
+
+/// Snapshots for one root as `(etag, snapshot)` pairs, least recently used first.
+type SnapshotCache = VecDeque<(String, Arc<TreeSnapshot>)>;
+
+type AnalyzerKey = (PathBuf, DisabledLanguages);
+
+/// Shared application state, registered with actix as `web::Data<AppState>`.
+pub struct AppState {
+    pub config: ServerConfig,
+    /// Incremental dependency analyzers, keyed by canonicalized root path and the
+    /// languages disabled for them, most recently used last. Each has its own lock, so
+    /// analyses of different roots run concurrently.
+    analyzers: Mutex<VecDeque<(AnalyzerKey, Arc<Mutex<IncrementalAnalyzer>>)>>,
+    /// Symbol indexes for `/api/symbols/*`, keyed by canonicalized root path, most recently
+    /// used last. Each has its own lock, so different roots are indexed concurrently.
+    symbol_indexes: Mutex<VecDeque<(PathBuf, Arc<Mutex<SymbolIndex>>)>>,
+    /// Recently served tree snapshots per root, most recently used last.
+    snapshots: Mutex<HashMap<PathBuf, SnapshotCache>>,
+    /// File lists of recently searched roots, most recently used last.
+    file_lists: Mutex<VecDeque<(FileListKey, Arc<FileList>)>>,
+    /// Filesystem watchers shared by `/api/watch` subscribers.
+    pub watchers: Arc<WatchRegistry>,
+    /// Roots recently served by `/api/directory`, persisted under the data directory.
+    pub recent: RecentRoots,
+    /// Directories the file endpoints may serve files from.
+    pub allowed_roots: AllowedRoots,
+    /// Per-client request limits, applied by the `rate_limit` middleware and to WebSocket methods.
+    pub rate_limiter: RateLimiter,
+    /// Recently read file contents, when `FILE_CACHE_BYTES` is set.
+    pub file_cache: FileCache,
+}
+
+impl AppState {
+    pub fn new(config: ServerConfig) -> Self {
+        let recent = RecentRoots::load(&config.data_dir, config.recent_roots_limit);
+        let allowed_roots = AllowedRoots::new(&config.allowed_roots, config.allow_broad_roots);
+        let rate_limiter = RateLimiter::new(config.rate_limit_expensive_per_min, config.rate_limit_cheap_per_min);
+        let file_cache = FileCache::new(config.file_cache_bytes);
+        AppState {
+            config,
+            analyzers: Mutex::new(VecDeque::new()),
+            symbol_indexes: Mutex::new(VecDeque::new()),
+            snapshots: Mutex::new(HashMap::new()),
+            file_lists: Mutex::new(VecDeque::new()),
+            watchers: Arc::new(WatchRegistry::default()),
+            recent,
+            allowed_roots,
+            rate_limiter,
+            file_cache,
+        }
+    }
+
+    /// Stores a snapshot under its etag, evicting the least recently used one for the root.
+    pub fn remember_snapshot(&self, root: &Path, etag: &str, snapshot: TreeSnapshot) {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let entries = snapshots.entry(root.to_path_buf()).or_default();
+        entries.retain(|(existing, _)| existing != etag);
+        entries.push_back((etag.to_string(), Arc::new(snapshot)));
+        while entries.len() > SNAPSHOTS_PER_ROOT {
+            entries.pop_front();
+        }
+    }
+
+    /// Looks up a previously served snapshot, marking it as recently used.
+    pub fn find_snapshot(&self, root: &Path, etag: &str) -> Option<Arc<TreeSnapshot>> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let entries = snapshots.get_mut(root)?;
+        let index = entries.iter().position(|(existing, _)| existing == etag)?;
+        let entry = entries.remove(index)?;
+        let snapshot = entry.1.clone();
+        entries.push_back(entry);
+        Some(snapshot)
+    }
+
+    /// Returns the dependency analyzer for a root and set of disabled languages, creating it
+    /// (and evicting the least recently used one) if needed. Only the returned analyzer is
+    /// locked while it runs, not every analyzer.
+    pub fn analyzer(&self, root: &Path, disabled: DisabledLanguages) -> Arc<Mutex<IncrementalAnalyzer>> {
+        let mut analyzers = self.analyzers.lock().unwrap();
+        let key = (root.to_path_buf(), disabled);
+        let entry = match analyzers.iter().position(|(existing, _)| *existing == key) {
+            Some(index) => analyzers.remove(index).unwrap(),
+            None => (key, Arc::default()),
+        };
+        let analyzer = entry.1.clone();
+        analyzers.push_back(entry);
+        while analyzers.len() > CACHED_ANALYZERS {
+            analyzers.pop_front();
+        }
+        analyzer
+    }
+
+    /// Returns the symbol index for a root, creating it (and evicting the least recently used
+    /// one) if needed. Only the returned index is locked while it updates, not every index.
+    pub fn symbol_index(&self, root: &Path) -> Arc<Mutex<SymbolIndex>> {
+        let mut indexes = self.symbol_indexes.lock().unwrap();
+        let entry = match indexes.iter().position(|(existing, _)| existing == root) {
+            Some(index) => indexes.remove(index).unwrap(),
+            None => (root.to_path_buf(), Arc::default()),
+        };
+        let index = entry.1.clone();
+        indexes.push_back(entry);
+        while indexes.len() > CACHED_SYMBOL_INDEXES {
+            indexes.pop_front();
+        }
+        index
+    }
+
+    /// Stores a root's file list, evicting the least recently used one.
+    pub fn remember_file_list(&self, key: FileListKey, list: Arc<FileList>) {
+        let mut lists = self.file_lists.lock().unwrap();
+        lists.retain(|(existing, _)| *existing != key);
+        lists.push_back((key, list));
+        while lists.len() > CACHED_FILE_LISTS {
+            lists.pop_front();
+        }
+    }
+
+    /// Looks up a root's file list, marking it as recently used. It may be stale.
+    pub fn find_file_list(&self, key: &FileListKey) -> Option<Arc<FileList>> {
+        let mut lists = self.file_lists.lock().unwrap();
+        let index = lists.iter().position(|(existing, _)| existing == key)?;
+        let entry = lists.remove(index)?;
+        let list = entry.1.clone();
+        lists.push_back(entry);
+        Some(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TempDir;
+
+    fn state(data_dir: &Path) -> AppState {
+        AppState::new(ServerConfig { data_dir: data_dir.to_path_buf(), ..Default::default() })
+    }
+
+    #[test]
+    fn analyzers_are_shared_per_root_and_disabled_set() {
+        let dir = TempDir::new();
+        let state = state(dir.path());
+        let root = Path::new("/project");
+        let first = state.analyzer(root, DisabledLanguages::default());
+        assert!(Arc::ptr_eq(&first, &state.analyzer(root, DisabledLanguages::default())));
+        let python_off: DisabledLanguages = "py".parse().unwrap();
+        assert!(!Arc::ptr_eq(&first, &state.analyzer(root, python_off)));
+        assert!(!Arc::ptr_eq(&first, &state.analyzer(Path::new("/other"), DisabledLanguages::default())));
+    }
+
+    #[test]
+    fn least_recently_used_analyzer_is_evicted() {
+        let dir = TempDir::new();
+        let state = state(dir.path());
+        let roots: Vec<PathBuf> = (0..=CACHED_ANALYZERS).map(|i| PathBuf::from(format!("/project{}", i))).collect();
+        let first = state.analyzer(&roots[0], DisabledLanguages::default());
+        let second = state.analyzer(&roots[1], DisabledLanguages::default());
+        for root in &roots[2..] {
+            // Keeps the first root recently used, so the second is the one evicted.
+            state.analyzer(&roots[0], DisabledLanguages::default());
+            state.analyzer(root, DisabledLanguages::default());
+        }
+        assert_eq!(state.analyzers.lock().unwrap().len(), CACHED_ANALYZERS);
+        assert!(Arc::ptr_eq(&first, &state.analyzer(&roots[0], DisabledLanguages::default())));
+        assert!(!Arc::ptr_eq(&second, &state.analyzer(&roots[1], DisabledLanguages::default())));
+    }
+
+    #[test]
+    fn analyzer_lock_is_independent_of_other_roots() {
+        let dir = TempDir::new();
+        let state = state(dir.path());
+        let busy = state.analyzer(Path::new("/busy"), DisabledLanguages::default());
+        let _running = busy.lock().unwrap();
+        // Another root's analyzer is still available while the first one is held.
+        let other = state.analyzer(Path::new("/idle"), DisabledLanguages::default());
+        assert!(other.try_lock().is_ok());
+    }
+
+
+    #[test]
+    fn symbol_indexes_are_per_root_and_bounded() {
+        let dir = TempDir::new();
+        let state = state(dir.path());
+        let roots: Vec<PathBuf> = (0..=CACHED_SYMBOL_INDEXES).map(|i| PathBuf::from(format!("/project{}", i))).collect();
+        let first = state.symbol_index(&roots[0]);
+        assert!(Arc::ptr_eq(&first, &state.symbol_index(&roots[0])));
+        let _updating = first.lock().unwrap();
+        // Another root's index is still available while the first one is held.
+        assert!(state.symbol_index(&roots[1]).try_lock().is_ok());
+        for root in &roots[2..] {
+            state.symbol_index(root);
+        }
+        assert_eq!(state.symbol_indexes.lock().unwrap().len(), CACHED_SYMBOL_INDEXES);
+        assert!(!Arc::ptr_eq(&first, &state.symbol_index(&roots[0])));
+    }
+}
\ No newline at end of file
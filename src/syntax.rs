@@ -0,0 +1,750 @@
+use crate::dependency_analyzer::file_stamp;
+use crate::models::{Caller, OutlineItem, QueryCapture, QueryMatch, Symbol};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::thread;
+use std::time::{Instant, SystemTime};
+use streaming_iterator::StreamingIterator;
+use tree_sitter::{Language, Node, Parser, Query, QueryCursor, Tree};
+
+/// Most threads used to extract symbols from the files of a root.
+const MAX_SYMBOL_THREADS: usize = 16;
+
+/// A language whose tree-sitter grammar is compiled in.
+pub struct Grammar {
+    pub name: &'static str,
+    /// Other names the language is accepted under, e.g. `js`.
+    aliases: &'static [&'static str],
+    /// Lower-cased extensions of the files parsed with this grammar, without the dot.
+    pub extensions: &'static [&'static str],
+    language: fn() -> Language,
+    /// How line comments start, e.g. `//`.
+    line_comment: &'static str,
+    /// Query capturing each definition as `@definition.<kind>` and its name as `@name`.
+    /// Where a node matches several patterns, the first one wins.
+    symbols: &'static str,
+    /// Query capturing each call as `@call` and the called function's name as `@callee`,
+    /// for the languages whose call graphs are supported.
+    pub calls: Option<&'static str>,
+}
+
+pub const JAVASCRIPT: Grammar = Grammar {
+    name: "javascript",
+    aliases: &["js"],
+    // TypeScript is parsed as JavaScript, as by the dependency analyzer.
+    extensions: &["js", "jsx", "mjs", "cjs", "ts", "tsx"],
+    language: || tree_sitter_javascript::LANGUAGE.into(),
+    line_comment: "//",
+    symbols: r#"
+(function_declaration name: (identifier) @name) @definition.function
+(generator_function_declaration name: (identifier) @name) @definition.function
+(class_declaration name: (identifier) @name) @definition.class
+(method_definition name: (property_identifier) @name) @definition.method
+(variable_declarator
+  name: (identifier) @name
+  value: [(arrow_function) (function_expression) (generator_function)]) @definition.function
+"#,
+    calls: None,
+};
+
+pub const PYTHON: Grammar = Grammar {
+    name: "python",
+    aliases: &["py"],
+    extensions: &["py", "pyi"],
+    language: || tree_sitter_python::LANGUAGE.into(),
+    line_comment: "#",
+    symbols: r#"
+(class_definition name: (identifier) @name) @definition.class
+(function_definition name: (identifier) @name) @definition.function
+"#,
+    calls: Some(
+        r#"
+(call function: (identifier) @callee) @call
+(call function: (attribute attribute: (identifier) @callee)) @call
+"#,
+    ),
+};
+
+pub const RUST: Grammar = Grammar {
+    name: "rust",
+    aliases: &["rs"],
+    extensions: &["rs"],
+    language: || tree_sitter_rust::LANGUAGE.into(),
+    line_comment: "//",
+    symbols: r#"
+(mod_item name: (identifier) @name) @definition.module
+(function_item name: (identifier) @name) @definition.function
+(function_signature_item name: (identifier) @name) @definition.function
+(struct_item name: (type_identifier) @name) @definition.struct
+(union_item name: (type_identifier) @name) @definition.union
+(enum_item name: (type_identifier) @name) @definition.enum
+(trait_item name: (type_identifier) @name) @definition.trait
+(impl_item type: (_) @name) @definition.impl
+(type_item name: (type_identifier) @name) @definition.type
+(const_item name: (identifier) @name) @definition.constant
+(static_item name: (identifier) @name) @definition.constant
+(macro_definition name: (identifier) @name) @definition.macro
+"#,
+    calls: Some(
+        r#"
+(call_expression function: (identifier) @callee) @call
+(call_expression function: (scoped_identifier name: (identifier) @callee)) @call
+(call_expression function: (field_expression field: (field_identifier) @callee)) @call
+(call_expression function: (generic_function function: (identifier) @callee)) @call
+(call_expression function: (generic_function function: (scoped_identifier name: (identifier) @callee))) @call
+(call_expression function: (generic_function function: (field_expression field: (field_identifier) @callee))) @call
+"#,
+    ),
+};
+
+pub const CPP: Grammar = Grammar {
+    name: "cpp",
+    aliases: &["c", "c++"],
+    extensions: &["cpp", "c", "h", "hpp", "hxx", "cc", "hh", "cxx"],
+    language: || tree_sitter_cpp::LANGUAGE.into(),
+    line_comment: "//",
+    symbols: r#"
+(namespace_definition name: (namespace_identifier) @name) @definition.module
+(class_specifier name: (type_identifier) @name body: (field_declaration_list)) @definition.class
+(struct_specifier name: (type_identifier) @name body: (field_declaration_list)) @definition.struct
+(union_specifier name: (type_identifier) @name body: (field_declaration_list)) @definition.union
+(enum_specifier name: (type_identifier) @name body: (enumerator_list)) @definition.enum
+(function_definition declarator: (function_declarator declarator: (_) @name)) @definition.function
+(function_definition
+  declarator: (pointer_declarator declarator: (function_declarator declarator: (_) @name))) @definition.function
+(function_definition
+  declarator: (reference_declarator (function_declarator declarator: (_) @name))) @definition.function
+(field_declaration declarator: (function_declarator declarator: (_) @name)) @definition.function
+"#,
+    calls: None,
+};
+
+pub const GO: Grammar = Grammar {
+    name: "go",
+    aliases: &["golang"],
+    extensions: &["go"],
+    language: || tree_sitter_go::LANGUAGE.into(),
+    line_comment: "//",
+    symbols: r#"
+(function_declaration name: (identifier) @name) @definition.function
+(method_declaration name: (field_identifier) @name) @definition.method
+(type_spec name: (type_identifier) @name type: (struct_type)) @definition.struct
+(type_spec name: (type_identifier) @name type: (interface_type)) @definition.interface
+(type_spec name: (type_identifier) @name) @definition.type
+"#,
+    calls: None,
+};
+
+pub const SWIFT: Grammar = Grammar {
+    name: "swift",
+    aliases: &[],
+    extensions: &["swift"],
+    language: || tree_sitter_swift::LANGUAGE.into(),
+    line_comment: "//",
+    symbols: r#"
+(class_declaration name: (type_identifier) @name) @definition.class
+(protocol_declaration name: (type_identifier) @name) @definition.interface
+(function_declaration name: (simple_identifier) @name) @definition.function
+(protocol_function_declaration name: (simple_identifier) @name) @definition.function
+"#,
+    calls: None,
+};
+
+pub const R: Grammar = Grammar {
+    name: "r",
+    aliases: &[],
+    extensions: &["r"],
+    language: || tree_sitter_r::LANGUAGE.into(),
+    line_comment: "#",
+    symbols: r#"
+(binary_operator lhs: (identifier) @name rhs: (function_definition)) @definition.function
+"#,
+    calls: None,
+};
+
+const GRAMMARS: &[Grammar] = &[JAVASCRIPT, PYTHON, RUST, CPP, GO, SWIFT, R];
+
+impl Grammar {
+    /// Looks a grammar up by name or alias, ignoring case.
+    pub fn by_name(name: &str) -> Option<&'static Grammar> {
+        let name = name.trim().to_ascii_lowercase();
+        GRAMMARS
+            .iter()
+            .find(|grammar| grammar.name == name || grammar.aliases.contains(&name.as_str()))
+    }
+
+    /// All compiled-in grammars.
+    pub fn all() -> &'static [Grammar] {
+        GRAMMARS
+    }
+
+    /// Names of all compiled-in grammars, for error messages.
+    pub fn names() -> Vec<&'static str> {
+        GRAMMARS.iter().map(|grammar| grammar.name).collect()
+    }
+
+    pub fn language(&self) -> Language {
+        (self.language)()
+    }
+
+    /// Looks up the grammar that parses a file, by its extension.
+    pub fn for_path(path: &Path) -> Option<&'static Grammar> {
+        let ext = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        GRAMMARS.iter().find(|grammar| grammar.extensions.contains(&ext.as_str()))
+    }
+
+    /// A parser for this grammar with its symbols query compiled, or `None` (logged) if
+    /// either fails.
+    pub fn symbol_parser(&self) -> Option<(Parser, Query)> {
+        let mut parser = Parser::new();
+        if let Err(e) = parser.set_language(&self.language()) {
+            warn!("Failed to set language for {}: {}", self.name, e);
+            return None;
+        }
+        match self.query(self.symbols) {
+            Ok(query) => Some((parser, query)),
+            Err(e) => {
+                warn!("{}. {} symbols will be skipped.", e, self.name);
+                None
+            }
+        }
+    }
+
+    /// Compiles a query against this grammar, describing where it fails to.
+    pub fn query(&self, source: &str) -> Result<Query, String> {
+        Query::new(&self.language(), source).map_err(|e| format!("Invalid {} query: {}", self.name, e))
+    }
+}
+
+/// Runs `query` over each of `files`, collecting up to `limit` matches in file order.
+/// Captures named with a leading `_` only serve predicates and are left out. Files that
+/// are larger than `max_bytes`, not UTF-8 or unreadable are skipped. The returned flag
+/// says whether matches were dropped to stay within `limit`.
+pub fn run_query(
+    grammar: &Grammar,
+    query: &Query,
+    files: &[String],
+    limit: usize,
+    max_bytes: u64,
+) -> (Vec<QueryMatch>, bool) {
+    let mut parser = Parser::new();
+    if let Err(e) = parser.set_language(&grammar.language()) {
+        warn!("Failed to set language for {}: {}", grammar.name, e);
+        return (Vec::new(), false);
+    }
+    let names = query.capture_names();
+    let mut matches = Vec::new();
+    for file in files {
+        let path = Path::new(file);
+        if fs::metadata(path).map_or(true, |meta| meta.len() > max_bytes) {
+            debug!("Skipping '{}' for custom query: unreadable or too large.", file);
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(path) else { continue };
+        let Some(tree) = parser.parse(content.as_bytes(), None) else { continue };
+
+        let mut cursor = QueryCursor::new();
+        let mut file_matches = cursor.matches(query, tree.root_node(), content.as_bytes());
+        while let Some(mat) = file_matches.next() {
+            let captures: Vec<QueryCapture> = mat
+                .captures
+                .iter()
+                .filter(|cap| !names[cap.index as usize].starts_with('_'))
+                .map(|cap| QueryCapture {
+                    name: names[cap.index as usize].to_string(),
+                    text: content[cap.node.byte_range()].to_string(),
+                    start_line: cap.node.start_position().row + 1,
+                    end_line: cap.node.end_position().row + 1,
+                })
+                .collect();
+            if captures.is_empty() {
+                continue;
+            }
+            if matches.len() == limit {
+                return (matches, true);
+            }
+            matches.push(QueryMatch { file: file.clone(), captures });
+        }
+    }
+    (matches, false)
+}
+
+/// Byte ranges of the comments in a file, in source order, or `None` if it can't be parsed.
+pub fn comment_ranges(grammar: &Grammar, content: &str) -> Option<Vec<Range<usize>>> {
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language()).ok()?;
+    let tree = parser.parse(content.as_bytes(), None)?;
+    let mut ranges = Vec::new();
+    let mut cursor = tree.walk();
+    'walk: loop {
+        // Comments can have children, e.g. Rust doc comments; they are not descended into.
+        if cursor.node().kind().contains("comment") {
+            ranges.push(cursor.node().byte_range());
+        } else if cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+    Some(ranges)
+}
+
+/// Kinds of definitions whose functions are reported as methods.
+const METHOD_CONTAINERS: &[&str] = &[
+    "class", "struct", "union", "enum", "trait", "impl", "interface", "protocol", "actor", "extension",
+];
+
+/// A definition found in a file by its grammar's symbols query.
+pub struct Definition {
+    pub name: String,
+    pub kind: String,
+    /// The 1-based line the definition starts on.
+    pub start_line: usize,
+    /// Name of the innermost definition enclosing this one, e.g. a method's class.
+    pub container: Option<String>,
+}
+
+/// Matches a symbols query over a parsed file, returning each definition's node, name and
+/// kind, outer definitions first and in source order.
+fn definition_nodes<'t>(tree: &'t Tree, query: &Query, content: &str) -> Vec<(Node<'t>, String, String)> {
+    let names = query.capture_names();
+    // (definition node, pattern index, name, kind) for each match.
+    let mut found: Vec<(Node, usize, String, String)> = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(query, tree.root_node(), content.as_bytes());
+    while let Some(mat) = matches.next() {
+        let mut name = None;
+        let mut definition = None;
+        for cap in mat.captures {
+            let capture = names[cap.index as usize];
+            if capture == "name" {
+                name = Some(content[cap.node.byte_range()].to_string());
+            } else if let Some(kind) = capture.strip_prefix("definition.") {
+                definition = Some((cap.node, kind));
+            }
+        }
+        if let (Some(name), Some((node, kind))) = (name, definition) {
+            // Swift declares classes, structs, enums and actors with one node type.
+            let kind = match node.child_by_field_name("declaration_kind") {
+                Some(keyword) => content[keyword.byte_range()].to_string(),
+                None => kind.to_string(),
+            };
+            found.push((node, mat.pattern_index, name, kind));
+        }
+    }
+    // Outer definitions first, and the first pattern first among matches of one node.
+    found.sort_by(|a, b| {
+        a.0.start_byte()
+            .cmp(&b.0.start_byte())
+            .then(b.0.end_byte().cmp(&a.0.end_byte()))
+            .then(a.1.cmp(&b.1))
+    });
+    found.dedup_by(|b, a| a.0 == b.0);
+    found.into_iter().map(|(node, _, name, kind)| (node, name, kind)).collect()
+}
+
+/// Extracts the definitions of one file, in source order. Functions directly inside a
+/// class, struct, trait, impl, interface or the like are reported as methods.
+pub fn file_definitions(parser: &mut Parser, query: &Query, content: &str) -> Vec<Definition> {
+    let Some(tree) = parser.parse(content.as_bytes(), None) else {
+        return Vec::new();
+    };
+    let found = definition_nodes(&tree, query, content);
+    let mut definitions: Vec<Definition> = Vec::with_capacity(found.len());
+    // End bytes and indices into `definitions` of the definitions enclosing the current one.
+    let mut enclosing: Vec<(usize, usize)> = Vec::new();
+    for (node, name, mut kind) in found {
+        while enclosing.last().is_some_and(|&(end, _)| end <= node.start_byte()) {
+            enclosing.pop();
+        }
+        let parent = enclosing.last().map(|&(_, index)| &definitions[index]);
+        if kind == "function" && parent.is_some_and(|p| METHOD_CONTAINERS.contains(&p.kind.as_str())) {
+            kind = "method".to_string();
+        }
+        definitions.push(Definition {
+            name,
+            kind,
+            start_line: node.start_position().row + 1,
+            container: parent.map(|p| p.name.clone()),
+        });
+        enclosing.push((node.end_byte(), definitions.len() - 1));
+    }
+    definitions
+}
+
+/// Nodes that wrap a declaration without being part of a definition's name, e.g.
+/// `export` or the `const` of `const f = () => {}`. Their text starts the signature.
+const DECLARATION_WRAPPERS: &[&str] = &["export_statement", "lexical_declaration", "variable_declaration", "template_declaration"];
+
+/// Extracts the outline of one file: its definitions nested as in the source, with their
+/// signatures and docstrings or leading comments.
+pub fn file_outline(parser: &mut Parser, query: &Query, content: &str) -> Vec<OutlineItem> {
+    let Some(tree) = parser.parse(content.as_bytes(), None) else {
+        return Vec::new();
+    };
+    let mut outline = Vec::new();
+    // End bytes of the definitions enclosing the current one, with their items so far.
+    let mut enclosing: Vec<(usize, OutlineItem)> = Vec::new();
+    for (node, name, mut kind) in definition_nodes(&tree, query, content) {
+        while enclosing.last().is_some_and(|(end, _)| *end <= node.start_byte()) {
+            let (_, item) = enclosing.pop().unwrap();
+            match enclosing.last_mut() {
+                Some((_, parent)) => parent.children.push(item),
+                None => outline.push(item),
+            }
+        }
+        let parent = enclosing.last().map(|(_, item)| item);
+        if kind == "function" && parent.is_some_and(|p| METHOD_CONTAINERS.contains(&p.kind.as_str())) {
+            kind = "method".to_string();
+        }
+        let item = OutlineItem {
+            name,
+            kind,
+            signature: signature(node, content),
+            start_line: node.start_position().row + 1,
+            end_line: node.end_position().row + 1,
+            doc: docstring(node, content).or_else(|| leading_comment(node, content)),
+            children: Vec::new(),
+        };
+        enclosing.push((node.end_byte(), item));
+    }
+    while let Some((_, item)) = enclosing.pop() {
+        match enclosing.last_mut() {
+            Some((_, parent)) => parent.children.push(item),
+            None => outline.push(item),
+        }
+    }
+    outline
+}
+
+/// The node a definition is declared by: the definition itself, or the `export`, `const`
+/// or `template` around it.
+fn declaration(mut node: Node) -> Node {
+    while let Some(parent) = node.parent() {
+        let wraps = match parent.kind() {
+            // `const a = () => {}, b = 1` declares more than the definition.
+            "lexical_declaration" | "variable_declaration" => parent.named_child_count() == 1,
+            kind => DECLARATION_WRAPPERS.contains(&kind),
+        };
+        if !wraps {
+            break;
+        }
+        node = parent;
+    }
+    node
+}
+
+/// A definition's text up to its body, on one line, e.g. `def parse(text: str) -> Ast`.
+/// Definitions without a body give their first line.
+fn signature(node: Node, content: &str) -> String {
+    let start = declaration(node).start_byte();
+    let text = match definition_body(node) {
+        Some(body) => &content[start..body.start_byte()],
+        None => content[start..node.end_byte()].lines().next().unwrap_or(""),
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    text.trim_end_matches([':', ';', '{', ' ']).to_string()
+}
+
+/// The body of a definition, or of the function assigned in it (`const f = () => {...}`
+/// in JavaScript, `f <- function(x) {...}` in R).
+fn definition_body(node: Node) -> Option<Node> {
+    node.child_by_field_name("body").or_else(|| {
+        let function = node.child_by_field_name("value").or_else(|| node.child_by_field_name("rhs"))?;
+        function.child_by_field_name("body")
+    })
+}
+
+/// The string literal starting a Python block, which is the docstring of its definition.
+fn docstring_node(body: Node) -> Option<Node> {
+    let body = Some(body).filter(|body| body.kind() == "block")?;
+    let statement = body.named_child(0).filter(|first| first.kind() == "expression_statement")?;
+    statement.named_child(0).filter(|expr| expr.kind() == "string")
+}
+
+/// A Python definition's docstring, with its common indentation removed.
+fn docstring(node: Node, content: &str) -> Option<String> {
+    let string = docstring_node(node.child_by_field_name("body")?)?;
+    let mut cursor = string.walk();
+    let text: String = string
+        .named_children(&mut cursor)
+        .filter(|part| part.kind() == "string_content")
+        .map(|part| &content[part.byte_range()])
+        .collect();
+    let indent = text
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| if i == 0 { line.trim() } else { line.get(indent..).unwrap_or("").trim_end() })
+        .collect();
+    let doc = lines.join("\n").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// The comments directly above a definition (or its decorators and attributes), without
+/// their comment markers. A blank line ends the comment.
+fn leading_comment(node: Node, content: &str) -> Option<String> {
+    let mut anchor = declaration(node);
+    while let Some(parent) = anchor.parent().filter(|p| p.kind() == "decorated_definition") {
+        anchor = parent;
+    }
+    let mut comments = Vec::new();
+    let mut next_row = anchor.start_position().row;
+    let mut sibling = anchor.prev_sibling();
+    while let Some(prev) = sibling {
+        let adjacent = prev.end_position().row + 1 >= next_row;
+        if prev.kind().contains("attribute") && adjacent {
+            // Rust attributes sit between doc comments and the item.
+        } else if prev.kind().contains("comment") && adjacent {
+            comments.push(&content[prev.byte_range()]);
+        } else {
+            break;
+        }
+        next_row = prev.start_position().row;
+        sibling = prev.prev_sibling();
+    }
+    let lines: Vec<&str> = comments
+        .iter()
+        .rev()
+        .flat_map(|comment| comment.lines())
+        .map(strip_comment_markers)
+        .collect();
+    let doc = lines.join("\n").trim().to_string();
+    (!doc.is_empty()).then_some(doc)
+}
+
+/// One line of a comment without its `//`, `///`, `#`, `/*`, `*` or `*/` markers.
+fn strip_comment_markers(line: &str) -> &str {
+    let line = line.trim();
+    let line = line.strip_suffix("*/").unwrap_or(line);
+    let line = ["///", "//!", "//", "/**", "/*!", "/*", "#'", "#", "*"]
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+        .unwrap_or(line);
+    line.trim()
+}
+
+/// Renders a file as a code map: its source with the bodies of functions and methods
+/// elided, keeping imports, constants, type definitions, signatures and docstrings.
+/// Bodies that fit on one line are kept.
+pub fn code_map(grammar: &Grammar, parser: &mut Parser, query: &Query, content: &str) -> String {
+    let Some(tree) = parser.parse(content.as_bytes(), None) else {
+        return content.to_string();
+    };
+    let mut map = String::with_capacity(content.len());
+    let mut copied = 0;
+    for (node, _, kind) in definition_nodes(&tree, query, content) {
+        // Definitions nested in an elided body went with it.
+        if !matches!(kind.as_str(), "function" | "method") || node.start_byte() < copied {
+            continue;
+        }
+        let Some(body) = definition_body(node) else { continue };
+        let Some(elided) = elided_body(grammar, body, content) else { continue };
+        map.push_str(&content[copied..body.start_byte()]);
+        map.push_str(&elided);
+        copied = body.end_byte();
+    }
+    map.push_str(&content[copied..]);
+    map
+}
+
+/// What replaces a function body in a code map: `{ /* ... */ }` for braced bodies, and for
+/// Python blocks `...` after the docstring, if any. `None` keeps the body.
+fn elided_body(grammar: &Grammar, body: Node, content: &str) -> Option<String> {
+    let text = &content[body.byte_range()];
+    let line_start = content[..body.start_byte()].rfind('\n').map_or(0, |i| i + 1);
+    let before = &content[line_start..body.start_byte()];
+    let indent = &before[..before.len() - before.trim_start().len()];
+    if text.starts_with('{') {
+        if !text.contains('\n') {
+            return None;
+        }
+        return Some(match grammar.line_comment {
+            "//" => "{ /* ... */ }".to_string(),
+            comment => format!("{{ {} ...\n{}}}", comment, indent),
+        });
+    }
+    // An indented block, unless it follows its signature on the same line.
+    if body.kind() != "block" || before.len() != indent.len() {
+        return None;
+    }
+    Some(match docstring_node(body) {
+        Some(string) if body.named_child_count() > 1 => {
+            format!("{}\n{}...", &content[body.start_byte()..string.end_byte()], indent)
+        }
+        Some(_) => return None,
+        None => "...".to_string(),
+    })
+}
+
+/// Extracts the definitions of many files on scoped threads, as symbols with paths relative
+/// to `root`, grouped by file in input order. Files without a grammar, larger than
+/// `max_bytes` or not UTF-8 get no symbols.
+fn index_symbols(root: &str, files: &[String], max_bytes: u64) -> Vec<Vec<Symbol>> {
+    fn index_chunk(root: &str, files: &[String], max_bytes: u64) -> Vec<Vec<Symbol>> {
+        // A parser and compiled query per grammar, created on first use.
+        let mut parsers: HashMap<&'static str, Option<(Parser, Query)>> = HashMap::new();
+        files
+            .iter()
+            .map(|file| {
+                let path = Path::new(file);
+                let Some(grammar) = Grammar::for_path(path) else { return Vec::new() };
+                let Some((parser, query)) = parsers.entry(grammar.name).or_insert_with(|| grammar.symbol_parser()) else {
+                    return Vec::new();
+                };
+                if fs::metadata(path).map_or(true, |meta| meta.len() > max_bytes) {
+                    return Vec::new();
+                }
+                let Ok(content) = fs::read_to_string(path) else { return Vec::new() };
+                let relative = file.strip_prefix(root).unwrap_or(file).trim_start_matches('/');
+                file_definitions(parser, query, &content)
+                    .into_iter()
+                    .map(|definition| Symbol {
+                        name: definition.name,
+                        kind: definition.kind,
+                        file: relative.to_string(),
+                        line: definition.start_line,
+                        container: definition.container,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_SYMBOL_THREADS)
+        .min(files.len());
+    if workers <= 1 {
+        return index_chunk(root, files, max_bytes);
+    }
+    let chunk_size = files.len().div_ceil(workers);
+    thread::scope(|scope| {
+        let handles: Vec<_> = files
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || index_chunk(root, chunk, max_bytes)))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("symbol extraction thread panicked"))
+            .collect()
+    })
+}
+
+/// The symbols of a root, kept between requests and brought up to date by re-parsing only
+/// the files whose mtime or size changed since the previous update, like the incremental
+/// dependency analyzer.
+#[derive(Default)]
+pub struct SymbolIndex {
+    files: HashMap<String, ((SystemTime, u64), Vec<Symbol>)>,
+}
+
+impl SymbolIndex {
+    /// Re-parses the changed files among `files` (absolute paths under `root`), forgets
+    /// files no longer listed and returns all symbols, in the order of `files`.
+    pub fn update(&mut self, root: &str, files: &[String], max_bytes: u64) -> Vec<Symbol> {
+        let start_time = Instant::now();
+        let stamped: Vec<(&String, (SystemTime, u64))> =
+            files.iter().filter_map(|file| file_stamp(file).map(|stamp| (file, stamp))).collect();
+        let changed: Vec<String> = stamped
+            .iter()
+            .filter(|(file, stamp)| self.files.get(*file).is_none_or(|(known, _)| known != stamp))
+            .map(|(file, _)| (*file).clone())
+            .collect();
+        let mut reparsed: HashMap<&String, Vec<Symbol>> =
+            changed.iter().zip(index_symbols(root, &changed, max_bytes)).collect();
+
+        let mut previous = std::mem::take(&mut self.files);
+        for (file, stamp) in stamped {
+            let known = previous.remove(file).map(|(_, symbols)| symbols);
+            let symbols = reparsed.remove(file).or(known).unwrap_or_default();
+            self.files.insert(file.clone(), (stamp, symbols));
+        }
+        debug!(
+            "Updated symbol index for '{}' in {:.2?}: re-parsed {} of {} files, removed {}.",
+            root,
+            start_time.elapsed(),
+            changed.len(),
+            files.len(),
+            previous.len()
+        );
+        files
+            .iter()
+            .filter_map(|file| self.files.get(file))
+            .flat_map(|(_, symbols)| symbols.iter().cloned())
+            .collect()
+    }
+}
+
+/// Finds the calls of functions or methods named `symbol` in one file, matched by name only,
+/// with the function each is made from. Files larger than `max_bytes`, not UTF-8 or not
+/// mentioning `symbol` are skipped without being parsed. `root` is the walked root, which
+/// the callers' paths are made relative to. Blocking.
+pub fn find_callers(grammar: &Grammar, path: &Path, root: &str, symbol: &str, max_bytes: u64) -> io::Result<Vec<Caller>> {
+    let Some(calls) = grammar.calls else { return Ok(Vec::new()) };
+    if fs::metadata(path)?.len() > max_bytes {
+        return Ok(Vec::new());
+    }
+    let Ok(content) = fs::read_to_string(path) else { return Ok(Vec::new()) };
+    if !content.contains(symbol) {
+        return Ok(Vec::new());
+    }
+    let (mut parser, symbols) = grammar.symbol_parser().ok_or_else(|| io::Error::other("grammar failed to load"))?;
+    let calls = grammar.query(calls).map_err(io::Error::other)?;
+    let Some(tree) = parser.parse(content.as_bytes(), None) else { return Ok(Vec::new()) };
+
+    let names = calls.capture_names();
+    let mut call_nodes = Vec::new();
+    let mut cursor = QueryCursor::new();
+    let mut matches = cursor.matches(&calls, tree.root_node(), content.as_bytes());
+    while let Some(mat) = matches.next() {
+        let callee = mat.captures.iter().find(|cap| names[cap.index as usize] == "callee");
+        let call = mat.captures.iter().find(|cap| names[cap.index as usize] == "call");
+        if let (Some(callee), Some(call)) = (callee, call) {
+            if &content[callee.node.byte_range()] == symbol {
+                call_nodes.push(call.node);
+            }
+        }
+    }
+    if call_nodes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let file = path.to_string_lossy();
+    let relative = file.strip_prefix(root).unwrap_or(&file).trim_start_matches('/');
+    // Outer definitions first, so the last one enclosing a call is the innermost.
+    let definitions = definition_nodes(&tree, &symbols, content.as_str());
+    let mut callers: Vec<Caller> = call_nodes
+        .into_iter()
+        .map(|call| {
+            let enclosing: Vec<&(Node, String, String)> = definitions
+                .iter()
+                .filter(|(node, _, _)| node.start_byte() <= call.start_byte() && call.end_byte() <= node.end_byte())
+                .collect();
+            let function = enclosing.iter().rposition(|(_, _, kind)| matches!(kind.as_str(), "function" | "method"));
+            let line = call.start_position().row;
+            Caller {
+                file: relative.to_string(),
+                line: line + 1,
+                function: function.map(|i| enclosing[i].1.clone()),
+                container: function.and_then(|i| i.checked_sub(1)).map(|i| enclosing[i].1.clone()),
+                text: content.lines().nth(line).unwrap_or("").trim().to_string(),
+            }
+        })
+        .collect();
+    callers.dedup_by(|b, a| a.line == b.line && a.function == b.function);
+    Ok(callers)
+}
\ No newline at end of file
@@ -1,3 +1,289 @@
+use crate::models::LineEdit;
+use std::borrow::Cow;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
 pub fn natural_compare(a: &str, b: &str) -> std::cmp::Ordering {
     natord::compare(a, b)
+}
+
+/// Normalizes a file name or path to Unicode NFC. macOS reports names decomposed (NFD),
+/// while clients and import strings are almost always composed.
+pub fn to_nfc(s: &str) -> String {
+    if is_nfc(s) {
+        s.to_string()
+    } else {
+        s.nfc().collect()
+    }
+}
+
+/// Strips a leading UTF-8 byte order mark and turns CRLF line endings into LF, for text
+/// pasted into prompts. Borrows `s` when there is nothing to change.
+pub fn normalize_content(s: &str) -> Cow<'_, str> {
+    let without_bom = s.strip_prefix('\u{feff}').unwrap_or(s);
+    if without_bom.contains("\r\n") {
+        Cow::Owned(without_bom.replace("\r\n", "\n"))
+    } else {
+        Cow::Borrowed(without_bom)
+    }
+}
+
+/// Applies line edits to `content`, bottom-up so each edit's line numbers refer to the
+/// original content. Fails if an edit's range is outside the content or overlaps another.
+/// A replacement gets the content's line ending appended unless it already ends with one
+/// or ends where the content ends without a final newline.
+pub fn apply_line_edits(content: &str, edits: &[LineEdit]) -> Result<String, String> {
+    let mut lines: Vec<&str> = content.split_inclusive('\n').collect();
+    let line_count = lines.len();
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let mut sorted: Vec<&LineEdit> = edits.iter().collect();
+    sorted.sort_by_key(|edit| (edit.start_line, edit.end_line));
+    for edit in &sorted {
+        if edit.start_line == 0 || edit.start_line > line_count + 1 {
+            return Err(format!("Line {} is out of range: the file has {} lines", edit.start_line, line_count));
+        }
+        if edit.end_line + 1 < edit.start_line || edit.end_line > line_count {
+            return Err(format!(
+                "Invalid range {}-{}: the end must be between {} and {}",
+                edit.start_line,
+                edit.end_line,
+                edit.start_line - 1,
+                line_count
+            ));
+        }
+    }
+    for pair in sorted.windows(2) {
+        let (first, second) = (pair[0], pair[1]);
+        if second.start_line <= first.end_line || second.start_line == first.start_line {
+            return Err(format!(
+                "Edits {}-{} and {}-{} overlap",
+                first.start_line, first.end_line, second.start_line, second.end_line
+            ));
+        }
+    }
+
+    let replacements: Vec<String> = sorted
+        .iter()
+        .map(|edit| {
+            let at_unterminated_end = edit.end_line == line_count && !content.is_empty() && !content.ends_with('\n');
+            // Lines appended after a last line without a newline start on a line of their own.
+            let prefix = if at_unterminated_end && edit.start_line > line_count { newline } else { "" };
+            if edit.replacement.is_empty() {
+                String::new()
+            } else if edit.replacement.ends_with('\n') || at_unterminated_end {
+                format!("{}{}", prefix, edit.replacement)
+            } else {
+                format!("{}{}", edit.replacement, newline)
+            }
+        })
+        .collect();
+    for (edit, replacement) in sorted.iter().zip(&replacements).rev() {
+        lines.splice(edit.start_line - 1..edit.end_line, [replacement.as_str()]);
+    }
+    Ok(lines.concat())
+}
+
+/// Finds the on-disk path that `path` refers to when its components are in a different
+/// Unicode normal form than the names stored on disk (e.g. an NFC path to an NFD-named
+/// file on Linux). Returns `None` if no such path exists.
+pub fn resolve_unicode_path(path: &Path) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path.to_path_buf());
+    }
+    if path.to_str().is_some_and(|s| s.is_ascii()) {
+        return None;
+    }
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            resolved.push(component);
+            continue;
+        };
+        let direct = resolved.join(name);
+        if direct.exists() {
+            resolved = direct;
+            continue;
+        }
+        let wanted = to_nfc(&name.to_string_lossy());
+        let dir = if resolved.as_os_str().is_empty() { Path::new(".") } else { resolved.as_path() };
+        let entry = fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| to_nfc(&entry.file_name().to_string_lossy()) == wanted)?;
+        resolved.push(entry.file_name());
+    }
+    Some(resolved)
+}
+
+/// Maps a client-supplied or tree path to the file on disk, matching names regardless of
+/// their Unicode normal form (tree paths are NFC, names on disk may be NFD) and accepting
+/// forward slashes on Windows.
+pub fn on_disk_path(path_str: &str) -> PathBuf {
+    let path = from_api_path(path_str);
+    resolve_unicode_path(&path).unwrap_or(path)
+}
+
+/// Formats a path the way the API reports it: with forward slashes and without the
+/// `\\?\` verbatim prefix that `canonicalize` adds on Windows (drive letters are kept,
+/// upper-cased, e.g. `C:/Users/me/project`). Elsewhere paths are returned unchanged.
+pub fn to_api_path(path: impl AsRef<Path>) -> String {
+    let path = path.as_ref().to_string_lossy();
+    if cfg!(windows) {
+        windows_api_path(&path)
+    } else {
+        path.into_owned()
+    }
+}
+
+/// Turns a client-supplied path back into a native one. The inverse of `to_api_path`:
+/// on Windows forward slashes become backslashes and a verbatim prefix is dropped.
+pub fn from_api_path(path: &str) -> PathBuf {
+    if cfg!(windows) {
+        PathBuf::from(windows_native_path(path))
+    } else {
+        PathBuf::from(path)
+    }
+}
+
+/// `to_api_path` for a Windows path, kept separate so it can be tested on every platform.
+fn windows_api_path(path: &str) -> String {
+    upper_case_drive(strip_verbatim_prefix(path).replace('\\', "/"))
+}
+
+/// `from_api_path` for a Windows path, kept separate so it can be tested on every platform.
+fn windows_native_path(path: &str) -> String {
+    upper_case_drive(strip_verbatim_prefix(path).replace('/', "\\"))
+}
+
+/// Removes a Windows verbatim prefix: `\\?\C:\x` becomes `C:\x` and `\\?\UNC\server\share`
+/// becomes `\\server\share`, so canonical paths compare equal to the ones clients send.
+fn strip_verbatim_prefix(path: &str) -> String {
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else {
+        path.strip_prefix(r"\\?\").unwrap_or(path).to_string()
+    }
+}
+
+/// Upper-cases a leading drive letter, as `canonicalize` reports it, so that `c:/x` and
+/// `C:/x` are the same path.
+fn upper_case_drive(mut path: String) -> String {
+    if path.as_bytes().get(1) == Some(&b':') {
+        path[..1].make_ascii_uppercase();
+    }
+    path
+}
+
+/// Formats a byte count for humans, e.g. `42 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{} MB", bytes / MB)
+    } else if bytes >= KB {
+        format!("{} KB", bytes / KB)
+    } else {
+        format!("{} bytes", bytes)
+    }
+}
+
+/// Maps a script's shebang line (e.g. `#!/usr/bin/env python3`) to a language tag.
+pub fn language_from_shebang(first_line: &str) -> Option<&'static str> {
+    let command = first_line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    match program {
+        "python" => Some("python"),
+        "node" | "deno" | "bun" => Some("javascript"),
+        "ts-node" => Some("typescript"),
+        "sh" | "bash" | "zsh" | "dash" | "ksh" => Some("bash"),
+        "ruby" => Some("ruby"),
+        "php" => Some("php"),
+        "Rscript" => Some("r"),
+        _ => None,
+    }
+}
+
+/// Maps a file path to the language tag used for fenced code blocks, based on its extension.
+pub fn language_for_path(path: &str) -> &'static str {
+    let path = Path::new(path);
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "rs" => "rust",
+        "py" | "pyi" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "c" | "h" => "c",
+        "cpp" | "cc" | "cxx" | "hpp" | "hxx" | "hh" => "cpp",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "rb" => "ruby",
+        "php" => "php",
+        "cs" => "csharp",
+        "r" => "r",
+        "sh" | "bash" | "zsh" => "bash",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "xml" => "xml",
+        "md" | "markdown" => "markdown",
+        "sql" => "sql",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_paths_use_forward_slashes() {
+        assert_eq!(windows_api_path(r"C:\Users\me\project"), "C:/Users/me/project");
+        assert_eq!(windows_api_path("C:/Users/me/mixed\\sep"), "C:/Users/me/mixed/sep");
+        assert_eq!(windows_native_path("C:/Users/me/project"), r"C:\Users\me\project");
+    }
+
+    #[test]
+    fn windows_verbatim_prefixes_are_dropped() {
+        assert_eq!(windows_api_path(r"\\?\C:\Users\me"), "C:/Users/me");
+        assert_eq!(windows_api_path(r"\\?\UNC\server\share\dir"), "//server/share/dir");
+        assert_eq!(windows_native_path(r"\\?\C:\Users\me"), r"C:\Users\me");
+        assert_eq!(windows_native_path("//server/share/dir"), r"\\server\share\dir");
+    }
+
+    #[test]
+    fn windows_drive_letters_are_upper_cased() {
+        assert_eq!(windows_api_path(r"c:\Users\me"), "C:/Users/me");
+        assert_eq!(windows_api_path(r"\\?\d:\data"), "D:/data");
+        assert_eq!(windows_native_path("c:/Users/me"), r"C:\Users\me");
+        // Only a leading drive letter is touched.
+        assert_eq!(windows_api_path(r"dir\c:x"), "dir/c:x");
+    }
+
+    #[test]
+    fn api_paths_round_trip_on_this_platform() {
+        let path = std::env::temp_dir().join("project").join("a.txt");
+        assert_eq!(from_api_path(&to_api_path(&path)), path);
+        assert!(!to_api_path(&path).contains('\\'));
+    }
 }
\ No newline at end of file
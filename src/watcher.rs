@@ -0,0 +1,203 @@
+use crate::file_system::{is_excluded_by_default, GitignoreStack};
+use crate::utils::to_api_path;
+use indexmap::IndexMap;
+use log::{debug, info, warn};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Events for the same root are held back until the filesystem has been quiet this long.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+/// Events buffered per root before slow subscribers start lagging.
+const WATCH_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchEventKind {
+    Created,
+    Modified,
+    Removed,
+}
+
+/// A debounced change to a file or directory, with `path` relative to the watched root.
+#[derive(Clone, Debug, Serialize)]
+pub struct WatchEvent {
+    pub event: WatchEventKind,
+    pub path: String,
+}
+
+/// One `notify` watcher shared by every subscriber of a root.
+struct RootWatcher {
+    sender: broadcast::Sender<WatchEvent>,
+    subscribers: usize,
+    _watcher: RecommendedWatcher,
+}
+
+/// Watched roots, each with a single filesystem watcher fanned out to its subscribers.
+#[derive(Default)]
+pub struct WatchRegistry {
+    roots: Mutex<HashMap<PathBuf, RootWatcher>>,
+}
+
+/// A subscriber's handle on a watched root. Dropping it unsubscribes, and the root's
+/// watcher is torn down once its last subscriber is gone.
+pub struct WatchSubscription {
+    registry: Arc<WatchRegistry>,
+    root: PathBuf,
+}
+
+impl Drop for WatchSubscription {
+    fn drop(&mut self) {
+        self.registry.unsubscribe(&self.root);
+    }
+}
+
+impl WatchRegistry {
+    /// Subscribes to events under `root`, starting a watcher for it if needed.
+    /// Fails if starting one would exceed `max_watchers` watched roots.
+    pub fn subscribe(
+        self: &Arc<Self>,
+        root: &Path,
+        max_watchers: usize,
+    ) -> Result<(WatchSubscription, broadcast::Receiver<WatchEvent>), String> {
+        let mut roots = self.roots.lock().unwrap();
+        let receiver = match roots.get_mut(root) {
+            Some(watcher) => {
+                watcher.subscribers += 1;
+                watcher.sender.subscribe()
+            }
+            None => {
+                if roots.len() >= max_watchers {
+                    return Err(format!("Too many watched directories (limit {})", max_watchers));
+                }
+                let (sender, receiver) = broadcast::channel(WATCH_CHANNEL_CAPACITY);
+                let watcher = start_watcher(root, sender.clone())?;
+                info!("Started watching '{}'", root.display());
+                roots.insert(root.to_path_buf(), RootWatcher { sender, subscribers: 1, _watcher: watcher });
+                receiver
+            }
+        };
+        let subscription = WatchSubscription { registry: Arc::clone(self), root: root.to_path_buf() };
+        Ok((subscription, receiver))
+    }
+
+    fn unsubscribe(&self, root: &Path) {
+        let mut roots = self.roots.lock().unwrap();
+        if let Some(watcher) = roots.get_mut(root) {
+            watcher.subscribers -= 1;
+            if watcher.subscribers == 0 {
+                roots.remove(root);
+                info!("Stopped watching '{}'", root.display());
+            }
+        }
+    }
+}
+
+/// Starts a recursive watcher on `root` whose events are debounced on a dedicated thread.
+/// The thread exits once the returned watcher (and with it the event channel) is dropped.
+fn start_watcher(root: &Path, sender: broadcast::Sender<WatchEvent>) -> Result<RecommendedWatcher, String> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| match result {
+        Ok(event) => {
+            let _ = tx.send(event);
+        }
+        Err(e) => warn!("Filesystem watcher error: {}", e),
+    })
+    .map_err(|e| format!("Failed to create watcher: {}", e))?;
+    watcher
+        .watch(root, RecursiveMode::Recursive)
+        .map_err(|e| format!("Failed to watch '{}': {}", root.display(), e))?;
+
+    let root = root.to_path_buf();
+    thread::spawn(move || debounce_events(&root, rx, sender));
+    Ok(watcher)
+}
+
+/// Collects raw events until the filesystem is quiet, then broadcasts one event per
+/// changed path, skipping `.git` internals, default excludes and gitignored paths.
+fn debounce_events(root: &Path, rx: mpsc::Receiver<Event>, sender: broadcast::Sender<WatchEvent>) {
+    let gitignore = GitignoreStack::load(root);
+    while let Ok(event) = rx.recv() {
+        let mut pending = IndexMap::new();
+        record_event(&mut pending, event);
+        loop {
+            match rx.recv_timeout(WATCH_DEBOUNCE) {
+                Ok(event) => record_event(&mut pending, event),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        for (path, kind) in pending {
+            if let Some(relative) = visible_path(root, &gitignore, &path, kind) {
+                debug!("Watch event {:?} for '{}'", kind, relative);
+                // Sending only fails when there are no subscribers left, which is fine.
+                let _ = sender.send(WatchEvent { event: kind, path: relative });
+            }
+        }
+    }
+    debug!("Watcher for '{}' shut down", root.display());
+}
+
+/// Folds a raw `notify` event into the pending per-path changes.
+fn record_event(pending: &mut IndexMap<PathBuf, WatchEventKind>, event: Event) {
+    let mut changes = Vec::new();
+    match event.kind {
+        EventKind::Create(_) => changes.extend(event.paths.into_iter().map(|p| (p, WatchEventKind::Created))),
+        EventKind::Remove(_) => changes.extend(event.paths.into_iter().map(|p| (p, WatchEventKind::Removed))),
+        EventKind::Modify(ModifyKind::Name(mode)) => {
+            for (i, path) in event.paths.into_iter().enumerate() {
+                let kind = match mode {
+                    RenameMode::From => WatchEventKind::Removed,
+                    RenameMode::To => WatchEventKind::Created,
+                    RenameMode::Both if i == 0 => WatchEventKind::Removed,
+                    RenameMode::Both => WatchEventKind::Created,
+                    _ if path.exists() => WatchEventKind::Created,
+                    _ => WatchEventKind::Removed,
+                };
+                changes.push((path, kind));
+            }
+        }
+        EventKind::Modify(_) => changes.extend(event.paths.into_iter().map(|p| (p, WatchEventKind::Modified))),
+        _ => {}
+    }
+
+    for (path, kind) in changes {
+        let merged = match (pending.get(&path).copied(), kind) {
+            (Some(WatchEventKind::Created), WatchEventKind::Modified) => Some(WatchEventKind::Created),
+            (Some(WatchEventKind::Created), WatchEventKind::Removed) => None,
+            (Some(WatchEventKind::Removed), WatchEventKind::Created) => Some(WatchEventKind::Modified),
+            (_, kind) => Some(kind),
+        };
+        match merged {
+            Some(kind) => {
+                pending.insert(path, kind);
+            }
+            None => {
+                pending.shift_remove(&path);
+            }
+        }
+    }
+}
+
+/// Returns `path` relative to `root` if the tree would show it.
+fn visible_path(root: &Path, gitignore: &GitignoreStack, path: &Path, kind: WatchEventKind) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    if relative.as_os_str().is_empty() {
+        return None;
+    }
+    if relative.components().any(|c| is_excluded_by_default(&c.as_os_str().to_string_lossy())) {
+        return None;
+    }
+    let is_dir = kind != WatchEventKind::Removed && path.is_dir();
+    if gitignore.is_path_ignored(path, is_dir) {
+        return None;
+    }
+    Some(to_api_path(relative))
+}
\ No newline at end of file
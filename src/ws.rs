@@ -0,0 +1,361 @@
+use crate::handlers::{check_batch_size, count_files, list_directory, normalize_files, load_dependency_graph, read_files, transport};
+use crate::middleware::{block, with_request_id, RequestId};
+use crate::models::{DirectoryQuery, FilesRequest};
+use crate::rate_limit::{retry_after_secs, EndpointClass};
+use crate::state::AppState;
+use crate::utils::to_api_path;
+use actix_web::{get, rt, web, HttpMessage, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, Session};
+use futures::future::{abortable, AbortHandle};
+use futures::StreamExt;
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::rc::Rc;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+
+/// Largest WebSocket message accepted from a client.
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Requests one session may have in flight; further requests are answered with an error
+/// until one completes.
+const MAX_IN_FLIGHT_REQUESTS: usize = 16;
+
+/// A client request, e.g. `{"id":1,"method":"directory","params":{"path":"..."}}`.
+#[derive(Deserialize)]
+struct RpcRequest {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct CancelParams {
+    id: Value,
+}
+
+/// Per-connection state: in-flight requests (for `cancel`) and the active watch, if any.
+#[derive(Default)]
+struct SessionTasks {
+    requests: HashMap<String, AbortHandle>,
+    watch: Option<AbortHandle>,
+}
+
+impl SessionTasks {
+    /// Tracks a request under its id, aborting an in-flight request that had the same id.
+    /// Fails if the session already has `MAX_IN_FLIGHT_REQUESTS` other requests in flight.
+    fn start_request(&mut self, key: String, handle: AbortHandle) -> Result<(), String> {
+        if self.requests.len() >= MAX_IN_FLIGHT_REQUESTS && !self.requests.contains_key(&key) {
+            return Err(format!(
+                "Too many requests in flight on this connection (at most {}); wait for one to finish",
+                MAX_IN_FLIGHT_REQUESTS
+            ));
+        }
+        if let Some(previous) = self.requests.insert(key, handle) {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    fn abort_all(&mut self) {
+        for (_, handle) in self.requests.drain() {
+            handle.abort();
+        }
+        if let Some(handle) = self.watch.take() {
+            handle.abort();
+        }
+    }
+}
+
+/// A persistent connection exposing the HTTP API as JSON-RPC-style messages.
+///
+/// Requests are answered with `{"id":...,"result":...}` or `{"id":...,"error":"..."}`.
+/// Methods: `directory`, `dependencies` and `files` (same parameters as their HTTP
+/// counterparts), `cancel` (`{"id":...}` of an in-flight request), and `watch`/`unwatch`,
+/// which start or stop `{"method":"fileChanged","params":{...}}` notifications. Methods
+/// count against the client's rate limits like their HTTP counterparts, and a session may
+/// have up to `MAX_IN_FLIGHT_REQUESTS` requests in flight.
+///
+/// `cancel` answers the cancelled request with an error right away and skips its remaining
+/// steps, but work already running on the blocking pool, such as a tree walk or a batch of
+/// file reads, still runs to completion in the background.
+#[get("/api/ws")]
+pub async fn websocket(
+    req: HttpRequest,
+    body: web::Payload,
+    state: web::Data<AppState>,
+) -> actix_web::Result<HttpResponse> {
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    let messages = messages
+        .max_frame_size(MAX_MESSAGE_SIZE)
+        .aggregate_continuations()
+        .max_continuation_size(MAX_MESSAGE_SIZE);
+    info!("WebSocket session opened.");
+    let client = req.peer_addr().map(|addr| addr.ip());
+
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone())
+        .unwrap_or_default();
+    rt::spawn(with_request_id(request_id, async move {
+        let tasks = Rc::new(RefCell::new(SessionTasks::default()));
+        let mut messages = messages;
+        let mut session = session;
+        while let Some(message) = messages.next().await {
+            match message {
+                Ok(AggregatedMessage::Text(text)) => handle_message(&text, client, &mut session, &state, &tasks).await,
+                Ok(AggregatedMessage::Ping(bytes)) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Ok(AggregatedMessage::Close(_)) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("WebSocket protocol error: {}", e);
+                    break;
+                }
+            }
+        }
+        tasks.borrow_mut().abort_all();
+        let _ = session.close(None).await;
+        info!("WebSocket session closed.");
+    }));
+
+    Ok(response)
+}
+
+/// Parses one client message and answers it, spawning long-running methods so that
+/// they can be cancelled while further messages are processed.
+async fn handle_message(
+    text: &str,
+    client: Option<IpAddr>,
+    session: &mut Session,
+    state: &web::Data<AppState>,
+    tasks: &Rc<RefCell<SessionTasks>>,
+) {
+    let request: RpcRequest = match serde_json::from_str(text) {
+        Ok(r) => r,
+        Err(e) => {
+            send(session, json!({ "id": Value::Null, "error": format!("Invalid request: {}", e) })).await;
+            return;
+        }
+    };
+    debug!("WebSocket request {}: {}", request.id, request.method);
+
+    match request.method.as_str() {
+        "cancel" => {
+            let result = parse_params::<CancelParams>(request.params).map(|params| {
+                let handle = tasks.borrow_mut().requests.remove(&params.id.to_string());
+                if let Some(handle) = &handle {
+                    handle.abort();
+                }
+                json!({ "cancelled": handle.is_some() })
+            });
+            send(session, reply(request.id, result)).await;
+        }
+        "watch" => {
+            let result = start_watch(request.params, session, state, tasks).await;
+            send(session, reply(request.id, result)).await;
+        }
+        "unwatch" => {
+            let stopped = tasks.borrow_mut().watch.take().map(|handle| handle.abort()).is_some();
+            send(session, reply(request.id, Ok(json!({ "stopped": stopped })))).await;
+        }
+        _ => {
+            let key = request.id.to_string();
+            let (call, handle) = abortable(dispatch(request.method, request.params, client, state.clone()));
+            let started = tasks.borrow_mut().start_request(key.clone(), handle);
+            if let Err(e) = started {
+                warn!("Rejected WebSocket request {}: {}", request.id, e);
+                send(session, reply(request.id, Err(e))).await;
+                return;
+            }
+            let mut session = session.clone();
+            let tasks = tasks.clone();
+            rt::spawn(async move {
+                let result = call.await.unwrap_or_else(|_| Err("Request cancelled".to_string()));
+                tasks.borrow_mut().requests.remove(&key);
+                send(&mut session, reply(request.id, result)).await;
+            });
+        }
+    }
+}
+
+/// Runs a request method against the same functions that back the HTTP endpoints, after
+/// taking a token from `client`'s rate limit for the matching endpoint class.
+async fn dispatch(
+    method: String,
+    params: Value,
+    client: Option<IpAddr>,
+    state: web::Data<AppState>,
+) -> Result<Value, String> {
+    let class = match method.as_str() {
+        "directory" | "dependencies" => EndpointClass::Expensive,
+        _ => EndpointClass::Cheap,
+    };
+    if let Some(client) = client {
+        if let Err(retry_after) = state.rate_limiter.check(client, class) {
+            let retry_after = retry_after_secs(retry_after);
+            warn!("Rate limit exceeded by {} for WebSocket method {}; retry after {}s.", client, method, retry_after);
+            return Err(format!("Too many requests; retry after {}s", retry_after));
+        }
+    }
+    match method.as_str() {
+        "directory" => {
+            let query: DirectoryQuery = parse_params(params)?;
+            list_directory(&query, &state).await.map_err(|e| e.message)
+        }
+        "dependencies" => {
+            let query: DirectoryQuery = parse_params(params)?;
+            let (path, graph, exports, edge_flags, disabled) =
+                load_dependency_graph(&query, &state).await.map_err(|e| e.message)?;
+            Ok(json!({
+                "root": to_api_path(&path),
+                "dependencyGraph": graph,
+                "exports": exports,
+                "typeCheckingOnly": edge_flags.type_checking_only,
+                "conditionalIncludes": edge_flags.conditional,
+                "disabled_languages": disabled.names(),
+            }))
+        }
+        "files" => {
+            let request: FilesRequest = parse_params(params)?;
+            check_batch_size(&request.paths, &state).map_err(|e| e.message)?;
+            let transport = transport(request.transport.as_deref()).map_err(|e| e.message)?;
+            let (mut files, truncated) = read_files(&request.paths, transport, request.max_bytes, &state).await;
+            if request.normalize.unwrap_or(false) {
+                normalize_files(&mut files);
+            }
+            let totals = request.counts.unwrap_or(false).then(|| count_files(&mut files));
+            let mut result = json!({ "files": files, "truncated": truncated });
+            if let Some(totals) = totals {
+                result["totals"] = json!(totals);
+            }
+            Ok(result)
+        }
+        other => Err(format!("Unknown method '{}'", other)),
+    }
+}
+
+/// Subscribes the session to filesystem events under `params.path`, replacing any
+/// previous watch, and forwards them as `fileChanged` notifications.
+async fn start_watch(
+    params: Value,
+    session: &Session,
+    state: &web::Data<AppState>,
+    tasks: &Rc<RefCell<SessionTasks>>,
+) -> Result<Value, String> {
+    let query: DirectoryQuery = parse_params(params)?;
+    let path = state.allowed_roots.validate_root(query.path.as_deref().unwrap_or("."))?;
+
+    let registry = state.watchers.clone();
+    let max_watchers = state.config.max_watchers;
+    let watch_path = path.clone();
+    let (subscription, receiver) = block(move || registry.subscribe(&watch_path, max_watchers))
+        .await
+        .map_err(|e| format!("Watch failed: {}", e))??;
+
+    let mut session = session.clone();
+    let (forward, handle) = abortable(async move {
+        // Keeps the subscription alive until the watch is stopped or the session ends.
+        let _subscription = subscription;
+        let mut events = BroadcastStream::new(receiver);
+        while let Some(item) = events.next().await {
+            let params = match item {
+                Ok(event) => json!(event),
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => json!({ "event": "lagged", "skipped": skipped }),
+            };
+            if session.text(json!({ "method": "fileChanged", "params": params }).to_string()).await.is_err() {
+                break;
+            }
+        }
+    });
+    if let Some(previous) = tasks.borrow_mut().watch.replace(handle) {
+        previous.abort();
+    }
+    rt::spawn(forward);
+
+    Ok(json!({ "watching": to_api_path(&path) }))
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, String> {
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| format!("Invalid params: {}", e))
+}
+
+fn reply(id: Value, result: Result<Value, String>) -> Value {
+    match result {
+        Ok(result) => json!({ "id": id, "result": result }),
+        Err(error) => json!({ "id": id, "error": error }),
+    }
+}
+
+async fn send(session: &mut Session, message: Value) {
+    if session.text(message.to_string()).await.is_err() {
+        debug!("Dropping WebSocket message: session closed.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+    use crate::test_dir::TempDir;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn sessions_cap_their_in_flight_requests() {
+        let mut tasks = SessionTasks::default();
+        let mut handles = Vec::new();
+        for i in 0..MAX_IN_FLIGHT_REQUESTS {
+            let (handle, _) = AbortHandle::new_pair();
+            tasks.start_request(i.to_string(), handle.clone()).unwrap();
+            handles.push(handle);
+        }
+        let (extra, _) = AbortHandle::new_pair();
+        assert!(tasks.start_request("extra".into(), extra).is_err());
+
+        // Reusing an id replaces the request with that id, aborting it.
+        let (replacement, _) = AbortHandle::new_pair();
+        tasks.start_request("0".into(), replacement).unwrap();
+        assert!(handles[0].is_aborted());
+        assert_eq!(tasks.requests.len(), MAX_IN_FLIGHT_REQUESTS);
+
+        tasks.requests.remove("1");
+        let (extra, _) = AbortHandle::new_pair();
+        assert!(tasks.start_request("extra".into(), extra).is_ok());
+    }
+
+    #[actix_web::test]
+    async fn methods_count_against_the_rate_limit_of_their_class() {
+        let root = TempDir::new();
+        root.write("a.txt", "a");
+        let config = ServerConfig {
+            rate_limit_expensive_per_min: 1,
+            rate_limit_cheap_per_min: 1,
+            data_dir: root.path().to_path_buf(),
+            ..Default::default()
+        };
+        let state = web::Data::new(AppState::new(config));
+        let client = Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let params = json!({ "path": to_api_path(root.path()) });
+        let files = json!({ "paths": [to_api_path(root.path().join("a.txt"))] });
+
+        assert!(dispatch("directory".into(), params.clone(), client, state.clone()).await.is_ok());
+        let error = dispatch("dependencies".into(), params.clone(), client, state.clone()).await.unwrap_err();
+        assert!(error.starts_with("Too many requests"), "{}", error);
+        // Cheap methods have their own budget, and clients without an address aren't limited.
+        assert!(dispatch("files".into(), files.clone(), client, state.clone()).await.is_ok());
+        assert!(dispatch("files".into(), files.clone(), client, state.clone()).await.is_err());
+        assert!(dispatch("directory".into(), params, None, state).await.is_ok());
+    }
+}
\ No newline at end of file